@@ -0,0 +1,64 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use cve_2021_44228_scanner::config::Config;
+use cve_2021_44228_scanner::rules::RuleSet;
+use cve_2021_44228_scanner::scanner::{archive_formats, scan_file};
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+use zip::write::FileOptions;
+use zip::{CompressionMethod, ZipWriter};
+
+/// Large enough for `scan_jar` to take the parallel path
+const JAR_SIZE: usize = 80 * 1024 * 1024;
+const CLASS_SIZE: usize = 64 * 1024;
+
+/// A stored jar of `JAR_SIZE` bytes of class entries, none of them log4j, so
+/// every entry is scanned
+fn write_uber_jar(path: &Path) {
+    let mut jar = ZipWriter::new(File::create(path).unwrap());
+    let options = FileOptions::default().compression_method(CompressionMethod::Stored);
+    let mut state: u64 = 0x9e37_79b9_7f4a_7c15;
+    let mut class = vec![0u8; CLASS_SIZE];
+    for index in 0..JAR_SIZE / CLASS_SIZE {
+        class[..4].copy_from_slice(&[0xca, 0xfe, 0xba, 0xbe]);
+        for byte in &mut class[4..] {
+            // xorshift, so the contents are the same every run
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            *byte = state as u8;
+        }
+        jar.start_file(format!("com/example/shaded/Class{}.class", index), options).unwrap();
+        jar.write_all(&class).unwrap();
+    }
+    jar.finish().unwrap();
+}
+
+/// Scan one large jar on pools of one thread and of every CPU; with one
+/// thread the entry chunks are scanned one after another
+fn scan_archive_parallel(c: &mut Criterion) {
+    let dir = tempfile::tempdir().unwrap();
+    let jar = dir.path().join("uber.jar");
+    write_uber_jar(&jar);
+
+    let config = Config::default();
+    let rules = RuleSet::from_config(&config).unwrap();
+    let archives = archive_formats(&config).unwrap();
+    let cpus = std::thread::available_parallelism().map(|n| n.get()).unwrap_or(1);
+    let mut thread_counts = vec![1, cpus];
+    thread_counts.dedup();
+
+    let mut group = c.benchmark_group("scan_archive_parallel");
+    group.sample_size(10);
+    group.throughput(Throughput::Bytes(std::fs::metadata(&jar).unwrap().len()));
+    for threads in thread_counts {
+        let pool = rayon::ThreadPoolBuilder::new().num_threads(threads).build().unwrap();
+        group.bench_with_input(BenchmarkId::from_parameter(threads), &threads, |b, _| {
+            b.iter(|| pool.install(|| scan_file(&jar, &config, &rules, &archives)));
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, scan_archive_parallel);
+criterion_main!(benches);
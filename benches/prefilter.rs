@@ -0,0 +1,94 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use cve_2021_44228_scanner::scanner::quick_prefilter;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+use walkdir::WalkDir;
+
+const CLASS_COUNT: usize = 2000;
+const CLASS_SIZE: usize = 4 * 1024;
+/// Classes read from the Maven repository named by `PREFILTER_CORPUS`, at most
+const REPOSITORY_CLASSES: usize = 100_000;
+
+/// Names in the constant pool of everyday classes, none a prefilter needle
+const COMMON_NAMES: [&str; 6] = ["java/lang/Object", "java/lang/String", "org/slf4j/Logger", "java/util/List", "com/fasterxml/jackson/databind/ObjectMapper", "Code"];
+/// Names that put a class through to pattern matching: a JNDI client or log4j
+const NEEDLE_NAMES: [&str; 3] = ["javax/naming/InitialContext", "org/apache/logging/log4j/core/lookup/JndiLookup", "${jndi:ldap://"];
+
+/// `CLASS_COUNT` class files of pseudo-random bytes with common names in
+/// their constant pool and, for the matching corpus, a needle as well
+fn class_corpus(matching: bool) -> Vec<Vec<u8>> {
+    let mut state: u64 = 0x9e37_79b9_7f4a_7c15;
+    (0..CLASS_COUNT).map(|index| {
+        let mut class = b"\xca\xfe\xba\xbe\x00\x00\x00\x34".to_vec();
+        while class.len() < CLASS_SIZE {
+            // xorshift, so the contents are the same every run, below 0x40 so
+            // no run of bytes spells a needle by chance
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            class.push(state as u8 & 0x3f);
+            if state.is_multiple_of(64) {
+                class.extend(COMMON_NAMES[state as usize / 64 % COMMON_NAMES.len()].as_bytes());
+            }
+        }
+        if matching {
+            class.extend(NEEDLE_NAMES[index % NEEDLE_NAMES.len()].as_bytes());
+        }
+        class
+    }).collect()
+}
+
+/// The class entries of the jars under `root`, e.g. `~/.m2/repository`
+fn repository_corpus(root: &Path) -> Vec<Vec<u8>> {
+    let mut classes = Vec::new();
+    let jars = WalkDir::new(root).into_iter().filter_map(Result::ok)
+        .filter(|entry| entry.file_type().is_file() && entry.path().extension().is_some_and(|ext| ext == "jar"));
+    for jar in jars {
+        let Ok(mut archive) = File::open(jar.path()).map_err(|_| ()).and_then(|file| zip::ZipArchive::new(file).map_err(|_| ())) else {
+            continue;
+        };
+        for index in 0..archive.len() {
+            let Ok(mut entry) = archive.by_index(index) else { continue };
+            if !entry.name().ends_with(".class") {
+                continue;
+            }
+            let mut class = Vec::new();
+            if entry.read_to_end(&mut class).is_ok() {
+                classes.push(class);
+            }
+            if classes.len() == REPOSITORY_CLASSES {
+                return classes;
+            }
+        }
+    }
+    classes
+}
+
+/// How many of the prefilter's inputs pass through to pattern matching, and
+/// how fast it decides. Set `PREFILTER_CORPUS` to a Maven repository to
+/// measure its classes too.
+fn prefilter_pass_fraction(c: &mut Criterion) {
+    let mut corpora = vec![
+        ("non_matching".to_string(), class_corpus(false)),
+        ("matching".to_string(), class_corpus(true)),
+    ];
+    if let Some(root) = std::env::var_os("PREFILTER_CORPUS") {
+        corpora.push((Path::new(&root).display().to_string(), repository_corpus(Path::new(&root))));
+    }
+
+    let mut group = c.benchmark_group("prefilter_pass_fraction");
+    for (name, corpus) in &corpora {
+        let passed = corpus.iter().filter(|class| quick_prefilter(class)).count();
+        println!("{}: {} of {} classes pass the prefilter ({:.2}%)", name, passed, corpus.len(), 100.0 * passed as f64 / corpus.len().max(1) as f64);
+
+        group.throughput(Throughput::Bytes(corpus.iter().map(|class| class.len() as u64).sum()));
+        group.bench_with_input(BenchmarkId::from_parameter(name), corpus, |b, corpus| {
+            b.iter(|| corpus.iter().filter(|class| quick_prefilter(class)).count());
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, prefilter_pass_fraction);
+criterion_main!(benches);
@@ -0,0 +1,31 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use cve_2021_44228_scanner::rules::RuleSet;
+use regex::Regex;
+
+/// The forms of a class entry's name, and contents with none of the needles
+/// of a content rule, so every rule is tried on every call
+const ENTRY_NAMES: [&str; 2] = ["com/example/shaded/Service.class", "Service.class"];
+const CONTENTS: &[u8] = b"\xca\xfe\xba\xbe\x00\x00\x00\x34java/lang/Object\x01org/slf4j/Logger\x01com/example/shaded/Service\x01toString";
+
+/// Calls per second of the built-in rules' matching, with the patterns
+/// compiled on every call as they once were, and compiled once up front
+fn match_built_in_rules(c: &mut Criterion) {
+    let rules = RuleSet::built_in();
+    let text = String::from_utf8_lossy(CONTENTS);
+
+    let mut group = c.benchmark_group("match_built_in_rules");
+    group.throughput(Throughput::Elements(1));
+    group.bench_function(BenchmarkId::from_parameter("compiled_per_call"), |b| {
+        b.iter(|| rules.all().iter().any(|rule| {
+            let pattern = Regex::new(rule.pattern.as_str()).unwrap();
+            ENTRY_NAMES.iter().any(|name| pattern.is_match(name)) || pattern.is_match(&text)
+        }));
+    });
+    group.bench_function(BenchmarkId::from_parameter("compiled_once"), |b| {
+        b.iter(|| rules.find_match(&ENTRY_NAMES, CONTENTS, None));
+    });
+    group.finish();
+}
+
+criterion_group!(benches, match_built_in_rules);
+criterion_main!(benches);
@@ -0,0 +1,211 @@
+use crate::class_path::normalize;
+use crate::config::Config;
+use crate::scanner::ScanResult;
+use crate::utils::HashAlgorithm;
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::path::{self, Path, PathBuf};
+
+/// Prefix of the annotation keys that name a file by its SHA3-256 hash
+const SHA3_PREFIX: &str = "sha3:";
+
+/// The `--annotations` file: notes such as a ticket or an owner, keyed by
+/// the findings they belong to, that every scan adds to those findings
+pub struct Annotations {
+    entries: Vec<(String, FindingKey, BTreeMap<String, String>)>,
+}
+
+/// What an annotation key picks findings by
+enum FindingKey {
+    /// `sha3:<hash>`, optionally `!<entry>`: files with that SHA3-256 hash,
+    /// wherever they are, or only those named `entry`
+    Hash { sha3: String, entry: Option<String> },
+    /// A file on disk or an archive entry, `app.war!WEB-INF/lib/log4j-core.jar`.
+    /// A relative path is tried against the current directory and the scan
+    /// root, so these are the absolute paths it may stand for.
+    Path(Vec<String>),
+}
+
+impl Annotations {
+    /// Read the YAML or JSON map of finding keys to annotations at `path`.
+    /// Hash keys need `sha3` among the `--hashes` the scan computes.
+    pub fn load(path: &Path, config: &Config) -> Result<Self, String> {
+        let contents = fs::read_to_string(path)
+            .map_err(|e| format!("unable to read annotations {}: {}", path.display(), e))?;
+        let map: BTreeMap<String, BTreeMap<String, String>> = serde_yaml::from_str(&contents)
+            .map_err(|e| format!("invalid annotations {}: {}", path.display(), e))?;
+
+        let roots = [env::current_dir().ok(), Some(PathBuf::from(&config.path)).filter(|_| !config.path.is_empty())];
+        let mut entries = Vec::new();
+        for (key, annotations) in map {
+            let finding_key = match key.strip_prefix(SHA3_PREFIX) {
+                Some(hash) => {
+                    if !config.hashes.contains(HashAlgorithm::Sha3) {
+                        return Err(format!("annotation key {} needs sha3 in --hashes", key));
+                    }
+                    let (sha3, entry) = match hash.split_once('!') {
+                        Some((sha3, entry)) => (sha3, Some(entry.to_string())),
+                        None => (hash, None),
+                    };
+                    FindingKey::Hash { sha3: sha3.to_ascii_lowercase(), entry }
+                }
+                None if Path::new(key.split('!').next().unwrap_or_default()).is_absolute() => FindingKey::Path(vec![normalized(&key)]),
+                None => FindingKey::Path(roots.iter().flatten().map(|root| normalized(&root.join(&key).to_string_lossy())).collect()),
+            };
+            entries.push((key, finding_key, annotations));
+        }
+        Ok(Annotations { entries })
+    }
+
+    /// Add to each of `results` the annotations whose keys match it, those
+    /// keyed by path after those keyed by hash so they win a clash. Returns
+    /// the keys that matched no result, annotations gone stale.
+    pub fn apply(&self, results: &mut [ScanResult]) -> Vec<String> {
+        let mut matched = vec![false; self.entries.len()];
+        for result in results.iter_mut() {
+            let path = normalized(&result.file_path);
+            for by_path in [false, true] {
+                for (index, (_, key, annotations)) in self.entries.iter().enumerate() {
+                    if matches!(key, FindingKey::Path(_)) == by_path && key.matches(result, &path) {
+                        matched[index] = true;
+                        result.annotations.get_or_insert_with(BTreeMap::new).extend(annotations.clone());
+                    }
+                }
+            }
+        }
+        self.entries.iter().zip(matched)
+            .filter(|(_, matched)| !matched)
+            .map(|((key, _, _), _)| key.clone())
+            .collect()
+    }
+}
+
+impl FindingKey {
+    /// Whether `result`, with its file path `normalized`, is a finding the key picks
+    fn matches(&self, result: &ScanResult, path: &str) -> bool {
+        match self {
+            FindingKey::Hash { sha3, entry } => {
+                result.sha3_hash.as_deref().is_some_and(|hash| hash.eq_ignore_ascii_case(sha3))
+                    && entry.as_deref().is_none_or(|entry| entry_name(&result.file_path) == entry)
+            }
+            // A file, or any entry of it when it is an archive
+            FindingKey::Path(candidates) => candidates.iter().any(|candidate| {
+                path.strip_prefix(candidate.as_str()).is_some_and(|rest| rest.is_empty() || rest.starts_with('!'))
+            }),
+        }
+    }
+}
+
+/// The path of the entry within its innermost archive, or the file name of
+/// a file on disk
+fn entry_name(file_path: &str) -> &str {
+    match file_path.rsplit_once('!') {
+        Some((_, entry)) => entry,
+        None => Path::new(file_path).file_name().and_then(|name| name.to_str()).unwrap_or(file_path),
+    }
+}
+
+/// `label` with its file on disk made absolute and its `.` and `..` dropped,
+/// so relative and absolute paths to one file compare equal
+fn normalized(label: &str) -> String {
+    let (file, entry) = match label.split_once('!') {
+        Some((file, entry)) => (file, Some(entry)),
+        None => (label, None),
+    };
+    let file = path::absolute(file).unwrap_or_else(|_| PathBuf::from(file));
+    let file = normalize(&file).to_string_lossy().to_string();
+    match entry {
+        Some(entry) => format!("{}!{}", file, entry),
+        None => file,
+    }
+}
+
+/// Annotations as `key=value` pairs for the text and table reports
+pub fn describe(annotations: &BTreeMap<String, String>) -> String {
+    annotations.iter().map(|(key, value)| format!("{}={}", key, value)).collect::<Vec<_>>().join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::HashAlgorithms;
+
+    const CLASS_HASH: &str = "3f2a9c";
+
+    fn result(path: &str, sha3: Option<&str>) -> ScanResult {
+        serde_json::from_value(serde_json::json!({
+            "file_path": path, "vulnerable": true, "status": "Vulnerable", "sha3_hash": sha3,
+            "file_size": 1, "entropy": 0.0, "fourier_coefficient": [0.0, 0.0], "markov_probability": 0.0,
+        })).unwrap()
+    }
+
+    fn results() -> Vec<ScanResult> {
+        vec![
+            result("/srv/app/log4j-core-2.14.1.jar", None),
+            result("/srv/app/app.war!WEB-INF/lib/log4j-core-2.14.1.jar", None),
+            result("/srv/app/lib/JndiLookup.class", Some(CLASS_HASH)),
+            result("/srv/app/copy/Other.class", Some(CLASS_HASH)),
+        ]
+    }
+
+    fn load(contents: &str, config: &Config) -> Result<Annotations, String> {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("annotations.yaml");
+        fs::write(&file, contents).unwrap();
+        Annotations::load(&file, config)
+    }
+
+    /// Each result's annotations as text, `-` for none
+    fn notes(results: &[ScanResult]) -> Vec<String> {
+        results.iter().map(|result| result.annotations.as_ref().map_or_else(|| "-".to_string(), describe)).collect()
+    }
+
+    fn config() -> Config {
+        Config { path: "/srv/app".to_string(), ..Default::default() }
+    }
+
+    #[test]
+    fn path_keys_match_relative_to_the_scan_root() {
+        let annotations = load("lib/../log4j-core-2.14.1.jar: { ticket: SEC-1 }\n/srv/app/./app.war: { owner: team-a }\n", &config()).unwrap();
+        let mut results = results();
+        assert!(annotations.apply(&mut results).is_empty());
+        // The war's key also covers its entries
+        assert_eq!(notes(&results), ["ticket=SEC-1", "owner=team-a", "-", "-"]);
+    }
+
+    #[test]
+    fn hash_keys_match_in_any_case_and_by_entry_name() {
+        let annotations = load(&format!("\"sha3:{}!JndiLookup.class\": {{ owner: team-a }}\n", CLASS_HASH.to_ascii_uppercase()), &config()).unwrap();
+        let mut by_entry = results();
+        annotations.apply(&mut by_entry);
+        assert_eq!(notes(&by_entry), ["-", "-", "owner=team-a", "-"]);
+
+        let annotations = load(&format!("sha3:{}: {{ owner: team-a }}\n", CLASS_HASH), &config()).unwrap();
+        let mut by_hash = results();
+        annotations.apply(&mut by_hash);
+        assert_eq!(notes(&by_hash), ["-", "-", "owner=team-a", "owner=team-a"]);
+    }
+
+    #[test]
+    fn path_keys_win_a_clash_with_hash_keys() {
+        let annotations = load(&format!("lib/JndiLookup.class: {{ owner: team-b }}\nsha3:{}: {{ owner: team-a, ticket: SEC-1 }}\n", CLASS_HASH), &config()).unwrap();
+        let mut results = results();
+        annotations.apply(&mut results);
+        assert_eq!(notes(&results)[2], "owner=team-b, ticket=SEC-1");
+    }
+
+    #[test]
+    fn keys_matching_no_result_are_stale() {
+        let annotations = load("missing/log4j-core.jar: { ticket: SEC-2 }\napp.war!WEB-INF/lib/gone.jar: { ticket: SEC-3 }\nlog4j-core-2.14.1.jar: { ticket: SEC-1 }\n", &config()).unwrap();
+        let mut results = results();
+        assert_eq!(annotations.apply(&mut results), ["app.war!WEB-INF/lib/gone.jar", "missing/log4j-core.jar"]);
+    }
+
+    #[test]
+    fn hash_keys_need_sha3() {
+        let config = Config { hashes: HashAlgorithms::from(vec![HashAlgorithm::Sha256]), ..config() };
+        let error = load(&format!("sha3:{}: {{ owner: team-a }}\n", CLASS_HASH), &config).err().unwrap();
+        assert_eq!(error, format!("annotation key sha3:{} needs sha3 in --hashes", CLASS_HASH));
+    }
+}
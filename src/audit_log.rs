@@ -0,0 +1,168 @@
+use crate::scanner::ScanSummary;
+use chrono::Utc;
+use log::warn;
+use serde_json::{json, Value};
+use std::fmt;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+use std::process;
+use std::sync::Mutex;
+use uuid::Uuid;
+
+/// Where `--audit-log` events go, and what the finish event reports
+struct AuditLog {
+    path: String,
+    file: File,
+    scan_id: String,
+    /// Counts of the scan, once it has run
+    scan: Option<Value>,
+    /// A write failed; later events are dropped rather than leaving gaps unnoticed
+    broken: bool,
+}
+
+static AUDIT_LOG: Mutex<Option<AuditLog>> = Mutex::new(None);
+
+/// What became of a file the walk found, recorded by `file` events
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Decision {
+    Scanned,
+    /// Matched an `--exclude` or source jar pattern
+    Excluded,
+    /// Left out by another filter, such as `--skip-hidden` or `--since`
+    Skipped,
+    Error,
+}
+
+/// Every event shares this envelope; `data` adds the event's own keys
+#[derive(serde::Serialize)]
+struct Envelope<'a> {
+    timestamp: String,
+    scan_id: &'a str,
+    event: &'a str,
+    #[serde(flatten)]
+    data: Value,
+}
+
+/// The audit log named in a report, to find a scan's events
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct AuditReference {
+    pub path: String,
+    pub scan_id: String,
+}
+
+/// Open `path` for appending audit events of a new scan and write its start event
+pub fn init(path: &str) -> Result<(), String> {
+    let file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .map_err(|e| format!("unable to open audit log {}: {}", path, e))?;
+    *AUDIT_LOG.lock().unwrap_or_else(|e| e.into_inner()) = Some(AuditLog {
+        path: path.to_string(),
+        file,
+        scan_id: Uuid::new_v4().to_string(),
+        scan: None,
+        broken: false,
+    });
+
+    let user = std::env::var("USER").or_else(|_| std::env::var("USERNAME")).ok();
+    let written = event("start", json!({
+        "version": env!("CARGO_PKG_VERSION"),
+        "user": user,
+        "pid": process::id(),
+        "args": std::env::args().collect::<Vec<_>>(),
+    }));
+    if written {
+        Ok(())
+    } else {
+        Err(format!("unable to write audit log {}", path))
+    }
+}
+
+/// The open audit log, if `--audit-log` was given
+pub fn reference() -> Option<AuditReference> {
+    AUDIT_LOG.lock().unwrap_or_else(|e| e.into_inner()).as_ref().map(|log| AuditReference {
+        path: log.path.clone(),
+        scan_id: log.scan_id.clone(),
+    })
+}
+
+/// Whether `--audit-log` was given
+pub fn enabled() -> bool {
+    AUDIT_LOG.lock().unwrap_or_else(|e| e.into_inner()).is_some()
+}
+
+/// Append an event and flush it to disk. Returns whether it was written;
+/// without an audit log there is nothing to write and this returns true.
+pub fn event(event: &str, data: Value) -> bool {
+    write(event, data, true)
+}
+
+/// Append a `file` event recording what became of one file, or of a
+/// directory left out with everything in it. Not flushed on its own, as
+/// there is one per file; the next event that is flushes it too.
+pub fn file(path: &Path, decision: Decision, reason: fmt::Arguments) {
+    if enabled() {
+        write("file", json!({ "path": path, "decision": decision, "reason": reason.to_string() }), false);
+    }
+}
+
+/// Stop writing events, so a later scan in the same process has no audit log
+pub fn close() {
+    AUDIT_LOG.lock().unwrap_or_else(|e| e.into_inner()).take();
+}
+
+fn write(event: &str, data: Value, flush: bool) -> bool {
+    let mut guard = AUDIT_LOG.lock().unwrap_or_else(|e| e.into_inner());
+    let log = match guard.as_mut() {
+        Some(log) => log,
+        None => return true,
+    };
+    if log.broken {
+        return false;
+    }
+
+    let envelope = Envelope {
+        timestamp: Utc::now().to_rfc3339(),
+        scan_id: &log.scan_id,
+        event,
+        data,
+    };
+    let written = serde_json::to_string(&envelope)
+        .map_err(|e| e.to_string())
+        .and_then(|line| log.file.write_all(format!("{}\n", line).as_bytes()).map_err(|e| e.to_string()))
+        .and_then(|_| if flush { log.file.sync_data().map_err(|e| e.to_string()) } else { Ok(()) });
+    if let Err(e) = written {
+        warn!("Error writing audit log: {} - {}; no further events are written", log.path, e);
+        log.broken = true;
+        return false;
+    }
+    true
+}
+
+/// Remember the counts of a finished scan for the finish event
+pub fn set_scan(summary: &ScanSummary, vulnerable_count: usize) {
+    if let Some(log) = AUDIT_LOG.lock().unwrap_or_else(|e| e.into_inner()).as_mut() {
+        log.scan = Some(json!({
+            "files_scanned": summary.files_scanned,
+            "vulnerable_count": vulnerable_count,
+            "truncated": summary.truncated,
+            "timed_out": summary.timed_out,
+            "max_duration_reached": summary.max_duration_reached,
+            "permission_denied": summary.permission_denied.len(),
+            "error_digest": summary.error_digest,
+            "coverage": summary.coverage,
+            "peak_queue_depth": summary.peak_queue_depth,
+        }));
+    }
+}
+
+/// Write the finish event with the exit code and what it means
+pub fn finish(exit_code: i32, meaning: &str) {
+    let scan = AUDIT_LOG.lock().unwrap_or_else(|e| e.into_inner()).as_ref().map(|log| log.scan.clone());
+    if let Some(scan) = scan {
+        event("finish", json!({ "exit_code": exit_code, "meaning": meaning, "scan": scan }));
+    }
+}
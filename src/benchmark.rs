@@ -0,0 +1,152 @@
+use crate::config::{Config, OutputLevel};
+use crate::scanner::scan_paths;
+use crate::utils::bytes_read;
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+/// A part of a scan `--benchmark` times on its own
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Phase {
+    /// Walking and filtering the directory tree, not counting waits for the workers
+    Walk,
+    /// Reading files and archive entries from disk
+    Read,
+    /// Matching contents against the detection rules
+    Match,
+    /// Computing file digests
+    Hash,
+}
+
+impl Phase {
+    const ALL: [Phase; 4] = [Phase::Walk, Phase::Read, Phase::Match, Phase::Hash];
+
+    fn name(self) -> &'static str {
+        match self {
+            Phase::Walk => "directory_walk",
+            Phase::Read => "file_read",
+            Phase::Match => "pattern_match",
+            Phase::Hash => "hash_computation",
+        }
+    }
+}
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+/// Nanoseconds spent in each phase, by `Phase` order, summed over every thread
+static PHASE_NANOS: [AtomicU64; 4] = [const { AtomicU64::new(0) }; 4];
+
+/// Times a phase from its creation until it is dropped, when `--benchmark`
+/// is on; otherwise it never reads the clock
+pub struct PhaseTimer {
+    phase: Phase,
+    started: Option<Instant>,
+}
+
+impl Drop for PhaseTimer {
+    fn drop(&mut self) {
+        if let Some(started) = self.started {
+            add(self.phase, started.elapsed());
+        }
+    }
+}
+
+pub fn time(phase: Phase) -> PhaseTimer {
+    PhaseTimer { phase, started: enabled().then(Instant::now) }
+}
+
+pub fn enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+pub fn add(phase: Phase, elapsed: Duration) {
+    PHASE_NANOS[phase as usize].fetch_add(elapsed.as_nanos() as u64, Ordering::Relaxed);
+}
+
+/// Min, max, mean and population standard deviation of one measure over the iterations
+#[derive(Debug, serde::Serialize)]
+struct Stats {
+    measure: String,
+    min: f64,
+    max: f64,
+    mean: f64,
+    stddev: f64,
+}
+
+impl Stats {
+    fn of(measure: &str, values: &[f64]) -> Self {
+        let mean = values.iter().sum::<f64>() / values.len() as f64;
+        let variance = values.iter().map(|value| (value - mean).powi(2)).sum::<f64>() / values.len() as f64;
+        Stats {
+            measure: measure.to_string(),
+            min: values.iter().copied().fold(f64::INFINITY, f64::min),
+            max: values.iter().copied().fold(f64::NEG_INFINITY, f64::max),
+            mean,
+            stddev: variance.sqrt(),
+        }
+    }
+}
+
+#[derive(serde::Serialize)]
+struct Benchmark {
+    iterations: usize,
+    files_scanned: usize,
+    stats: Vec<Stats>,
+}
+
+/// Scan `roots` `iterations` times without writing a report, and print the
+/// spread of the total time, files per second and megabytes read per second,
+/// then the time spent in each phase. Phase times are summed over the worker
+/// threads, so with several threads they can add up to more than the total.
+pub fn run(config: &Config, roots: &[PathBuf], iterations: usize) -> Result<(), Box<dyn std::error::Error>> {
+    let config = Config { output_level: OutputLevel::Quiet, ..config.clone() };
+    ENABLED.store(true, Ordering::Relaxed);
+
+    let mut totals = Vec::new();
+    let mut files_per_second = Vec::new();
+    let mut megabytes_per_second = Vec::new();
+    let mut phases: [Vec<f64>; 4] = Default::default();
+    let mut files_scanned = 0;
+    for _ in 0..iterations {
+        for nanos in &PHASE_NANOS {
+            nanos.store(0, Ordering::Relaxed);
+        }
+        let read_before = bytes_read();
+        let started = Instant::now();
+        let (_, summary) = scan_paths(&config, roots)?;
+        let seconds = started.elapsed().as_secs_f64().max(f64::EPSILON);
+
+        files_scanned = summary.files_scanned;
+        totals.push(seconds);
+        files_per_second.push(summary.files_scanned as f64 / seconds);
+        megabytes_per_second.push((bytes_read() - read_before) as f64 / (1024.0 * 1024.0) / seconds);
+        for phase in Phase::ALL {
+            phases[phase as usize].push(PHASE_NANOS[phase as usize].load(Ordering::Relaxed) as f64 / 1e9);
+        }
+    }
+    ENABLED.store(false, Ordering::Relaxed);
+
+    let mut stats = vec![
+        Stats::of("total_seconds", &totals),
+        Stats::of("files_per_second", &files_per_second),
+        Stats::of("megabytes_per_second", &megabytes_per_second),
+    ];
+    for phase in Phase::ALL {
+        stats.push(Stats::of(&format!("{}_seconds", phase.name()), &phases[phase as usize]));
+    }
+
+    let mut output = io::stdout().lock();
+    if config.format == "json" {
+        serde_json::to_writer_pretty(&mut output, &Benchmark { iterations, files_scanned, stats })?;
+        writeln!(output)?;
+        return Ok(());
+    }
+
+    writeln!(output, "Benchmark: {} iterations, {} files scanned per iteration", iterations, files_scanned)?;
+    writeln!(output, "{:<26} | {:>12} | {:>12} | {:>12} | {:>12}", "Measure", "Min", "Max", "Mean", "Stddev")?;
+    for stat in &stats {
+        writeln!(output, "{:<26} | {:>12.3} | {:>12.3} | {:>12.3} | {:>12.3}", stat.measure, stat.min, stat.max, stat.mean, stat.stddev)?;
+    }
+    writeln!(output, "Phase times are summed over the worker threads")?;
+    Ok(())
+}
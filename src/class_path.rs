@@ -0,0 +1,114 @@
+use crate::scan_errors::{self, ScanErrorKind};
+use log::{debug, warn};
+use percent_encoding::percent_decode_str;
+use std::fs::File;
+use std::io::Read;
+use std::path::{Component, Path, PathBuf};
+use zip::ZipArchive;
+
+const MANIFEST_PATH: &str = "META-INF/MANIFEST.MF";
+/// Manifests are a few kilobytes; anything much larger is not one
+const MAX_MANIFEST_SIZE: u64 = 1024 * 1024;
+
+/// The files and directories a jar's `Class-Path` manifest header puts on
+/// the class path, resolved against the jar's directory as the JVM does.
+/// References that do not exist, or are not local, are logged and left out.
+pub fn referenced_paths(jar: &Path) -> Vec<PathBuf> {
+    let manifest = match read_manifest(jar) {
+        Some(manifest) => manifest,
+        None => return Vec::new(),
+    };
+    let base = jar.parent().unwrap_or(Path::new(""));
+    class_path_references(&manifest).into_iter()
+        .filter_map(|reference| {
+            let resolved = match resolve(base, &reference) {
+                Some(resolved) => resolved,
+                None => {
+                    warn!("Unable to resolve Class-Path reference '{}' of {:?}: not a local path", reference, jar);
+                    return None;
+                }
+            };
+            if resolved.exists() {
+                debug!("Class-Path of {:?} references {:?}", jar, resolved);
+                Some(resolved)
+            } else {
+                warn!("Unable to resolve Class-Path reference '{}' of {:?}: {:?} does not exist", reference, jar, resolved);
+                None
+            }
+        })
+        .collect()
+}
+
+fn read_manifest(jar: &Path) -> Option<String> {
+    let file = match File::open(jar) {
+        Ok(file) => file,
+        Err(e) => {
+            scan_errors::record(jar, ScanErrorKind::of_io(&e), format_args!("Error opening JAR file for its manifest: {:?} - {}", jar, e));
+            return None;
+        }
+    };
+    // Unreadable archives are reported by the scan itself
+    let mut archive = ZipArchive::new(file).ok()?;
+    let entry = archive.by_name(MANIFEST_PATH).ok()?;
+    let mut contents = Vec::new();
+    if let Err(e) = entry.take(MAX_MANIFEST_SIZE).read_to_end(&mut contents) {
+        debug!("Unable to read {} of {:?}: {}", MANIFEST_PATH, jar, e);
+        return None;
+    }
+    Some(String::from_utf8_lossy(&contents).into_owned())
+}
+
+/// The space-separated URLs of the main section's `Class-Path` header.
+/// Manifest lines wrap at 72 bytes, continuing on lines that start with a
+/// single space.
+fn class_path_references(manifest: &str) -> Vec<String> {
+    let mut headers: Vec<String> = Vec::new();
+    for line in manifest.lines() {
+        let line = line.trim_end_matches('\r');
+        if line.is_empty() {
+            // The main section ends at the first blank line
+            break;
+        }
+        match (line.strip_prefix(' '), headers.last_mut()) {
+            (Some(continued), Some(header)) => header.push_str(continued),
+            _ => headers.push(line.to_string()),
+        }
+    }
+    headers.iter()
+        .filter_map(|header| header.split_once(':'))
+        .filter(|(name, _)| name.trim().eq_ignore_ascii_case("Class-Path"))
+        .flat_map(|(_, value)| value.split_whitespace().map(String::from).collect::<Vec<_>>())
+        .collect()
+}
+
+/// A reference is a URL relative to the jar, or an absolute `file:` URL
+fn resolve(base: &Path, reference: &str) -> Option<PathBuf> {
+    let (path, relative) = match reference.strip_prefix("file:") {
+        Some(path) => (path.trim_start_matches("//"), false),
+        None if reference.contains("://") => return None,
+        None => (reference, true),
+    };
+    let path = percent_decode_str(path).decode_utf8_lossy();
+    let path = path.trim_end_matches('/');
+    if path.is_empty() {
+        return None;
+    }
+    let path = if relative { base.join(path.split('/').collect::<PathBuf>()) } else { PathBuf::from(path) };
+    Some(normalize(&path))
+}
+
+/// Drop the `.` and `..` components references usually start with, so
+/// findings show where the file is rather than how it was reached
+pub fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir if matches!(normalized.components().next_back(), Some(Component::Normal(_))) => {
+                normalized.pop();
+            }
+            component => normalized.push(component),
+        }
+    }
+    normalized
+}
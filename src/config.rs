@@ -1,32 +1,458 @@
-#[derive(Debug, Clone)]
+use crate::reporter::DEDUP_HASHES;
+use crate::scanner::{Confidence, Severity, ARCHIVE_FORMATS, DEFAULT_ARCHIVES};
+use crate::utils::{HashAlgorithm, HashAlgorithms, IoBudget, DEFAULT_IO_BUFFER_SIZE};
+use regex::Regex;
+use serde_json::{json, Value};
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+use std::time::{Duration, SystemTime};
+
+/// How much the scanner writes besides the report
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum OutputLevel {
+    /// Progress bar, info logs and the full report
+    #[default]
+    Normal,
+    /// No progress bar or info logs; the report lists only findings
+    Quiet,
+    /// No logs at all, and no report on stdout unless the format is machine-readable;
+    /// the exit code is the signal
+    Silent,
+}
+
+/// A preset of settings selected with `--profile`. It only supplies defaults:
+/// the config file and command-line options override any key it sets.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Profile {
+    /// SHA-256 only, duplicates grouped, and only structural or exact findings
+    Fast,
+    /// The scanner's own defaults
+    Default,
+    /// Every digest, POM files, and zip and tar archives
+    Thorough,
+    /// Thorough, and any file that could not be fully examined fails the scan
+    Paranoid,
+}
+
+impl Profile {
+    /// The config keys this profile sets, with their values
+    fn settings(self) -> Vec<(&'static str, Value)> {
+        let all_hashes: Vec<&str> = HashAlgorithm::ALL.iter().map(|algorithm| algorithm.name()).collect();
+        let all_archives: Vec<&str> = ARCHIVE_FORMATS.iter()
+            .map(|format| format.ext)
+            .filter(|ext| !DEFAULT_ARCHIVES.contains(ext))
+            .collect();
+        let thorough = vec![
+            ("hashes", json!(all_hashes)),
+            ("scan_pom_xml", json!(true)),
+            ("include_archives", json!(all_archives)),
+        ];
+        match self {
+            Profile::Fast => vec![
+                ("hashes", json!([HashAlgorithm::Sha256.name()])),
+                ("group_duplicates", json!(true)),
+                ("min_confidence", json!(Confidence::High)),
+            ],
+            Profile::Default => Vec::new(),
+            Profile::Thorough => thorough,
+            Profile::Paranoid => thorough.into_iter()
+                .chain([
+                    ("fail_on_permission_denied", json!(true)),
+                    ("fail_on_unscannable", json!(true)),
+                ])
+                .collect(),
+        }
+    }
+}
+
+impl FromStr for Profile {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.to_ascii_lowercase().as_str() {
+            "fast" => Ok(Profile::Fast),
+            "default" => Ok(Profile::Default),
+            "thorough" => Ok(Profile::Thorough),
+            "paranoid" => Ok(Profile::Paranoid),
+            _ => Err(format!("invalid profile '{}' (expected fast, default, thorough or paranoid)", value)),
+        }
+    }
+}
+
+impl fmt::Display for Profile {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Profile::Fast => write!(f, "fast"),
+            Profile::Default => write!(f, "default"),
+            Profile::Thorough => write!(f, "thorough"),
+            Profile::Paranoid => write!(f, "paranoid"),
+        }
+    }
+}
+
+/// When the text report wraps file paths in OSC 8 hyperlinks
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HyperlinkMode {
+    /// When the report goes to a terminal known to support them
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+impl FromStr for HyperlinkMode {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.to_ascii_lowercase().as_str() {
+            "auto" => Ok(HyperlinkMode::Auto),
+            "always" => Ok(HyperlinkMode::Always),
+            "never" => Ok(HyperlinkMode::Never),
+            _ => Err(format!("invalid hyperlink mode '{}' (expected auto, always or never)", value)),
+        }
+    }
+}
+
+impl fmt::Display for HyperlinkMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            HyperlinkMode::Auto => write!(f, "auto"),
+            HyperlinkMode::Always => write!(f, "always"),
+            HyperlinkMode::Never => write!(f, "never"),
+        }
+    }
+}
+
+/// Scan threads per logical CPU in `ThreadMode::IoOptimized`, and the most it uses
+const IO_THREADS_PER_CPU: usize = 4;
+const MAX_IO_THREADS: usize = 64;
+
+/// How many threads scan files, selected with `--thread-mode`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum ThreadMode {
+    /// One per logical CPU, or the `threads` a config file sets: best when
+    /// reading is fast and matching keeps the CPUs busy
+    #[default]
+    Auto,
+    /// Exactly this many, given with `--threads`
+    Fixed(usize),
+    /// Four per logical CPU, at most 64: on network filesystems and spinning
+    /// disks most threads wait on reads, and more of them keep the CPUs busy
+    IoOptimized,
+}
+
+/// Jars published next to a Maven artifact that hold no code of it to run:
+/// its sources, its javadoc and its tests. Excluded unless `--scan-source-jars`.
+pub const SOURCE_JAR_PATTERNS: [&str; 3] = ["*-sources.jar", "*-javadoc.jar", "*-test.jar"];
+
+/// Scan settings. Serialized into JSON reports as `scan_config`, and read back
+/// from a TOML file with `--config` using the same keys.
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize)]
+#[serde(default, deny_unknown_fields)]
 pub struct Config {
     pub path: String,
     pub format: String,
     pub threads: Option<usize>,
     pub exclude: Vec<String>,
     pub custom_patterns: Vec<String>,
-    pub quiet: bool,
+    pub output_level: OutputLevel,
     pub output: Option<String>,
+    pub since: Option<SystemTime>,
+    pub scan_pom_xml: bool,
+    pub prioritize: bool,
+    pub max_findings: Option<usize>,
+    pub fail_fast: bool,
+    pub fail_on_permission_denied: bool,
+    pub fail_on_unscannable: bool,
+    pub maven_artifact: Option<String>,
+    pub maven_repo_url: Option<String>,
+    pub maven_cache_dir: Option<String>,
+    pub threads_max_memory: Option<usize>,
+    pub syslog: bool,
+    pub rules: Vec<String>,
+    pub disable_rules: Vec<String>,
+    pub min_confidence: Option<Confidence>,
+    pub group_duplicates: bool,
+    pub no_summary: bool,
+    pub sign_key: Option<String>,
+    pub encoding: String,
+    pub io_limit: Option<f64>,
+    pub nice: bool,
+    pub timeout: Option<u64>,
+    pub discover_java: bool,
+    /// Size in bytes of the buffers files are read through; zero uses the default
+    pub io_buffer_size: usize,
+    /// `user@host:/path` to scan over SSH instead of a local path
+    pub ssh: Option<String>,
+    pub ssh_key: Option<String>,
+    /// Concurrent SFTP transfers; defaults to the thread count
+    pub ssh_connections: Option<usize>,
+    /// Digests computed for every result
+    pub hashes: HashAlgorithms,
+    /// Depth below each scan root at which `--summary-by-dir` aggregates findings
+    pub summary_by_dir: Option<usize>,
+    /// Archive formats added to, or with a `-` prefix removed from, the default jar, war and ear
+    pub include_archives: Vec<String>,
+    /// Recipients of the report email; none disables it
+    pub email_to: Vec<String>,
+    pub email_from: Option<String>,
+    /// `host:port` of the SMTP server the report email is sent through
+    pub smtp_server: Option<String>,
+    /// `starttls`, `implicit` for TLS from the start of the connection, or `none`
+    pub smtp_tls: String,
+    /// Mail only when a finding is at least this severe; any finding when unset
+    pub email_min_severity: Option<Severity>,
+    pub email_always: bool,
+    /// Fail the scan when the report email cannot be sent
+    pub email_required: bool,
+    /// File explaining the exit code, written whenever the scanner exits
+    pub exit_code_manifest: Option<String>,
+    /// Preset the other settings were defaulted from
+    pub profile: Option<Profile>,
+    /// Plain lists instead of bordered tables in the text report
+    pub no_table: bool,
+    /// Leave out hidden files and directories: dot-prefixed on Unix, hidden or system on Windows
+    pub skip_hidden: bool,
+    /// NDJSON file the scan's audit events are appended to
+    pub audit_log: Option<String>,
+    /// Clickable file paths in the text report
+    pub ansi_hyperlinks: HyperlinkMode,
+    /// Container image scanned from its registry instead of a path
+    pub registry_image: Option<String>,
+    pub registry_plain_http: bool,
+    /// Add each result's byte frequency histogram to reports
+    pub include_byte_histogram: bool,
+    /// `s3://bucket/prefix` scanned instead of a path
+    pub s3: Option<String>,
+    /// `gs://bucket/prefix` scanned instead of a path
+    pub gcs: Option<String>,
+    /// Concurrent object downloads for `s3` and `gcs`
+    pub bucket_connections: Option<usize>,
+    /// Follow `Class-Path` manifest headers of scanned jars to the files they reference
+    pub manifest_scan: bool,
+    /// Scan standard input as one file instead of a path
+    pub stdin: bool,
+    /// Label of the `stdin` results
+    pub stdin_filename: Option<String>,
+    /// Leave run-specific values out of reports
+    pub reproducible: bool,
+    /// Walk into virtual filesystems such as /proc and /sys
+    pub no_vfs_filter: bool,
+    /// Start a new `output` file after this many megabytes
+    pub output_rotate: Option<u64>,
+    /// Count the unique hashes each rule matched
+    pub stats: bool,
+    /// Also scan files whose contents, not their name, make them a jar or class file
+    pub content_type_detection: bool,
+    /// Walk a directory again when another path, such as a bind mount, leads to it
+    pub no_loop_detection: bool,
+    /// Columns of the `table` format, in order; empty for the default ones
+    pub columns: Vec<String>,
+    /// Scan source, javadoc and test jars instead of excluding them
+    pub scan_source_jars: bool,
+    /// Packs of the `rules` files to use; empty for all of them
+    pub packs: Vec<String>,
+    /// List every entry the scan skipped, with why, in the coverage section
+    pub list_skipped: bool,
+    /// Files the walker may queue ahead of the scan workers
+    pub queue_capacity: usize,
+    /// Regexes of the paths reports list findings for; empty for all of them
+    pub report_filter: Vec<String>,
+    pub thread_mode: ThreadMode,
+    /// Gzip-compress the `output` file
+    pub output_compress: bool,
+    /// Gzip level of `output_compress`, 0 to 9
+    pub output_compress_level: u32,
+    /// Archives nested deeper than this are reported instead of opened; None
+    /// for `DEFAULT_MAX_ARCHIVE_DEPTH`
+    pub max_archive_depth: Option<usize>,
+    /// Leave the severity bars out of the text report
+    pub no_summary_histogram: bool,
+    /// YAML or JSON file of notes to add to the findings they are keyed by
+    pub annotations: Option<String>,
+    /// Class entries inflating to more than this many times their stored
+    /// size are reported; None for `DEFAULT_SUSPICIOUS_RATIO`
+    pub suspicious_ratio: Option<f64>,
+    /// No more files are handed to the workers once this long has passed
+    pub max_duration: Option<Duration>,
+    /// Build the Markov transition matrix of large contents on every thread
+    pub markov_parallel: bool,
+    /// Read budget of the running scan, made from `io_limit` as it starts;
+    /// neither read nor written
+    #[serde(skip)]
+    pub io_budget: IoBudget,
 }
 
 impl Config {
-    pub fn new(
-        path: String,
-        format: String,
-        threads: Option<usize>,
-        exclude: Vec<String>,
-        custom_patterns: Vec<String>,
-        quiet: bool,
-        output: Option<String>,
-    ) -> Self {
-        Config {
-            path,
-            format,
-            threads,
-            exclude,
-            custom_patterns,
-            quiet,
-            output,
+    /// Quiet or silent: no progress bar, and reports list only findings
+    pub fn quiet(&self) -> bool {
+        self.output_level != OutputLevel::Normal
+    }
+
+    /// The `--exclude` patterns, followed by those of source, javadoc and
+    /// test jars unless `--scan-source-jars`
+    pub fn exclude_patterns(&self) -> Vec<&str> {
+        let source_jars = SOURCE_JAR_PATTERNS.iter().copied().filter(|_| !self.scan_source_jars);
+        self.exclude.iter().map(String::as_str).chain(source_jars).collect()
+    }
+
+    /// Check that the features in use have the digests they rely on: STIX
+    /// identifies files by SHA-256 and `--group-duplicates` needs any one digest
+    pub fn check_hashes(&self) -> Result<(), String> {
+        if self.format == "stix" && !self.hashes.contains(HashAlgorithm::Sha256) {
+            return Err("The stix format identifies files by their SHA-256 hash; add sha256 to --hashes".to_string());
+        }
+        if self.group_duplicates && !DEDUP_HASHES.iter().any(|&algorithm| self.hashes.contains(algorithm)) {
+            return Err("--group-duplicates groups files by hash; select at least one with --hashes".to_string());
+        }
+        Ok(())
+    }
+
+    /// Check that `--output-rotate` has a file to rotate and a format that can
+    /// be split into complete documents
+    pub fn check_output_rotate(&self) -> Result<(), String> {
+        match self.output_rotate {
+            None => Ok(()),
+            Some(0) => Err("--output-rotate must be at least 1 megabyte".to_string()),
+            Some(_) if self.output.is_none() => Err("--output-rotate rotates the --output file; give --output".to_string()),
+            Some(_) if self.format != "json" => Err("--output-rotate splits JSON reports only; use --format json".to_string()),
+            Some(_) => Ok(()),
+        }
+    }
+
+    /// Check that `--output-compress` has a file to compress, which is not
+    /// also rotated, at a level gzip has
+    pub fn check_output_compress(&self) -> Result<(), String> {
+        if !self.output_compress {
+            return Ok(());
+        }
+        if self.output.is_none() {
+            return Err("--output-compress compresses the --output file; give --output".to_string());
+        }
+        if self.output_rotate.is_some() {
+            return Err("--output-compress cannot be combined with --output-rotate".to_string());
+        }
+        if self.output_compress_level > 9 {
+            return Err(format!("--output-compress-level must be 0 to 9, not {}", self.output_compress_level));
+        }
+        Ok(())
+    }
+
+    /// Size of the buffers files are read through
+    pub fn read_buffer_size(&self) -> usize {
+        if self.io_buffer_size > 0 { self.io_buffer_size } else { DEFAULT_IO_BUFFER_SIZE }
+    }
+
+    /// Threads the scan pool gets under `thread_mode`. Without `--threads`,
+    /// `--nice` scans with a quarter of the cores.
+    pub fn scan_threads(&self) -> usize {
+        let cpus = num_cpus::get();
+        match self.thread_mode {
+            ThreadMode::Fixed(threads) => threads,
+            ThreadMode::IoOptimized => (cpus * IO_THREADS_PER_CPU).min(MAX_IO_THREADS),
+            ThreadMode::Auto => self.threads.unwrap_or(if self.nice { (cpus / 4).max(1) } else { cpus }),
+        }
+    }
+
+    /// Check that every `--report-filter` regex compiles, so a typo fails
+    /// before the scan rather than after it
+    pub fn check_report_filter(&self) -> Result<(), String> {
+        for pattern in &self.report_filter {
+            Regex::new(pattern).map_err(|e| format!("invalid --report-filter {}: {}", pattern, e))?;
         }
+        Ok(())
     }
+
+    /// Check that `--filename` has `--stdin` results to name. Checked once the
+    /// config file is applied, since either may come from it.
+    pub fn check_stdin_filename(&self) -> Result<(), String> {
+        if self.stdin_filename.is_some() && !self.stdin {
+            return Err("--filename names the --stdin results; give --stdin".to_string());
+        }
+        Ok(())
+    }
+
+    /// Check that `--suspicious-ratio` is a ratio an entry can exceed
+    pub fn check_suspicious_ratio(&self) -> Result<(), String> {
+        match self.suspicious_ratio {
+            Some(ratio) if !(ratio.is_finite() && ratio >= 1.0) => Err(format!("--suspicious-ratio must be a number of at least 1, not {}", ratio)),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// `config` with the settings of `profile` applied, except `explicit` keys
+/// given on the command line
+pub fn apply_profile(config: Config, profile: Profile, explicit: &[&str]) -> Result<Config, String> {
+    let settings = profile.settings().into_iter().map(|(key, value)| (key.to_string(), value));
+    merge(config, settings, explicit).map_err(|e| format!("invalid profile {}: {}", profile, e))
+}
+
+/// `config` with the keys of a TOML config file applied, except `explicit` keys
+/// given on the command line, which take precedence over the file. A `profile`
+/// key in the file is applied first, so the file's other keys override it.
+pub fn apply_file(config: Config, path: &Path, explicit: &[&str]) -> Result<Config, String> {
+    let contents = fs::read_to_string(path)
+        .map_err(|e| format!("unable to read config {}: {}", path.display(), e))?;
+    let file: toml::Table = toml::from_str(&contents)
+        .map_err(|e| format!("invalid config {}: {}", path.display(), e))?;
+
+    let config = match file.get("profile").filter(|_| !explicit.contains(&"profile")) {
+        Some(profile) => {
+            let profile = profile.as_str()
+                .ok_or_else(|| "profile must be a string".to_string())
+                .and_then(Profile::from_str)
+                .map_err(|e| format!("invalid config {}: {}", path.display(), e))?;
+            apply_profile(config, profile, explicit)?
+        }
+        None => config,
+    };
+    let settings = file.into_iter()
+        .map(|(key, value)| serde_json::to_value(value).map(|value| (key, value)))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())?;
+    merge(config, settings, explicit).map_err(|e| format!("invalid config {}: {}", path.display(), e))
+}
+
+/// Overwrite the keys of `config` with `settings`, skipping `explicit` keys
+fn merge(config: Config, settings: impl IntoIterator<Item = (String, Value)>, explicit: &[&str]) -> serde_json::Result<Config> {
+    let mut merged = serde_json::to_value(config)?;
+    for (key, value) in settings {
+        if !explicit.contains(&key.as_str()) {
+            merged[key] = value;
+        }
+    }
+    serde_json::from_value::<Config>(merged)
+}
+
+/// The resolved settings as a TOML config file, which `--config` reads back.
+/// They already include the profile's settings, so the profile is only named
+/// in a comment: read back, it would also fill in the keys the file leaves out.
+pub fn to_toml(config: &Config) -> Result<String, String> {
+    let settings = toml::to_string(&Config { profile: None, ..config.clone() })
+        .map_err(|e| format!("unable to print config: {}", e))?;
+    Ok(match config.profile {
+        Some(profile) => format!("# Resolved from profile {}\n{}", profile, settings),
+        None => settings,
+    })
+}
+
+/// Compile every `--custom-patterns` regex, returning all invalid patterns
+/// with their errors if any fail
+pub fn validate_custom_patterns(patterns: &[String]) -> Result<Vec<Regex>, Vec<(String, regex::Error)>> {
+    let (valid, invalid): (Vec<_>, Vec<_>) = patterns.iter()
+        .map(|p| (p, Regex::new(p)))
+        .partition(|(_, regex)| regex.is_ok());
+
+    if !invalid.is_empty() {
+        return Err(invalid.into_iter()
+            .map(|(p, regex)| (p.clone(), regex.unwrap_err()))
+            .collect());
+    }
+
+    Ok(valid.into_iter().map(|(_, regex)| regex.unwrap()).collect())
 }
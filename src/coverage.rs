@@ -0,0 +1,144 @@
+use crate::config::Config;
+use once_cell::sync::Lazy;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// Why a file or directory the walk reached was not scanned
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SkipReason {
+    /// Matched an `--exclude` or source jar pattern
+    ExcludedByGlob,
+    /// Not modified since `--since`
+    NotModified,
+    /// Not a file type the scanner examines, or a package format it cannot open
+    UnsupportedType,
+    PermissionDenied,
+    /// An archive whose entries are encrypted
+    Encrypted,
+    /// Left when the `--timeout` deadline passed
+    Timeout,
+    /// Not handed to a worker before `--max-duration` passed
+    MaxDuration,
+    /// Left when `--max-findings` or `--fail-fast` stopped the scan
+    Stopped,
+    SymlinkNotFollowed,
+    /// Hidden, with `--skip-hidden`
+    Hidden,
+    /// A system directory or virtual filesystem
+    SystemDirectory,
+    /// A directory already walked under another path
+    AlreadyWalked,
+}
+
+impl fmt::Display for SkipReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            SkipReason::ExcludedByGlob => "excluded-by-glob",
+            SkipReason::NotModified => "not-modified",
+            SkipReason::UnsupportedType => "unsupported-type",
+            SkipReason::PermissionDenied => "permission-denied",
+            SkipReason::Encrypted => "encrypted",
+            SkipReason::Timeout => "timeout",
+            SkipReason::MaxDuration => "max-duration",
+            SkipReason::Stopped => "stopped",
+            SkipReason::SymlinkNotFollowed => "symlink-not-followed",
+            SkipReason::Hidden => "hidden",
+            SkipReason::SystemDirectory => "system-directory",
+            SkipReason::AlreadyWalked => "already-walked",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// An entry the scan did not examine, for `--list-skipped`
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct SkippedEntry {
+    pub path: PathBuf,
+    pub reason: SkipReason,
+}
+
+/// How much of what the walk reached was scanned. A skipped directory counts
+/// as one entry, however much is under it.
+#[derive(Debug, Clone, Default, serde::Serialize)]
+pub struct Coverage {
+    pub entries_walked: usize,
+    pub scanned: usize,
+    pub skipped: usize,
+    pub errored: usize,
+    /// Percentages of `entries_walked`, to two decimal places
+    pub scanned_percent: f64,
+    pub skipped_percent: f64,
+    pub errored_percent: f64,
+    pub skipped_by_reason: BTreeMap<SkipReason, usize>,
+    /// Every skipped entry, in path order, with `--list-skipped`
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub skipped_entries: Vec<SkippedEntry>,
+}
+
+#[derive(Default)]
+struct Collector {
+    scanned: usize,
+    errored: usize,
+    skipped_by_reason: BTreeMap<SkipReason, usize>,
+    skipped_entries: Vec<SkippedEntry>,
+}
+
+/// Coverage of the running scan, shared by the walk and every worker thread
+static COLLECTOR: Lazy<Mutex<Collector>> = Lazy::new(Default::default);
+
+pub fn scanned() {
+    COLLECTOR.lock().unwrap_or_else(|e| e.into_inner()).scanned += 1;
+}
+
+pub fn errored() {
+    COLLECTOR.lock().unwrap_or_else(|e| e.into_inner()).errored += 1;
+}
+
+/// Count a skipped entry, keeping its path too when `config` asks for
+/// `--list-skipped`
+pub fn skipped(path: &Path, reason: SkipReason, config: &Config) {
+    let mut collector = COLLECTOR.lock().unwrap_or_else(|e| e.into_inner());
+    *collector.skipped_by_reason.entry(reason).or_default() += 1;
+    if config.list_skipped {
+        collector.skipped_entries.push(SkippedEntry { path: path.to_path_buf(), reason });
+    }
+}
+
+/// The coverage recorded since the last call. Resets it for the next scan.
+pub fn take() -> Coverage {
+    let mut collector = std::mem::take(&mut *COLLECTOR.lock().unwrap_or_else(|e| e.into_inner()));
+    // Worker threads skip files in no particular order
+    collector.skipped_entries.sort_by(|a, b| a.path.cmp(&b.path));
+    let skipped = collector.skipped_by_reason.values().sum();
+    let entries_walked = collector.scanned + skipped + collector.errored;
+    let percent = |count: usize| {
+        if entries_walked == 0 {
+            0.0
+        } else {
+            (count as f64 * 10_000.0 / entries_walked as f64).round() / 100.0
+        }
+    };
+    Coverage {
+        entries_walked,
+        scanned: collector.scanned,
+        skipped,
+        errored: collector.errored,
+        scanned_percent: percent(collector.scanned),
+        skipped_percent: percent(skipped),
+        errored_percent: percent(collector.errored),
+        skipped_by_reason: collector.skipped_by_reason,
+        skipped_entries: collector.skipped_entries,
+    }
+}
+
+/// `Coverage: 120 entries walked, 100 scanned (83.33%), 18 skipped (15%), 2 errored (1.67%)`
+pub fn coverage_line(coverage: &Coverage) -> String {
+    format!(
+        "Coverage: {} entries walked, {} scanned ({}%), {} skipped ({}%), {} errored ({}%)",
+        coverage.entries_walked, coverage.scanned, coverage.scanned_percent, coverage.skipped,
+        coverage.skipped_percent, coverage.errored, coverage.errored_percent,
+    )
+}
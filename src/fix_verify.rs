@@ -0,0 +1,143 @@
+use crate::config::Config;
+use crate::reporter::DEDUP_HASHES;
+use crate::rules::RuleSet;
+use crate::scanner::{archive_formats, retain_min_confidence, scan_file, ScanResult, ScanStatus};
+use crate::schema;
+use crate::utils::{HashAlgorithms, IoBudget};
+use rayon::prelude::*;
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+
+/// What became of a finding of an earlier report
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+pub enum FixStatus {
+    /// The file is still there and a scan no longer finds anything in it
+    Fixed,
+    /// The same file, by hash, is still vulnerable, or could not be scanned
+    StillVulnerable,
+    /// The file was replaced and the replacement is vulnerable too
+    ChangedStillVulnerable,
+    /// The file, or the archive holding it, is gone
+    FileMissing,
+}
+
+/// One finding of an earlier report checked by scanning its file again
+#[derive(Debug, serde::Serialize)]
+pub struct FixVerification {
+    pub file_path: String,
+    pub status: FixStatus,
+    /// Rule behind the earlier finding
+    pub rule_id: Option<String>,
+    /// Why the file is still reported, when it is
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
+
+/// Scan again only the files with findings in an earlier JSON report, and
+/// print what became of each finding. Returns whether every finding is
+/// fixed or its file removed.
+pub fn run(report: &Path, config: &Config) -> Result<bool, Box<dyn std::error::Error>> {
+    let contents = fs::read(report).map_err(|e| format!("unable to read report {}: {}", report.display(), e))?;
+    let parsed: serde_json::Value = serde_json::from_slice(&contents)
+        .map_err(|e| format!("invalid JSON report {}: {}", report.display(), e))?;
+    let mut parsed = schema::upgrade(parsed);
+    let results: Vec<ScanResult> = serde_json::from_value(parsed["results"].take())
+        .map_err(|e| format!("invalid results in report {}: {}", report.display(), e))?;
+
+    let verified = verify(&results, config)?;
+    let count = |status| verified.iter().filter(|result| result.status == status).count();
+    let fixed = count(FixStatus::StillVulnerable) + count(FixStatus::ChangedStillVulnerable) == 0;
+
+    let mut output = io::stdout().lock();
+    if config.format == "json" {
+        serde_json::to_writer_pretty(&mut output, &verified)?;
+        writeln!(output)?;
+        return Ok(fixed);
+    }
+    for result in &verified {
+        match &result.reason {
+            Some(reason) => writeln!(output, "{}: {:?} ({})", result.file_path, result.status, reason)?,
+            None => writeln!(output, "{}: {:?}", result.file_path, result.status)?,
+        }
+    }
+    writeln!(
+        output,
+        "Fixed: {}, StillVulnerable: {}, ChangedStillVulnerable: {}, FileMissing: {}",
+        count(FixStatus::Fixed),
+        count(FixStatus::StillVulnerable),
+        count(FixStatus::ChangedStillVulnerable),
+        count(FixStatus::FileMissing),
+    )?;
+    Ok(fixed)
+}
+
+/// Check each finding of `results`, once per path, by scanning the file it
+/// was found in again with the rules of `config`. A finding in an archive
+/// entry (`outer.war!WEB-INF/lib/log4j-core.jar`) is looked up among the
+/// results of scanning its outer file, which is scanned once for all of them.
+pub fn verify(results: &[ScanResult], config: &Config) -> Result<Vec<FixVerification>, Box<dyn std::error::Error>> {
+    let mut seen = HashSet::new();
+    let findings: Vec<&ScanResult> = results.iter()
+        .filter(|result| result.vulnerable && seen.insert(result.file_path.as_str()))
+        .collect();
+    let mut by_file: BTreeMap<&Path, Vec<&ScanResult>> = BTreeMap::new();
+    for finding in &findings {
+        let file = Path::new(finding.file_path.split('!').next().unwrap_or(&finding.file_path));
+        by_file.entry(file).or_default().push(finding);
+    }
+
+    // Hash with what the report recorded, to tell a replaced file from the same one
+    let hashes = HashAlgorithms::from(DEDUP_HASHES.iter()
+        .copied()
+        .filter(|&algorithm| findings.iter().any(|finding| finding.hash(algorithm).is_some()))
+        .collect::<Vec<_>>());
+    let config = &Config { hashes, io_budget: IoBudget::new(config.io_limit), ..config.clone() };
+    let rules = RuleSet::from_config(config)?;
+    let archives = archive_formats(config)?;
+
+    let verified = by_file.into_par_iter()
+        .flat_map_iter(|(file, findings)| {
+            let rescanned = if file.exists() {
+                let mut rescanned = scan_file(file, config, &rules, &archives);
+                retain_min_confidence(&mut rescanned, config.min_confidence);
+                Some(rescanned)
+            } else {
+                None
+            };
+            findings.into_iter()
+                .map(|finding| check_finding(finding, rescanned.as_deref()))
+                .collect::<Vec<_>>()
+        })
+        .collect();
+    Ok(verified)
+}
+
+/// Compare a finding with the results of scanning its file again, None when
+/// the file is gone
+fn check_finding(finding: &ScanResult, rescanned: Option<&[ScanResult]>) -> FixVerification {
+    let current = rescanned.and_then(|rescanned| rescanned.iter().find(|result| result.file_path == finding.file_path));
+    let (status, reason) = match (rescanned, current) {
+        (None, _) => (FixStatus::FileMissing, None),
+        (Some(_), None) => (FixStatus::Fixed, None),
+        (Some(_), Some(current)) if !current.vulnerable && current.status != ScanStatus::Vulnerable => {
+            // Not finding anything in a file that could not be read proves nothing
+            (FixStatus::StillVulnerable, Some(format!("could not be scanned: {}", current.reason.as_deref().unwrap_or("unknown reason"))))
+        }
+        (Some(_), Some(current)) if !current.vulnerable => (FixStatus::Fixed, None),
+        (Some(_), Some(current)) => {
+            let changed = DEDUP_HASHES.iter()
+                .find_map(|&algorithm| finding.hash(algorithm).map(|hash| current.hash(algorithm) != Some(hash)))
+                .unwrap_or(false);
+            let status = if changed { FixStatus::ChangedStillVulnerable } else { FixStatus::StillVulnerable };
+            (status, current.reason.clone())
+        }
+    };
+    FixVerification {
+        file_path: finding.file_path.clone(),
+        status,
+        rule_id: finding.rule_id.clone(),
+        reason,
+    }
+}
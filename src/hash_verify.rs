@@ -0,0 +1,133 @@
+use crate::config::Config;
+use crate::reporter::DEDUP_HASHES;
+use crate::rules::RuleSet;
+use crate::scanner::{archive_formats, scan_file, ArchiveFormat, ScanResult};
+use crate::schema;
+use crate::utils::{calculate_file_hashes, HashAlgorithm, HashAlgorithms, IoBudget};
+use log::warn;
+use std::collections::HashSet;
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+
+/// What became of a file listed in an earlier report
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+pub enum VerificationStatus {
+    /// Same hash as in the report: still potentially vulnerable
+    Verified,
+    /// A different hash, or an archive entry a scan no longer finds: possibly patched
+    Changed,
+    /// The file no longer exists: removed
+    Missing,
+}
+
+/// One file of an earlier report checked against the file system
+#[derive(Debug, serde::Serialize)]
+pub struct VerificationResult {
+    pub file_path: String,
+    pub status: VerificationStatus,
+    pub hash_algorithm: HashAlgorithm,
+    pub expected_hash: String,
+    /// The hash now, when the file or archive entry still exists
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub current_hash: Option<String>,
+}
+
+/// Re-hash every file listed in a JSON report, with the first digest the
+/// report recorded for it, and print whether each is unchanged, changed or
+/// gone. An entry inside an archive (`outer.jar!inner.jar`) is looked up by
+/// scanning its outer file again with the rules of `config`.
+pub fn run(report: &Path, config: &Config) -> Result<(), Box<dyn std::error::Error>> {
+    let contents = fs::read(report).map_err(|e| format!("unable to read report {}: {}", report.display(), e))?;
+    let parsed: serde_json::Value = serde_json::from_slice(&contents)
+        .map_err(|e| format!("invalid JSON report {}: {}", report.display(), e))?;
+    let mut parsed = schema::upgrade(parsed);
+    let results: Vec<ScanResult> = serde_json::from_value(parsed["results"].take())
+        .map_err(|e| format!("invalid results in report {}: {}", report.display(), e))?;
+
+    // A file with several findings is listed once per finding
+    let mut seen = HashSet::new();
+    let mut listed = Vec::new();
+    for result in results.iter().filter(|result| seen.insert(result.file_path.as_str())) {
+        match DEDUP_HASHES.iter().find_map(|&algorithm| result.hash(algorithm).map(|hash| (algorithm, hash))) {
+            Some((algorithm, hash)) => listed.push((result.file_path.as_str(), algorithm, hash)),
+            None => warn!("Not verifying {}: the report has no hash for it", result.file_path),
+        }
+    }
+    let hashes = HashAlgorithms::from(listed.iter().map(|&(_, algorithm, _)| algorithm).collect::<Vec<_>>());
+    let config = &Config { hashes, io_budget: IoBudget::new(config.io_limit), ..config.clone() };
+
+    let rules = RuleSet::from_config(config)?;
+    let archives = archive_formats(config)?;
+    let mut verified = Vec::new();
+    for (file_path, algorithm, expected_hash) in listed {
+        match verify_file(file_path, algorithm, expected_hash, config, &rules, &archives) {
+            Ok(result) => verified.push(result),
+            Err(e) => warn!("Not verifying {}: {}", file_path, e),
+        }
+    }
+
+    let mut output = io::stdout().lock();
+    if config.format == "json" {
+        serde_json::to_writer_pretty(&mut output, &verified)?;
+        writeln!(output)?;
+        return Ok(());
+    }
+    for result in &verified {
+        let detail = match (result.status, &result.current_hash) {
+            (VerificationStatus::Verified, _) => format!("{} unchanged, still potentially vulnerable", result.hash_algorithm.name()),
+            (VerificationStatus::Changed, Some(current)) => format!("{} {} is now {}, possibly patched", result.hash_algorithm.name(), result.expected_hash, current),
+            (VerificationStatus::Changed, None) => "no longer found in its archive, possibly patched".to_string(),
+            (VerificationStatus::Missing, _) => "removed".to_string(),
+        };
+        writeln!(output, "{}: {:?} ({})", result.file_path, result.status, detail)?;
+    }
+    let count = |status| verified.iter().filter(|result| result.status == status).count();
+    writeln!(
+        output,
+        "Verified: {}, Changed: {}, Missing: {}",
+        count(VerificationStatus::Verified),
+        count(VerificationStatus::Changed),
+        count(VerificationStatus::Missing),
+    )?;
+    Ok(())
+}
+
+fn verify_file(
+    file_path: &str,
+    algorithm: HashAlgorithm,
+    expected_hash: &str,
+    config: &Config,
+    rules: &RuleSet,
+    archives: &[&ArchiveFormat],
+) -> io::Result<VerificationResult> {
+    let file = Path::new(file_path.split('!').next().unwrap_or(file_path));
+    let current_hash = if !file.exists() {
+        None
+    } else if file_path.contains('!') {
+        scan_file(file, config, rules, archives).into_iter()
+            .find(|result| result.file_path == file_path)
+            .and_then(|result| result.hash(algorithm).map(String::from))
+    } else {
+        let hashes = calculate_file_hashes(file, config.hashes, config.read_buffer_size(), &config.io_budget)?;
+        match algorithm {
+            HashAlgorithm::Sha256 => hashes.sha256,
+            HashAlgorithm::Sha3 => hashes.sha3,
+            HashAlgorithm::Blake3 => hashes.blake3,
+            HashAlgorithm::Xxh3 => hashes.xxh3,
+        }
+    };
+
+    let status = match &current_hash {
+        _ if !file.exists() => VerificationStatus::Missing,
+        Some(current) if current == expected_hash => VerificationStatus::Verified,
+        _ => VerificationStatus::Changed,
+    };
+    Ok(VerificationResult {
+        file_path: file_path.to_string(),
+        status,
+        hash_algorithm: algorithm,
+        expected_hash: expected_hash.to_string(),
+        current_hash,
+    })
+}
@@ -0,0 +1,165 @@
+use crate::config::Config;
+use crate::rules::RuleSet;
+use crate::scan_errors::{self, ScanErrorKind};
+use crate::scanner::{scan_entry_name, skipped_scan_result, ScanResult};
+use crate::utils::{nested_path, read_file};
+use log::debug;
+use std::path::Path;
+
+const JIMAGE_MAGIC: u32 = 0xCAFE_DADA;
+const HEADER_SIZE: usize = 28;
+
+// Location attribute kinds
+const ATTRIBUTE_END: u8 = 0;
+const ATTRIBUTE_MODULE: u8 = 1;
+const ATTRIBUTE_PARENT: u8 = 2;
+const ATTRIBUTE_BASE: u8 = 3;
+const ATTRIBUTE_EXTENSION: u8 = 4;
+const ATTRIBUTE_OFFSET: u8 = 5;
+const ATTRIBUTE_COMPRESSED: u8 = 6;
+const ATTRIBUTE_UNCOMPRESSED: u8 = 7;
+const ATTRIBUTE_COUNT: usize = 8;
+
+/// Match the resource names in a runtime's `lib/modules` jrt image against the
+/// entry-name rules, labelling findings with their nested path, e.g.
+/// `jdk/lib/modules!org.apache.logging.log4j.core/org/apache/.../JndiLookup.class`.
+/// Resource contents are not decompressed or matched.
+pub fn scan_jimage(path: &Path, config: &Config, rules: &RuleSet) -> Vec<ScanResult> {
+    debug!("Scanning jimage file: {:?}", path);
+
+    let data = match read_file(path, &config.io_budget) {
+        Ok(data) => data,
+        Err(e) => {
+            scan_errors::record(path, ScanErrorKind::of_io(&e), format_args!("Error reading jimage file: {:?} - {}", path, e));
+            return Vec::new();
+        }
+    };
+
+    let name = path.to_string_lossy();
+    let mut results = Vec::new();
+    let listed = visit_resources(&data, |resource, contents| {
+        let nested_name = nested_path(&name, resource);
+        // Metrics need some bytes; an empty resource falls back to its name
+        let contents = if contents.is_empty() { resource.as_bytes() } else { contents };
+        results.extend(scan_entry_name(&nested_name, resource, contents, config, rules));
+    });
+
+    if let Err(reason) = listed {
+        scan_errors::record(path, ScanErrorKind::InvalidArchive, format_args!("Skipping jimage: {:?} - {}", path, reason));
+        results.push(skipped_scan_result(path, &data, format!("Skipped jimage: {}", reason), config));
+    }
+
+    results
+}
+
+/// Call `visit` with the name of each resource in a jimage, `module/parent/base.extension`,
+/// and its stored (possibly compressed) bytes
+fn visit_resources<F>(data: &[u8], mut visit: F) -> Result<(), String>
+where
+    F: FnMut(&str, &[u8]),
+{
+    let header = Header::parse(data)?;
+    let offsets_start = HEADER_SIZE + header.table_length * 4;
+    let locations_start = offsets_start + header.table_length * 4;
+    let strings_start = locations_start + header.locations_size;
+    let index_size = strings_start + header.strings_size;
+
+    let locations = data.get(locations_start..strings_start).ok_or("truncated jimage index")?;
+    let strings = data.get(strings_start..index_size).ok_or("truncated jimage index")?;
+
+    for i in 0..header.table_length {
+        let offset = read_u32(data, offsets_start + i * 4, header.big_endian)? as usize;
+        let attributes = decode_location(locations, offset)?;
+        let string = |kind: u8| read_string(strings, attributes[kind as usize] as usize);
+
+        let mut name = String::new();
+        for kind in [ATTRIBUTE_MODULE, ATTRIBUTE_PARENT] {
+            let part = string(kind)?;
+            if !part.is_empty() {
+                name.push_str(&part);
+                name.push('/');
+            }
+        }
+        name.push_str(&string(ATTRIBUTE_BASE)?);
+        let extension = string(ATTRIBUTE_EXTENSION)?;
+        if !extension.is_empty() {
+            name.push('.');
+            name.push_str(&extension);
+        }
+
+        let start = index_size + attributes[ATTRIBUTE_OFFSET as usize] as usize;
+        let size = match attributes[ATTRIBUTE_COMPRESSED as usize] {
+            0 => attributes[ATTRIBUTE_UNCOMPRESSED as usize],
+            compressed => compressed,
+        } as usize;
+        let contents = data.get(start..start.saturating_add(size)).unwrap_or_default();
+        visit(&name, contents);
+    }
+
+    Ok(())
+}
+
+struct Header {
+    big_endian: bool,
+    table_length: usize,
+    locations_size: usize,
+    strings_size: usize,
+}
+
+impl Header {
+    /// The header is written in the byte order of the platform that built the image
+    fn parse(data: &[u8]) -> Result<Self, String> {
+        let magic: [u8; 4] = data.get(..4).and_then(|m| m.try_into().ok()).ok_or("not a jimage file")?;
+        let big_endian = if u32::from_le_bytes(magic) == JIMAGE_MAGIC {
+            false
+        } else if u32::from_be_bytes(magic) == JIMAGE_MAGIC {
+            true
+        } else {
+            return Err("not a jimage file".to_string());
+        };
+
+        let read = |offset| read_u32(data, offset, big_endian).map(|value| value as usize);
+        Ok(Header {
+            big_endian,
+            table_length: read(16)?,
+            locations_size: read(20)?,
+            strings_size: read(24)?,
+        })
+    }
+}
+
+fn read_u32(data: &[u8], offset: usize, big_endian: bool) -> Result<u32, String> {
+    let bytes: [u8; 4] = data.get(offset..offset + 4)
+        .and_then(|b| b.try_into().ok())
+        .ok_or("truncated jimage index")?;
+    Ok(if big_endian { u32::from_be_bytes(bytes) } else { u32::from_le_bytes(bytes) })
+}
+
+/// Decode a location's attributes: each starts with a byte holding the kind
+/// in its top five bits and the value length minus one in its low three,
+/// followed by the big-endian value
+fn decode_location(locations: &[u8], mut offset: usize) -> Result<[u64; ATTRIBUTE_COUNT], String> {
+    let mut attributes = [0u64; ATTRIBUTE_COUNT];
+    loop {
+        let byte = *locations.get(offset).ok_or("truncated jimage location")?;
+        let kind = byte >> 3;
+        if kind == ATTRIBUTE_END {
+            return Ok(attributes);
+        }
+        if kind as usize >= ATTRIBUTE_COUNT {
+            return Err(format!("invalid jimage location attribute {}", kind));
+        }
+
+        let length = (byte & 0x7) as usize + 1;
+        let value = locations.get(offset + 1..offset + 1 + length).ok_or("truncated jimage location")?;
+        attributes[kind as usize] = value.iter().fold(0u64, |acc, &b| (acc << 8) | b as u64);
+        offset += 1 + length;
+    }
+}
+
+/// A NUL-terminated string from the strings table
+fn read_string(strings: &[u8], offset: usize) -> Result<String, String> {
+    let tail = strings.get(offset..).ok_or("invalid jimage string offset")?;
+    let end = tail.iter().position(|&b| b == 0).ok_or("unterminated jimage string")?;
+    Ok(String::from_utf8_lossy(&tail[..end]).into_owned())
+}
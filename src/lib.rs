@@ -0,0 +1,33 @@
+pub mod annotations;
+pub mod audit_log;
+pub mod benchmark;
+pub mod class_path;
+pub mod config;
+pub mod coverage;
+pub mod dex;
+pub mod discovery;
+pub mod email;
+pub mod event_log;
+pub mod exit_manifest;
+pub mod fix_verify;
+pub mod hash_verify;
+pub mod jimage;
+pub mod log4j_config;
+pub mod maven;
+pub mod object_storage;
+pub mod package;
+pub mod patterns_update;
+pub mod remote;
+pub mod registry;
+pub mod reporter;
+pub mod rule_stats;
+pub mod rules;
+pub mod scan_errors;
+pub mod scanner;
+pub mod schema;
+pub mod selftest;
+pub mod server;
+pub mod signing;
+pub mod socket_server;
+pub mod stored_report;
+pub mod utils;
@@ -0,0 +1,179 @@
+use once_cell::sync::Lazy;
+use regex::Regex;
+use std::path::Path;
+
+/// Longest excerpt of an offending line quoted in a finding
+const MAX_EXCERPT_LEN: usize = 160;
+
+static CONFIG_FILE_NAME: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"(?i)^log4j2?([-.][a-z0-9_]+)*\.(xml|properties|ya?ml)$").unwrap()
+});
+
+/// A setting that widens exposure, matched line by line after comments are removed
+struct RiskySetting {
+    /// Stable identifier, reported as the finding's rule id
+    id: &'static str,
+    description: &'static str,
+    pattern: Regex,
+    cve: Option<&'static str>,
+}
+
+static RISKY_SETTINGS: Lazy<Vec<RiskySetting>> = Lazy::new(|| {
+    let setting = |id, description, pattern, cve| RiskySetting {
+        id,
+        description,
+        pattern: Regex::new(pattern).unwrap(),
+        cve,
+    };
+    vec![
+        setting("config-jndi-lookup", "JNDI lookup in configuration", r"\$\{jndi:", Some("CVE-2021-44228")),
+        setting("config-message-lookups", "message lookups enabled", r"%(m|msg|message)\{[^}]*lookups", Some("CVE-2021-45046")),
+        setting("config-jndi-enabled", "JNDI lookups enabled", r"(?i)enableJndi\w*\s*[=:]\s*.?true", Some("CVE-2021-44228")),
+        setting("config-message-lookups-not-disabled", "message lookups not disabled", r"(?i)formatMsgNoLookups\s*[=:]\s*.?false", Some("CVE-2021-44228")),
+        setting("config-log4j1-jms-appender", "log4j 1.x JMSAppender", r"org\.apache\.log4j\.net\.JMSAppender", Some("CVE-2021-4104")),
+        setting("config-jms-appender", "JMS appender", r"(<JMS(Queue|Topic)?[\s>/]|(?i)type\s*[=:]\s*JMS\b|^\s*JMS(Queue|Topic)?:)", None),
+        setting("config-jndi-data-source", "JDBC data source looked up through JNDI", r"(?i)jndiName\s*[=:]", Some("CVE-2021-44832")),
+    ]
+});
+
+/// A risky setting, or a file that could not be parsed, found in a log4j configuration
+pub struct ConfigIssue {
+    pub id: &'static str,
+    pub description: String,
+    /// 1-based line of the offending setting; `None` for parse failures
+    pub line: Option<usize>,
+    pub excerpt: String,
+    pub cve: Option<&'static str>,
+    pub parse_failure: bool,
+}
+
+/// Check if a file or jar entry is a log4j 1.x or 2.x configuration file,
+/// e.g. `log4j2.xml`, `log4j2-test.yaml`, `log4j2.component.properties` or `log4j.properties`
+pub fn is_log4j_config(path: &Path) -> bool {
+    path.file_name()
+        .and_then(|name| name.to_str())
+        .map(|name| CONFIG_FILE_NAME.is_match(name))
+        .unwrap_or(false)
+}
+
+/// Find risky settings in a configuration, reporting a parse failure as one
+/// more issue instead of giving up: lines of a malformed file are still checked
+pub fn find_issues(name: &str, contents: &str) -> Vec<ConfigIssue> {
+    let extension = name.rsplit('.').next().unwrap_or_default().to_ascii_lowercase();
+    let (text, parse_error) = match extension.as_str() {
+        "xml" => strip_xml_comments(contents),
+        "properties" => (strip_line_comments(contents, &["#", "!"]), check_properties(contents)),
+        _ => (strip_line_comments(contents, &["#"]), check_yaml(contents)),
+    };
+
+    let mut issues: Vec<ConfigIssue> = parse_error.into_iter()
+        .map(|reason| ConfigIssue {
+            id: "config-parse-failure",
+            description: format!("unable to parse configuration ({})", reason),
+            line: None,
+            excerpt: String::new(),
+            cve: None,
+            parse_failure: true,
+        })
+        .collect();
+
+    for (number, line) in text.lines().enumerate() {
+        for setting in RISKY_SETTINGS.iter().filter(|setting| setting.pattern.is_match(line)) {
+            issues.push(ConfigIssue {
+                id: setting.id,
+                description: setting.description.to_string(),
+                line: Some(number + 1),
+                excerpt: excerpt(line),
+                cve: setting.cve,
+                parse_failure: false,
+            });
+        }
+    }
+    issues
+}
+
+fn excerpt(line: &str) -> String {
+    let line = line.trim();
+    match line.char_indices().nth(MAX_EXCERPT_LEN) {
+        Some((end, _)) => format!("{}...", &line[..end]),
+        None => line.to_string(),
+    }
+}
+
+/// Blank out `<!-- -->` comments, keeping line breaks so line numbers hold,
+/// and check that elements are balanced
+fn strip_xml_comments(contents: &str) -> (String, Option<String>) {
+    let mut text = String::with_capacity(contents.len());
+    let mut rest = contents;
+    let mut error = None;
+    while let Some(start) = rest.find("<!--") {
+        text.push_str(&rest[..start]);
+        match rest[start..].find("-->") {
+            Some(end) => {
+                let comment = &rest[start..start + end + 3];
+                text.extend(comment.chars().filter(|&c| c == '\n'));
+                rest = &rest[start + end + 3..];
+            }
+            None => {
+                error = Some("unterminated comment".to_string());
+                rest = "";
+            }
+        }
+    }
+    text.push_str(rest);
+
+    let error = error.or_else(|| check_xml_elements(&text));
+    (text, error)
+}
+
+static XML_TAG: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r#"<(/?)([A-Za-z_][\w:.-]*)((?:[^>"']|"[^"]*"|'[^']*')*?)(/?)>"#).unwrap()
+});
+
+/// Check that start and end tags pair up, ignoring declarations and processing instructions
+fn check_xml_elements(text: &str) -> Option<String> {
+    let mut open: Vec<&str> = Vec::new();
+    for tag in XML_TAG.captures_iter(text) {
+        let name = tag.get(2).unwrap().as_str();
+        if &tag[1] == "/" {
+            match open.pop() {
+                Some(expected) if expected == name => {}
+                Some(expected) => return Some(format!("</{}> closes <{}>", name, expected)),
+                None => return Some(format!("unexpected </{}>", name)),
+            }
+        } else if &tag[4] != "/" {
+            open.push(name);
+        }
+    }
+    open.last().map(|name| format!("<{}> is never closed", name))
+}
+
+fn strip_line_comments(contents: &str, markers: &[&str]) -> String {
+    contents.lines()
+        .map(|line| if markers.iter().any(|marker| line.trim_start().starts_with(marker)) { "" } else { line })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// `Properties.load` rejects malformed `\uXXXX` escapes
+fn check_properties(contents: &str) -> Option<String> {
+    contents.lines().enumerate().find_map(|(number, line)| {
+        let mut chars = line.chars();
+        while let Some(c) = chars.next() {
+            if c == '\\' && chars.next() == Some('u') {
+                let hex: String = chars.by_ref().take(4).collect();
+                if hex.len() != 4 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+                    return Some(format!("malformed \\uXXXX escape on line {}", number + 1));
+                }
+            }
+        }
+        None
+    })
+}
+
+/// YAML forbids tabs in indentation
+fn check_yaml(contents: &str) -> Option<String> {
+    contents.lines()
+        .position(|line| line.chars().take_while(|c| c.is_whitespace()).any(|c| c == '\t'))
+        .map(|number| format!("tab in indentation on line {}", number + 1))
+}
@@ -1,74 +1,981 @@
-mod config;
-mod reporter;
-mod scanner;
-mod utils;
+use cve_2021_44228_scanner::{
+    annotations, audit_log, benchmark, config, discovery, email, event_log, exit_manifest, fix_verify, hash_verify, maven, object_storage, patterns_update, remote, registry, reporter, rules, scanner, schema, selftest, server, signing, socket_server, stored_report, utils,
+};
 
-use clap::Parser;
-use config::Config;
-use log::{error, info};
-use scanner::scan_directory;
-use std::process;
+use annotations::Annotations;
+use clap::parser::ValueSource;
+use clap::error::ErrorKind;
+use clap::{ArgAction, CommandFactory, FromArgMatches, Parser, Subcommand};
+use config::{Config, OutputLevel, ThreadMode};
+use event_log::EventLog;
+use log::{error, info, warn, LevelFilter};
+use scanner::{list_extensions, retain_min_confidence, scan_directory, sort_results, Confidence};
+use std::panic;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+use uuid::Uuid;
 
 #[derive(Parser)]
-#[command(author, version, about, long_about = None)]
+#[command(author, version, about, long_about = None, subcommand_negates_reqs = true)]
 struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+
     /// Path to scan
-    #[arg(short, long)]
-    path: String,
+    #[arg(short, long, required_unless_present_any = ["maven_artifact", "discover_java", "ssh", "registry_image", "s3", "gcs", "stdin", "config", "print_config"])]
+    path: Option<String>,
+
+    /// Read options from a TOML config file, such as a JSON report's scan_config; command-line options take precedence
+    #[arg(long, value_name = "FILE")]
+    config: Option<String>,
+
+    /// Preset defaults: fast, default, thorough or paranoid; the config file and command-line options override them
+    #[arg(long, value_name = "NAME")]
+    profile: Option<config::Profile>,
+
+    /// Print the resolved configuration as a TOML config file and exit without scanning
+    #[arg(long)]
+    print_config: bool,
+
+    /// List the files a scan would examine, after every filter, without opening any of them
+    #[arg(long)]
+    dry_run: bool,
 
-    /// Output format (json or text)
-    #[arg(short, long, default_value = "text")]
+    /// Like --dry-run, but only count the files
+    #[arg(long)]
+    dry_run_summary: bool,
+
+    /// Scan N times without writing a report and print the min, max, mean and standard deviation
+    /// of the scan time, files/sec, MB/sec read and the time spent walking, reading, matching and hashing
+    #[arg(long, value_name = "N", value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..), conflicts_with_all = ["dry_run", "dry_run_summary"])]
+    benchmark: Option<usize>,
+
+    /// Output format (text, table, json, msgpack, msgpack-base64, checkstyle, stix, text-stable or teamcity)
+    #[arg(short, long, default_value = "text", global = true)]
     format: String,
 
-    /// Number of threads to use for scanning (default: number of logical CPUs)
+    /// Columns of the table format, comma separated, in order
+    /// (default: path,severity,cve,version,confidence,reason)
+    #[arg(long, value_name = "COLUMNS", value_delimiter = ',', value_parser = ["path", "severity", "cve", "version", "confidence", "reason", "hash"])]
+    columns: Vec<String>,
+
+    /// Number of threads to use for scanning; selects --thread-mode fixed
     #[arg(short, long)]
     threads: Option<usize>,
 
+    /// How many threads scan: auto, one per logical CPU, suits local SSDs, where matching rather
+    /// than reading bounds the scan; io, four per logical CPU up to 64, suits network filesystems
+    /// and spinning disks, where most threads wait on reads, at the cost of more memory and open
+    /// files and of seeking on a single disk; fixed uses the --threads count [default: auto, or
+    /// fixed with --threads]
+    #[arg(long, value_name = "MODE", value_parser = ["auto", "fixed", "io"])]
+    thread_mode: Option<String>,
+
+    /// Limit the combined size in MB of files being scanned at once; workers wait when it is reached
+    #[arg(long)]
+    threads_max_memory: Option<usize>,
+
+    /// Open archives nested at most this deep: 1 opens a jar in a war but not a jar in that jar.
+    /// Deeper archives are reported as skipped [default: 3]
+    #[arg(long, value_name = "N")]
+    max_archive_depth: Option<usize>,
+
+    /// Report class entries of archives that inflate to more than this many times their stored
+    /// size, as jar bombs and obfuscated classes do, as low-severity findings [default: 100]
+    #[arg(long, value_name = "RATIO")]
+    suspicious_ratio: Option<f64>,
+
+    /// Files the directory walk may queue ahead of the scan workers; the walk waits while the queue
+    /// is full, so a fast disk cannot list millions of files ahead of slow workers
+    #[arg(long, value_name = "FILES", default_value = "4096")]
+    queue_capacity: usize,
+
+    /// Limit reads from scanned files to this many MB per second, shared by all threads
+    #[arg(long, value_parser = utils::parse_io_limit)]
+    io_limit: Option<f64>,
+
+    /// Size in KB of the buffers files are hashed and read through
+    #[arg(long, value_name = "KB", default_value = "64", value_parser = utils::parse_io_buffer_size)]
+    io_buffer_size: usize,
+
+    /// Digests to compute for each file, comma separated: sha256, sha3, blake3, xxh3 (fast, not
+    /// cryptographic), or none
+    #[arg(long, value_name = "LIST", default_value = "sha256,sha3,blake3", value_parser = utils::parse_hashes)]
+    hashes: utils::HashAlgorithms,
+
+    /// Run gently: lowest CPU and IO priority and a quarter of the cores unless --threads is given
+    #[arg(long)]
+    nice: bool,
+
+    /// Stop scanning after this many seconds and report the partial results, exiting with code 3
+    #[arg(long, value_name = "SECONDS")]
+    timeout: Option<u64>,
+
+    /// Stop handing out files once this long has passed, e.g. 2h, and report the files not reached,
+    /// exiting with code 4; files being scanned are finished
+    #[arg(long, value_name = "DURATION", value_parser = utils::parse_duration)]
+    max_duration: Option<Duration>,
+
+    /// Also scan these archive formats, comma separated (zip, tar, tar.gz, tgz, tar.bz2, tar.xz, tar.zst);
+    /// prefix one with - to stop scanning it, e.g. -ear. jar, war, ear and aar are scanned by default
+    #[arg(long, value_name = "EXTENSIONS", value_delimiter = ',', allow_hyphen_values = true, value_parser = scanner::parse_archive_extension)]
+    include_archives: Vec<String>,
+
+    /// Also scan the jars and directories named in each scanned jar's META-INF/MANIFEST.MF
+    /// Class-Path header, resolved against the jar's directory, even outside the scan path
+    #[arg(long)]
+    manifest_scan: bool,
+
+    /// Skip hidden files and directories: names starting with . on Unix and macOS, the hidden or
+    /// system attribute on Windows. --skip-hidden=false scans them, e.g. ~/.m2 and ~/.gradle
+    #[arg(long, value_name = "BOOL", default_value_t = true, num_args = 0..=1, require_equals = true, default_missing_value = "true", action = ArgAction::Set)]
+    skip_hidden: bool,
+
+    /// Walk into virtual filesystems too: /proc, /sys, /dev and /run in a scan of /, and any
+    /// procfs, sysfs, cgroupfs or similar mount found on Linux
+    #[arg(long)]
+    no_vfs_filter: bool,
+
+    /// Walk every path to a directory, even when the directory (same device and inode) was already
+    /// walked under another path, such as a bind mount of a directory inside itself. For
+    /// filesystems that report the same inode for different directories
+    #[arg(long)]
+    no_loop_detection: bool,
+
+    /// Also scan files with other names, or none, whose contents start like a jar (PK\x03\x04) or
+    /// a class file (0xCAFEBABE), such as renamed jars. Reads the start of every other file
+    #[arg(long)]
+    content_type_detection: bool,
+
+    /// Also scan source, javadoc and test jars (names ending in -sources.jar, -javadoc.jar or
+    /// -test.jar). By default they are excluded as if given to --exclude, since they hold no code
+    /// that runs and Maven repositories keep one next to most artifacts
+    #[arg(long, visible_alias = "no-skip-source-jars")]
+    scan_source_jars: bool,
+
+    /// List every file and directory the scan skipped, with the reason, after the coverage
+    /// summary (text) or as summary.coverage.skipped_entries (JSON)
+    #[arg(long)]
+    list_skipped: bool,
+
     /// Exclusion patterns (glob syntax)
     #[arg(short, long)]
     exclude: Vec<String>,
 
     /// Custom vulnerability patterns (regex)
-    #[arg(short, long)]
+    #[arg(short, long, global = true)]
     custom_patterns: Vec<String>,
 
-    /// Quiet mode (only output vulnerable files)
+    /// Load additional detection rules from a JSON rules file (can be used multiple times). Each
+    /// file is a rule pack, named by its top-level "pack" key or else its file name
+    #[arg(long, global = true)]
+    rules: Vec<String>,
+
+    /// Use only these rule packs of the --rules files, comma separated, e.g. log4shell,internal;
+    /// built-in rules and --custom-patterns are always used
+    #[arg(long, value_name = "PACKS", value_delimiter = ',', global = true)]
+    packs: Vec<String>,
+
+    /// Only list findings whose file path or archive entry matches this regex (can be used
+    /// multiple times; a finding matching any is listed). Summary totals still count every finding
+    #[arg(long, value_name = "REGEX", global = true)]
+    report_filter: Vec<String>,
+
+    /// YAML or JSON file mapping findings, by path or by `sha3:<hash>[!entry]`, to notes such as a
+    /// ticket and an owner, which are added to the findings in every report
+    #[arg(long, value_name = "FILE")]
+    annotations: Option<String>,
+
+    /// Disable a detection rule by id (can be used multiple times)
+    #[arg(long = "disable-rule", global = true)]
+    disable_rules: Vec<String>,
+
+    /// Quiet mode: no progress bar or info logs, and only vulnerable files in the report
     #[arg(short, long)]
     quiet: bool,
 
+    /// Silent mode: no logs, and no report on stdout unless the format is machine-readable
+    #[arg(long)]
+    silent: bool,
+
     /// Save results to file
     #[arg(short, long)]
     output: Option<String>,
+
+    /// Split the --output JSON report into files of about this many megabytes, <output>, then
+    /// <output>.1, <output>.2 and so on, each a complete report with part of the results
+    #[arg(long, value_name = "MB", requires = "output")]
+    output_rotate: Option<u64>,
+
+    /// Gzip-compress the --output file, adding .gz to its name unless it already ends with .gz
+    #[arg(long, requires = "output", conflicts_with = "output_rotate")]
+    output_compress: bool,
+
+    /// Gzip compression level of --output-compress, from 0 (none, fastest) to 9 (smallest)
+    #[arg(long, value_name = "0-9", default_value = "6", value_parser = clap::value_parser!(u32).range(0..=9))]
+    output_compress_level: u32,
+
+    /// Also count the distinct file hashes each rule matched in the rule statistics
+    #[arg(long)]
+    stats: bool,
+
+    /// Only scan files modified since an RFC3339 timestamp or a duration ago (e.g. 24h, 7d)
+    #[arg(long, value_parser = utils::parse_since)]
+    since: Option<SystemTime>,
+
+    /// Also scan Maven POM files for vulnerable log4j-core dependencies
+    #[arg(long)]
+    scan_pom_xml: bool,
+
+    /// Scan likely-vulnerable files first (log4j names, lib directories, jars, small files)
+    #[arg(long)]
+    prioritize: bool,
+
+    /// Stop scanning once this many vulnerable files have been found
+    #[arg(long)]
+    max_findings: Option<usize>,
+
+    /// Stop scanning as soon as the first Critical finding is found
+    #[arg(long)]
+    fail_fast: bool,
+
+    /// Exit with an error if any file could not be scanned due to missing read permission
+    #[arg(long)]
+    fail_on_permission_denied: bool,
+
+    /// Exit with an error if any archive was skipped (e.g. encrypted entries) or could not be read (corrupted)
+    #[arg(long)]
+    fail_on_unscannable: bool,
+
+    /// Download and scan a Maven artifact instead of a path (groupId:artifactId:version)
+    #[arg(long, conflicts_with = "path")]
+    maven_artifact: Option<String>,
+
+    /// Find Java installations and application servers (JAVA_HOME, /usr/lib/jvm, SDKMAN, Tomcat, Jetty,
+    /// WildFly, running JVMs) and scan only their library, extension and deployment directories
+    #[arg(long, conflicts_with_all = ["path", "maven_artifact"])]
+    discover_java: bool,
+
+    /// Scan a remote host over SSH instead of a local path (user@host:/path); needs the ssh client
+    #[arg(long, value_name = "USER@HOST:/PATH", conflicts_with_all = ["path", "maven_artifact", "discover_java"])]
+    ssh: Option<String>,
+
+    /// Private key for --ssh authentication
+    #[arg(long, value_name = "FILE", requires = "ssh")]
+    ssh_key: Option<String>,
+
+    /// Maximum concurrent SFTP transfers for --ssh (default: --threads or the number of logical CPUs)
+    #[arg(long, value_name = "N", requires = "ssh")]
+    ssh_connections: Option<usize>,
+
+    /// Scan a container image straight from its registry ([registry/]name[:tag|@digest]), streaming
+    /// its layers without saving them. Log in with `docker login` or RUSTYLOG4JGUARD_REGISTRY_USERNAME
+    /// and RUSTYLOG4JGUARD_REGISTRY_PASSWORD
+    #[arg(long, value_name = "IMAGE", conflicts_with_all = ["path", "maven_artifact", "discover_java", "ssh"])]
+    registry_image: Option<String>,
+
+    /// Talk to the --registry-image registry over plain HTTP, e.g. a local test registry
+    #[arg(long, requires = "registry_image")]
+    registry_plain_http: bool,
+
+    /// Scan the objects under an S3 prefix, downloading them into memory. Credentials and region
+    /// come from the AWS SDK's default chain; set AWS_ENDPOINT_URL for S3-compatible stores
+    #[arg(long, value_name = "s3://BUCKET/PREFIX", conflicts_with_all = ["path", "maven_artifact", "discover_java", "ssh", "registry_image"])]
+    s3: Option<String>,
+
+    /// Scan the objects under a Google Cloud Storage prefix, downloading them into memory.
+    /// Credentials come from Application Default Credentials; public buckets need none
+    #[arg(long, value_name = "gs://BUCKET/PREFIX", conflicts_with_all = ["path", "maven_artifact", "discover_java", "ssh", "registry_image", "s3"])]
+    gcs: Option<String>,
+
+    /// Scan a single file piped to standard input, e.g. `curl ... | rustylog4jguard --stdin --filename app.jar`.
+    /// Jars, jmods and class files are recognized by their contents
+    #[arg(long, conflicts_with_all = ["path", "maven_artifact", "discover_java", "ssh", "registry_image", "s3", "gcs"])]
+    stdin: bool,
+
+    /// Name --stdin results are reported under; it also marks log4j configuration files and bundles (default: <stdin>)
+    #[arg(long, value_name = "NAME")]
+    filename: Option<String>,
+
+    /// Maximum concurrent downloads for --s3 and --gcs (default: --threads or the number of logical CPUs)
+    #[arg(long, value_name = "N")]
+    bucket_connections: Option<usize>,
+
+    /// Maven repository to download from instead of Maven Central
+    #[arg(long, requires = "maven_artifact")]
+    maven_repo_url: Option<String>,
+
+    /// Directory for caching downloaded Maven artifacts (default: ~/.cache/rustylog4jguard/maven)
+    #[arg(long, requires = "maven_artifact")]
+    maven_cache_dir: Option<String>,
+
+    /// Send scan start, finding and scan end events to syslog (Windows: Application Event Log)
+    #[arg(long)]
+    syslog: bool,
+
+    /// Only report findings at or above this confidence (tentative, high or confirmed)
+    #[arg(long)]
+    min_confidence: Option<Confidence>,
+
+    /// Group findings for the same file contents found under several paths
+    #[arg(long)]
+    group_duplicates: bool,
+
+    /// Aggregate findings by directory, DEPTH levels below the scan root, to split remediation by team
+    #[arg(long, value_name = "DEPTH")]
+    summary_by_dir: Option<usize>,
+
+    /// Make reports byte-identical across runs over the same files: zero the audit log scan id
+    /// and leave the thread count out of scan_config
+    #[arg(long)]
+    reproducible: bool,
+
+    /// Omit the summary block (counts and top remediation targets) from the text report
+    #[arg(long)]
+    no_summary: bool,
+
+    /// Show a bar of each severity's share of the findings under the vulnerable count in the text
+    /// report (the default)
+    #[arg(long, overrides_with = "no_summary_histogram")]
+    summary_histogram: bool,
+
+    /// Leave the severity bars out of the text report
+    #[arg(long, overrides_with = "summary_histogram")]
+    no_summary_histogram: bool,
+
+    /// Add each result's byte frequency histogram (256 values, 255 = 100%) to JSON and MessagePack reports
+    #[arg(long)]
+    include_byte_histogram: bool,
+
+    /// Build the Markov transition matrix of files and entries over 100 KB on all threads instead
+    /// of one; the markov_probability metric is the same either way
+    #[arg(long)]
+    markov_parallel: bool,
+
+    /// List the severity counts and findings of the text report as plain lines instead of tables
+    #[arg(long)]
+    no_table: bool,
+
+    /// Make file paths in the text report clickable with OSC 8 hyperlinks: auto (terminals known
+    /// to support them, such as iTerm2, Windows Terminal, Kitty or VTE-based ones), always or never
+    #[arg(long, value_name = "WHEN", default_value = "auto", num_args = 0..=1, require_equals = true, default_missing_value = "always")]
+    ansi_hyperlinks: config::HyperlinkMode,
+
+    /// Sign the report with an Ed25519 private key (PKCS#8 PEM or raw 32 bytes), writing <output>.sig
+    #[arg(long, requires = "output")]
+    sign_key: Option<String>,
+
+    /// Report text encoding; utf16le writes UTF-16LE with a byte order mark for Windows tools
+    #[arg(long, default_value = "utf8", value_parser = ["utf8", "utf16le"])]
+    encoding: String,
+
+    /// Email the report to this address after the scan (can be used multiple times)
+    #[arg(long, value_name = "ADDRESS")]
+    email_to: Vec<String>,
+
+    /// Sender address of the report email
+    #[arg(long, value_name = "ADDRESS")]
+    email_from: Option<String>,
+
+    /// SMTP server to send the report email through (host:port); the login is read from
+    /// RUSTYLOG4JGUARD_SMTP_USERNAME and RUSTYLOG4JGUARD_SMTP_PASSWORD
+    #[arg(long, value_name = "HOST:PORT")]
+    smtp_server: Option<String>,
+
+    /// How to secure the SMTP connection: starttls, implicit TLS (usually port 465), or none for
+    /// a relay on the local host
+    #[arg(long, default_value = "starttls", value_parser = ["starttls", "implicit", "none"])]
+    smtp_tls: String,
+
+    /// Only email the report when a finding is at or above this severity (low, medium, high or critical)
+    #[arg(long, value_name = "SEVERITY")]
+    email_min_severity: Option<scanner::Severity>,
+
+    /// Email the report after every scan, even without findings
+    #[arg(long)]
+    email_always: bool,
+
+    /// Exit with an error if the report email cannot be sent
+    #[arg(long)]
+    email_required: bool,
+
+    /// Append audit events of the scan (start, config-resolved, filter-summary, finish, and a file
+    /// event with the decision made for each file walked) to this NDJSON file
+    #[arg(long, value_name = "FILE")]
+    audit_log: Option<String>,
+
+    /// Write a JSON file explaining the exit code (exit_code, meaning, vulnerable_count, scan_path,
+    /// timestamp) when the scanner exits, even if it crashes
+    #[arg(long, value_name = "FILE", global = true)]
+    exit_code_manifest: Option<String>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Check every built-in pattern against synthetic samples, scan embedded known-vulnerable
+    /// fixtures and check every output format
+    #[command(alias = "selftest")]
+    SelfTest,
+
+    /// Work with detection rules
+    Rules {
+        #[command(subcommand)]
+        command: RulesCommand,
+    },
+
+    /// Print the JSON report schema or validate a report against it
+    Schema {
+        #[command(subcommand)]
+        command: SchemaCommand,
+    },
+
+    /// Verify a report signature written by --sign-key, or re-hash the files of an earlier JSON report
+    Verify {
+        /// Signed report
+        #[arg(required_unless_present = "previous")]
+        report: Option<String>,
+
+        /// Detached signature (<report>.sig)
+        #[arg(required_unless_present = "previous")]
+        sig: Option<String>,
+
+        /// Ed25519 public key (PEM or raw 32 bytes)
+        #[arg(long, required_unless_present = "previous")]
+        pub_key: Option<String>,
+
+        /// Earlier JSON report whose files to re-hash: each is reported Verified (same hash), Changed or Missing
+        #[arg(long = "report", value_name = "REPORT", conflicts_with_all = ["report", "sig", "pub_key"])]
+        previous: Option<String>,
+    },
+
+    /// Scan again only the files with findings in an earlier JSON report, and report each finding
+    /// Fixed, StillVulnerable, ChangedStillVulnerable (replaced by another vulnerable file) or
+    /// FileMissing; exits with 1 if any is still vulnerable
+    VerifyFixes {
+        /// JSON report written by --format json
+        report: String,
+    },
+
+    /// Render an earlier JSON report again in --format, listing only the findings --report-filter
+    /// keeps, without scanning
+    Report {
+        /// JSON report written by --format json
+        report: String,
+    },
+
+    /// Count the files in a directory by extension and show which a scan would examine, without scanning
+    ListExtensions {
+        /// Directory to list
+        #[arg(short, long)]
+        path: String,
+    },
+
+    /// Run every detection rule against one file, whatever its extension, and show which matched;
+    /// exits with 1 if none did
+    PatternTest {
+        /// File, class or jar to test
+        file: String,
+    },
+
+    /// Fetch a rules file of detection patterns, check it, and save it to
+    /// ~/.config/rustylog4jguard/patterns.json; later scans add its rules, replacing the built-in
+    /// rules with the same ids
+    UpdatePatterns {
+        /// Where to fetch the patterns from
+        #[arg(long, default_value = patterns_update::DEFAULT_URL)]
+        url: String,
+
+        /// Only report whether the fetched patterns differ from the saved ones, without saving them
+        #[arg(long)]
+        check: bool,
+    },
+
+    /// Serve scans over HTTP as JSON-RPC 2.0 requests to POST /scan, or to local agents over a
+    /// Unix domain socket with --listen-uds
+    Server {
+        /// Address to listen on
+        #[arg(long, default_value = "127.0.0.1:8080")]
+        listen: String,
+
+        /// Directory HTTP clients may scan inside (can be used multiple times); requests for
+        /// any other path are refused
+        #[arg(long = "allow-root", value_name = "DIR", required_unless_present = "listen_uds")]
+        allow_roots: Vec<PathBuf>,
+
+        /// Instead of HTTP, answer newline-delimited JSON requests for single files, {"path": ...} or
+        /// {"inline_b64": ..., "name": ...}, on this Unix domain socket (named pipe on Windows)
+        #[arg(long, value_name = "PATH", conflicts_with = "listen")]
+        listen_uds: Option<String>,
+
+        /// Maximum --listen-uds requests scanned or queued at once, across all clients
+        /// (default: twice the scan threads of --thread-mode)
+        #[arg(long, value_name = "N", requires = "listen_uds")]
+        max_in_flight: Option<usize>,
+    },
+}
+
+#[derive(Subcommand)]
+enum SchemaCommand {
+    /// Print the JSON Schema for the JSON report
+    Print,
+
+    /// Check a JSON report against the schema, listing every violation
+    Validate {
+        /// JSON report to validate
+        report: String,
+    },
+}
+
+#[derive(Subcommand)]
+enum RulesCommand {
+    /// List every detection rule in effect, including rules files and disabled rules
+    List,
+
+    /// Show where a pattern or rules file matches a file or the class entries of a jar
+    Test {
+        /// Regex to test
+        #[arg(long, required_unless_present = "rules")]
+        pattern: Option<String>,
+
+        /// File or jar to test against
+        file: String,
+    },
+}
+
+/// `println!` panics once stdout's reader has gone, as in `rules list | head`.
+/// Exit without the panic message and backtrace instead, as shell tools do,
+/// and leave every other panic to the default hook.
+fn exit_quietly_on_broken_pipe() {
+    let default_hook = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        let payload = info.payload();
+        let message = payload.downcast_ref::<String>().map(String::as_str).or_else(|| payload.downcast_ref::<&str>().copied());
+        if message.is_some_and(|message| message.contains("Broken pipe")) {
+            exit_manifest::exit(0, "Output closed by its reader");
+        }
+        default_hook(info);
+    }));
 }
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let matches = Cli::command().get_matches();
+    let cli = Cli::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+    let output_level = if cli.silent {
+        OutputLevel::Silent
+    } else if cli.quiet {
+        OutputLevel::Quiet
+    } else {
+        OutputLevel::Normal
+    };
+
+    exit_manifest::init(cli.exit_code_manifest.clone(), cli.path.as_deref().unwrap_or_default());
+    // process::exit skips destructors, so this only writes the manifest for a panic
+    let _crash_guard = scopeguard::guard_on_unwind((), |_| exit_manifest::write(exit_manifest::CRASH_EXIT_CODE, "Scanner crashed"));
+    exit_quietly_on_broken_pipe();
+
+    let thread_mode = match (cli.thread_mode.as_deref(), cli.threads) {
+        (None | Some("fixed"), Some(threads)) => ThreadMode::Fixed(threads),
+        (Some("fixed"), None) => Cli::command().error(ErrorKind::MissingRequiredArgument, "--thread-mode fixed uses the --threads count; give --threads").exit(),
+        (Some(mode), Some(_)) => Cli::command().error(ErrorKind::ArgumentConflict, format!("--threads selects --thread-mode fixed, not {}", mode)).exit(),
+        (Some("io"), None) => ThreadMode::IoOptimized,
+        _ => ThreadMode::Auto,
+    };
+    let config = Config {
+        path: cli.path.unwrap_or_default(),
+        format: cli.format,
+        threads: cli.threads,
+        exclude: cli.exclude,
+        custom_patterns: cli.custom_patterns,
+        output_level,
+        output: cli.output,
+        since: cli.since,
+        scan_pom_xml: cli.scan_pom_xml,
+        prioritize: cli.prioritize,
+        max_findings: cli.max_findings,
+        fail_fast: cli.fail_fast,
+        fail_on_permission_denied: cli.fail_on_permission_denied,
+        fail_on_unscannable: cli.fail_on_unscannable,
+        maven_artifact: cli.maven_artifact,
+        maven_repo_url: cli.maven_repo_url,
+        maven_cache_dir: cli.maven_cache_dir,
+        threads_max_memory: cli.threads_max_memory,
+        syslog: cli.syslog,
+        rules: cli.rules,
+        disable_rules: cli.disable_rules,
+        min_confidence: cli.min_confidence,
+        group_duplicates: cli.group_duplicates,
+        no_summary: cli.no_summary,
+        sign_key: cli.sign_key,
+        encoding: cli.encoding,
+        io_limit: cli.io_limit,
+        nice: cli.nice,
+        timeout: cli.timeout,
+        discover_java: cli.discover_java,
+        io_buffer_size: cli.io_buffer_size,
+        ssh: cli.ssh,
+        ssh_key: cli.ssh_key,
+        ssh_connections: cli.ssh_connections,
+        hashes: cli.hashes,
+        summary_by_dir: cli.summary_by_dir,
+        include_archives: cli.include_archives,
+        email_to: cli.email_to,
+        email_from: cli.email_from,
+        smtp_server: cli.smtp_server,
+        smtp_tls: cli.smtp_tls,
+        email_min_severity: cli.email_min_severity,
+        email_always: cli.email_always,
+        email_required: cli.email_required,
+        exit_code_manifest: cli.exit_code_manifest,
+        profile: cli.profile,
+        no_table: cli.no_table,
+        skip_hidden: cli.skip_hidden,
+        audit_log: cli.audit_log,
+        ansi_hyperlinks: cli.ansi_hyperlinks,
+        registry_image: cli.registry_image,
+        registry_plain_http: cli.registry_plain_http,
+        include_byte_histogram: cli.include_byte_histogram,
+        s3: cli.s3,
+        gcs: cli.gcs,
+        bucket_connections: cli.bucket_connections,
+        manifest_scan: cli.manifest_scan,
+        stdin: cli.stdin,
+        stdin_filename: cli.filename,
+        reproducible: cli.reproducible,
+        no_vfs_filter: cli.no_vfs_filter,
+        output_rotate: cli.output_rotate,
+        stats: cli.stats,
+        content_type_detection: cli.content_type_detection,
+        no_loop_detection: cli.no_loop_detection,
+        columns: cli.columns,
+        scan_source_jars: cli.scan_source_jars,
+        packs: cli.packs,
+        list_skipped: cli.list_skipped,
+        queue_capacity: cli.queue_capacity,
+        report_filter: cli.report_filter,
+        thread_mode,
+        output_compress: cli.output_compress,
+        output_compress_level: cli.output_compress_level,
+        max_archive_depth: cli.max_archive_depth,
+        no_summary_histogram: cli.no_summary_histogram,
+        annotations: cli.annotations,
+        suspicious_ratio: cli.suspicious_ratio,
+        max_duration: cli.max_duration,
+        markov_parallel: cli.markov_parallel,
+        io_budget: Default::default(),
+    };
+
+    // Options given on the command line override the config file
+    let explicit: Vec<&str> = matches.ids()
+        .filter(|id| matches.value_source(id.as_str()) == Some(ValueSource::CommandLine))
+        .flat_map(|id| match id.as_str() {
+            "quiet" | "silent" => vec!["output_level"],
+            // --threads also selects the fixed thread mode
+            "threads" => vec!["threads", "thread_mode"],
+            // --summary-histogram undoes a config file's no_summary_histogram
+            "summary_histogram" => vec!["no_summary_histogram"],
+            "filename" => vec!["stdin_filename"],
+            id => vec![id],
+        })
+        .collect();
+    // Precedence: profile, then config file, then command line
+    let profiled = match cli.profile {
+        Some(profile) => config::apply_profile(config, profile, &explicit),
+        None => Ok(config),
+    };
+    let loaded = profiled.and_then(|config| match &cli.config {
+        Some(path) => config::apply_file(config, Path::new(path), &explicit),
+        None => Ok(config),
+    });
+
+    // RUST_LOG still selects what is logged; quiet and silent cap it
     env_logger::init();
-    let cli = Cli::parse();
-    let config = Config::new(
-        cli.path,
-        cli.format,
-        cli.threads,
-        cli.exclude,
-        cli.custom_patterns,
-        cli.quiet,
-        cli.output,
-    );
-
-    if !config.quiet {
-        info!("Starting CVE-2021-44228 scanner");
+    let output_level = loaded.as_ref().map(|config| config.output_level).unwrap_or(output_level);
+    match output_level {
+        OutputLevel::Normal => {}
+        OutputLevel::Quiet => log::set_max_level(log::max_level().min(LevelFilter::Warn)),
+        OutputLevel::Silent => log::set_max_level(LevelFilter::Off),
+    }
+
+    let mut config = match loaded {
+        Ok(config) => config,
+        Err(e) => fail(1, &e),
+    };
+    // A config file may name the manifest too
+    exit_manifest::init(config.exit_code_manifest.clone(), &config.path);
+    if cli.print_config {
+        match config::to_toml(&config) {
+            Ok(toml) => print!("{}", toml),
+            Err(e) => fail(1, &e),
+        }
+        exit_manifest::write(0, "Configuration printed");
+        return Ok(());
+    }
+    if config.path.is_empty() && config.maven_artifact.is_none() && !config.discover_java && config.ssh.is_none() && config.registry_image.is_none() && config.s3.is_none() && config.gcs.is_none() && !config.stdin && cli.command.is_none() {
+        fail(1, "No path to scan: give --path, --maven-artifact, --discover-java, --ssh or --registry-image, or set path in the config file");
+    }
+    if let Err(e) = config.check_hashes().and_then(|_| config.check_output_rotate()).and_then(|_| config.check_output_compress()).and_then(|_| config.check_report_filter()).and_then(|_| config.check_suspicious_ratio()).and_then(|_| config.check_stdin_filename()) {
+        fail(1, &e);
+    }
+    // The compressed report is named for what it holds
+    if config.output_compress {
+        config.output = config.output.map(|output| if output.ends_with(".gz") { output } else { format!("{}.gz", output) });
+    }
+
+    match config::validate_custom_patterns(&config.custom_patterns) {
+        Ok(patterns) => {
+            for pattern in &patterns {
+                if let Some(id) = rules::redundant_builtin_rule(pattern) {
+                    warn!("Custom pattern {} is already covered by built-in rule {}", pattern, id);
+                }
+            }
+        }
+        Err(invalid) => {
+            for (pattern, e) in &invalid {
+                error!("Invalid custom pattern {:?}:\n{}", pattern, e);
+            }
+            exit_manifest::exit(1, &format!("{} invalid custom patterns", invalid.len()));
+        }
+    }
+
+    if let Some(command) = cli.command {
+        match command {
+            Command::SelfTest => {
+                if !selftest::run() {
+                    exit_manifest::exit(1, "Self-test failed");
+                }
+            }
+            Command::Rules { command } => {
+                let result = match command {
+                    RulesCommand::List => rules::list(&config),
+                    RulesCommand::Test { pattern, file } => rules::test(pattern.as_deref(), &config, Path::new(&file)),
+                };
+                if let Err(e) = result {
+                    fail(1, &e.to_string());
+                }
+            }
+            Command::Schema { command } => match command {
+                SchemaCommand::Print => schema::print(),
+                SchemaCommand::Validate { report } => match schema::validate(Path::new(&report)) {
+                    Ok(true) => {}
+                    Ok(false) => exit_manifest::exit(1, "Report does not match the schema"),
+                    Err(e) => fail(1, &e.to_string()),
+                },
+            },
+            Command::Verify { previous: Some(previous), .. } => {
+                if let Err(e) = hash_verify::run(Path::new(&previous), &config) {
+                    fail(1, &e.to_string());
+                }
+            }
+            Command::Verify { report, sig, pub_key, previous: None } => {
+                // clap requires all three when --report is absent
+                let (report, sig, pub_key) = (report.unwrap_or_default(), sig.unwrap_or_default(), pub_key.unwrap_or_default());
+                match signing::verify(Path::new(&report), Path::new(&sig), Path::new(&pub_key)) {
+                    Ok(true) => {}
+                    Ok(false) => exit_manifest::exit(1, "Report signature is invalid"),
+                    Err(e) => fail(1, &e.to_string()),
+                }
+            }
+            Command::VerifyFixes { report } => match fix_verify::run(Path::new(&report), &config) {
+                Ok(true) => {}
+                Ok(false) => exit_manifest::exit(1, "Findings are still vulnerable"),
+                Err(e) => fail(1, &e.to_string()),
+            },
+            Command::Report { report } => {
+                if let Err(e) = stored_report::run(Path::new(&report), &config) {
+                    fail(1, &e.to_string());
+                }
+            }
+            Command::ListExtensions { path } => {
+                if let Err(e) = list_extensions(Path::new(&path), &config) {
+                    fail(1, &e.to_string());
+                }
+            }
+            Command::PatternTest { file } => match rules::pattern_test(&config, Path::new(&file)) {
+                Ok(true) => {}
+                Ok(false) => exit_manifest::exit(1, "No detection rule matched"),
+                Err(e) => fail(1, &e.to_string()),
+            },
+            Command::UpdatePatterns { url, check } => {
+                if let Err(e) = patterns_update::run(&url, check, &config) {
+                    fail(1, &e.to_string());
+                }
+            }
+            Command::Server { listen_uds: Some(path), max_in_flight, .. } => {
+                if let Err(e) = socket_server::run(&config, &path, max_in_flight) {
+                    fail(1, &format!("Server error: {}", e));
+                }
+            }
+            Command::Server { listen, allow_roots, .. } => {
+                if let Err(e) = server::run(&config, &listen, &allow_roots) {
+                    fail(1, &format!("Server error: {}", e));
+                }
+            }
+        }
+        exit_manifest::write(0, "Command completed");
+        return Ok(());
+    }
+
+    if cli.dry_run || cli.dry_run_summary {
+        if config.maven_artifact.is_some() || config.ssh.is_some() || config.registry_image.is_some() || config.s3.is_some() || config.gcs.is_some() || config.stdin {
+            fail(1, "A dry run walks local directories; it cannot be used with --maven-artifact, --ssh, --registry-image, --s3, --gcs or --stdin");
+        }
+        let roots = if config.discover_java { discovery::discovered_scan_roots() } else { vec![PathBuf::from(&config.path)] };
+        if let Err(e) = scanner::dry_run(&config, &roots, cli.dry_run_summary) {
+            fail(1, &e.to_string());
+        }
+        exit_manifest::write(0, "Dry run completed");
+        return Ok(());
+    }
+
+    if let Some(iterations) = cli.benchmark {
+        if config.maven_artifact.is_some() || config.ssh.is_some() || config.registry_image.is_some() || config.s3.is_some() || config.gcs.is_some() || config.stdin {
+            fail(1, "A benchmark walks local directories; it cannot be used with --maven-artifact, --ssh, --registry-image, --s3, --gcs or --stdin");
+        }
+        let roots = if config.discover_java { discovery::discovered_scan_roots() } else { vec![PathBuf::from(&config.path)] };
+        if let Err(e) = benchmark::run(&config, &roots, iterations) {
+            fail(1, &e.to_string());
+        }
+        exit_manifest::write(0, "Benchmark completed");
+        return Ok(());
     }
+
+    info!("Starting CVE-2021-44228 scanner");
     
-    match scan_directory(&config) {
-        Ok(results) => {
-            reporter::report_results(&results, &config)?;
-            if !config.quiet {
-                info!("Scanning complete");
+    let signing_key = match config.sign_key.as_deref().map(|path| signing::load_signing_key(Path::new(path))) {
+        Some(Ok(key)) => Some(key),
+        Some(Err(e)) => fail(1, &e.to_string()),
+        None => None,
+    };
+
+    let mailer = match email::Mailer::from_config(&config) {
+        Ok(mailer) => mailer,
+        Err(e) => fail(1, &e),
+    };
+
+    let annotations = match config.annotations.as_deref().map(|path| Annotations::load(Path::new(path), &config)) {
+        Some(Ok(annotations)) => Some(annotations),
+        Some(Err(e)) => fail(1, &e),
+        None => None,
+    };
+
+    let target = if config.discover_java {
+        "discovered Java installations"
+    } else if config.stdin {
+        config.stdin_filename.as_deref().unwrap_or("standard input")
+    } else {
+        &config.path
+    };
+    let target = config.maven_artifact.as_deref().or(config.ssh.as_deref()).or(config.registry_image.as_deref()).or(config.s3.as_deref()).or(config.gcs.as_deref()).unwrap_or(target);
+    exit_manifest::set_scan_path(target);
+    if let Some(path) = &config.audit_log {
+        if let Err(e) = audit_log::init(path) {
+            fail(1, &e);
+        }
+        let rule_set = match rules::RuleSet::from_config(&config) {
+            Ok(rule_set) => rule_set,
+            Err(e) => fail(1, &e.to_string()),
+        };
+        audit_log::event("config-resolved", serde_json::json!({
+            "target": target,
+            "rules_digest": rule_set.digest(),
+            "rules": rule_set.all().iter().filter(|rule| rule.enabled).map(|rule| &rule.id).collect::<Vec<_>>(),
+            "config": &config,
+        }));
+    }
+    let mut event_log = if config.syslog { EventLog::open() } else { None };
+    if let Some(event_log) = event_log.as_mut() {
+        event_log.scan_start(target);
+    }
+
+    let scan = match (&config.maven_artifact, &config.ssh, &config.registry_image, &config.s3, &config.gcs) {
+        (Some(coordinates), _, _, _, _) => maven::scan_artifact(&config, coordinates),
+        (None, Some(target), _, _, _) => remote::scan_ssh(&config, target),
+        (None, None, Some(image), _, _) => registry::scan_image(&config, image),
+        (None, None, None, Some(url), _) => object_storage::scan_s3(&config, url),
+        (None, None, None, None, Some(url)) => object_storage::scan_gcs(&config, url),
+        (None, None, None, None, None) if config.discover_java => discovery::scan_discovered(&config),
+        (None, None, None, None, None) if config.stdin => scanner::scan_stdin(&config),
+        (None, None, None, None, None) => scan_directory(&config),
+    };
+
+    match scan {
+        Ok((mut results, mut summary)) => {
+            summary.signing_key_fingerprint = signing_key.as_ref().map(|key| signing::fingerprint(&key.verifying_key()));
+            summary.audit_log = audit_log::reference();
+            sort_results(&mut results, &mut summary);
+            retain_min_confidence(&mut results, config.min_confidence);
+            if let Some(annotations) = &annotations {
+                summary.stale_annotations = annotations.apply(&mut results);
+            }
+            // Leave out what differs between runs over the same files: the
+            // audit log's scan id, and the thread count, which changes how
+            // fast a scan runs but never what it finds
+            let config = if config.reproducible {
+                if let Some(audit_log) = summary.audit_log.as_mut() {
+                    audit_log.scan_id = Uuid::nil().to_string();
+                }
+                Config { threads: None, thread_mode: ThreadMode::Auto, ..config.clone() }
+            } else {
+                config.clone()
+            };
+            let vulnerable = results.iter().filter(|r| r.vulnerable).count();
+            exit_manifest::set_vulnerable_count(vulnerable);
+            audit_log::set_scan(&summary, vulnerable);
+            if let Some(event_log) = event_log.as_mut() {
+                for result in results.iter().filter(|r| r.vulnerable) {
+                    event_log.finding(result);
+                }
+                event_log.scan_end(&results, &summary);
+            }
+            if let Err(e) = reporter::report_results(&results, &summary, &config) {
+                fail(1, &format!("Error writing report: {}", e));
             }
+            if let Some(key) = &signing_key {
+                for part in reporter::output_parts(&config) {
+                    match signing::sign_report(&part, key) {
+                        Ok(sig_path) => info!("Report signature written to {}", sig_path.display()),
+                        Err(e) => fail(1, &e.to_string()),
+                    }
+                }
+            }
+            let mut email_error = None;
+            if let Some(mailer) = mailer.as_ref().filter(|mailer| mailer.should_send(&results)) {
+                if let Err(e) = mailer.send(&results, &summary, &config, target) {
+                    if config.email_required {
+                        error!("{}", e);
+                    } else {
+                        warn!("{}", e);
+                    }
+                    email_error = Some(e);
+                }
+            }
+            if summary.timed_out {
+                fail(3, &format!("Scan timed out after {} seconds; results are incomplete", config.timeout.unwrap_or_default()));
+            }
+            if summary.max_duration_reached {
+                fail(4, &format!("Scan reached --max-duration with {} files not reached; results are incomplete", summary.files_not_reached()));
+            }
+            if config.fail_on_permission_denied && !summary.permission_denied.is_empty() {
+                fail(1, &format!("Permission denied for {} files", summary.permission_denied.len()));
+            }
+            let unscannable = results.iter().filter(|r| r.unscannable()).count();
+            if config.fail_on_unscannable && unscannable > 0 {
+                fail(1, &format!("{} files could not be scanned", unscannable));
+            }
+            if let Some(e) = email_error.filter(|_| config.email_required) {
+                exit_manifest::exit(1, &e);
+            }
+            info!("Scanning complete");
+            exit_manifest::write(0, &format!("Scan completed; {} vulnerable files found", vulnerable));
             Ok(())
         }
-        Err(e) => {
-            error!("Error during scanning: {}", e);
-            process::exit(1);
-        }
+        Err(e) => fail(1, &format!("Error during scanning: {}", e)),
     }
 }
+
+/// Log `message` as an error and exit with `code`, explaining both in the
+/// exit code manifest
+fn fail(code: i32, message: &str) -> ! {
+    error!("{}", message);
+    exit_manifest::exit(code, message)
+}
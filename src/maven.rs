@@ -0,0 +1,397 @@
+use crate::config::Config;
+use crate::rule_stats;
+use crate::rules::RuleSet;
+use crate::scan_errors;
+use crate::scanner::{scan_bytes, take_deepest_archive, ScanResult, ScanSummary};
+use log::{debug, info, warn};
+use sha1::Sha1;
+use sha2::{Digest, Sha256};
+use std::env;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+const MAVEN_CENTRAL_SEARCH_URL: &str = "https://search.maven.org/solrsearch/select";
+const MAVEN_CENTRAL_DOWNLOAD_URL: &str = "https://search.maven.org/remotecontent";
+
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct MavenCoordinates {
+    pub group_id: String,
+    pub artifact_id: String,
+    pub version: String,
+}
+
+impl MavenCoordinates {
+    /// Parse `groupId:artifactId:version`
+    pub fn parse(coordinates: &str) -> Result<Self, String> {
+        match coordinates.split(':').collect::<Vec<_>>().as_slice() {
+            [group_id, artifact_id, version]
+                if !group_id.is_empty() && !artifact_id.is_empty() && !version.is_empty() =>
+            {
+                Ok(MavenCoordinates {
+                    group_id: group_id.to_string(),
+                    artifact_id: artifact_id.to_string(),
+                    version: version.to_string(),
+                })
+            }
+            _ => Err(format!("expected groupId:artifactId:version, got '{}'", coordinates)),
+        }
+    }
+
+    /// Read the `groupId`, `artifactId` and `version` keys of a jar's
+    /// `META-INF/maven/<groupId>/<artifactId>/pom.properties`
+    pub fn from_pom_properties(contents: &str) -> Option<Self> {
+        let mut group_id = None;
+        let mut artifact_id = None;
+        let mut version = None;
+        for line in contents.lines().map(str::trim) {
+            if line.starts_with('#') || line.starts_with('!') {
+                continue;
+            }
+            let (key, value) = match line.split_once(['=', ':']) {
+                Some((key, value)) => (key.trim(), value.trim().to_string()),
+                None => continue,
+            };
+            match key {
+                "groupId" => group_id = Some(value),
+                "artifactId" => artifact_id = Some(value),
+                "version" => version = Some(value),
+                _ => {}
+            }
+        }
+
+        Some(MavenCoordinates {
+            group_id: group_id.filter(|v| !v.is_empty())?,
+            artifact_id: artifact_id.filter(|v| !v.is_empty())?,
+            version: version.filter(|v| !v.is_empty())?,
+        })
+    }
+
+    /// Path of the artifact jar in the standard Maven repository layout
+    fn jar_path(&self) -> String {
+        format!(
+            "{}/{}/{}/{}-{}.jar",
+            self.group_id.replace('.', "/"),
+            self.artifact_id,
+            self.version,
+            self.artifact_id,
+            self.version
+        )
+    }
+}
+
+impl std::fmt::Display for MavenCoordinates {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}:{}:{}", self.group_id, self.artifact_id, self.version)
+    }
+}
+
+/// Download (or load from cache) the jar for `--maven-artifact` and scan it in memory
+pub fn scan_artifact(config: &Config, coordinates: &str) -> Result<(Vec<ScanResult>, ScanSummary), Box<dyn std::error::Error>> {
+    let coordinates = MavenCoordinates::parse(coordinates)?;
+    info!("Scanning Maven artifact: {}", coordinates);
+
+    let rules = RuleSet::from_config(config)?;
+    let data = fetch_artifact(&coordinates, config)?;
+    let results = scan_bytes(&coordinates.to_string(), &data, config, &rules)
+        .into_iter()
+        .collect();
+    let summary = ScanSummary {
+        files_scanned: 1,
+        error_digest: scan_errors::take_digest(),
+        rule_stats: rule_stats::take(&rules, config),
+        archive_depth_reached: take_deepest_archive(),
+        rule_packs: rules.packs().to_vec(),
+        ..Default::default()
+    };
+
+    Ok((results, summary))
+}
+
+fn fetch_artifact(coordinates: &MavenCoordinates, config: &Config) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    let cache_path = cache_dir(config)?.join(coordinates.jar_path());
+    if let Some(data) = cached_artifact(&cache_path) {
+        debug!("Using cached artifact: {:?}", cache_path);
+        return Ok(data);
+    }
+
+    let client = reqwest::blocking::Client::builder()
+        .user_agent(concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION")))
+        .build()?;
+
+    let url = match &config.maven_repo_url {
+        Some(repo_url) => format!("{}/{}", repo_url.trim_end_matches('/'), coordinates.jar_path()),
+        None => resolve_maven_central(&client, coordinates)?,
+    };
+
+    debug!("Downloading {}", url);
+    let data = client.get(&url).send()?.error_for_status()?.bytes()?.to_vec();
+    let (algorithm, expected) = fetch_checksum(&client, &url)?;
+    let actual = algorithm.digest(&data);
+    if actual != expected {
+        return Err(format!("{} does not match its .{} checksum: expected {}, got {}", url, algorithm.extension(), expected, actual).into());
+    }
+
+    // The checksum goes first, so a cached jar always has one beside it
+    write_atomically(&checksum_path(&cache_path, algorithm), expected.as_bytes())?;
+    write_atomically(&cache_path, &data)?;
+
+    Ok(data)
+}
+
+/// Checksum files a Maven repository publishes next to an artifact, tried in this order
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ChecksumAlgorithm {
+    Sha256,
+    Sha1,
+}
+
+impl ChecksumAlgorithm {
+    const ALL: [ChecksumAlgorithm; 2] = [ChecksumAlgorithm::Sha256, ChecksumAlgorithm::Sha1];
+
+    fn extension(self) -> &'static str {
+        match self {
+            ChecksumAlgorithm::Sha256 => "sha256",
+            ChecksumAlgorithm::Sha1 => "sha1",
+        }
+    }
+
+    /// Lowercase hex digest of `data`
+    fn digest(self, data: &[u8]) -> String {
+        let digest = match self {
+            ChecksumAlgorithm::Sha256 => Sha256::digest(data).to_vec(),
+            ChecksumAlgorithm::Sha1 => Sha1::digest(data).to_vec(),
+        };
+        digest.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    /// The digest in a checksum file, which may be followed by the file name
+    fn parse(self, contents: &str) -> Option<String> {
+        let digest = contents.split_whitespace().next()?.to_ascii_lowercase();
+        let length = match self {
+            ChecksumAlgorithm::Sha256 => 64,
+            ChecksumAlgorithm::Sha1 => 40,
+        };
+        Some(digest).filter(|digest| digest.len() == length && digest.bytes().all(|b| b.is_ascii_hexdigit()))
+    }
+}
+
+/// The first checksum the repository publishes for the artifact at `url`
+fn fetch_checksum(client: &reqwest::blocking::Client, url: &str) -> Result<(ChecksumAlgorithm, String), Box<dyn std::error::Error>> {
+    for algorithm in ChecksumAlgorithm::ALL {
+        let checksum_url = format!("{}.{}", url, algorithm.extension());
+        let response = client.get(&checksum_url).send()?;
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            continue;
+        }
+        let contents = response.error_for_status()?.text()?;
+        let digest = algorithm.parse(&contents).ok_or_else(|| format!("{} is not a {} checksum", checksum_url, algorithm.extension()))?;
+        return Ok((algorithm, digest));
+    }
+    Err(format!("no .sha256 or .sha1 checksum is published for {}", url).into())
+}
+
+/// The cached jar at `path`, if a checksum saved with it still matches;
+/// a jar changed since it was cached is downloaded again
+fn cached_artifact(path: &Path) -> Option<Vec<u8>> {
+    let data = fs::read(path).ok()?;
+    let (algorithm, expected) = ChecksumAlgorithm::ALL.into_iter().find_map(|algorithm| {
+        let contents = fs::read_to_string(checksum_path(path, algorithm)).ok()?;
+        Some((algorithm, algorithm.parse(&contents)?))
+    })?;
+    if algorithm.digest(&data) != expected {
+        warn!("Cached artifact {:?} does not match its .{} checksum; downloading it again", path, algorithm.extension());
+        return None;
+    }
+    Some(data)
+}
+
+fn checksum_path(path: &Path, algorithm: ChecksumAlgorithm) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".");
+    name.push(algorithm.extension());
+    PathBuf::from(name)
+}
+
+/// Write `data` to a temporary file beside `path` and rename it into place,
+/// so a scan running at the same time never reads a partly written file
+fn write_atomically(path: &Path, data: &[u8]) -> io::Result<()> {
+    let parent = path.parent().unwrap_or(Path::new("."));
+    fs::create_dir_all(parent)?;
+    let mut file = tempfile::NamedTempFile::new_in(parent)?;
+    file.write_all(data)?;
+    file.persist(path).map_err(|e| e.error)?;
+    Ok(())
+}
+
+/// Confirm the artifact exists via the Maven Central search API and return its download URL
+fn resolve_maven_central(client: &reqwest::blocking::Client, coordinates: &MavenCoordinates) -> Result<String, Box<dyn std::error::Error>> {
+    let query = format!(
+        "g:\"{}\" AND a:\"{}\" AND v:\"{}\"",
+        coordinates.group_id, coordinates.artifact_id, coordinates.version
+    );
+    let response: serde_json::Value = client
+        .get(MAVEN_CENTRAL_SEARCH_URL)
+        .query(&[("q", query.as_str()), ("core", "gav"), ("rows", "1"), ("wt", "json")])
+        .send()?
+        .error_for_status()?
+        .json()?;
+
+    let found = response["response"]["numFound"].as_u64().unwrap_or(0);
+    if found == 0 {
+        return Err(format!("artifact not found on Maven Central: {}", coordinates).into());
+    }
+
+    Ok(format!("{}?filepath={}", MAVEN_CENTRAL_DOWNLOAD_URL, coordinates.jar_path()))
+}
+
+/// `--maven-cache-dir`, or `~/.cache/rustylog4jguard/maven` (under
+/// `$XDG_CACHE_HOME` when it is set). It belongs to the user running the
+/// scan, so no one else can swap the cached jars.
+fn cache_dir(config: &Config) -> Result<PathBuf, String> {
+    if let Some(dir) = &config.maven_cache_dir {
+        return Ok(PathBuf::from(dir));
+    }
+    let cache_home = match env::var_os("XDG_CACHE_HOME").filter(|dir| !dir.is_empty()) {
+        Some(dir) => PathBuf::from(dir),
+        None => env::var_os("HOME").or_else(|| env::var_os("USERPROFILE"))
+            .map(|home| PathBuf::from(home).join(".cache"))
+            .ok_or("no home directory to cache Maven artifacts in; give --maven-cache-dir")?,
+    };
+    Ok(cache_home.join("rustylog4jguard").join("maven"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader};
+    use std::net::TcpListener;
+
+    const JAR: &[u8] = b"PK\x03\x04 log4j-core";
+
+    fn coordinates() -> MavenCoordinates {
+        MavenCoordinates::parse("org.apache.logging.log4j:log4j-core:2.14.1").unwrap()
+    }
+
+    /// Serve `files` by path over HTTP, one request per connection, and
+    /// return the base URL
+    fn serve(files: Vec<(String, Vec<u8>)>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut request_line = String::new();
+                let mut reader = BufReader::new(&stream);
+                reader.read_line(&mut request_line).unwrap();
+                let mut line = String::new();
+                while reader.read_line(&mut line).is_ok_and(|n| n > 2) {
+                    line.clear();
+                }
+                let path = request_line.split_whitespace().nth(1).unwrap_or_default();
+                let response = match files.iter().find(|(name, _)| path == format!("/{}", name)) {
+                    Some((_, body)) => [format!("HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n", body.len()).into_bytes(), body.clone()].concat(),
+                    None => b"HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_vec(),
+                };
+                let _ = stream.write_all(&response);
+            }
+        });
+        url
+    }
+
+    fn config(repo_url: String, cache_dir: &Path) -> Config {
+        Config {
+            maven_repo_url: Some(repo_url),
+            maven_cache_dir: Some(cache_dir.to_string_lossy().to_string()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn pom_properties_keys_are_read_in_any_order() {
+        let contents = "#Generated by Maven\n! a comment\nversion = 2.14.1\nartifactId:log4j-core\ngroupId=org.apache.logging.log4j\n";
+        assert_eq!(MavenCoordinates::from_pom_properties(contents), Some(coordinates()));
+    }
+
+    #[test]
+    fn pom_properties_without_a_version_has_no_coordinates() {
+        assert_eq!(MavenCoordinates::from_pom_properties("groupId=org.apache.logging.log4j\nartifactId=log4j-core\n"), None);
+        assert_eq!(MavenCoordinates::from_pom_properties("groupId=org.apache.logging.log4j\nartifactId=log4j-core\nversion=\n"), None);
+    }
+
+    #[test]
+    fn verified_download_is_cached_with_its_checksum() {
+        let jar_path = coordinates().jar_path();
+        let url = serve(vec![
+            (jar_path.clone(), JAR.to_vec()),
+            (format!("{}.sha256", jar_path), format!("{}  log4j-core-2.14.1.jar\n", ChecksumAlgorithm::Sha256.digest(JAR)).into_bytes()),
+        ]);
+        let cache = tempfile::tempdir().unwrap();
+
+        assert_eq!(fetch_artifact(&coordinates(), &config(url, cache.path())).unwrap(), JAR);
+        let cached = cache.path().join(&jar_path);
+        assert_eq!(fs::read(&cached).unwrap(), JAR);
+        assert_eq!(fs::read_to_string(checksum_path(&cached, ChecksumAlgorithm::Sha256)).unwrap(), ChecksumAlgorithm::Sha256.digest(JAR));
+    }
+
+    #[test]
+    fn sha1_is_used_when_there_is_no_sha256() {
+        let jar_path = coordinates().jar_path();
+        let url = serve(vec![
+            (jar_path.clone(), JAR.to_vec()),
+            (format!("{}.sha1", jar_path), ChecksumAlgorithm::Sha1.digest(JAR).into_bytes()),
+        ]);
+        let cache = tempfile::tempdir().unwrap();
+
+        assert_eq!(fetch_artifact(&coordinates(), &config(url, cache.path())).unwrap(), JAR);
+        assert!(checksum_path(&cache.path().join(&jar_path), ChecksumAlgorithm::Sha1).exists());
+    }
+
+    #[test]
+    fn checksum_mismatch_is_an_error_and_nothing_is_cached() {
+        let jar_path = coordinates().jar_path();
+        let url = serve(vec![
+            (jar_path.clone(), JAR.to_vec()),
+            (format!("{}.sha1", jar_path), ChecksumAlgorithm::Sha1.digest(b"another jar").into_bytes()),
+        ]);
+        let cache = tempfile::tempdir().unwrap();
+
+        let error = fetch_artifact(&coordinates(), &config(url, cache.path())).unwrap_err();
+        assert!(error.to_string().contains("does not match its .sha1 checksum"), "{}", error);
+        assert!(!cache.path().join(&jar_path).exists());
+    }
+
+    #[test]
+    fn missing_checksum_is_an_error() {
+        let url = serve(vec![(coordinates().jar_path(), JAR.to_vec())]);
+        let cache = tempfile::tempdir().unwrap();
+
+        let error = fetch_artifact(&coordinates(), &config(url, cache.path())).unwrap_err();
+        assert!(error.to_string().starts_with("no .sha256 or .sha1 checksum"), "{}", error);
+    }
+
+    #[test]
+    fn changed_cached_jar_is_downloaded_again() {
+        let jar_path = coordinates().jar_path();
+        let url = serve(vec![
+            (jar_path.clone(), JAR.to_vec()),
+            (format!("{}.sha256", jar_path), ChecksumAlgorithm::Sha256.digest(JAR).into_bytes()),
+        ]);
+        let cache = tempfile::tempdir().unwrap();
+        let config = config(url, cache.path());
+        fetch_artifact(&coordinates(), &config).unwrap();
+
+        fs::write(cache.path().join(&jar_path), b"PK\x03\x04 tampered").unwrap();
+        assert_eq!(cached_artifact(&cache.path().join(&jar_path)), None);
+        assert_eq!(fetch_artifact(&coordinates(), &config).unwrap(), JAR);
+        assert_eq!(fs::read(cache.path().join(&jar_path)).unwrap(), JAR);
+    }
+
+    #[test]
+    fn cached_jar_without_a_checksum_is_not_used() {
+        let cache = tempfile::tempdir().unwrap();
+        let jar = cache.path().join("log4j-core.jar");
+        fs::write(&jar, JAR).unwrap();
+        assert_eq!(cached_artifact(&jar), None);
+    }
+}
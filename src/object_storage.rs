@@ -0,0 +1,323 @@
+use crate::config::Config;
+use crate::package::is_scannable_member;
+use crate::rule_stats;
+use crate::rules::RuleSet;
+use crate::scan_errors::{self, ScanErrorKind};
+use crate::scanner::{scan_bytes, take_deepest_archive, ScanResult, ScanSummary};
+use crate::utils::format_rfc3339;
+use aws_sdk_s3::error::DisplayErrorContext;
+use glob::Pattern;
+use google_cloud_storage::client::{Client as GcsClient, ClientConfig as GcsClientConfig};
+use google_cloud_storage::http::objects::download::Range;
+use google_cloud_storage::http::objects::get::GetObjectRequest;
+use google_cloud_storage::http::objects::list::ListObjectsRequest;
+use log::{debug, info, warn};
+use std::env;
+use std::sync::Arc;
+use std::time::SystemTime;
+use tokio::sync::Semaphore;
+use tokio::task::JoinSet;
+
+/// Objects at least this large are downloaded in parallel ranged parts
+const RANGED_DOWNLOAD_THRESHOLD: u64 = 16 * 1024 * 1024;
+const PART_SIZE: u64 = 8 * 1024 * 1024;
+/// Points Google Cloud Storage clients at an emulator, as Google's own libraries do
+const GCS_EMULATOR_VAR: &str = "STORAGE_EMULATOR_HOST";
+
+/// Where a finding's object came from, besides its `s3://` or `gs://` path
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct StorageObject {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub etag: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub storage_class: Option<String>,
+    /// Google Cloud Storage generation of the object version scanned
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub generation: Option<i64>,
+}
+
+/// A `s3://bucket/prefix` or `gs://bucket/prefix` scan target
+struct BucketUrl {
+    scheme: &'static str,
+    bucket: String,
+    prefix: String,
+}
+
+impl BucketUrl {
+    fn parse(url: &str, scheme: &'static str) -> Result<Self, String> {
+        let rest = url.strip_prefix(scheme).and_then(|rest| rest.strip_prefix("://"));
+        match rest.map(|rest| rest.split_once('/').unwrap_or((rest, ""))) {
+            Some((bucket, prefix)) if !bucket.is_empty() => Ok(BucketUrl {
+                scheme,
+                bucket: bucket.to_string(),
+                prefix: prefix.to_string(),
+            }),
+            _ => Err(format!("expected {}://bucket/prefix, got '{}'", scheme, url)),
+        }
+    }
+
+    fn object_url(&self, key: &str) -> String {
+        format!("{}://{}/{}", self.scheme, self.bucket, key)
+    }
+}
+
+/// An object listed under the prefix
+struct Listed {
+    key: String,
+    size: u64,
+    last_modified: Option<String>,
+    metadata: StorageObject,
+}
+
+enum Bucket {
+    S3 { client: aws_sdk_s3::Client, bucket: String },
+    Gcs { client: Box<GcsClient>, bucket: String },
+}
+
+impl Bucket {
+    /// An S3 client from the AWS SDK's default provider chains: credentials
+    /// from the environment, profiles, SSO, web identity, ECS or EC2 instance
+    /// metadata, and the region likewise. S3-compatible stores reached through
+    /// `AWS_ENDPOINT_URL` get path-style requests, which they all support.
+    async fn s3(bucket: &str) -> Self {
+        let shared = aws_config::defaults(aws_config::BehaviorVersion::latest()).load().await;
+        let config = aws_sdk_s3::config::Builder::from(&shared)
+            .force_path_style(shared.endpoint_url().is_some())
+            .build();
+        Bucket::S3 { client: aws_sdk_s3::Client::from_conf(config), bucket: bucket.to_string() }
+    }
+
+    /// A Google Cloud Storage client with Application Default Credentials,
+    /// or anonymous access for public buckets when there are none
+    async fn gcs(bucket: &str) -> Self {
+        let config = match env::var(GCS_EMULATOR_VAR) {
+            Ok(host) => {
+                let storage_endpoint = if host.contains("://") { host } else { format!("http://{}", host) };
+                GcsClientConfig { storage_endpoint, ..GcsClientConfig::default() }.anonymous()
+            }
+            Err(_) => match GcsClientConfig::default().with_auth().await {
+                Ok(config) => config,
+                Err(e) => {
+                    warn!("No Google Cloud credentials found ({}); accessing gs://{} anonymously", e, bucket);
+                    GcsClientConfig::default().anonymous()
+                }
+            },
+        };
+        Bucket::Gcs { client: Box::new(GcsClient::new(config)), bucket: bucket.to_string() }
+    }
+
+    /// Every object under `prefix`, following the listing's pages
+    async fn list(&self, prefix: &str) -> Result<Vec<Listed>, String> {
+        let mut listed = Vec::new();
+        match self {
+            Bucket::S3 { client, bucket } => {
+                let mut pages = client.list_objects_v2().bucket(bucket).prefix(prefix).into_paginator().send();
+                while let Some(page) = pages.next().await {
+                    let page = page.map_err(|e| format!("unable to list s3://{}/{}: {}", bucket, prefix, DisplayErrorContext(e)))?;
+                    for object in page.contents() {
+                        let key = match object.key() {
+                            Some(key) => key,
+                            None => continue,
+                        };
+                        listed.push(Listed {
+                            key: key.to_string(),
+                            size: object.size().unwrap_or_default().max(0) as u64,
+                            last_modified: object.last_modified().and_then(|time| SystemTime::try_from(*time).ok()).map(format_rfc3339),
+                            metadata: StorageObject {
+                                etag: object.e_tag().map(String::from),
+                                storage_class: object.storage_class().map(|class| class.as_str().to_string()),
+                                generation: None,
+                            },
+                        });
+                    }
+                }
+            }
+            Bucket::Gcs { client, bucket } => {
+                let mut page_token = None;
+                loop {
+                    let request = ListObjectsRequest {
+                        bucket: bucket.clone(),
+                        prefix: Some(prefix.to_string()),
+                        page_token,
+                        ..Default::default()
+                    };
+                    let page = client.list_objects(&request).await
+                        .map_err(|e| format!("unable to list gs://{}/{}: {}", bucket, prefix, e))?;
+                    for object in page.items.unwrap_or_default() {
+                        listed.push(Listed {
+                            size: object.size.max(0) as u64,
+                            last_modified: object.updated.map(|time| format_rfc3339(SystemTime::from(time))),
+                            metadata: StorageObject {
+                                etag: Some(object.etag),
+                                storage_class: object.storage_class,
+                                generation: Some(object.generation),
+                            },
+                            key: object.name,
+                        });
+                    }
+                    page_token = match page.next_page_token {
+                        Some(token) => Some(token),
+                        None => break,
+                    };
+                }
+            }
+        }
+        Ok(listed)
+    }
+
+    /// The object's bytes, or the inclusive byte `range` of them. Requests are
+    /// conditional on the listed ETag or generation, so ranged parts cannot
+    /// mix two versions of an object overwritten mid-download.
+    async fn get(&self, object: &Listed, range: Option<(u64, u64)>) -> Result<Vec<u8>, String> {
+        match self {
+            Bucket::S3 { client, bucket } => {
+                let mut request = client.get_object().bucket(bucket).key(&object.key);
+                if let Some(etag) = &object.metadata.etag {
+                    request = request.if_match(etag);
+                }
+                if let Some((start, end)) = range {
+                    request = request.range(format!("bytes={}-{}", start, end));
+                }
+                let output = request.send().await.map_err(|e| DisplayErrorContext(e).to_string())?;
+                let data = output.body.collect().await.map_err(|e| e.to_string())?;
+                Ok(data.into_bytes().to_vec())
+            }
+            Bucket::Gcs { client, bucket } => {
+                let request = GetObjectRequest {
+                    bucket: bucket.clone(),
+                    object: object.key.clone(),
+                    if_generation_match: object.metadata.generation,
+                    ..Default::default()
+                };
+                let range = match range {
+                    Some((start, end)) => Range(Some(start), Some(end)),
+                    None => Range(None, None),
+                };
+                client.download_object(&request, &range).await.map_err(|e| e.to_string())
+            }
+        }
+    }
+}
+
+/// Download an object into memory: in one request, or in parallel ranged
+/// parts when it is large. Each request holds one of `permits`.
+async fn download(bucket: Arc<Bucket>, object: Arc<Listed>, permits: Arc<Semaphore>) -> Result<Vec<u8>, String> {
+    if object.size < RANGED_DOWNLOAD_THRESHOLD {
+        let _permit = permits.acquire().await.expect("semaphore is never closed");
+        return bucket.get(&object, None).await;
+    }
+
+    let mut parts = JoinSet::new();
+    for (index, start) in (0..object.size).step_by(PART_SIZE as usize).enumerate() {
+        let end = (start + PART_SIZE).min(object.size) - 1;
+        let (bucket, object, permits) = (Arc::clone(&bucket), Arc::clone(&object), Arc::clone(&permits));
+        parts.spawn(async move {
+            let _permit = permits.acquire().await.expect("semaphore is never closed");
+            bucket.get(&object, Some((start, end))).await.map(|data| (index, data))
+        });
+    }
+
+    let mut received = Vec::new();
+    while let Some(part) = parts.join_next().await {
+        received.push(part.map_err(|e| e.to_string())??);
+    }
+    received.sort_by_key(|(index, _)| *index);
+    let data: Vec<u8> = received.into_iter().flat_map(|(_, data)| data).collect();
+    if data.len() as u64 != object.size {
+        return Err(format!("expected {} bytes, received {}", object.size, data.len()));
+    }
+    Ok(data)
+}
+
+/// List the jar, class, jmod, bundle and log4j configuration objects under
+/// the prefix that no `--exclude` or source jar pattern matches, download each into memory,
+/// at most `--bucket-connections` requests at a time, and scan it. Findings
+/// are labelled with the object's URL and carry its ETag and storage class.
+async fn scan_bucket(config: &Config, url: &BucketUrl, bucket: Bucket) -> Result<(Vec<ScanResult>, ScanSummary), Box<dyn std::error::Error>> {
+    let rules = Arc::new(RuleSet::from_config(config)?);
+    let scan_config = Arc::new(config.clone());
+    let exclude_patterns: Vec<Pattern> = config.exclude_patterns().into_iter()
+        .map(|pattern| Pattern::new(pattern).map_err(|e| format!("invalid exclude pattern {}: {}", pattern, e)))
+        .collect::<Result<_, _>>()?;
+
+    let listed = bucket.list(&url.prefix).await?;
+    let total = listed.len();
+    let objects: Vec<Listed> = listed.into_iter()
+        .filter(|object| is_scannable_member(&object.key))
+        .filter(|object| !exclude_patterns.iter().any(|pattern| pattern.matches(&object.key)))
+        .collect();
+    info!("Found {} objects to scan of {} under {}", objects.len(), total, url.object_url(&url.prefix));
+
+    let connections = config.bucket_connections.or(config.threads).unwrap_or_else(num_cpus::get).max(1);
+    let permits = Arc::new(Semaphore::new(connections));
+    let bucket = Arc::new(bucket);
+    let mut downloads = JoinSet::new();
+    for (index, object) in objects.into_iter().enumerate() {
+        let (bucket, permits, rules, config) = (Arc::clone(&bucket), Arc::clone(&permits), Arc::clone(&rules), Arc::clone(&scan_config));
+        let name = url.object_url(&object.key);
+        let object = Arc::new(object);
+        downloads.spawn(async move {
+            match download(bucket, Arc::clone(&object), permits).await {
+                Ok(data) => {
+                    debug!("Downloaded {} ({} bytes)", name, data.len());
+                    let results = tokio::task::spawn_blocking(move || scan_bytes(&name, &data, &config, &rules)).await.unwrap_or_default();
+                    let results = results.into_iter()
+                        .map(|result| ScanResult {
+                            modified: result.modified.or_else(|| object.last_modified.clone()),
+                            storage_object: Some(object.metadata.clone()),
+                            ..result
+                        })
+                        .collect();
+                    (index, Some(results))
+                }
+                Err(e) => {
+                    scan_errors::record(&name, ScanErrorKind::Read, format_args!("Error downloading object: {} - {}", name, e));
+                    (index, None)
+                }
+            }
+        });
+    }
+
+    let mut scanned: Vec<(usize, Vec<ScanResult>)> = Vec::new();
+    while let Some(download) = downloads.join_next().await {
+        if let Ok((index, Some(results))) = download {
+            scanned.push((index, results));
+        }
+    }
+    // Report in listing order, whatever order the downloads finished in
+    scanned.sort_by_key(|(index, _)| *index);
+
+    let summary = ScanSummary {
+        files_scanned: scanned.len(),
+        error_digest: scan_errors::take_digest(),
+        rule_stats: rule_stats::take(&rules, config),
+        archive_depth_reached: take_deepest_archive(),
+        rule_packs: rules.packs().to_vec(),
+        ..Default::default()
+    };
+    Ok((scanned.into_iter().flat_map(|(_, results)| results).collect(), summary))
+}
+
+/// Scan the objects under an `s3://bucket/prefix` URL
+pub fn scan_s3(config: &Config, url: &str) -> Result<(Vec<ScanResult>, ScanSummary), Box<dyn std::error::Error>> {
+    let url = BucketUrl::parse(url, "s3")?;
+    info!("Scanning {}", url.object_url(&url.prefix));
+
+    let runtime = tokio::runtime::Builder::new_multi_thread().enable_all().build()?;
+    runtime.block_on(async {
+        let bucket = Bucket::s3(&url.bucket).await;
+        scan_bucket(config, &url, bucket).await
+    })
+}
+
+/// Scan the objects under a `gs://bucket/prefix` URL
+pub fn scan_gcs(config: &Config, url: &str) -> Result<(Vec<ScanResult>, ScanSummary), Box<dyn std::error::Error>> {
+    let url = BucketUrl::parse(url, "gs")?;
+    info!("Scanning {}", url.object_url(&url.prefix));
+
+    let runtime = tokio::runtime::Builder::new_multi_thread().enable_all().build()?;
+    runtime.block_on(async {
+        let bucket = Bucket::gcs(&url.bucket).await;
+        scan_bucket(config, &url, bucket).await
+    })
+}
@@ -0,0 +1,425 @@
+use crate::config::Config;
+use crate::log4j_config::is_log4j_config;
+use crate::rules::RuleSet;
+use crate::scan_errors::{self, ScanErrorKind};
+use crate::scanner::{scan_nested, skipped_scan_result, ScanResult};
+use crate::utils::{is_bundle_file, is_class_file, is_deb_file, is_jar_file, is_jmod_file, nested_path, read_file};
+use bzip2::read::BzDecoder;
+use crossbeam_channel::{Receiver, Sender};
+use flate2::read::GzDecoder;
+use log::{debug, warn};
+use std::io::{self, Cursor, Read, Write};
+use std::path::Path;
+
+const RPM_LEAD_MAGIC: &[u8] = b"\xed\xab\xee\xdb";
+const RPM_LEAD_SIZE: usize = 96;
+const RPM_HEADER_MAGIC: &[u8] = b"\x8e\xad\xe8";
+const CPIO_NEWC_MAGIC: &[u8] = b"070701";
+const CPIO_CRC_MAGIC: &[u8] = b"070702";
+const CPIO_HEADER_SIZE: usize = 110;
+const CPIO_TRAILER: &str = "TRAILER!!!";
+/// Longest cpio member name accepted, well past any path length limit
+const CPIO_MAX_NAME_SIZE: usize = 64 * 1024;
+
+const GZIP_MAGIC: &[u8] = b"\x1f\x8b";
+const XZ_MAGIC: &[u8] = b"\xfd7zXZ\x00";
+const ZSTD_MAGIC: &[u8] = b"\x28\xb5\x2f\xfd";
+const BZIP2_MAGIC: &[u8] = b"BZh";
+/// `data.tar` members of a Debian package whose compression can be read
+const DEB_DATA_MEMBERS: &[&str] = &["data.tar", "data.tar.gz", "data.tar.xz", "data.tar.zst", "data.tar.bz2"];
+/// Size of the chunks an xz payload is decompressed in, and how many may wait to be read
+const XZ_CHUNK_SIZE: usize = 64 * 1024;
+const XZ_CHUNKS_AHEAD: usize = 4;
+
+/// Scan the jars, jmods, class files and log4j configuration files bundled in an `.rpm` or `.deb` package,
+/// labelling findings with their nested path, e.g. `foo-1.2.rpm!usr/share/java/log4j-core.jar`.
+/// A package that cannot be unpacked yields a single skipped result with the reason.
+pub fn scan_package(path: &Path, config: &Config, rules: &RuleSet) -> Vec<ScanResult> {
+    debug!("Scanning package: {:?}", path);
+
+    let data = match read_file(path, &config.io_budget) {
+        Ok(data) => data,
+        Err(e) => {
+            scan_errors::record(path, ScanErrorKind::of_io(&e), format_args!("Error reading package: {:?} - {}", path, e));
+            return Vec::new();
+        }
+    };
+
+    let name = path.to_string_lossy();
+    let mut results = Vec::new();
+    let mut scan_member = |member: &str, contents: Vec<u8>| {
+        let nested_name = nested_path(&name, member.trim_start_matches("./"));
+        results.extend(scan_nested(&nested_name, &contents, config, rules));
+    };
+
+    let unpacked = if is_deb_file(path) {
+        visit_deb_members(&data, &mut scan_member)
+    } else {
+        visit_rpm_members(&data, &mut scan_member)
+    };
+
+    if let Err(reason) = unpacked {
+        scan_errors::record(path, ScanErrorKind::InvalidArchive, format_args!("Skipping package: {:?} - {}", path, reason));
+        results.push(skipped_scan_result(path, &data, format!("Skipped package: {}", reason), config));
+    }
+
+    results
+}
+
+/// Scan the jars, bundles, class files and log4j configuration files in a tar
+/// archive, compressed or not, labelling findings with their nested path.
+/// An archive that cannot be unpacked yields a single skipped result with the reason.
+pub fn scan_tar(path: &Path, config: &Config, rules: &RuleSet) -> Vec<ScanResult> {
+    debug!("Scanning tar archive: {:?}", path);
+
+    let data = match read_file(path, &config.io_budget) {
+        Ok(data) => data,
+        Err(e) => {
+            scan_errors::record(path, ScanErrorKind::of_io(&e), format_args!("Error reading tar archive: {:?} - {}", path, e));
+            return Vec::new();
+        }
+    };
+
+    let name = path.to_string_lossy();
+    let mut results = Vec::new();
+    let unpacked = decompress(&data).and_then(|reader| visit_tar_members(reader, &mut |member: &str, contents: Vec<u8>| {
+        let nested_name = nested_path(&name, member.trim_start_matches("./"));
+        results.extend(scan_nested(&nested_name, &contents, config, rules));
+    }));
+
+    if let Err(reason) = unpacked {
+        scan_errors::record(path, ScanErrorKind::InvalidArchive, format_args!("Skipping tar archive: {:?} - {}", path, reason));
+        results.push(skipped_scan_result(path, &data, format!("Skipped archive: {}", reason), config));
+    }
+
+    results
+}
+
+/// Whether a package member is worth reading into memory
+pub fn is_scannable_member(name: &str) -> bool {
+    let path = Path::new(name);
+    is_jar_file(path) || is_bundle_file(path) || is_class_file(path) || is_jmod_file(path) || is_log4j_config(path)
+}
+
+/// Whether a payload starts with the magic of a compression `decompress` reads
+fn is_compressed(data: &[u8]) -> bool {
+    [GZIP_MAGIC, ZSTD_MAGIC, XZ_MAGIC, BZIP2_MAGIC].iter().any(|magic| data.starts_with(magic))
+}
+
+/// Wrap a payload in a decoder chosen by its magic bytes
+fn decompress<'a>(data: &'a [u8]) -> Result<Box<dyn Read + 'a>, String> {
+    if data.starts_with(GZIP_MAGIC) {
+        Ok(Box::new(GzDecoder::new(data)))
+    } else if data.starts_with(ZSTD_MAGIC) {
+        let decoder = zstd::stream::read::Decoder::new(data)
+            .map_err(|e| format!("invalid zstd payload: {}", e))?;
+        Ok(Box::new(decoder))
+    } else if data.starts_with(XZ_MAGIC) {
+        Ok(Box::new(XzReader::new(data)))
+    } else if data.starts_with(BZIP2_MAGIC) {
+        Ok(Box::new(BzDecoder::new(data)))
+    } else {
+        Ok(Box::new(data))
+    }
+}
+
+/// Reads an xz stream as it is decompressed. lzma-rs only decompresses into a
+/// writer, so it runs on a thread of its own and hands its output over in
+/// chunks, and the payload is never held in memory whole.
+struct XzReader {
+    chunks: Receiver<io::Result<Vec<u8>>>,
+    chunk: Cursor<Vec<u8>>,
+}
+
+/// Sends what is written to it to an `XzReader`, failing once the reader is gone
+struct ChunkWriter(Sender<io::Result<Vec<u8>>>);
+
+impl XzReader {
+    fn new(data: &[u8]) -> Self {
+        let data = data.to_vec();
+        let (sender, chunks) = crossbeam_channel::bounded(XZ_CHUNKS_AHEAD);
+        std::thread::spawn(move || {
+            let mut output = io::BufWriter::with_capacity(XZ_CHUNK_SIZE, ChunkWriter(sender.clone()));
+            let decompressed = lzma_rs::xz_decompress(&mut data.as_slice(), &mut output)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("invalid xz payload: {}", e)))
+                .and_then(|()| output.flush());
+            if let Err(e) = decompressed {
+                let _ = sender.send(Err(e));
+            }
+        });
+        XzReader { chunks, chunk: Cursor::new(Vec::new()) }
+    }
+}
+
+impl Read for XzReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            let read = self.chunk.read(buf)?;
+            if read > 0 || buf.is_empty() {
+                return Ok(read);
+            }
+            match self.chunks.recv() {
+                Ok(chunk) => self.chunk = Cursor::new(chunk?),
+                // The decompressing thread is done
+                Err(_) => return Ok(0),
+            }
+        }
+    }
+}
+
+impl Write for ChunkWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.send(Ok(buf.to_vec())).map_err(|_| io::Error::from(io::ErrorKind::BrokenPipe))?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Call `visit` with each jar and class file in an RPM's cpio payload
+fn visit_rpm_members<F>(data: &[u8], visit: &mut F) -> Result<(), String>
+where
+    F: FnMut(&str, Vec<u8>),
+{
+    if !data.starts_with(RPM_LEAD_MAGIC) || data.len() < RPM_LEAD_SIZE {
+        return Err("not an RPM package".to_string());
+    }
+
+    // The signature header is padded to an 8-byte boundary, the main header is not
+    let signature_end = RPM_LEAD_SIZE + rpm_header_size(&data[RPM_LEAD_SIZE..])?;
+    let header_start = signature_end.div_ceil(8) * 8;
+    let payload_start = header_start + rpm_header_size(data.get(header_start..).unwrap_or_default())?;
+    let payload = data.get(payload_start..).ok_or("truncated RPM header")?;
+    if !is_compressed(payload) && !payload.starts_with(CPIO_NEWC_MAGIC) && !payload.starts_with(CPIO_CRC_MAGIC) {
+        return Err("unsupported RPM payload compression".to_string());
+    }
+
+    visit_cpio_members(decompress(payload)?, visit)
+}
+
+/// Size of an RPM header structure: 16-byte intro, index entries, then the
+/// data store. The counts come from the file, so a size past its end is an error.
+fn rpm_header_size(header: &[u8]) -> Result<usize, String> {
+    if !header.starts_with(RPM_HEADER_MAGIC) || header.len() < 16 {
+        return Err("invalid RPM header".to_string());
+    }
+    let index_count = u32::from_be_bytes(header[8..12].try_into().unwrap()) as usize;
+    let store_size = u32::from_be_bytes(header[12..16].try_into().unwrap()) as usize;
+    index_count.checked_mul(16)
+        .and_then(|index_size| index_size.checked_add(16))
+        .and_then(|size| size.checked_add(store_size))
+        .filter(|&size| size <= header.len())
+        .ok_or_else(|| "truncated RPM header".to_string())
+}
+
+/// Call `visit` with each jar and class file in a cpio "newc" archive
+fn visit_cpio_members<R, F>(mut reader: R, visit: &mut F) -> Result<(), String>
+where
+    R: Read,
+    F: FnMut(&str, Vec<u8>),
+{
+    let mut offset = 0usize;
+    loop {
+        let mut header = [0u8; CPIO_HEADER_SIZE];
+        reader.read_exact(&mut header).map_err(|e| format!("truncated cpio payload: {}", e))?;
+        if !header.starts_with(CPIO_NEWC_MAGIC) && !header.starts_with(CPIO_CRC_MAGIC) {
+            return Err("unsupported cpio payload format".to_string());
+        }
+
+        let field = |index: usize| {
+            let start = 6 + index * 8;
+            std::str::from_utf8(&header[start..start + 8]).ok()
+                .and_then(|hex| usize::from_str_radix(hex, 16).ok())
+                .ok_or("invalid cpio header")
+        };
+        let file_size = field(6)?;
+        let name_size = field(11)?;
+
+        if name_size > CPIO_MAX_NAME_SIZE {
+            return Err(format!("cpio member name of {} bytes", name_size));
+        }
+        let name = read_member(&mut reader, name_size)?;
+        let name = String::from_utf8_lossy(&name).trim_end_matches('\0').to_string();
+        offset += CPIO_HEADER_SIZE + name_size;
+        skip(&mut reader, padding(offset))?;
+        offset += padding(offset);
+
+        if name == CPIO_TRAILER {
+            return Ok(());
+        }
+
+        if is_scannable_member(&name) {
+            visit(&name, read_member(&mut reader, file_size)?);
+        } else {
+            skip(&mut reader, file_size)?;
+        }
+        offset += file_size;
+        skip(&mut reader, padding(offset))?;
+        offset += padding(offset);
+    }
+}
+
+/// Read `size` bytes of a cpio member. The size is from the member's header,
+/// so the buffer only grows as the bytes arrive rather than being allocated
+/// up front, and a payload that ends early is an error.
+fn read_member<R: Read>(reader: &mut R, size: usize) -> Result<Vec<u8>, String> {
+    let mut contents = Vec::new();
+    reader.take(size as u64).read_to_end(&mut contents).map_err(|e| format!("truncated cpio payload: {}", e))?;
+    if contents.len() < size {
+        return Err(format!("truncated cpio payload: member of {} bytes ends after {}", size, contents.len()));
+    }
+    Ok(contents)
+}
+
+/// Bytes needed to bring `offset` to the 4-byte cpio alignment
+fn padding(offset: usize) -> usize {
+    (4 - offset % 4) % 4
+}
+
+fn skip<R: Read>(reader: &mut R, count: usize) -> Result<(), String> {
+    io::copy(&mut reader.take(count as u64), &mut io::sink())
+        .map_err(|e| format!("truncated cpio payload: {}", e))?;
+    Ok(())
+}
+
+/// Call `visit` with each jar and class file in a Debian package's `data.tar.*` member
+fn visit_deb_members<F>(data: &[u8], visit: &mut F) -> Result<(), String>
+where
+    F: FnMut(&str, Vec<u8>),
+{
+    let mut archive = ar::Archive::new(data);
+    while let Some(entry) = archive.next_entry() {
+        let mut entry = entry.map_err(|e| format!("invalid ar archive: {}", e))?;
+        let identifier = String::from_utf8_lossy(entry.header().identifier()).to_string();
+        if !identifier.starts_with("data.tar") {
+            continue;
+        }
+        if !DEB_DATA_MEMBERS.contains(&identifier.as_str()) {
+            return Err(format!("unsupported data archive compression: {}", identifier));
+        }
+
+        let mut member = Vec::new();
+        entry.read_to_end(&mut member).map_err(|e| format!("truncated data archive: {}", e))?;
+        return visit_tar_members(decompress(&member)?, visit);
+    }
+
+    Err("no data.tar member found".to_string())
+}
+
+/// Call `visit` with each jar and class file in a tar archive
+fn visit_tar_members<R, F>(reader: R, visit: &mut F) -> Result<(), String>
+where
+    R: Read,
+    F: FnMut(&str, Vec<u8>),
+{
+    let mut archive = tar::Archive::new(reader);
+    let entries = archive.entries().map_err(|e| format!("invalid tar archive: {}", e))?;
+    for entry in entries {
+        let mut entry = entry.map_err(|e| format!("invalid tar archive: {}", e))?;
+        let name = match entry.path() {
+            Ok(path) => path.to_string_lossy().to_string(),
+            Err(e) => {
+                warn!("Error reading tar entry name: {}", e);
+                continue;
+            }
+        };
+
+        if entry.header().entry_type().is_file() && is_scannable_member(&name) {
+            let mut contents = Vec::new();
+            entry.read_to_end(&mut contents).map_err(|e| format!("truncated tar archive: {}", e))?;
+            visit(&name, contents);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A newc cpio header for a member of `file_size` bytes named by `name_size` bytes
+    fn cpio_header(file_size: usize, name_size: usize) -> Vec<u8> {
+        let mut fields = [0usize; 13];
+        fields[6] = file_size;
+        fields[11] = name_size;
+        let fields: String = fields.iter().map(|field| format!("{:08x}", field)).collect();
+        [CPIO_NEWC_MAGIC, fields.as_bytes()].concat()
+    }
+
+    fn cpio_member(name: &str, contents: &[u8]) -> Vec<u8> {
+        let name = format!("{}\0", name);
+        let mut member = cpio_header(contents.len(), name.len());
+        member.extend_from_slice(name.as_bytes());
+        member.resize(member.len() + padding(member.len()), 0);
+        member.extend_from_slice(contents);
+        member.resize(member.len() + padding(member.len()), 0);
+        member
+    }
+
+    fn cpio(members: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut archive: Vec<u8> = members.iter().flat_map(|(name, contents)| cpio_member(name, contents)).collect();
+        archive.extend(cpio_member(CPIO_TRAILER, b""));
+        archive
+    }
+
+    fn members(payload: &[u8]) -> Result<Vec<(String, Vec<u8>)>, String> {
+        let mut members = Vec::new();
+        visit_cpio_members(decompress(payload)?, &mut |name: &str, contents: Vec<u8>| members.push((name.to_string(), contents)))?;
+        Ok(members)
+    }
+
+    #[test]
+    fn cpio_members_are_visited() {
+        let payload = cpio(&[("./usr/share/java/log4j-core.jar", b"PK\x03\x04"), ("./usr/share/doc/README", b"text")]);
+        assert_eq!(members(&payload).unwrap(), [("./usr/share/java/log4j-core.jar".to_string(), b"PK\x03\x04".to_vec())]);
+    }
+
+    #[test]
+    fn oversized_cpio_member_is_truncated_not_allocated() {
+        let name = "log4j-core.jar\0";
+        let mut payload = cpio_header(u32::MAX as usize, name.len());
+        payload.extend_from_slice(name.as_bytes());
+        payload.extend_from_slice(b"\0\0\0PK\x03\x04");
+        let error = members(&payload).unwrap_err();
+        assert!(error.starts_with("truncated cpio payload"), "{}", error);
+    }
+
+    #[test]
+    fn oversized_cpio_name_is_an_error() {
+        let payload = cpio_header(0, u32::MAX as usize);
+        assert_eq!(members(&payload).unwrap_err(), format!("cpio member name of {} bytes", u32::MAX));
+    }
+
+    #[test]
+    fn xz_payload_is_streamed() {
+        let jar = vec![b'x'; 3 * XZ_CHUNK_SIZE];
+        let payload = cpio(&[("log4j-core.jar", &jar)]);
+        let mut compressed = Vec::new();
+        lzma_rs::xz_compress(&mut payload.as_slice(), &mut compressed).unwrap();
+        assert_eq!(members(&compressed).unwrap(), [("log4j-core.jar".to_string(), jar)]);
+    }
+
+    #[test]
+    fn corrupt_xz_payload_is_an_error() {
+        let mut compressed = Vec::new();
+        lzma_rs::xz_compress(&mut cpio(&[("log4j-core.jar", b"PK")]).as_slice(), &mut compressed).unwrap();
+        let corrupt = [&compressed[..XZ_MAGIC.len() + 6], &[0xff; 16]].concat();
+        let error = members(&corrupt).unwrap_err();
+        assert!(error.contains("invalid xz payload"), "{}", error);
+    }
+
+    #[test]
+    fn rpm_header_counts_past_the_end_are_errors() {
+        let mut header = [RPM_HEADER_MAGIC, &[1, 0, 0, 0, 0]].concat();
+        header.extend_from_slice(&u32::MAX.to_be_bytes());
+        header.extend_from_slice(&u32::MAX.to_be_bytes());
+        assert_eq!(rpm_header_size(&header), Err("truncated RPM header".to_string()));
+
+        header[8..12].copy_from_slice(&0u32.to_be_bytes());
+        header[12..16].copy_from_slice(&4u32.to_be_bytes());
+        header.extend_from_slice(b"data");
+        assert_eq!(rpm_header_size(&header), Ok(20));
+    }
+}
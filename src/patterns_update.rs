@@ -0,0 +1,88 @@
+use crate::config::Config;
+use crate::rules::parse_rules_file;
+use std::env;
+use std::fs;
+use std::io::{self, Write};
+use std::path::PathBuf;
+
+/// Where `update-patterns` fetches from unless `--url` says otherwise
+pub const DEFAULT_URL: &str = "https://raw.githubusercontent.com/yezzfusl/RustyLog4jGuard/main/patterns.json";
+
+/// `~/.config/rustylog4jguard/patterns.json`, or under `$XDG_CONFIG_HOME`
+/// when it is set; None when there is no home directory
+pub fn saved_path() -> Option<PathBuf> {
+    let config_home = match env::var_os("XDG_CONFIG_HOME").filter(|dir| !dir.is_empty()) {
+        Some(dir) => PathBuf::from(dir),
+        None => PathBuf::from(env::var_os("HOME").or_else(|| env::var_os("USERPROFILE"))?).join(".config"),
+    };
+    Some(config_home.join("rustylog4jguard").join("patterns.json"))
+}
+
+#[derive(serde::Serialize)]
+struct UpdateStatus {
+    url: String,
+    path: String,
+    update_available: bool,
+    applied: bool,
+    current_version: Option<String>,
+    latest_version: Option<String>,
+    rules: usize,
+}
+
+/// Fetch the rules file at `url`, check that it parses and that every
+/// pattern compiles, and save it for later scans to load; with `check`,
+/// only report whether it differs from the saved file
+pub fn run(url: &str, check: bool, config: &Config) -> Result<(), Box<dyn std::error::Error>> {
+    let path = saved_path().ok_or("no home directory to save the patterns under")?;
+
+    let client = reqwest::blocking::Client::builder()
+        .user_agent(concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION")))
+        .build()?;
+    let latest = client.get(url).send()?.error_for_status()?.text()
+        .map_err(|e| format!("unable to fetch {}: {}", url, e))?;
+    let (latest_pack, _) = parse_rules_file(&latest, &path)
+        .map_err(|e| format!("{} (fetched from {})", e, url))?;
+
+    let current = fs::read_to_string(&path).ok();
+    let current_version = current.as_deref()
+        .and_then(|contents| parse_rules_file(contents, &path).ok())
+        .and_then(|(pack, _)| pack.version);
+    let update_available = current.as_deref() != Some(latest.as_str());
+
+    let applied = update_available && !check;
+    if applied {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&path, &latest)
+            .map_err(|e| format!("unable to save {}: {}", path.display(), e))?;
+    }
+
+    let status = UpdateStatus {
+        url: url.to_string(),
+        path: path.display().to_string(),
+        update_available,
+        applied,
+        current_version,
+        latest_version: latest_pack.version,
+        rules: latest_pack.rules,
+    };
+
+    let mut output = io::stdout().lock();
+    if config.format == "json" {
+        serde_json::to_writer_pretty(&mut output, &status)?;
+        writeln!(output)?;
+        return Ok(());
+    }
+
+    let version = |version: &Option<String>| version.clone().unwrap_or_else(|| "unversioned".to_string());
+    if !status.update_available {
+        writeln!(output, "Patterns are up to date ({}, {} rules): {}", version(&status.latest_version), status.rules, status.path)?;
+    } else if status.applied {
+        writeln!(output, "Saved {} rules ({}) from {} to {}", status.rules, version(&status.latest_version), status.url, status.path)?;
+    } else {
+        let current = if current.is_some() { version(&status.current_version) } else { "none saved".to_string() };
+        writeln!(output, "Update available: {} -> {} ({} rules) from {}", current, version(&status.latest_version), status.rules, status.url)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,680 @@
+use crate::config::Config;
+use crate::package::is_scannable_member;
+use crate::rule_stats;
+use crate::rules::RuleSet;
+use crate::scan_errors;
+use crate::scanner::{scan_bytes, take_deepest_archive, ScanResult, ScanSummary};
+use crate::utils::nested_path;
+use base64::Engine;
+use flate2::read::GzDecoder;
+use log::{debug, info, warn};
+use once_cell::sync::Lazy;
+use regex::Regex;
+use reqwest::blocking::{Client, RequestBuilder, Response};
+use reqwest::header::{ACCEPT, AUTHORIZATION, RANGE, WWW_AUTHENTICATE};
+use reqwest::StatusCode;
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::env;
+use std::fmt;
+use std::fs;
+use std::io::{self, Read};
+use std::path::PathBuf;
+use std::thread;
+use std::time::Duration;
+
+/// Environment variables holding the registry login, so it never appears in
+/// the process list or a report's `scan_config`
+const USERNAME_VAR: &str = "RUSTYLOG4JGUARD_REGISTRY_USERNAME";
+const PASSWORD_VAR: &str = "RUSTYLOG4JGUARD_REGISTRY_PASSWORD";
+
+/// Docker Hub's name in image references, the key of its login in
+/// `config.json`, and the host actually serving its API
+const DOCKER_HUB: &str = "docker.io";
+const DOCKER_HUB_AUTH_KEY: &str = "https://index.docker.io/v1/";
+const DOCKER_HUB_API: &str = "registry-1.docker.io";
+
+/// Manifest formats asked for: image indexes and manifest lists, then single-platform manifests
+const MANIFEST_TYPES: &str = "application/vnd.oci.image.index.v1+json, \
+    application/vnd.docker.distribution.manifest.list.v2+json, \
+    application/vnd.oci.image.manifest.v1+json, \
+    application/vnd.docker.distribution.manifest.v2+json";
+/// Platform picked from a multi-platform image
+const DEFAULT_OS: &str = "linux";
+const DEFAULT_ARCHITECTURE: &str = "amd64";
+
+/// Times a dropped layer download is resumed before the scan fails
+const RESUME_ATTEMPTS: u32 = 3;
+/// Wait before the first resume, doubled before each later one
+const RESUME_DELAY: Duration = Duration::from_secs(2);
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// A file named `.wh.<name>` deletes `<name>` from the layers below; this
+/// one hides everything the layers below put in its directory
+const WHITEOUT_PREFIX: &str = ".wh.";
+const OPAQUE_WHITEOUT: &str = ".wh..wh..opq";
+
+/// `key="value"` parameters of a `WWW-Authenticate` challenge
+static CHALLENGE_PARAMETER: Lazy<Regex> = Lazy::new(|| Regex::new(r#"(\w+)="([^"]*)""#).unwrap());
+
+/// A `[registry/]repository[:tag|@digest]` image reference, resolved the way
+/// `docker pull` does: Docker Hub unless the first component names a host,
+/// `library/` for official images and the `latest` tag by default
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct ImageReference {
+    registry: String,
+    repository: String,
+    /// Tag or `sha256:...` digest
+    reference: String,
+}
+
+impl ImageReference {
+    fn parse(image: &str) -> Result<Self, String> {
+        let (name, digest) = match image.split_once('@') {
+            Some((name, digest)) => (name, Some(digest)),
+            None => (image, None),
+        };
+        // A colon after the last slash starts the tag; one before it is a registry port
+        let (name, tag) = match name.rfind(':').filter(|&colon| !name[colon..].contains('/')) {
+            Some(colon) => (&name[..colon], Some(&name[colon + 1..])),
+            None => (name, None),
+        };
+        let (registry, repository) = match name.split_once('/') {
+            Some((host, rest)) if host.contains(['.', ':']) || host == "localhost" => (host.to_string(), rest.to_string()),
+            _ => (DOCKER_HUB.to_string(), name.to_string()),
+        };
+        let repository = if registry == DOCKER_HUB && !repository.contains('/') {
+            format!("library/{}", repository)
+        } else {
+            repository
+        };
+
+        let reference = digest.or(tag).unwrap_or("latest");
+        let valid = !repository.is_empty()
+            && !reference.is_empty()
+            && repository.split('/').all(|part| !part.is_empty())
+            && repository.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || "._-/".contains(c));
+        if !valid {
+            return Err(format!("invalid image reference '{}' (expected [registry/]name[:tag|@digest])", image));
+        }
+        Ok(ImageReference { registry, repository, reference: reference.to_string() })
+    }
+
+    /// Host serving the registry API
+    fn api_host(&self) -> &str {
+        if self.registry == DOCKER_HUB { DOCKER_HUB_API } else { &self.registry }
+    }
+}
+
+impl fmt::Display for ImageReference {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let separator = if self.reference.contains(':') { '@' } else { ':' };
+        write!(f, "{}/{}{}{}", self.registry, self.repository, separator, self.reference)
+    }
+}
+
+/// Username and password for the registry: the environment first, then the
+/// login `docker login` stored in `config.json`
+fn credentials(registry: &str) -> Option<(String, String)> {
+    if let Ok(username) = env::var(USERNAME_VAR) {
+        return Some((username, env::var(PASSWORD_VAR).unwrap_or_default()));
+    }
+    docker_config_credentials(registry)
+}
+
+/// The `auths` entry for `registry` in `$DOCKER_CONFIG/config.json` or
+/// `~/.docker/config.json`. Logins kept by a credential helper (`credsStore`)
+/// are not read.
+fn docker_config_credentials(registry: &str) -> Option<(String, String)> {
+    let path = match env::var_os("DOCKER_CONFIG") {
+        Some(dir) => PathBuf::from(dir).join("config.json"),
+        None => PathBuf::from(env::var_os("HOME").or_else(|| env::var_os("USERPROFILE"))?).join(".docker").join("config.json"),
+    };
+    let config: Value = serde_json::from_str(&fs::read_to_string(&path).ok()?)
+        .map_err(|e| warn!("Ignoring invalid Docker config {}: {}", path.display(), e))
+        .ok()?;
+
+    let key = if registry == DOCKER_HUB { DOCKER_HUB_AUTH_KEY } else { registry };
+    let entry = config["auths"].as_object()?.iter()
+        .find(|(name, _)| {
+            let host = name.trim_start_matches("https://").trim_start_matches("http://");
+            name.as_str() == key || host.split('/').next() == Some(key)
+        })
+        .map(|(_, entry)| entry)?;
+    if let (Some(username), Some(password)) = (entry["username"].as_str(), entry["password"].as_str()) {
+        return Some((username.to_string(), password.to_string()));
+    }
+    let decoded = base64::engine::general_purpose::STANDARD.decode(entry["auth"].as_str()?).ok()?;
+    let (username, password) = String::from_utf8(decoded).ok()?.split_once(':').map(|(u, p)| (u.to_string(), p.to_string()))?;
+    debug!("Using the {} login from {}", registry, path.display());
+    Some((username, password))
+}
+
+/// A connection to one repository of a registry, answering the
+/// authentication challenges of the distribution API as they come
+struct Registry {
+    client: Client,
+    /// `https://host` or, with `--registry-plain-http`, `http://host`
+    base_url: String,
+    repository: String,
+    credentials: Option<(String, String)>,
+    /// `Authorization` header sent with every request once a challenge was answered
+    authorization: Option<String>,
+}
+
+impl Registry {
+    fn new(image: &ImageReference, plain_http: bool) -> Result<Self, String> {
+        let client = Client::builder()
+            .user_agent(concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION")))
+            .connect_timeout(CONNECT_TIMEOUT)
+            // Layers can take far longer than any fixed timeout to stream
+            .timeout(None)
+            .build()
+            .map_err(|e| e.to_string())?;
+        Ok(Registry {
+            client,
+            base_url: format!("{}://{}", if plain_http { "http" } else { "https" }, image.api_host()),
+            repository: image.repository.clone(),
+            credentials: credentials(&image.registry),
+            authorization: None,
+        })
+    }
+
+    /// GET `/v2/<repository>/<path>`, authenticating and retrying once when
+    /// the registry challenges the request. Error statuses are errors.
+    fn get(&mut self, path: &str, build: impl Fn(RequestBuilder) -> RequestBuilder) -> Result<Response, String> {
+        let url = format!("{}/v2/{}/{}", self.base_url, self.repository, path);
+        let send = |authorization: Option<&String>| {
+            let request = build(self.client.get(&url));
+            let request = match authorization {
+                Some(authorization) => request.header(AUTHORIZATION, authorization),
+                None => request,
+            };
+            request.send().map_err(|e| format!("{}: {}", url, e))
+        };
+
+        let mut response = send(self.authorization.as_ref())?;
+        if response.status() == StatusCode::UNAUTHORIZED {
+            let challenge = response.headers().get(WWW_AUTHENTICATE)
+                .and_then(|value| value.to_str().ok())
+                .ok_or_else(|| format!("{}: unauthorized, without an authentication challenge", url))?
+                .to_string();
+            self.authorization = Some(self.authenticate(&challenge)?);
+            response = send(self.authorization.as_ref())?;
+        }
+        response.error_for_status().map_err(|e| match e.status() {
+            Some(StatusCode::UNAUTHORIZED) | Some(StatusCode::FORBIDDEN) => format!(
+                "{}: access denied; log in with `docker login` or set {} and {}", url, USERNAME_VAR, PASSWORD_VAR),
+            _ => e.to_string(),
+        })
+    }
+
+    /// The `Authorization` header answering a `WWW-Authenticate` challenge:
+    /// a bearer token from the token service it names (anonymous without
+    /// credentials, as Docker Hub allows for public images), or basic auth
+    fn authenticate(&self, challenge: &str) -> Result<String, String> {
+        let (scheme, parameters) = challenge.split_once(' ').unwrap_or((challenge, ""));
+        let basic = self.credentials.as_ref().map(|(username, password)| {
+            format!("Basic {}", base64::engine::general_purpose::STANDARD.encode(format!("{}:{}", username, password)))
+        });
+
+        if scheme.eq_ignore_ascii_case("basic") {
+            return basic.ok_or_else(|| format!("the registry needs a login: set {} and {}, or use `docker login`", USERNAME_VAR, PASSWORD_VAR));
+        }
+        if !scheme.eq_ignore_ascii_case("bearer") {
+            return Err(format!("unsupported registry authentication scheme: {}", scheme));
+        }
+
+        let parameters: BTreeMap<&str, &str> = CHALLENGE_PARAMETER.captures_iter(parameters)
+            .map(|captures| (captures.get(1).unwrap().as_str(), captures.get(2).unwrap().as_str()))
+            .collect();
+        let realm = parameters.get("realm").ok_or("bearer challenge without a realm")?;
+        let scope = format!("repository:{}:pull", self.repository);
+        let mut query = vec![("scope", parameters.get("scope").copied().unwrap_or(&scope))];
+        if let Some(service) = parameters.get("service") {
+            query.push(("service", service));
+        }
+
+        debug!("Requesting a registry token from {}", realm);
+        let mut request = self.client.get(*realm).query(&query);
+        if let Some(basic) = &basic {
+            request = request.header(AUTHORIZATION, basic);
+        }
+        let token: Value = request.send()
+            .and_then(Response::error_for_status)
+            .and_then(Response::json)
+            .map_err(|e| format!("registry token request failed: {}", e))?;
+        token["token"].as_str()
+            .or_else(|| token["access_token"].as_str())
+            .map(|token| format!("Bearer {}", token))
+            .ok_or_else(|| "registry token response without a token".to_string())
+    }
+
+    fn manifest(&mut self, reference: &str) -> Result<Value, String> {
+        self.get(&format!("manifests/{}", reference), |request| request.header(ACCEPT, MANIFEST_TYPES))?
+            .json()
+            .map_err(|e| format!("invalid manifest {}: {}", reference, e))
+    }
+
+    /// The layers of the image, bottom first. A multi-platform image
+    /// resolves to its linux/amd64 manifest, or its first one without it.
+    fn layers(&mut self, reference: &str) -> Result<Vec<Layer>, String> {
+        let mut manifest = self.manifest(reference)?;
+        if let Some(manifests) = manifest["manifests"].as_array() {
+            fn platform(entry: &Value) -> (Option<&str>, Option<&str>) {
+                (entry["platform"]["os"].as_str(), entry["platform"]["architecture"].as_str())
+            }
+            let chosen = manifests.iter()
+                .find(|entry| platform(entry) == (Some(DEFAULT_OS), Some(DEFAULT_ARCHITECTURE)))
+                .or_else(|| manifests.first())
+                .ok_or("image index without manifests")?;
+            let digest = chosen["digest"].as_str().ok_or("image index entry without a digest")?.to_string();
+            let (os, architecture) = platform(chosen);
+            info!("Scanning platform {}/{} ({})", os.unwrap_or("?"), architecture.unwrap_or("?"), digest);
+            manifest = self.manifest(&digest)?;
+        }
+
+        let layers = manifest["layers"].as_array()
+            .ok_or("unsupported manifest: no layers (schema 1 manifests are not supported)")?;
+        layers.iter()
+            .map(|layer| Ok(Layer {
+                digest: layer["digest"].as_str().ok_or("layer without a digest")?.to_string(),
+                media_type: layer["mediaType"].as_str().unwrap_or_default().to_string(),
+            }))
+            .collect()
+    }
+}
+
+struct Layer {
+    digest: String,
+    media_type: String,
+}
+
+/// A layer blob streamed from the registry and hashed as it is read. When
+/// the connection drops mid-layer, the download resumes where it stopped with
+/// a range request, if the registry honors one.
+struct BlobReader<'a> {
+    registry: &'a mut Registry,
+    digest: &'a str,
+    response: Response,
+    offset: u64,
+    resumes: u32,
+    hasher: Sha256,
+}
+
+impl<'a> BlobReader<'a> {
+    fn open(registry: &'a mut Registry, digest: &'a str) -> Result<Self, String> {
+        let response = registry.get(&format!("blobs/{}", digest), |request| request)?;
+        Ok(BlobReader { registry, digest, response, offset: 0, resumes: 0, hasher: Sha256::new() })
+    }
+
+    /// Request the rest of the blob from `offset` on
+    fn resume(&mut self) -> Result<(), String> {
+        let range = format!("bytes={}-", self.offset);
+        let response = self.registry.get(&format!("blobs/{}", self.digest), |request| request.header(RANGE, range.as_str()))?;
+        if response.status() != StatusCode::PARTIAL_CONTENT {
+            return Err("the registry does not support range requests".to_string());
+        }
+        self.response = response;
+        Ok(())
+    }
+
+    /// Read what the tar reader left, and check the blob against its digest
+    fn finish(mut self) -> Result<(), String> {
+        io::copy(&mut self, &mut io::sink()).map_err(|e| e.to_string())?;
+        let actual = format!("sha256:{:x}", self.hasher.finalize());
+        if self.digest.starts_with("sha256:") && actual != self.digest {
+            return Err(format!("digest mismatch: got {}", actual));
+        }
+        Ok(())
+    }
+}
+
+impl Read for BlobReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            match self.response.read(buf) {
+                Ok(read) => {
+                    self.hasher.update(&buf[..read]);
+                    self.offset += read as u64;
+                    return Ok(read);
+                }
+                Err(e) if self.resumes < RESUME_ATTEMPTS => {
+                    let delay = RESUME_DELAY * 2u32.pow(self.resumes);
+                    self.resumes += 1;
+                    warn!("Layer {} download failed at byte {}: {}; resuming in {:?}", self.digest, self.offset, e, delay);
+                    thread::sleep(delay);
+                    self.resume().map_err(|resume_error| io::Error::new(e.kind(), format!("{} (resume failed: {})", e, resume_error)))?;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
+
+/// Findings of the image's files so far, by path, with the index of the
+/// layer that added each file
+type LayerFiles = BTreeMap<String, (usize, Vec<ScanResult>)>;
+
+/// Drop what layers below `layer` put at `path`, and under it if it is a directory
+fn remove_lower(files: &mut LayerFiles, path: &str, layer: usize) {
+    let prefix = format!("{}/", path);
+    let hidden: Vec<String> = files.range(path.to_string()..)
+        .take_while(|(name, _)| name.starts_with(path))
+        .filter(|(name, (added, _))| *added < layer && (name.as_str() == path || name.starts_with(&prefix)))
+        .map(|(name, _)| name.clone())
+        .collect();
+    for name in hidden {
+        files.remove(&name);
+    }
+}
+
+/// Stream one layer through its decompressor and tar reader, applying its
+/// whiteouts and replacements to the files of the layers below and scanning
+/// the jar, class and log4j configuration files it adds, returning how many
+fn scan_layer(registry: &mut Registry, image: &str, index: usize, layer: &Layer, config: &Config, rules: &RuleSet, files: &mut LayerFiles) -> Result<usize, String> {
+    let mut files_scanned = 0;
+    let mut blob = BlobReader::open(registry, &layer.digest)?;
+    {
+        let reader: Box<dyn Read + '_> = if layer.media_type.contains("zstd") {
+            Box::new(zstd::stream::read::Decoder::new(&mut blob).map_err(|e| format!("invalid zstd layer: {}", e))?)
+        } else if layer.media_type.contains("gzip") {
+            Box::new(GzDecoder::new(&mut blob))
+        } else {
+            Box::new(&mut blob)
+        };
+
+        let mut archive = tar::Archive::new(reader);
+        let entries = archive.entries().map_err(|e| format!("invalid layer: {}", e))?;
+        for entry in entries {
+            let mut entry = entry.map_err(|e| format!("invalid layer: {}", e))?;
+            let path = match entry.path() {
+                Ok(path) => path.to_string_lossy().trim_start_matches("./").trim_start_matches('/').trim_end_matches('/').to_string(),
+                Err(e) => {
+                    warn!("Error reading layer entry name: {}", e);
+                    continue;
+                }
+            };
+            let (directory, name) = match path.rsplit_once('/') {
+                Some((directory, name)) => (directory, name),
+                None => ("", path.as_str()),
+            };
+
+            if name == OPAQUE_WHITEOUT {
+                let prefix = if directory.is_empty() { String::new() } else { format!("{}/", directory) };
+                files.retain(|file, (added, _)| *added == index || !file.starts_with(&prefix));
+            } else if let Some(deleted) = name.strip_prefix(WHITEOUT_PREFIX) {
+                let deleted = if directory.is_empty() { deleted.to_string() } else { format!("{}/{}", directory, deleted) };
+                remove_lower(files, &deleted, index);
+            } else if !entry.header().entry_type().is_dir() {
+                remove_lower(files, &path, index);
+                if entry.header().entry_type().is_file() && is_scannable_member(&path) {
+                    let mut contents = Vec::new();
+                    entry.read_to_end(&mut contents).map_err(|e| format!("truncated layer: {}", e))?;
+                    files_scanned += 1;
+                    let results = scan_bytes(&nested_path(image, &path), &contents, config, rules);
+                    files.insert(path, (index, results));
+                }
+            }
+        }
+    }
+    blob.finish()?;
+    Ok(files_scanned)
+}
+
+/// Scan `--registry-image` straight from its registry: fetch the manifest
+/// over the OCI distribution API and stream each layer through the scanner
+/// without writing it to disk. Whiteouts and replaced files are applied as
+/// the layers are stacked, so only files of the final image are reported.
+/// Findings are labelled `registry/repository:tag!path/in/image`.
+pub fn scan_image(config: &Config, image: &str) -> Result<(Vec<ScanResult>, ScanSummary), Box<dyn std::error::Error>> {
+    let image = ImageReference::parse(image)?;
+    info!("Scanning image {}", image);
+
+    let rules = RuleSet::from_config(config)?;
+    let mut registry = Registry::new(&image, config.registry_plain_http)?;
+    let layers = registry.layers(&image.reference)?;
+
+    let label = image.to_string();
+    let mut files = LayerFiles::new();
+    let mut files_scanned = 0;
+    for (index, layer) in layers.iter().enumerate() {
+        info!("Scanning layer {}/{}: {}", index + 1, layers.len(), layer.digest);
+        files_scanned += scan_layer(&mut registry, &label, index, layer, config, &rules, &mut files)
+            .map_err(|e| format!("layer {}: {}", layer.digest, e))?;
+    }
+
+    let results = files.into_values().flat_map(|(_, results)| results).collect();
+    let summary = ScanSummary {
+        files_scanned,
+        error_digest: scan_errors::take_digest(),
+        rule_stats: rule_stats::take(&rules, config),
+        archive_depth_reached: take_deepest_archive(),
+        rule_packs: rules.packs().to_vec(),
+        ..Default::default()
+    };
+    Ok((results, summary))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    const JAR: &[u8] = include_bytes!("../fixtures/log4j-fixture.jar");
+    const TOKEN: &str = "mock-token";
+
+    /// The path and headers of a request to the mock registry
+    struct Request {
+        path: String,
+        headers: Vec<(String, String)>,
+    }
+
+    impl Request {
+        fn header(&self, name: &str) -> Option<&str> {
+            self.headers.iter().find(|(key, _)| key.eq_ignore_ascii_case(name)).map(|(_, value)| value.as_str())
+        }
+    }
+
+    type Handler = dyn Fn(&Request, &str) -> Vec<u8> + Send + Sync;
+
+    /// Answer each request with `handle(request, base_url)`, one request per
+    /// connection, and return the `host:port` served on
+    fn serve(handle: Arc<Handler>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let host = listener.local_addr().unwrap().to_string();
+        let base_url = format!("http://{}", host);
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut reader = BufReader::new(&stream);
+                let mut request_line = String::new();
+                reader.read_line(&mut request_line).unwrap();
+                let mut headers = Vec::new();
+                let mut line = String::new();
+                while reader.read_line(&mut line).is_ok_and(|n| n > 2) {
+                    if let Some((key, value)) = line.trim_end().split_once(':') {
+                        headers.push((key.to_string(), value.trim().to_string()));
+                    }
+                    line.clear();
+                }
+                let path = request_line.split_whitespace().nth(1).unwrap_or_default().to_string();
+                let _ = stream.write_all(&handle(&Request { path, headers }, &base_url));
+            }
+        });
+        host
+    }
+
+    fn response(status: &str, headers: &[(&str, String)], body: &[u8]) -> Vec<u8> {
+        let mut head = format!("HTTP/1.1 {}\r\nContent-Length: {}\r\nConnection: close\r\n", status, body.len());
+        for (name, value) in headers {
+            head.push_str(&format!("{}: {}\r\n", name, value));
+        }
+        [head.into_bytes(), b"\r\n".to_vec(), body.to_vec()].concat()
+    }
+
+    fn not_found() -> Vec<u8> {
+        response("404 Not Found", &[], b"")
+    }
+
+    /// A gzip compressed layer tar of `files`, with its digest
+    fn layer(files: &[(&str, &[u8])]) -> (String, Vec<u8>) {
+        let mut tar = tar::Builder::new(GzEncoder::new(Vec::new(), Compression::default()));
+        for (path, contents) in files {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(contents.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            tar.append_data(&mut header, path, *contents).unwrap();
+        }
+        let blob = tar.into_inner().unwrap().finish().unwrap();
+        (format!("sha256:{:x}", Sha256::digest(&blob)), blob)
+    }
+
+    fn manifest(layers: &[(String, Vec<u8>)]) -> Vec<u8> {
+        let layers: Vec<Value> = layers.iter()
+            .map(|(digest, blob)| serde_json::json!({
+                "mediaType": "application/vnd.oci.image.layer.v1.tar+gzip",
+                "digest": digest,
+                "size": blob.len(),
+            }))
+            .collect();
+        serde_json::json!({"schemaVersion": 2, "mediaType": "application/vnd.oci.image.manifest.v1+json", "layers": layers}).to_string().into_bytes()
+    }
+
+    /// A registry serving `team/app:1.0` with `layers`, behind bearer token
+    /// auth like Docker Hub's
+    fn bearer_registry(layers: Vec<(String, Vec<u8>)>) -> String {
+        serve(Arc::new(move |request: &Request, base_url: &str| {
+            if request.path.starts_with("/token?") {
+                assert!(request.path.contains("scope=repository%3Ateam%2Fapp%3Apull"), "{}", request.path);
+                return response("200 OK", &[], format!("{{\"token\": \"{}\"}}", TOKEN).as_bytes());
+            }
+            if request.header("Authorization") != Some(&format!("Bearer {}", TOKEN)) {
+                let challenge = format!("Bearer realm=\"{}/token\",service=\"mock\"", base_url);
+                return response("401 Unauthorized", &[("WWW-Authenticate", challenge)], b"");
+            }
+            match request.path.strip_prefix("/v2/team/app/") {
+                Some("manifests/1.0") => response("200 OK", &[], &manifest(&layers)),
+                Some(blob) => match layers.iter().find(|(digest, _)| blob == format!("blobs/{}", digest)) {
+                    Some((_, contents)) => response("200 OK", &[], contents),
+                    None => not_found(),
+                },
+                None => not_found(),
+            }
+        }))
+    }
+
+    fn config() -> Config {
+        Config { registry_plain_http: true, ..Default::default() }
+    }
+
+    fn found(results: &[ScanResult]) -> Vec<&str> {
+        results.iter().filter(|r| r.vulnerable).map(|r| r.file_path.as_str()).collect()
+    }
+
+    #[test]
+    fn image_references_resolve_like_docker_pull() {
+        let parse = |image| ImageReference::parse(image).map(|image| image.to_string());
+        assert_eq!(parse("tomcat"), Ok("docker.io/library/tomcat:latest".to_string()));
+        assert_eq!(parse("bitnami/tomcat:9.0"), Ok("docker.io/bitnami/tomcat:9.0".to_string()));
+        assert_eq!(parse("localhost:5000/team/app:1.0"), Ok("localhost:5000/team/app:1.0".to_string()));
+        assert_eq!(parse("ghcr.io/team/app@sha256:abc"), Ok("ghcr.io/team/app@sha256:abc".to_string()));
+        assert_eq!(ImageReference::parse("localhost:5000/team/app").unwrap().api_host(), "localhost:5000");
+        assert_eq!(ImageReference::parse("tomcat").unwrap().api_host(), DOCKER_HUB_API);
+        assert!(parse("Team/App").is_err());
+        assert!(parse("team//app").is_err());
+    }
+
+    /// Layers are streamed in order: a whiteout in an upper layer hides a jar
+    /// of the layer below, and a replaced jar is reported as the upper layer has it
+    #[test]
+    fn layers_are_stacked_with_their_whiteouts() {
+        let lower = layer(&[
+            ("app/lib/log4j-core-2.14.1.jar", JAR),
+            ("opt/old/log4j-core-2.14.1.jar", JAR),
+            ("srv/log4j-core.jar", JAR),
+        ]);
+        let upper = layer(&[("opt/.wh.old", b""), ("srv/log4j-core.jar", b"PK\x05\x06 patched")]);
+        let host = bearer_registry(vec![lower, upper]);
+
+        let image = format!("{}/team/app:1.0", host);
+        let (results, summary) = scan_image(&config(), &image).unwrap();
+        assert_eq!(found(&results), [format!("{}!app/lib/log4j-core-2.14.1.jar", image)]);
+        assert_eq!(summary.files_scanned, 4);
+    }
+
+    /// A registry asking for basic auth gets the login
+    #[test]
+    fn basic_auth_sends_the_login() {
+        let (digest, blob) = layer(&[("app/log4j-core.jar", JAR)]);
+        let expected = format!("Basic {}", base64::engine::general_purpose::STANDARD.encode("scanner:secret"));
+        let layers = vec![(digest.clone(), blob)];
+        let host = serve(Arc::new(move |request: &Request, _: &str| {
+            if request.header("Authorization") != Some(expected.as_str()) {
+                return response("401 Unauthorized", &[("WWW-Authenticate", "Basic realm=\"mock\"".to_string())], b"");
+            }
+            match request.path.as_str() {
+                "/v2/team/app/manifests/1.0" => response("200 OK", &[], &manifest(&layers)),
+                _ => not_found(),
+            }
+        }));
+        let image = ImageReference::parse(&format!("{}/team/app:1.0", host)).unwrap();
+
+        let mut registry = Registry::new(&image, true).unwrap();
+        registry.credentials = None;
+        let error = registry.layers("1.0").err().unwrap();
+        assert!(error.starts_with("the registry needs a login"), "{}", error);
+
+        registry.credentials = Some(("scanner".to_string(), "secret".to_string()));
+        let layers = registry.layers("1.0").unwrap();
+        assert_eq!(layers.iter().map(|layer| layer.digest.as_str()).collect::<Vec<_>>(), [digest.as_str()]);
+    }
+
+    /// The first download of the layer stops halfway; the rest is fetched with
+    /// a range request and the layer still matches its digest
+    #[test]
+    fn interrupted_layer_resumes_with_a_range_request() {
+        let (digest, blob) = layer(&[("app/log4j-core.jar", JAR)]);
+        let layers = vec![(digest.clone(), blob.clone())];
+        let downloads = Arc::new(AtomicUsize::new(0));
+        let counted = Arc::clone(&downloads);
+        let host = serve(Arc::new(move |request: &Request, _: &str| {
+            match request.path.strip_prefix("/v2/team/app/") {
+                Some("manifests/1.0") => response("200 OK", &[], &manifest(&layers)),
+                Some(path) if path == format!("blobs/{}", digest) => match (counted.fetch_add(1, Ordering::SeqCst), request.header("Range")) {
+                    // Promise the whole blob, send half and hang up
+                    (0, _) => {
+                        let full = response("200 OK", &[], &blob);
+                        full[..full.len() - blob.len() / 2].to_vec()
+                    }
+                    (_, Some(range)) => {
+                        let start: usize = range.trim_start_matches("bytes=").trim_end_matches('-').parse().unwrap();
+                        let content_range = format!("bytes {}-{}/{}", start, blob.len() - 1, blob.len());
+                        response("206 Partial Content", &[("Content-Range", content_range)], &blob[start..])
+                    }
+                    (_, None) => response("200 OK", &[], &blob),
+                },
+                _ => not_found(),
+            }
+        }));
+
+        let image = format!("{}/team/app:1.0", host);
+        let (results, _) = scan_image(&config(), &image).unwrap();
+        assert_eq!(found(&results), [format!("{}!app/log4j-core.jar", image)]);
+        assert_eq!(downloads.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn missing_image_is_an_error() {
+        let host = bearer_registry(Vec::new());
+        let error = scan_image(&config(), &format!("{}/team/app:2.0", host)).unwrap_err();
+        assert!(error.to_string().contains("404"), "{}", error);
+    }
+}
@@ -0,0 +1,350 @@
+use crate::config::Config;
+use crate::log4j_config::is_log4j_config;
+use crate::rule_stats;
+use crate::rules::RuleSet;
+use crate::scan_errors;
+use crate::scanner::{scan_bytes, take_deepest_archive, ScanResult, ScanSummary};
+use crate::utils::{is_class_file, is_jar_file, is_jmod_file};
+use log::{debug, info};
+use std::path::Path;
+
+/// A `user@host:/path` scan target
+struct SshTarget {
+    destination: String,
+    path: String,
+}
+
+impl SshTarget {
+    fn parse(target: &str) -> Result<Self, String> {
+        let host_end = target.find('@').map(|at| at + 1).unwrap_or(0);
+        match target[host_end..].find(':').map(|colon| host_end + colon) {
+            Some(colon) if colon > host_end && colon + 1 < target.len() => Ok(SshTarget {
+                destination: target[..colon].to_string(),
+                path: target[colon + 1..].to_string(),
+            }),
+            _ => Err(format!("expected user@host:/path, got '{}'", target)),
+        }
+    }
+}
+
+/// Whether a remote file is worth downloading: the files scanned from memory
+fn is_remote_scannable(path: &str) -> bool {
+    let path = Path::new(path);
+    is_jar_file(path) || is_class_file(path) || is_jmod_file(path) || is_log4j_config(path)
+}
+
+/// List the jar, class, jmod and log4j configuration files under a remote path
+/// with `find`, download each over SFTP and scan it in memory. At most
+/// `--ssh-connections` transfers run at once. `--exclude`, `--since`,
+/// `--timeout` and `--max-findings` apply as they do to local scans. Findings
+/// are labelled `user@host:/path/to/file`.
+#[cfg(unix)]
+pub fn scan_ssh(config: &Config, target: &str) -> Result<(Vec<ScanResult>, ScanSummary), Box<dyn std::error::Error>> {
+    let target = SshTarget::parse(target)?;
+    info!("Scanning {} over SSH", target.destination);
+
+    let rules = RuleSet::from_config(config)?;
+    let connections = config.ssh_connections.or(config.threads).unwrap_or_else(num_cpus::get).max(1);
+    let runtime = tokio::runtime::Builder::new_multi_thread().enable_all().build()?;
+    runtime.block_on(ssh::scan(config, &target, &rules, connections))
+}
+
+/// SSH scanning needs OpenSSH's connection multiplexing, which is Unix only
+#[cfg(not(unix))]
+pub fn scan_ssh(_config: &Config, target: &str) -> Result<(Vec<ScanResult>, ScanSummary), Box<dyn std::error::Error>> {
+    SshTarget::parse(target)?;
+    Err("--ssh is not supported on this platform".into())
+}
+
+#[cfg(unix)]
+mod ssh {
+    use super::*;
+    use crate::scan_errors::ScanErrorKind;
+    use crate::scanner::{limit_findings, unread_scan_result, ScanStatus};
+    use crate::utils::format_rfc3339;
+    use glob::Pattern;
+    use openssh::{KnownHosts, SessionBuilder};
+    use openssh_sftp_client::file::TokioCompatFile;
+    use openssh_sftp_client::{Sftp, SftpOptions};
+    use std::sync::Arc;
+    use std::time::{Duration, SystemTime};
+    use tokio::io::{AsyncRead, AsyncReadExt};
+    use tokio::sync::Semaphore;
+    use tokio::task::{JoinError, JoinSet};
+    use tokio::time::Instant;
+
+    /// Remote files larger than this are reported as skipped instead of
+    /// being downloaded into memory
+    const MAX_DOWNLOAD_SIZE: u64 = 512 * 1024 * 1024;
+
+    /// Why a remote file was not scanned
+    #[derive(Debug)]
+    pub(super) enum TransferError {
+        /// Larger than `MAX_DOWNLOAD_SIZE`, by its listed size or by the bytes received
+        TooLarge,
+        Failed(String),
+    }
+
+    impl From<openssh_sftp_client::Error> for TransferError {
+        fn from(error: openssh_sftp_client::Error) -> Self {
+            TransferError::Failed(error.to_string())
+        }
+    }
+
+    impl From<std::io::Error> for TransferError {
+        fn from(error: std::io::Error) -> Self {
+            TransferError::Failed(error.to_string())
+        }
+    }
+
+    /// What became of one listed file
+    enum Transfer {
+        Scanned(Vec<ScanResult>),
+        Unscanned(Box<ScanResult>),
+        /// Not modified since `--since`
+        Unchanged,
+    }
+
+    /// Read a download to the end, failing once it passes `limit` bytes: a
+    /// file can grow between checking its size and reading it
+    pub(super) async fn read_limited<R: AsyncRead + Unpin>(reader: R, limit: u64) -> Result<Vec<u8>, TransferError> {
+        let mut data = Vec::new();
+        reader.take(limit + 1).read_to_end(&mut data).await?;
+        if data.len() as u64 > limit {
+            return Err(TransferError::TooLarge);
+        }
+        Ok(data)
+    }
+
+    /// The result recording why a remote file was not scanned. Failed
+    /// downloads are counted as scan errors.
+    pub(super) fn unscanned_result(name: &str, error: TransferError, config: &Config) -> ScanResult {
+        match error {
+            TransferError::TooLarge => {
+                unread_scan_result(name, ScanStatus::Skipped, format!("Larger than the {} MB download limit", MAX_DOWNLOAD_SIZE / 1024 / 1024), config)
+            }
+            TransferError::Failed(e) => {
+                scan_errors::record(name, ScanErrorKind::Read, format_args!("Error downloading file: {} - {}", name, e));
+                unread_scan_result(name, ScanStatus::Error, format!("Download failed: {}", e), config)
+            }
+        }
+    }
+
+    /// Download a remote file unless it is unchanged since `since` or too large
+    async fn download(sftp: &Sftp, path: &str, since: Option<SystemTime>) -> Result<Option<Vec<u8>>, TransferError> {
+        let mut file = sftp.open(path).await?;
+        let metadata = file.metadata().await?;
+        let modified = metadata.modified().map(|modified| modified.as_system_time());
+        if matches!((since, modified), (Some(cutoff), Some(modified)) if modified < cutoff) {
+            return Ok(None);
+        }
+        if metadata.len().is_some_and(|size| size > MAX_DOWNLOAD_SIZE) {
+            return Err(TransferError::TooLarge);
+        }
+        read_limited(Box::pin(TokioCompatFile::new(file)), MAX_DOWNLOAD_SIZE).await.map(Some)
+    }
+
+    /// Re-raise a transfer's panic; cancelled transfers are left out
+    fn finished<T>(transfer: Result<T, JoinError>) -> Option<T> {
+        match transfer {
+            Ok(value) => Some(value),
+            Err(e) if e.is_panic() => std::panic::resume_unwind(e.into_panic()),
+            Err(_) => None,
+        }
+    }
+
+    pub(super) async fn scan(config: &Config, target: &SshTarget, rules: &RuleSet, connections: usize) -> Result<(Vec<ScanResult>, ScanSummary), Box<dyn std::error::Error>> {
+        let exclude_patterns: Vec<Pattern> = config.exclude_patterns().into_iter()
+            .map(|pattern| Pattern::new(pattern).map_err(|e| format!("invalid exclude pattern {}: {}", pattern, e)))
+            .collect::<Result<_, _>>()?;
+        let deadline = config.timeout.map(|seconds| Instant::now() + Duration::from_secs(seconds));
+
+        let mut builder = SessionBuilder::default();
+        builder.known_hosts_check(KnownHosts::Strict);
+        if let Some(key) = &config.ssh_key {
+            builder.keyfile(key);
+        }
+        let session = builder.connect(&target.destination).await
+            .map_err(|e| format!("unable to connect to {}: {}", target.destination, e))?;
+
+        let listing = session.command("find")
+            .arg(&target.path)
+            .args(["-type", "f", "(", "-iname", "*.jar", "-o", "-iname", "*.class*", "-o", "-iname", "*.jmod", "-o", "-iname", "log4j*", ")"])
+            .output().await
+            .map_err(|e| format!("unable to list {}: {}", target.path, e))?;
+        if !listing.status.success() && listing.stdout.is_empty() {
+            return Err(format!("find failed on {}: {}", target.destination, String::from_utf8_lossy(&listing.stderr).trim()).into());
+        }
+        let paths: Vec<String> = String::from_utf8_lossy(&listing.stdout).lines()
+            .filter(|path| is_remote_scannable(path))
+            .filter(|path| !exclude_patterns.iter().any(|pattern| pattern.matches(path)))
+            .map(String::from)
+            .collect();
+        debug!("Found {} files to download from {}", paths.len(), target.destination);
+
+        let sftp = Arc::new(Sftp::from_session(session, SftpOptions::default()).await
+            .map_err(|e| format!("unable to start SFTP on {}: {}", target.destination, e))?);
+        let permits = Arc::new(Semaphore::new(connections));
+        let rules = Arc::new(rules.clone());
+        let scan_config = Arc::new(config.clone());
+        let since = config.since;
+        let mut transfers = JoinSet::new();
+        for (index, path) in paths.into_iter().enumerate() {
+            let (sftp, permits, rules, config) = (Arc::clone(&sftp), Arc::clone(&permits), Arc::clone(&rules), Arc::clone(&scan_config));
+            let name = format!("{}:{}", target.destination, path);
+            transfers.spawn(async move {
+                let data = {
+                    let _permit = permits.acquire().await.expect("semaphore is never closed");
+                    download(&sftp, &path, since).await
+                };
+                let transfer = match data {
+                    Ok(Some(data)) => {
+                        let results = tokio::task::spawn_blocking(move || scan_bytes(&name, &data, &config, &rules)).await;
+                        Transfer::Scanned(finished(results).unwrap_or_default())
+                    }
+                    Ok(None) => Transfer::Unchanged,
+                    Err(e) => Transfer::Unscanned(Box::new(unscanned_result(&name, e, &config))),
+                };
+                (index, transfer)
+            });
+        }
+
+        let mut scanned = Vec::new();
+        let mut files_scanned = 0;
+        let mut found = 0;
+        let (mut truncated, mut timed_out) = (false, false);
+        loop {
+            let next = match deadline {
+                Some(deadline) => match tokio::time::timeout_at(deadline, transfers.join_next()).await {
+                    Ok(next) => next,
+                    Err(_) => {
+                        timed_out = true;
+                        break;
+                    }
+                },
+                None => transfers.join_next().await,
+            };
+            let Some(transfer) = next else { break };
+            match finished(transfer) {
+                Some((index, Transfer::Scanned(results))) => {
+                    files_scanned += 1;
+                    found += results.iter().filter(|r| r.vulnerable).count();
+                    scanned.push((index, results));
+                }
+                Some((index, Transfer::Unscanned(result))) => scanned.push((index, vec![*result])),
+                Some((_, Transfer::Unchanged)) | None => {}
+            }
+            if config.max_findings.is_some_and(|max| found >= max) {
+                truncated = true;
+                break;
+            }
+        }
+        transfers.abort_all();
+        while let Some(transfer) = transfers.join_next().await {
+            finished(transfer);
+        }
+        // Report in listing order, whatever order the transfers finished in
+        scanned.sort_by_key(|(index, _)| *index);
+        let mut results: Vec<ScanResult> = scanned.into_iter().flat_map(|(_, results)| results).collect();
+        if let Some(max) = config.max_findings {
+            limit_findings(&mut results, max);
+        }
+
+        if let Ok(sftp) = Arc::try_unwrap(sftp) {
+            if let Err(e) = sftp.close().await {
+                debug!("Error closing SFTP session: {}", e);
+            }
+        }
+
+        let summary = ScanSummary {
+            files_scanned,
+            truncated,
+            timed_out,
+            since: config.since.map(format_rfc3339),
+            error_digest: scan_errors::take_digest(),
+            rule_stats: rule_stats::take(&rules, config),
+            archive_depth_reached: take_deepest_archive(),
+            rule_packs: rules.packs().to_vec(),
+            ..Default::default()
+        };
+        Ok((results, summary))
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::ssh::{read_limited, unscanned_result, TransferError};
+    use super::*;
+    use crate::scanner::ScanStatus;
+    use std::io;
+    use std::pin::Pin;
+    use std::task::{Context, Poll};
+    use tokio::io::{AsyncRead, ReadBuf};
+
+    /// A download that sends `remaining` bytes and then loses its connection
+    struct FailingReader {
+        remaining: usize,
+    }
+
+    impl AsyncRead for FailingReader {
+        fn poll_read(mut self: Pin<&mut Self>, _cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+            if self.remaining == 0 {
+                return Poll::Ready(Err(io::Error::new(io::ErrorKind::ConnectionReset, "connection lost")));
+            }
+            let sent = self.remaining.min(buf.remaining()).min(1024);
+            buf.put_slice(&vec![b'x'; sent]);
+            self.remaining -= sent;
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    fn block_on<F: std::future::Future>(future: F) -> F::Output {
+        tokio::runtime::Builder::new_current_thread().build().unwrap().block_on(future)
+    }
+
+    #[test]
+    fn parses_ssh_targets() {
+        let target = SshTarget::parse("user@host:/opt/app").unwrap();
+        assert_eq!((target.destination.as_str(), target.path.as_str()), ("user@host", "/opt/app"));
+        assert!(SshTarget::parse("user@host").is_err());
+        assert!(SshTarget::parse("host:").is_err());
+    }
+
+    #[test]
+    fn reads_downloads_up_to_the_limit() {
+        let data = block_on(read_limited(&b"PK\x03\x04"[..], 4)).unwrap();
+        assert_eq!(data, b"PK\x03\x04");
+    }
+
+    #[test]
+    fn downloads_past_the_limit_are_too_large() {
+        let error = block_on(read_limited(&b"PK\x03\x04"[..], 3)).unwrap_err();
+        assert!(matches!(error, TransferError::TooLarge));
+    }
+
+    #[test]
+    fn interrupted_downloads_fail() {
+        let error = block_on(read_limited(FailingReader { remaining: 4096 }, 1 << 20)).unwrap_err();
+        assert!(matches!(error, TransferError::Failed(ref e) if e.contains("connection lost")));
+    }
+
+    #[test]
+    fn failed_downloads_are_scan_errors() {
+        let name = "user@failed-download:/opt/app/lib/log4j-core.jar";
+        let error = block_on(read_limited(FailingReader { remaining: 10 }, 1 << 20)).unwrap_err();
+        let result = unscanned_result(name, error, &Config::default());
+        assert_eq!(result.file_path, name);
+        assert_eq!(result.status, ScanStatus::Error);
+        assert!(!result.vulnerable);
+        assert!(result.reason.unwrap().contains("connection lost"));
+        assert!(crate::scan_errors::take_message(Path::new(name)).is_some());
+    }
+
+    #[test]
+    fn oversized_downloads_are_skipped() {
+        let name = "user@too-large:/opt/app/huge.jar";
+        let result = unscanned_result(name, TransferError::TooLarge, &Config::default());
+        assert_eq!(result.status, ScanStatus::Skipped);
+        assert!(crate::scan_errors::take_message(Path::new(name)).is_none());
+    }
+}
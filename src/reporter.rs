@@ -1,43 +1,504 @@
-use crate::scanner::ScanResult;
-use crate::config::Config;
-use log::info;
+use crate::annotations;
+use crate::rule_stats::RuleStats;
+use crate::scanner::{jndi_host, ScanResult, ScanStatus, ScanSummary, Severity};
+use crate::config::{Config, HyperlinkMode, OutputLevel};
+use crate::coverage::coverage_line;
+use crate::scan_errors::digest_line;
+use crate::rules::cve_name;
+use crate::schema::SCHEMA_VERSION;
+use crate::utils::{terminal_supports_hyperlinks, HashAlgorithm};
+use base64::Engine;
+use comfy_table::{presets, CellAlignment, ColumnConstraint, ContentArrangement, Table};
+use report_filter::{FilterNote, ReportFilter};
 use serde_json;
-use std::fs::File;
-use std::io::{self, Write};
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::fs::{self, File};
+use std::io::{self, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
 
-pub fn report_results(results: &[ScanResult], config: &Config) -> io::Result<()> {
-    let output: Box<dyn Write> = if let Some(path) = &config.output {
+mod report_filter;
+mod stix;
+mod table;
+mod teamcity;
+mod text_stable;
+
+/// Unique artifacts listed under "Top remediation targets"
+const TOP_REMEDIATION_TARGETS: usize = 10;
+/// Characters of the file hash shown in the findings table
+const TABLE_HASH_LENGTH: usize = 12;
+
+#[derive(serde::Serialize)]
+struct JsonReport<'a> {
+    schema_version: u64,
+    summary: ReportSummary<'a>,
+    results: Vec<&'a ScanResult>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    grouped: Option<Vec<DuplicateGroup<'a>>>,
+    /// The settings the scan ran with, reusable as a `--config` file
+    scan_config: &'a Config,
+}
+
+#[derive(serde::Serialize)]
+struct ReportSummary<'a> {
+    #[serde(flatten)]
+    scan: &'a ScanSummary,
+    total_affected_paths: usize,
+    unique_vulnerable_artifacts: usize,
+    skipped: usize,
+    errors: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    by_directory: Option<Vec<DirectorySummary>>,
+    #[serde(skip_serializing_if = "BTreeSet::is_empty")]
+    unique_jndi_hosts: BTreeSet<String>,
+    /// How many findings `results` lists under `--report-filter`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    report_filter: Option<FilterNote>,
+}
+
+/// Findings under one directory, for `--summary-by-dir`
+#[derive(Debug, Default, serde::Serialize)]
+pub struct DirectorySummary {
+    pub directory: String,
+    pub files_scanned: usize,
+    pub vulnerable: usize,
+    pub critical: usize,
+    pub high: usize,
+    pub medium: usize,
+    pub low: usize,
+    pub unique_vulnerable_hashes: usize,
+}
+
+/// Digests duplicates are grouped by, the first one computed
+pub const DEDUP_HASHES: [HashAlgorithm; 4] = [HashAlgorithm::Sha256, HashAlgorithm::Blake3, HashAlgorithm::Sha3, HashAlgorithm::Xxh3];
+
+/// Vulnerable files sharing a hash, i.e. one artifact reachable through several paths
+#[derive(serde::Serialize)]
+struct DuplicateGroup<'a> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    hash_algorithm: Option<HashAlgorithm>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    hash: Option<&'a str>,
+    finding: &'a ScanResult,
+    paths: Vec<&'a str>,
+}
+
+/// Group vulnerable results by file hash, in order of first appearance.
+/// Results without any digest are never merged.
+fn group_duplicates<'a>(results: impl IntoIterator<Item = &'a ScanResult>) -> Vec<DuplicateGroup<'a>> {
+    let mut groups: Vec<DuplicateGroup> = Vec::new();
+    for result in results.into_iter().filter(|r| r.vulnerable) {
+        let key = DEDUP_HASHES.iter().find_map(|&algorithm| result.hash(algorithm).map(|hash| (algorithm, hash)));
+        let existing = key.and_then(|(algorithm, hash)| groups.iter_mut()
+            .find(|g| g.hash_algorithm == Some(algorithm) && g.hash == Some(hash)));
+        match existing {
+            Some(group) => group.paths.push(&result.file_path),
+            None => groups.push(DuplicateGroup {
+                hash_algorithm: key.map(|(algorithm, _)| algorithm),
+                hash: key.map(|(_, hash)| hash),
+                finding: result,
+                paths: vec![&result.file_path],
+            }),
+        }
+    }
+    groups
+}
+
+/// Aggregate scanned files and results by the directory `depth` levels below
+/// the scan root each was found under, in directory order. Files nearer the
+/// root than `depth` count towards their own directory.
+pub fn summarize_by_dir(results: &[ScanResult], summary: &ScanSummary, depth: usize) -> Vec<DirectorySummary> {
+    let mut directories: BTreeMap<PathBuf, (DirectorySummary, BTreeSet<&str>)> = BTreeMap::new();
+    let empty = |directory: &PathBuf| (DirectorySummary { directory: directory.to_string_lossy().to_string(), ..Default::default() }, BTreeSet::new());
+
+    for path in &summary.scanned_files {
+        directories.entry(directory_at_depth(path, &summary.roots, depth)).or_insert_with_key(empty).0.files_scanned += 1;
+    }
+    for result in results.iter().filter(|r| r.vulnerable) {
+        // Findings inside archives are labelled archive!entry
+        let path = Path::new(result.file_path.split('!').next().unwrap_or_default());
+        let (directory, hashes) = directories.entry(directory_at_depth(path, &summary.roots, depth)).or_insert_with_key(empty);
+        directory.vulnerable += 1;
+        match result.severity {
+            Some(Severity::Critical) => directory.critical += 1,
+            Some(Severity::High) => directory.high += 1,
+            Some(Severity::Medium) => directory.medium += 1,
+            Some(Severity::Low) => directory.low += 1,
+            None => {}
+        }
+        // Without a digest, every vulnerable file counts as unique
+        hashes.insert(DEDUP_HASHES.iter().find_map(|&algorithm| result.hash(algorithm)).unwrap_or(&result.file_path));
+    }
+
+    directories.into_values()
+        .map(|(directory, hashes)| DirectorySummary { unique_vulnerable_hashes: hashes.len(), ..directory })
+        .collect()
+}
+
+/// The directory `depth` levels below whichever of `roots` contains `path`
+/// most closely, or below the filesystem root if none does
+fn directory_at_depth(path: &Path, roots: &[PathBuf], depth: usize) -> PathBuf {
+    let root = roots.iter()
+        .filter(|root| path.starts_with(root))
+        .max_by_key(|root| root.components().count());
+    let (base, relative) = match root {
+        Some(root) => (root.clone(), path.strip_prefix(root).unwrap_or(path)),
+        None => (PathBuf::new(), path),
+    };
+    let below: PathBuf = relative.parent().unwrap_or(Path::new("")).components().take(depth).collect();
+    if below.as_os_str().is_empty() {
+        base
+    } else {
+        base.join(below)
+    }
+}
+
+pub fn report_results(results: &[ScanResult], summary: &ScanSummary, config: &Config) -> io::Result<()> {
+    // Silent keeps stdout for machine-readable reports only
+    if config.output_level == OutputLevel::Silent && matches!(config.format.as_str(), "text" | "table") && config.output.is_none() {
+        return Ok(());
+    }
+
+    if let (Some(megabytes), Some(path)) = (config.output_rotate, &config.output) {
+        let mut output = RotatingWriter::create(Path::new(path), megabytes * 1024 * 1024, config.encoding == "utf16le")?;
+        report_json_rotating(results, summary, &mut output, config)?;
+        return output.finish();
+    }
+
+    let mut compressed = None;
+    let output: Box<dyn Write> = if let (Some(path), true) = (&config.output, config.output_compress) {
+        let encoder = Rc::new(RefCell::new(GzEncoder::new(File::create(path)?, Compression::new(config.output_compress_level))));
+        compressed = Some(Rc::clone(&encoder));
+        Box::new(GzipOutput(encoder))
+    } else if let Some(path) = &config.output {
         Box::new(File::create(path)?)
     } else {
         Box::new(io::stdout())
     };
+    // MessagePack is binary and is never transcoded
+    let output: Box<dyn Write> = if config.encoding == "utf16le" && config.format != "msgpack" {
+        Box::new(Utf16LeWriter::new(output)?)
+    } else {
+        output
+    };
 
-    match config.format.as_str() {
-        "json" => report_json(results, output, config.quiet),
-        _ => report_text(results, output, config.quiet),
+    write_report(results, summary, output, config)?;
+    match compressed.and_then(|encoder| Rc::try_unwrap(encoder).ok()) {
+        Some(encoder) => encoder.into_inner().finish().map(drop),
+        None => Ok(()),
     }
 }
 
-fn report_text(results: &[ScanResult], mut output: Box<dyn Write>, quiet: bool) -> io::Result<()> {
-    let vulnerable_results: Vec<_> = results.iter().filter(|r| r.vulnerable).collect();
-    let vulnerable_count = vulnerable_results.len();
-    
+/// Write the report in `config.format` to `output`
+fn write_report(results: &[ScanResult], summary: &ScanSummary, output: Box<dyn Write>, config: &Config) -> io::Result<()> {
+    // Reports with totals filter their own listings; the rest only list
+    let shown = report_filter::shown(results, ReportFilter::of(config).as_ref());
+    let written = match config.format.as_str() {
+        "json" => report_json(results, summary, output, config),
+        "msgpack" => report_msgpack(&shown, output, config.quiet(), false),
+        "msgpack-base64" => report_msgpack(&shown, output, config.quiet(), true),
+        "checkstyle" => report_checkstyle(&shown, output, config),
+        "stix" => stix::report_stix(&shown, output),
+        "text-stable" => text_stable::report_text_stable(&shown, output),
+        "teamcity" => teamcity::report_teamcity(results, summary, output, config),
+        "table" => table::report_table(&shown, output, config),
+        _ => report_text(results, summary, output, config),
+    };
+    // A reader that stops early, like `| head`, closes the pipe; that ends
+    // the report the way it ends any other shell tool's output
+    match written {
+        Err(e) if e.kind() == io::ErrorKind::BrokenPipe => Ok(()),
+        written => written,
+    }
+}
+
+/// The `--output-compress` file. The reporters drop their writer when they
+/// are done, so the encoder is shared with `report_results`, which finishes
+/// it and so can report an error writing the gzip trailer.
+struct GzipOutput(Rc<RefCell<GzEncoder<File>>>);
+
+impl Write for GzipOutput {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.borrow_mut().flush()
+    }
+}
+
+/// The `table` report of `columns` as a terminal `width` characters wide
+/// would show it, without colors
+pub fn table_at_width(results: &[ScanResult], columns: &[&str], width: usize) -> String {
+    table::render(results, columns, Some(width), false)
+}
+
+/// The files the report was written to: `output`, followed by its numbered
+/// parts when `--output-rotate` split it
+pub fn output_parts(config: &Config) -> Vec<PathBuf> {
+    let output = match &config.output {
+        Some(output) => PathBuf::from(output),
+        None => return Vec::new(),
+    };
+    let mut parts = vec![output.clone()];
+    if config.output_rotate.is_some() {
+        parts.extend((1..).map(|part| part_path(&output, part)).take_while(|path| path.exists()));
+    }
+    parts
+}
+
+/// `report.json` becomes `report.json.1`, `report.json.2` and so on
+fn part_path(path: &Path, part: usize) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(format!(".{}", part));
+    PathBuf::from(name)
+}
+
+/// Writes a report to `path`, then to `path.1`, `path.2` and so on as the
+/// report rotates it. Counts the bytes written to the current file, before
+/// any UTF-16 transcoding, so the report knows when to rotate.
+struct RotatingWriter {
+    path: PathBuf,
+    limit: u64,
+    utf16: bool,
+    part: usize,
+    written: u64,
+    inner: Box<dyn Write>,
+}
+
+impl RotatingWriter {
+    fn create(path: &Path, limit: u64, utf16: bool) -> io::Result<Self> {
+        let inner = Self::open(path, utf16)?;
+        Ok(RotatingWriter { path: path.to_path_buf(), limit, utf16, part: 0, written: 0, inner })
+    }
+
+    fn open(path: &Path, utf16: bool) -> io::Result<Box<dyn Write>> {
+        let file = io::BufWriter::new(File::create(path)?);
+        if utf16 {
+            Ok(Box::new(Utf16LeWriter::new(file)?))
+        } else {
+            Ok(Box::new(file))
+        }
+    }
+
+    /// Whether writing `len` more bytes would take the current file past the limit
+    fn would_overflow(&self, len: usize) -> bool {
+        self.written + len as u64 > self.limit
+    }
+
+    /// Continue in the next numbered file
+    fn rotate(&mut self) -> io::Result<()> {
+        self.inner.flush()?;
+        self.part += 1;
+        self.inner = Self::open(&part_path(&self.path, self.part), self.utf16)?;
+        self.written = 0;
+        Ok(())
+    }
+
+    /// Flush the last file and remove the higher-numbered parts an earlier,
+    /// larger report left behind, so every part on disk belongs to this one
+    fn finish(mut self) -> io::Result<()> {
+        self.inner.flush()?;
+        for stale in (self.part + 1..).map(|part| part_path(&self.path, part)).take_while(|path| path.exists()) {
+            fs::remove_file(&stale)?;
+        }
+        Ok(())
+    }
+}
+
+impl Write for RotatingWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.written += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Transcodes the UTF-8 report text to UTF-16LE, starting with a byte order mark,
+/// for Windows tools such as PowerShell and Excel
+struct Utf16LeWriter<W: Write> {
+    inner: W,
+    /// Trailing bytes of a UTF-8 sequence split across writes
+    pending: Vec<u8>,
+}
+
+impl<W: Write> Utf16LeWriter<W> {
+    const BOM: [u8; 2] = [0xFF, 0xFE];
+
+    fn new(mut inner: W) -> io::Result<Self> {
+        inner.write_all(&Self::BOM)?;
+        Ok(Utf16LeWriter { inner, pending: Vec::new() })
+    }
+}
+
+impl<W: Write> Write for Utf16LeWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.pending.extend_from_slice(buf);
+        let valid_up_to = match std::str::from_utf8(&self.pending) {
+            Ok(text) => text.len(),
+            Err(e) if e.error_len().is_none() => e.valid_up_to(),
+            Err(e) => return Err(io::Error::new(io::ErrorKind::InvalidData, e)),
+        };
+
+        let text = std::str::from_utf8(&self.pending[..valid_up_to]).unwrap();
+        let encoded: Vec<u8> = text.encode_utf16().flat_map(u16::to_le_bytes).collect();
+        self.inner.write_all(&encoded)?;
+        self.pending.drain(..valid_up_to);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+fn report_text(results: &[ScanResult], summary: &ScanSummary, mut output: Box<dyn Write>, config: &Config) -> io::Result<()> {
+    let quiet = config.quiet();
+    let groups = group_duplicates(results);
+    // The counts above are of the whole scan; the listings below, of what --report-filter keeps
+    let filter = ReportFilter::of(config);
+    let listed: Vec<&ScanResult> = results.iter().filter(|r| filter.as_ref().is_none_or(|filter| filter.matches(r))).collect();
+    let vulnerable_results: Vec<&ScanResult> = listed.iter().copied().filter(|r| r.vulnerable).collect();
+    let listed_groups = group_duplicates(vulnerable_results.iter().copied());
+
+    let layout = TableLayout::of(config);
+    let links = hyperlinks(config);
+
     if !quiet {
-        writeln!(output, "Scan Results:")?;
-        writeln!(output, "Total files scanned: {}", results.len())?;
-        writeln!(output, "Vulnerable files found: {}", vulnerable_count)?;
+        write_header(&mut output, results, summary, &groups, !config.no_summary_histogram)?;
+        if let Some(filter) = &filter {
+            writeln!(output, "{}", filter.note(results).line())?;
+        }
+        if !config.no_summary {
+            write_summary_block(&mut output, results, summary, &groups, layout)?;
+        }
+    }
+
+    if let Some(depth) = config.summary_by_dir {
+        write_directory_table(&mut output, &summarize_by_dir(results, summary, depth))?;
+    }
+
+    if !summary.permission_denied.is_empty() {
+        writeln!(output, "Files skipped due to missing read permission: {}", summary.permission_denied.len())?;
+        if !quiet {
+            for path in &summary.permission_denied {
+                writeln!(output, "- {}", hyperlink(&path.to_string_lossy(), links))?;
+            }
+        }
+    }
+
+    if !summary.error_digest.is_empty() {
+        writeln!(output, "Errors reading files:")?;
+        for entry in &summary.error_digest {
+            writeln!(output, "- {}", digest_line(entry))?;
+        }
+    }
+
+    if let Some(coverage) = &summary.coverage {
+        if !quiet && coverage.skipped > 0 {
+            writeln!(output, "Skipped by reason:")?;
+            for (reason, count) in &coverage.skipped_by_reason {
+                writeln!(output, "- {}: {}", reason, count)?;
+            }
+        }
+        if !coverage.skipped_entries.is_empty() {
+            writeln!(output, "Skipped entries:")?;
+            for entry in &coverage.skipped_entries {
+                writeln!(output, "- {} [{}]", hyperlink(&entry.path.to_string_lossy(), links), entry.reason)?;
+            }
+        }
     }
+
+    let unscanned: Vec<_> = listed.iter().filter(|r| r.status != ScanStatus::Vulnerable).collect();
+    if !unscanned.is_empty() {
+        writeln!(output, "Files that could not be scanned: {}", unscanned.len())?;
+        if !quiet {
+            for result in unscanned {
+                writeln!(output, "- {} [{:?}]: {}", hyperlink(&result.file_path, links), result.status, result.reason.as_deref().unwrap_or_default())?;
+            }
+        }
+    }
+
+    write_incomplete_notice(&mut output, summary)?;
     
-    if vulnerable_count > 0 {
+    if config.group_duplicates && !vulnerable_results.is_empty() {
+        writeln!(output, "\nVulnerable Artifacts:")?;
+        for group in &listed_groups {
+            let result = group.finding;
+            writeln!(output, "- {}", group.hash.unwrap_or("unknown hash"))?;
+            writeln!(output, "  Size: {} bytes", result.file_size)?;
+            if let Some(coordinates) = &result.maven_coordinates {
+                writeln!(output, "  Maven: {}", coordinates)?;
+            }
+            if let Some(class_version) = &result.class_version {
+                writeln!(output, "  Compiled for: {}", class_version)?;
+            }
+            if let (Some(compressed), Some(uncompressed)) = (result.compressed_size_bytes, result.uncompressed_size_bytes) {
+                writeln!(output, "  Entry size: {} bytes, {} compressed", uncompressed, compressed)?;
+            }
+            if let Some(annotations) = &result.annotations {
+                writeln!(output, "  Annotations: {}", annotations::describe(annotations))?;
+            }
+            if let Some(reason) = &result.reason {
+                writeln!(output, "  Reason: {}", reason)?;
+            }
+            if let Some(severity) = &result.severity {
+                writeln!(output, "  Severity: {}", severity)?;
+            }
+            if let Some(confidence) = &result.confidence {
+                writeln!(output, "  Confidence: {:?}", confidence)?;
+            }
+            writeln!(output, "  Locations ({}):", group.paths.len())?;
+            for path in &group.paths {
+                writeln!(output, "    {}", hyperlink(path, links))?;
+            }
+            writeln!(output)?;
+        }
+    } else if let (Some(layout), false) = (layout, vulnerable_results.is_empty()) {
+        writeln!(output, "\nVulnerable Files:")?;
+        write_findings_table(&mut output, &vulnerable_results, layout, links)?;
+    } else if !vulnerable_results.is_empty() {
         writeln!(output, "\nVulnerable Files:")?;
         for result in vulnerable_results {
-            writeln!(output, "- {}", result.file_path)?;
-            writeln!(output, "  Hash: {}", result.file_hash)?;
+            writeln!(output, "- {}", hyperlink(&result.file_path, links))?;
+            if let Some(hash) = &result.file_hash {
+                writeln!(output, "  Hash: {}", hash)?;
+            }
+            writeln!(output, "  Size: {} bytes", result.file_size)?;
+            if let Some(modified) = &result.modified {
+                writeln!(output, "  Modified: {}", modified)?;
+            }
+            if let Some(coordinates) = &result.maven_coordinates {
+                writeln!(output, "  Maven: {}", coordinates)?;
+            }
+            if let Some(class_version) = &result.class_version {
+                writeln!(output, "  Compiled for: {}", class_version)?;
+            }
+            if let (Some(compressed), Some(uncompressed)) = (result.compressed_size_bytes, result.uncompressed_size_bytes) {
+                writeln!(output, "  Entry size: {} bytes, {} compressed", uncompressed, compressed)?;
+            }
+            if let Some(annotations) = &result.annotations {
+                writeln!(output, "  Annotations: {}", annotations::describe(annotations))?;
+            }
             if let Some(reason) = &result.reason {
                 writeln!(output, "  Reason: {}", reason)?;
             }
             if let Some(severity) = &result.severity {
-                writeln!(output, "  Severity: {:?}", severity)?;
+                writeln!(output, "  Severity: {}", severity)?;
+            }
+            if let Some(confidence) = &result.confidence {
+                writeln!(output, "  Confidence: {:?}", confidence)?;
+            }
+            if !result.jndi_urls.is_empty() {
+                writeln!(output, "  JNDI URLs: {}", result.jndi_urls.join(", "))?;
             }
             writeln!(output)?;
         }
@@ -46,14 +507,709 @@ fn report_text(results: &[ScanResult], mut output: Box<dyn Write>, quiet: bool)
     Ok(())
 }
 
-fn report_json(results: &[ScanResult], mut output: Box<dyn Write>, quiet: bool) -> io::Result<()> {
-    let json = if quiet {
-        let vulnerable_results: Vec<_> = results.iter().filter(|r| r.vulnerable).collect();
-        serde_json::to_string_pretty(&vulnerable_results)
-    } else {
-        serde_json::to_string_pretty(&results)
-    }.map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+/// The text report without its per-file listings: the counts, the summary
+/// block and any `--summary-by-dir` table, for email bodies
+pub fn text_summary(results: &[ScanResult], summary: &ScanSummary, config: &Config) -> io::Result<String> {
+    let mut output = Vec::new();
+    let groups = group_duplicates(results);
+    write_header(&mut output, results, summary, &groups, !config.no_summary_histogram)?;
+    write_summary_block(&mut output, results, summary, &groups, None)?;
+    if let Some(depth) = config.summary_by_dir {
+        write_directory_table(&mut output, &summarize_by_dir(results, summary, depth))?;
+    }
+    write_incomplete_notice(&mut output, summary)?;
+    String::from_utf8(output).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+/// The scan counts, with a bar of each severity's share of the findings
+/// under the vulnerable count when `histogram` is set
+fn write_header(output: &mut dyn Write, results: &[ScanResult], summary: &ScanSummary, groups: &[DuplicateGroup], histogram: bool) -> io::Result<()> {
+    let vulnerable: Vec<&ScanResult> = results.iter().filter(|r| r.vulnerable).collect();
+    writeln!(output, "Scan Results:")?;
+    if let Some(since) = &summary.since {
+        writeln!(output, "Modified since: {}", since)?;
+    }
+    if let Some(fingerprint) = &summary.signing_key_fingerprint {
+        writeln!(output, "Signing key: {}", fingerprint)?;
+    }
+    if let Some(audit_log) = &summary.audit_log {
+        writeln!(output, "Audit log: {} (scan {})", audit_log.path, audit_log.scan_id)?;
+    }
+    for pack in &summary.rule_packs {
+        writeln!(output, "Rule pack: {} {} ({})", pack.name, pack.version.as_deref().unwrap_or("(unversioned)"), pack.source)?;
+    }
+    if !summary.discovered_roots.is_empty() {
+        writeln!(output, "Discovered Java installations: {}", summary.discovered_roots.len())?;
+        for root in &summary.discovered_roots {
+            writeln!(output, "- {} [{}, via {}]: {} vulnerable, {} unscannable", root.path.display(), root.kind, root.found_via, root.findings, root.unscannable)?;
+        }
+    }
+    if !summary.stale_annotations.is_empty() {
+        writeln!(output, "Annotations matching no result: {}", summary.stale_annotations.len())?;
+        for key in &summary.stale_annotations {
+            writeln!(output, "- {}", key)?;
+        }
+    }
+    writeln!(output, "Total files scanned: {}", summary.files_scanned)?;
+    if let Some(coverage) = &summary.coverage {
+        writeln!(output, "{}", coverage_line(coverage))?;
+    }
+    if summary.archive_depth_reached > 0 {
+        writeln!(output, "Deepest nested archive: depth {}", summary.archive_depth_reached)?;
+    }
+    writeln!(output, "Vulnerable files found: {}", vulnerable.len())?;
+    if histogram && !vulnerable.is_empty() {
+        for severity in [Severity::Critical, Severity::High, Severity::Medium, Severity::Low] {
+            let count = vulnerable.iter().filter(|r| r.severity.as_ref() == Some(&severity)).count();
+            let share = (count as f64 * 100.0 / vulnerable.len() as f64).round();
+            let label = format!("{}:", severity);
+            writeln!(output, "  {:<9} {} {:>2} ({:>2}%)", label, format_severity_bar(count, vulnerable.len(), SEVERITY_BAR_WIDTH), count, share)?;
+        }
+    }
+    writeln!(output, "Unique vulnerable artifacts: {}", groups.len())
+}
+
+/// Characters in each bar of the severity histogram
+const SEVERITY_BAR_WIDTH: usize = 10;
+
+/// A bar `width` characters long, filled in proportion to `count` of `total`
+fn format_severity_bar(count: usize, total: usize, width: usize) -> String {
+    let filled = (count * width + total / 2).checked_div(total).unwrap_or(0).min(width);
+    format!("{}{}", "\u{2588}".repeat(filled), "\u{2591}".repeat(width - filled))
+}
+
+fn write_incomplete_notice(output: &mut dyn Write, summary: &ScanSummary) -> io::Result<()> {
+    if summary.timed_out {
+        writeln!(output, "Scan timed out after examining {} files; results are incomplete", summary.files_scanned)?;
+    } else if summary.max_duration_reached {
+        writeln!(output, "Scan reached --max-duration after examining {} files, {} not reached; results are incomplete", summary.files_scanned, summary.files_not_reached())?;
+    } else if summary.truncated {
+        writeln!(output, "Scan terminated early after examining {} files; results are incomplete", summary.files_scanned)?;
+    }
+    Ok(())
+}
+
+/// Counts by severity, CVE and status, then the riskiest unique artifacts.
+/// Lines stay short so the block reads well in a narrow terminal. With a
+/// `layout`, the severity counts are a table with their share of findings.
+fn write_summary_block(output: &mut dyn Write, results: &[ScanResult], summary: &ScanSummary, groups: &[DuplicateGroup], layout: Option<TableLayout>) -> io::Result<()> {
+    let vulnerable: Vec<_> = results.iter().filter(|r| r.vulnerable).collect();
+
+    writeln!(output, "\nSummary:")?;
+    writeln!(output, "  By status:")?;
+    writeln!(output, "    Vulnerable: {}", vulnerable.len())?;
+    writeln!(output, "    Skipped: {}", count_status(results, ScanStatus::Skipped))?;
+    writeln!(output, "    Error: {}", count_status(results, ScanStatus::Error))?;
+    writeln!(output, "    Permission denied: {}", summary.permission_denied.len())?;
+
+    if !vulnerable.is_empty() {
+        writeln!(output, "  By severity:")?;
+        let mut table = layout.map(|layout| layout.table(&["Severity", "Count", "%"], &[1, 2]));
+        for severity in [Severity::Critical, Severity::High, Severity::Medium, Severity::Low] {
+            let count = vulnerable.iter().filter(|r| r.severity.as_ref() == Some(&severity)).count();
+            match table.as_mut() {
+                Some(table) if count > 0 => {
+                    let share = format!("{:.1}", count as f64 * 100.0 / vulnerable.len() as f64);
+                    table.add_row(vec![severity.to_string(), count.to_string(), share]);
+                }
+                None if count > 0 => writeln!(output, "    {}: {}", severity, count)?,
+                _ => {}
+            }
+        }
+        if let Some(table) = table {
+            writeln!(output, "{}", table)?;
+        }
+
+        let mut by_cve: BTreeMap<&str, usize> = BTreeMap::new();
+        for result in &vulnerable {
+            *by_cve.entry(result.cve.as_deref().unwrap_or("no CVE")).or_default() += 1;
+        }
+        writeln!(output, "  By CVE:")?;
+        for (cve, count) in by_cve {
+            match cve_name(cve) {
+                Some(name) => writeln!(output, "    {} ({}): {}", cve, name, count)?,
+                None => writeln!(output, "    {}: {}", cve, count)?,
+            }
+        }
+
+        let hosts = unique_jndi_hosts(results);
+        if !hosts.is_empty() {
+            writeln!(output, "  Unique JNDI hosts:")?;
+            for host in hosts {
+                writeln!(output, "    {}", host)?;
+            }
+        }
+    }
+
+    let mut targets: Vec<&DuplicateGroup> = groups.iter().collect();
+    targets.sort_by(|a, b| {
+        (&b.finding.severity, b.finding.confidence, b.paths.len())
+            .cmp(&(&a.finding.severity, a.finding.confidence, a.paths.len()))
+    });
+    if !targets.is_empty() {
+        writeln!(output, "  Top remediation targets:")?;
+        for (rank, group) in targets.iter().take(TOP_REMEDIATION_TARGETS).enumerate() {
+            let path = group.paths[0];
+            let name = path.rsplit(['/', '\\', '!']).next().unwrap_or(path);
+            let severity = group.finding.severity.as_ref().map(Severity::to_string).unwrap_or_default();
+            writeln!(output, "  {:>2}. {}", rank + 1, name)?;
+            writeln!(output, "      {}, {} location(s)", severity, group.paths.len())?;
+            writeln!(output, "      {}", path)?;
+        }
+    }
+
+    write_rule_stats(output, &summary.rule_stats, layout)
+}
+
+/// Findings of every rule, so rules that never match stand out
+fn write_rule_stats(output: &mut dyn Write, rule_stats: &[RuleStats], layout: Option<TableLayout>) -> io::Result<()> {
+    if rule_stats.is_empty() {
+        return Ok(());
+    }
+    let unique_hashes = rule_stats.iter().any(|stats| stats.unique_hashes.is_some());
+    let rule_name = |stats: &RuleStats| if stats.enabled { stats.id.clone() } else { format!("{} (disabled)", stats.id) };
+
+    writeln!(output, "  Rule statistics:")?;
+    match layout {
+        Some(layout) => {
+            let (header, numeric): (&[&str], &[usize]) = if unique_hashes {
+                (&["Rule", "Files", "Matches", "Unique Hashes"], &[1, 2, 3])
+            } else {
+                (&["Rule", "Files", "Matches"], &[1, 2])
+            };
+            let mut table = layout.table(header, numeric);
+            for stats in rule_stats {
+                let mut row = vec![rule_name(stats), stats.files_matched.to_string(), stats.total_matches.to_string()];
+                row.extend(stats.unique_hashes.map(|count| count.to_string()));
+                table.add_row(row);
+            }
+            writeln!(output, "{}", table)
+        }
+        None => {
+            for stats in rule_stats {
+                write!(output, "    {}: {} files, {} matches", rule_name(stats), stats.files_matched, stats.total_matches)?;
+                if let Some(count) = stats.unique_hashes {
+                    write!(output, ", {} unique hashes", count)?;
+                }
+                writeln!(output)?;
+            }
+            Ok(())
+        }
+    }
+}
+
+/// How bordered tables are drawn in the text report, unless `--no-table`
+#[derive(Clone, Copy)]
+struct TableLayout {
+    /// Width to fit the table to; unlimited when the report is not going to a terminal
+    width: Option<u16>,
+}
+
+impl TableLayout {
+    fn of(config: &Config) -> Option<Self> {
+        if config.no_table {
+            return None;
+        }
+        // A report saved to a file keeps whole paths on one line
+        let width = match &config.output {
+            Some(_) => None,
+            None => terminal_size::terminal_size().map(|(terminal_size::Width(width), _)| width),
+        };
+        Some(TableLayout { width })
+    }
+
+    /// An empty ASCII table with `header`, its `numeric` columns right aligned.
+    /// Only the first column wraps to fit the width; the others are short.
+    fn table(self, header: &[&str], numeric: &[usize]) -> Table {
+        let mut table = Table::new();
+        table.load_preset(presets::ASCII_FULL).set_header(header.to_vec());
+        if let Some(width) = self.width {
+            table.set_content_arrangement(ContentArrangement::Dynamic).set_width(width);
+        }
+        for (index, column) in table.column_iter_mut().enumerate() {
+            if index > 0 {
+                column.set_constraint(ColumnConstraint::ContentWidth);
+            }
+            if numeric.contains(&index) {
+                column.set_cell_alignment(CellAlignment::Right);
+            }
+        }
+        table
+    }
+}
+
+/// One row per finding, with the first hash computed cut short
+fn write_findings_table(output: &mut dyn Write, findings: &[&ScanResult], layout: TableLayout, links: bool) -> io::Result<()> {
+    // Annotations get a column only when some finding has them
+    let annotated = findings.iter().any(|result| result.annotations.is_some());
+    let mut header = vec!["File Path", "Version", "Severity", "CVE", "Hash"];
+    if annotated {
+        header.push("Annotations");
+    }
+    let mut table = layout.table(&header, &[]);
+    for result in findings {
+        let version = result.maven_coordinates.as_ref().map_or("-", |coordinates| coordinates.version.as_str());
+        let severity = result.severity.as_ref().map_or_else(|| "-".to_string(), |severity| severity.to_string());
+        let hash = DEDUP_HASHES.iter()
+            .find_map(|&algorithm| result.hash(algorithm))
+            .map_or("-", |hash| &hash[..hash.len().min(TABLE_HASH_LENGTH)]);
+        let notes = result.annotations.as_ref().map_or_else(|| "-".to_string(), annotations::describe);
+        let mut row = vec![result.file_path.as_str(), version, &severity, result.cve.as_deref().unwrap_or("-"), hash];
+        if annotated {
+            row.push(&notes);
+        }
+        table.add_row(row);
+    }
+    if !links {
+        return writeln!(output, "{}", table);
+    }
+
+    // Links are added to the rendered table, since escapes would throw off
+    // its column widths; a path wrapped over several lines stays plain
+    let paths: HashSet<&str> = findings.iter().map(|result| result.file_path.as_str()).collect();
+    for line in table.to_string().lines() {
+        let cell = line.strip_prefix("| ")
+            .and_then(|rest| rest.split(" |").next())
+            .map(str::trim_end)
+            .filter(|cell| paths.contains(cell));
+        match cell {
+            Some(path) => writeln!(output, "{}", line.replacen(path, &hyperlink(path, true), 1))?,
+            None => writeln!(output, "{}", line)?,
+        }
+    }
+    Ok(())
+}
+
+/// Whether the text report wraps file paths in OSC 8 hyperlinks
+fn hyperlinks(config: &Config) -> bool {
+    match config.ansi_hyperlinks {
+        HyperlinkMode::Always => true,
+        HyperlinkMode::Never => false,
+        // Only a report shown on the terminal itself can be clicked
+        HyperlinkMode::Auto => config.output.is_none() && io::stdout().is_terminal() && terminal_supports_hyperlinks(),
+    }
+}
+
+/// `path` as an OSC 8 hyperlink to the file it names, when `enabled`. Paths
+/// inside archives (`outer.jar!inner.jar`) link to the outer file; paths that
+/// do not resolve to a local file, such as remote ones, are left as they are.
+fn hyperlink(path: &str, enabled: bool) -> Cow<'_, str> {
+    if !enabled {
+        return Cow::Borrowed(path);
+    }
+    let file = path.split('!').next().unwrap_or(path);
+    match fs::canonicalize(file) {
+        Ok(absolute) => Cow::Owned(format!("\x1b]8;;{}\x1b\\{}\x1b]8;;\x1b\\", file_url(&absolute), path)),
+        Err(_) => Cow::Borrowed(path),
+    }
+}
+
+/// `file://` URL of an absolute path, percent-encoding all but unreserved characters
+fn file_url(path: &Path) -> String {
+    let path = path.to_string_lossy();
+    // canonicalize returns verbatim `\\?\C:\...` paths on Windows
+    let path = path.strip_prefix(r"\\?\").unwrap_or(&path).replace('\\', "/");
+    let mut url = String::from(if path.starts_with('/') { "file://" } else { "file:///" });
+    for byte in path.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'/' | b':' | b'-' | b'.' | b'_' | b'~' => url.push(byte as char),
+            _ => url.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    url
+}
+
+/// `--summary-by-dir` as a table, one row per directory
+fn write_directory_table(output: &mut dyn Write, directories: &[DirectorySummary]) -> io::Result<()> {
+    let width = directories.iter().map(|d| d.directory.chars().count()).max().unwrap_or(0).max("Directory".len());
+    writeln!(output, "\nFindings by directory:")?;
+    writeln!(output, "{:<width$} | {:>9} | {:>10} | {:>8} | {:>6} | {:>6} | {:>6} | {:>13}",
+        "Directory", "Scanned", "Vulnerable", "Critical", "High", "Medium", "Low", "Unique hashes", width = width)?;
+    for d in directories {
+        writeln!(output, "{:<width$} | {:>9} | {:>10} | {:>8} | {:>6} | {:>6} | {:>6} | {:>13}",
+            d.directory, d.files_scanned, d.vulnerable, d.critical, d.high, d.medium, d.low, d.unique_vulnerable_hashes, width = width)?;
+    }
+    Ok(())
+}
+
+/// The hosts named by the JNDI lookup URLs of every finding, in sorted order
+fn unique_jndi_hosts(results: &[ScanResult]) -> BTreeSet<String> {
+    results.iter()
+        .filter(|r| r.vulnerable)
+        .flat_map(|r| &r.jndi_urls)
+        .filter_map(|url| jndi_host(url))
+        .collect()
+}
+
+fn count_status(results: &[ScanResult], status: ScanStatus) -> usize {
+    results.iter().filter(|r| r.status == status).count()
+}
+
+fn json_report<'a>(results: &'a [ScanResult], summary: &'a ScanSummary, config: &'a Config) -> JsonReport<'a> {
+    let groups = group_duplicates(results);
+    // The summary counts the whole scan; results and grouped, what --report-filter keeps
+    let filter = ReportFilter::of(config);
+    let listed = |r: &&ScanResult| filter.as_ref().is_none_or(|filter| filter.matches(r));
+    JsonReport {
+        schema_version: SCHEMA_VERSION,
+        summary: ReportSummary {
+            scan: summary,
+            total_affected_paths: groups.iter().map(|g| g.paths.len()).sum(),
+            unique_vulnerable_artifacts: groups.len(),
+            skipped: count_status(results, ScanStatus::Skipped),
+            errors: count_status(results, ScanStatus::Error),
+            by_directory: config.summary_by_dir.map(|depth| summarize_by_dir(results, summary, depth)),
+            unique_jndi_hosts: unique_jndi_hosts(results),
+            report_filter: filter.as_ref().map(|filter| filter.note(results)),
+        },
+        results: results.iter().filter(|r| !config.quiet() || r.vulnerable).filter(listed).collect(),
+        grouped: config.group_duplicates.then(|| match &filter {
+            Some(_) => group_duplicates(results.iter().filter(listed)),
+            None => groups,
+        }),
+        scan_config: config,
+    }
+}
+
+/// The JSON report as a value, for embedding in other responses
+pub fn json_report_value(results: &[ScanResult], summary: &ScanSummary, config: &Config) -> serde_json::Result<serde_json::Value> {
+    serde_json::to_value(json_report(results, summary, config))
+}
+
+/// The JSON report exactly as `--format json` writes it
+pub fn json_report_string(results: &[ScanResult], summary: &ScanSummary, config: &Config) -> serde_json::Result<String> {
+    serde_json::to_string_pretty(&json_report(results, summary, config))
+}
+
+fn report_json(results: &[ScanResult], summary: &ScanSummary, mut output: Box<dyn Write>, config: &Config) -> io::Result<()> {
+    let json = json_report_string(results, summary, config)
+        .map_err(io::Error::other)?;
     
     writeln!(output, "{}", json)?;
     Ok(())
 }
+
+/// The JSON report split across the files of `output`. Each file is a
+/// complete report with the same summary and the next results that fit:
+/// the `results` array is closed and a new file started when the next result
+/// would take the current one past the limit. A file always holds at least
+/// one result, so a single result larger than the limit gets a file of its own.
+fn report_json_rotating(results: &[ScanResult], summary: &ScanSummary, output: &mut RotatingWriter, config: &Config) -> io::Result<()> {
+    let mut report = json_report(results, summary, config);
+    let results = std::mem::take(&mut report.results);
+    let framing = serde_json::to_string_pretty(&report)
+        .map_err(io::Error::other)?;
+    // The results go between the brackets of the empty array, indented as
+    // the pretty printer would have indented them
+    const EMPTY_RESULTS: &str = "\n  \"results\": []";
+    let split = framing.find(EMPTY_RESULTS).expect("report has a results array") + EMPTY_RESULTS.len() - 1;
+    let (head, tail) = framing.split_at(split);
+    let closing = format!("\n  {}\n", tail);
+
+    output.write_all(head.as_bytes())?;
+    let mut in_part = 0;
+    for result in results {
+        let item = serde_json::to_string_pretty(result)
+            .map_err(io::Error::other)?;
+        let item = format!("\n    {}", item.replace('\n', "\n    "));
+        if in_part > 0 && output.would_overflow(1 + item.len() + closing.len()) {
+            output.write_all(closing.as_bytes())?;
+            output.rotate()?;
+            output.write_all(head.as_bytes())?;
+            in_part = 0;
+        }
+        if in_part > 0 {
+            output.write_all(b",")?;
+        }
+        output.write_all(item.as_bytes())?;
+        in_part += 1;
+    }
+    if in_part > 0 {
+        output.write_all(closing.as_bytes())
+    } else {
+        writeln!(output, "{}", tail)
+    }
+}
+
+/// Write results as a MessagePack array of maps, optionally base64 encoded for text pipelines
+fn report_msgpack(results: &[ScanResult], mut output: Box<dyn Write>, quiet: bool, base64: bool) -> io::Result<()> {
+    let results: Vec<&ScanResult> = results.iter().filter(|r| !quiet || r.vulnerable).collect();
+    let encoded = rmp_serde::to_vec_named(&results)
+        .map_err(io::Error::other)?;
+
+    if base64 {
+        writeln!(output, "{}", base64::engine::general_purpose::STANDARD.encode(encoded))
+    } else {
+        output.write_all(&encoded)
+    }
+}
+
+/// Write vulnerable results as Checkstyle XML, one `<file>` per path in order of first
+/// appearance. Findings are file-level, so each `<error>` carries `line="0"` as
+/// Checkstyle does for violations without a line.
+fn report_checkstyle(results: &[ScanResult], mut output: Box<dyn Write>, config: &Config) -> io::Result<()> {
+    let mut files: Vec<(&str, Vec<&ScanResult>)> = Vec::new();
+    for result in results.iter().filter(|r| r.vulnerable) {
+        match files.iter_mut().find(|(path, _)| *path == result.file_path) {
+            Some((_, findings)) => findings.push(result),
+            None => files.push((&result.file_path, vec![result])),
+        }
+    }
+
+    let encoding = if config.encoding == "utf16le" { "UTF-16" } else { "UTF-8" };
+    writeln!(output, "<?xml version=\"1.0\" encoding=\"{}\"?>", encoding)?;
+    writeln!(output, "<checkstyle version=\"{}\">", env!("CARGO_PKG_VERSION"))?;
+    for (path, findings) in files {
+        writeln!(output, "  <file name=\"{}\">", xml_escape(path))?;
+        for finding in findings {
+            let severity = match finding.severity {
+                Some(Severity::Critical) | Some(Severity::High) => "error",
+                Some(Severity::Low) => "info",
+                Some(Severity::Medium) | None => "warning",
+            };
+            let mut message = finding.reason.clone().unwrap_or_else(|| "Vulnerable log4j artifact".to_string());
+            if let Some(cve) = &finding.cve {
+                message = format!("{} ({})", message, cve);
+            }
+            let source = match &finding.cve {
+                Some(cve) => format!("{}.{}", env!("CARGO_PKG_NAME"), cve),
+                None => env!("CARGO_PKG_NAME").to_string(),
+            };
+            writeln!(
+                output,
+                "    <error line=\"0\" severity=\"{}\" message=\"{}\" source=\"{}\"/>",
+                severity,
+                xml_escape(&message),
+                xml_escape(&source)
+            )?;
+        }
+        writeln!(output, "  </file>")?;
+    }
+    writeln!(output, "</checkstyle>")
+}
+
+/// Escape text for an XML attribute, replacing characters XML 1.0 cannot represent
+fn xml_escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            '\t' | '\n' | '\r' => escaped.push_str(&format!("&#{};", c as u32)),
+            c if c < ' ' || c == '\u{FFFE}' || c == '\u{FFFF}' => escaped.push('\u{FFFD}'),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A finding at `path` with `severity` and `hash`, or a clean result
+    /// when `severity` is None
+    fn result(path: &str, severity: Option<&str>, hash: Option<&str>) -> ScanResult {
+        serde_json::from_value(serde_json::json!({
+            "file_path": path, "vulnerable": severity.is_some(),
+            "status": if severity.is_some() { "Vulnerable" } else { "Skipped" },
+            "severity": severity, "file_hash": hash, "file_size": 1,
+            "entropy": 0.0, "fourier_coefficient": [0.0, 0.0], "markov_probability": 0.0,
+        })).unwrap()
+    }
+
+    fn summary(roots: &[&str], scanned_files: &[&str]) -> ScanSummary {
+        ScanSummary {
+            roots: roots.iter().map(PathBuf::from).collect(),
+            scanned_files: scanned_files.iter().map(PathBuf::from).collect(),
+            ..Default::default()
+        }
+    }
+
+    /// (directory, files scanned, vulnerable, critical, high, medium, low, unique hashes)
+    type Row = (String, usize, usize, usize, usize, usize, usize, usize);
+
+    fn rows(directories: Vec<DirectorySummary>) -> Vec<Row> {
+        directories.into_iter()
+            .map(|d| (d.directory, d.files_scanned, d.vulnerable, d.critical, d.high, d.medium, d.low, d.unique_vulnerable_hashes))
+            .collect()
+    }
+
+    #[test]
+    fn findings_are_counted_under_their_top_level_directory() {
+        let results = vec![
+            result("/srv/team-a/app/log4j-core.jar", Some("Critical"), Some("aa")),
+            result("/srv/team-a/other/log4j-core.jar", Some("Critical"), Some("aa")),
+            result("/srv/team-a/app/log4j2.xml", Some("Medium"), Some("bb")),
+            result("/srv/team-b/lib/shaded.jar!org/apache/logging/log4j/core/lookup/JndiLookup.class", Some("High"), Some("cc")),
+            result("/srv/team-b/encrypted.jar", None, None),
+        ];
+        let summary = summary(&["/srv"], &[
+            "/srv/team-a/app/log4j-core.jar", "/srv/team-a/other/log4j-core.jar", "/srv/team-a/app/log4j2.xml",
+            "/srv/team-a/app/clean.jar", "/srv/team-b/lib/shaded.jar", "/srv/team-b/encrypted.jar",
+        ]);
+
+        assert_eq!(rows(summarize_by_dir(&results, &summary, 1)), [
+            ("/srv/team-a".to_string(), 4, 3, 2, 0, 1, 0, 2),
+            ("/srv/team-b".to_string(), 2, 1, 0, 1, 0, 0, 1),
+        ]);
+        assert_eq!(rows(summarize_by_dir(&results, &summary, 2)), [
+            ("/srv/team-a/app".to_string(), 3, 2, 1, 0, 1, 0, 2),
+            ("/srv/team-a/other".to_string(), 1, 1, 1, 0, 0, 0, 1),
+            ("/srv/team-b".to_string(), 1, 0, 0, 0, 0, 0, 0),
+            ("/srv/team-b/lib".to_string(), 1, 1, 0, 1, 0, 0, 1),
+        ]);
+    }
+
+    /// Each file counts below the deepest root it was found under, and a
+    /// relative root stays relative
+    #[test]
+    fn directories_are_counted_below_each_root() {
+        let results = vec![
+            result("/opt/java/lib/log4j-core.jar", Some("Critical"), None),
+            result("/opt/java/jdk/lib/log4j-core.jar", Some("Critical"), None),
+            result("apps/web/log4j-core.jar", Some("Low"), None),
+        ];
+        let summary = summary(&["/opt/java", "/opt/java/jdk", "apps"], &[
+            "/opt/java/lib/log4j-core.jar", "/opt/java/jdk/lib/log4j-core.jar", "apps/web/log4j-core.jar",
+        ]);
+
+        assert_eq!(rows(summarize_by_dir(&results, &summary, 1)), [
+            ("/opt/java/jdk/lib".to_string(), 1, 1, 1, 0, 0, 0, 1),
+            ("/opt/java/lib".to_string(), 1, 1, 1, 0, 0, 0, 1),
+            ("apps/web".to_string(), 1, 1, 0, 0, 0, 1, 1),
+        ]);
+    }
+
+    /// A pipe whose reader goes away after `capacity` bytes, as `| head` does;
+    /// what got through is kept in `received`
+    struct ClosingPipe {
+        received: Rc<RefCell<Vec<u8>>>,
+        capacity: usize,
+        error: io::ErrorKind,
+    }
+
+    impl ClosingPipe {
+        fn new(capacity: usize, error: io::ErrorKind) -> (Self, Rc<RefCell<Vec<u8>>>) {
+            let received = Rc::new(RefCell::new(Vec::new()));
+            (ClosingPipe { received: Rc::clone(&received), capacity, error }, received)
+        }
+    }
+
+    impl Write for ClosingPipe {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            let mut received = self.received.borrow_mut();
+            let room = self.capacity - received.len();
+            if room == 0 {
+                return Err(io::Error::from(self.error));
+            }
+            let accepted = buf.len().min(room);
+            received.extend_from_slice(&buf[..accepted]);
+            Ok(accepted)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn many_findings() -> (Vec<ScanResult>, ScanSummary) {
+        let results: Vec<ScanResult> = (0..50)
+            .map(|i| result(&format!("/srv/app{}/log4j-core.jar", i), Some("Critical"), Some(&format!("{:064x}", i))))
+            .collect();
+        let summary = summary(&["/srv"], &[]);
+        (results, summary)
+    }
+
+    /// Every format stops quietly when the pipe closes, having written what
+    /// the reader took
+    #[test]
+    fn closed_pipe_ends_the_report_quietly() {
+        let (results, summary) = many_findings();
+        for format in ["text", "json", "table", "checkstyle", "stix", "text-stable", "teamcity", "msgpack"] {
+            let config = Config { format: format.to_string(), no_table: true, ..Default::default() };
+            let (whole, report) = ClosingPipe::new(usize::MAX, io::ErrorKind::BrokenPipe);
+            write_report(&results, &summary, Box::new(whole), &config).unwrap();
+            let report = report.borrow().clone();
+            assert!(report.len() > 200, "{}", format);
+
+            let (pipe, received) = ClosingPipe::new(200, io::ErrorKind::BrokenPipe);
+            write_report(&results, &summary, Box::new(pipe), &config).unwrap();
+            assert_eq!(received.borrow().as_slice(), &report[..200], "{}", format);
+        }
+    }
+
+    #[test]
+    fn other_write_errors_are_reported() {
+        let (results, summary) = many_findings();
+        for format in ["text", "json"] {
+            let config = Config { format: format.to_string(), ..Default::default() };
+            let (pipe, _) = ClosingPipe::new(200, io::ErrorKind::StorageFull);
+            let error = write_report(&results, &summary, Box::new(pipe), &config).unwrap_err();
+            assert_eq!(error.kind(), io::ErrorKind::StorageFull, "{}", format);
+        }
+    }
+
+    /// Without a digest every vulnerable file is its own artifact
+    #[test]
+    fn findings_without_a_hash_are_unique_by_path() {
+        let results = vec![
+            result("/srv/a/one.jar", Some("Critical"), None),
+            result("/srv/a/two.jar", Some("Critical"), None),
+        ];
+        let summary = summary(&["/srv"], &["/srv/a/one.jar", "/srv/a/two.jar"]);
+        assert_eq!(rows(summarize_by_dir(&results, &summary, 1)), [("/srv/a".to_string(), 2, 2, 2, 0, 0, 0, 2)]);
+    }
+
+    /// The results in each part of a rotated report, checking every part is
+    /// a complete report of at most `limit` bytes
+    fn rotated_parts(config: &Config, limit: u64) -> Vec<Vec<String>> {
+        output_parts(config).iter()
+            .map(|part| {
+                let contents = fs::read(part).unwrap();
+                assert!(contents.len() as u64 <= limit, "{}: {} bytes", part.display(), contents.len());
+                let report: serde_json::Value = serde_json::from_slice(&contents).unwrap();
+                assert_eq!(report["summary"]["files_scanned"], 1000, "{}", part.display());
+                report["results"].as_array().unwrap().iter()
+                    .map(|r| r["file_path"].as_str().unwrap().to_string())
+                    .collect()
+            })
+            .collect()
+    }
+
+    fn write_rotated(results: &[ScanResult], summary: &ScanSummary, config: &Config, limit: u64) {
+        let mut output = RotatingWriter::create(Path::new(config.output.as_ref().unwrap()), limit, false).unwrap();
+        report_json_rotating(results, summary, &mut output, config).unwrap();
+        output.finish().unwrap();
+    }
+
+    #[test]
+    fn json_report_rotates_at_the_limit() {
+        const LIMIT: u64 = 10 * 1024;
+        let dir = tempfile::tempdir().unwrap();
+        let paths: Vec<String> = (0..1000).map(|i| format!("/srv/app/lib{:04}/log4j-core.jar", i)).collect();
+        let results: Vec<ScanResult> = paths.iter().map(|path| result(path, Some("Critical"), None)).collect();
+        let summary = ScanSummary { files_scanned: 1000, ..Default::default() };
+        let config = Config {
+            format: "json".to_string(),
+            output: Some(dir.path().join("report.json").to_string_lossy().into_owned()),
+            output_rotate: Some(1),
+            ..Default::default()
+        };
+
+        write_rotated(&results, &summary, &config, LIMIT);
+        let parts = rotated_parts(&config, LIMIT);
+        assert!(parts.len() > 10, "{} parts", parts.len());
+        assert!(parts.iter().all(|part| !part.is_empty()));
+        assert_eq!(parts.concat(), paths);
+
+        // A smaller report leaves no parts of the larger one behind
+        write_rotated(&results[..100], &summary, &config, LIMIT);
+        let parts = rotated_parts(&config, LIMIT);
+        assert_eq!(parts.concat(), paths[..100]);
+        assert!(!part_path(Path::new(config.output.as_ref().unwrap()), parts.len()).exists());
+    }
+}
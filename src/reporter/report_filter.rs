@@ -0,0 +1,135 @@
+use crate::config::Config;
+use crate::scanner::ScanResult;
+use regex::Regex;
+use std::borrow::Cow;
+
+/// The `--report-filter` regexes: a report lists only the results whose
+/// file path or archive entry one of them matches
+pub struct ReportFilter {
+    patterns: Vec<String>,
+    regexes: Vec<Regex>,
+}
+
+/// How many findings a filtered report lists out of all the scan found
+#[derive(Debug, PartialEq, Eq, serde::Serialize)]
+pub struct FilterNote {
+    pub patterns: Vec<String>,
+    pub shown: usize,
+    pub total: usize,
+}
+
+impl FilterNote {
+    pub fn line(&self) -> String {
+        format!("Showing {} of {} findings (filter: {})", self.shown, self.total, self.patterns.join(", "))
+    }
+}
+
+impl ReportFilter {
+    /// The filter of `config`, or None without `--report-filter`. Patterns
+    /// are checked before the scan; one that does not compile matches nothing.
+    pub fn of(config: &Config) -> Option<Self> {
+        if config.report_filter.is_empty() {
+            return None;
+        }
+        Some(ReportFilter {
+            patterns: config.report_filter.clone(),
+            regexes: config.report_filter.iter().filter_map(|pattern| Regex::new(pattern).ok()).collect(),
+        })
+    }
+
+    pub fn matches(&self, result: &ScanResult) -> bool {
+        self.regexes.iter().any(|regex| {
+            regex.is_match(&result.file_path) || result.inner_path.as_deref().is_some_and(|entry| regex.is_match(entry))
+        })
+    }
+
+    /// The findings of `results` the filter lists, out of all of them
+    pub fn note(&self, results: &[ScanResult]) -> FilterNote {
+        let findings = results.iter().filter(|result| result.vulnerable);
+        FilterNote {
+            patterns: self.patterns.clone(),
+            shown: findings.clone().filter(|result| self.matches(result)).count(),
+            total: findings.count(),
+        }
+    }
+}
+
+/// The results a report lists: those `filter` matches, or all of them
+pub fn shown<'a>(results: &'a [ScanResult], filter: Option<&ReportFilter>) -> Cow<'a, [ScanResult]> {
+    match filter {
+        Some(filter) => Cow::Owned(results.iter().filter(|result| filter.matches(result)).cloned().collect()),
+        None => Cow::Borrowed(results),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn result(path: &str, inner_path: Option<&str>, vulnerable: bool) -> ScanResult {
+        serde_json::from_value(serde_json::json!({
+            "file_path": path, "inner_path": inner_path, "vulnerable": vulnerable,
+            "status": if vulnerable { "Vulnerable" } else { "Skipped" },
+            "file_size": 1, "entropy": 0.0, "fourier_coefficient": [0.0, 0.0], "markov_probability": 0.0,
+        })).unwrap()
+    }
+
+    fn results() -> Vec<ScanResult> {
+        vec![
+            result("/opt/tomcat/lib/log4j-core-2.14.1.jar", None, true),
+            result("/srv/app.war!WEB-INF/lib/log4j-core-2.14.1.jar", Some("WEB-INF/lib/log4j-core-2.14.1.jar"), true),
+            result("/srv/other/log4j-core.jar", None, true),
+            result("/opt/tomcat/encrypted.jar", None, false),
+        ]
+    }
+
+    fn filter(patterns: &[&str]) -> ReportFilter {
+        ReportFilter::of(&Config { report_filter: patterns.iter().map(|p| p.to_string()).collect(), ..Default::default() }).unwrap()
+    }
+
+    fn shown_paths(results: &[ScanResult], patterns: &[&str]) -> Vec<String> {
+        shown(results, Some(&filter(patterns))).iter().map(|r| r.file_path.clone()).collect()
+    }
+
+    #[test]
+    fn filter_lists_results_a_pattern_matches() {
+        let results = results();
+        assert_eq!(shown_paths(&results, &["^/opt/tomcat/"]), ["/opt/tomcat/lib/log4j-core-2.14.1.jar", "/opt/tomcat/encrypted.jar"]);
+        assert_eq!(shown_paths(&results, &["^/opt/tomcat/", "other"]), [
+            "/opt/tomcat/lib/log4j-core-2.14.1.jar",
+            "/srv/other/log4j-core.jar",
+            "/opt/tomcat/encrypted.jar",
+        ]);
+        assert!(shown_paths(&results, &["^/nowhere/"]).is_empty());
+    }
+
+    #[test]
+    fn filter_matches_archive_entries() {
+        let results = results();
+        assert_eq!(shown_paths(&results, &["^WEB-INF/"]), ["/srv/app.war!WEB-INF/lib/log4j-core-2.14.1.jar"]);
+    }
+
+    #[test]
+    fn no_filter_shows_everything() {
+        let results = results();
+        assert!(ReportFilter::of(&Config::default()).is_none());
+        assert!(matches!(shown(&results, None), Cow::Borrowed(shown) if shown.len() == 4));
+    }
+
+    /// The note counts findings only, out of all of them, whatever the filter shows
+    #[test]
+    fn note_counts_the_findings_shown_of_all() {
+        let note = filter(&["^/opt/tomcat/", "\\.war!"]).note(&results());
+        assert_eq!(note, FilterNote { patterns: vec!["^/opt/tomcat/".to_string(), "\\.war!".to_string()], shown: 2, total: 3 });
+        assert_eq!(note.line(), "Showing 2 of 3 findings (filter: ^/opt/tomcat/, \\.war!)");
+        assert_eq!(filter(&["^/nowhere/"]).note(&results()).line(), "Showing 0 of 3 findings (filter: ^/nowhere/)");
+    }
+
+    #[test]
+    fn invalid_patterns_fail_the_config_check() {
+        let config = Config { report_filter: vec!["^/opt/".to_string(), "log4j-(core".to_string()], ..Default::default() };
+        let error = config.check_report_filter().unwrap_err();
+        assert!(error.starts_with("invalid --report-filter log4j-(core: "), "{}", error);
+        assert_eq!(filter(&["log4j-(core"]).note(&results()).shown, 0);
+    }
+}
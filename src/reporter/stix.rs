@@ -0,0 +1,249 @@
+use super::group_duplicates;
+use crate::scanner::ScanResult;
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::collections::BTreeSet;
+use std::io::{self, Write};
+use uuid::Uuid;
+
+const SPEC_VERSION: &str = "2.1";
+/// Namespace the STIX 2.1 specification defines for deterministic SCO identifiers
+const SCO_NAMESPACE: Uuid = Uuid::from_u128(0x00abedb4_aa42_466c_9c01_fed23315a9b7);
+/// `created` and `modified` of every object this tool creates: the Log4Shell
+/// disclosure date, so repeated scans produce identical objects rather than new versions
+const OBJECT_TIMESTAMP: &str = "2021-12-10T00:00:00.000Z";
+const TOOL_NAME: &str = "RustyLog4jGuard";
+
+#[derive(Serialize)]
+struct Bundle {
+    #[serde(rename = "type")]
+    object_type: &'static str,
+    id: String,
+    objects: Vec<StixObject>,
+}
+
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "kebab-case")]
+enum StixObject {
+    Identity(Identity),
+    File(File),
+    Vulnerability(Vulnerability),
+    Relationship(Relationship),
+}
+
+#[derive(Serialize)]
+struct Identity {
+    spec_version: &'static str,
+    id: String,
+    created: &'static str,
+    modified: &'static str,
+    name: &'static str,
+    identity_class: &'static str,
+}
+
+/// A `file` cyber-observable: one per unique vulnerable artifact, and one
+/// per archive on disk that holds such an artifact inside it
+#[derive(Serialize)]
+struct File {
+    spec_version: &'static str,
+    id: String,
+    name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    size: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    hashes: Option<Hashes>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    extensions: Option<Extensions>,
+    /// Every path the file was found under
+    x_rustylog4jguard_paths: Vec<String>,
+}
+
+#[derive(Serialize)]
+struct Extensions {
+    #[serde(rename = "archive-ext")]
+    archive: ArchiveExtension,
+}
+
+#[derive(Serialize)]
+struct ArchiveExtension {
+    contains_refs: Vec<String>,
+}
+
+#[derive(Serialize)]
+struct Hashes {
+    #[serde(rename = "SHA-256", skip_serializing_if = "Option::is_none")]
+    sha256: Option<String>,
+    #[serde(rename = "SHA3-256", skip_serializing_if = "Option::is_none")]
+    sha3_256: Option<String>,
+}
+
+#[derive(Serialize)]
+struct Vulnerability {
+    spec_version: &'static str,
+    id: String,
+    created_by_ref: String,
+    created: &'static str,
+    modified: &'static str,
+    name: String,
+    external_references: Vec<ExternalReference>,
+}
+
+#[derive(Serialize)]
+struct ExternalReference {
+    source_name: &'static str,
+    external_id: String,
+}
+
+#[derive(Serialize)]
+struct Relationship {
+    spec_version: &'static str,
+    id: String,
+    created_by_ref: String,
+    created: &'static str,
+    modified: &'static str,
+    relationship_type: &'static str,
+    source_ref: String,
+    target_ref: String,
+    description: String,
+}
+
+/// Write vulnerable results as a STIX 2.1 bundle: the tool's identity, a `file`
+/// per unique artifact, a `vulnerability` per CVE and `related-to` relationships
+/// between them. Every identifier is a UUIDv5, so a threat-intel platform sees
+/// the same objects on every scan instead of duplicates.
+pub fn report_stix(results: &[ScanResult], mut output: Box<dyn Write>) -> io::Result<()> {
+    let bundle = build_bundle(results);
+    let json = serde_json::to_string_pretty(&bundle)
+        .map_err(io::Error::other)?;
+    writeln!(output, "{}", json)
+}
+
+fn build_bundle(results: &[ScanResult]) -> Bundle {
+    let identity_id = object_id("identity", TOOL_NAME);
+    let mut objects = vec![StixObject::Identity(Identity {
+        spec_version: SPEC_VERSION,
+        id: identity_id.clone(),
+        created: OBJECT_TIMESTAMP,
+        modified: OBJECT_TIMESTAMP,
+        name: TOOL_NAME,
+        identity_class: "system",
+    })];
+
+    let groups = group_duplicates(results);
+    let cves: BTreeSet<&str> = groups.iter().filter_map(|g| g.finding.cve.as_deref()).collect();
+    for cve in &cves {
+        objects.push(StixObject::Vulnerability(Vulnerability {
+            spec_version: SPEC_VERSION,
+            id: object_id("vulnerability", cve),
+            created_by_ref: identity_id.clone(),
+            created: OBJECT_TIMESTAMP,
+            modified: OBJECT_TIMESTAMP,
+            name: cve.to_string(),
+            external_references: vec![ExternalReference { source_name: "cve", external_id: cve.to_string() }],
+        }));
+    }
+
+    // The archives on disk holding nested artifacts, by path, with the
+    // artifacts found in each; the hashes of a nested finding are the entry's
+    let mut archives: Vec<(&str, BTreeSet<String>)> = Vec::new();
+    for group in &groups {
+        let finding = group.finding;
+        let name = file_name(group.paths[0]).to_string();
+        let file_id = file_id(&match &finding.file_hash {
+            Some(sha256) => json!({ "hashes": { "SHA-256": sha256 } }),
+            None => json!({ "name": name }),
+        });
+        objects.push(StixObject::File(File {
+            spec_version: SPEC_VERSION,
+            id: file_id.clone(),
+            name,
+            size: Some(finding.file_size),
+            hashes: Some(Hashes { sha256: finding.file_hash.clone(), sha3_256: finding.sha3_hash.clone() }),
+            extensions: None,
+            x_rustylog4jguard_paths: group.paths.iter().map(|path| path.to_string()).collect(),
+        }));
+        for path in &group.paths {
+            if let Some((archive, _)) = path.split_once('!') {
+                match archives.iter_mut().find(|(path, _)| *path == archive) {
+                    Some((_, contents)) => { contents.insert(file_id.clone()); }
+                    None => archives.push((archive, BTreeSet::from([file_id.clone()]))),
+                }
+            }
+        }
+
+        if let Some(cve) = &finding.cve {
+            let vulnerability_id = object_id("vulnerability", cve);
+            objects.push(StixObject::Relationship(Relationship {
+                spec_version: SPEC_VERSION,
+                id: object_id("relationship", &format!("{}|{}", file_id, vulnerability_id)),
+                created_by_ref: identity_id.clone(),
+                created: OBJECT_TIMESTAMP,
+                modified: OBJECT_TIMESTAMP,
+                relationship_type: "related-to",
+                source_ref: file_id,
+                target_ref: vulnerability_id,
+                description: finding.reason.clone().unwrap_or_default(),
+            }));
+        }
+    }
+
+    // Archives are not hashed, so one is identified by its name and contents:
+    // copies of the same archive become one object listing every path
+    for (path, contents) in archives {
+        let name = file_name(path).to_string();
+        let contains_refs: Vec<String> = contents.into_iter().collect();
+        let id = file_id(&json!({ "name": name, "extensions": { "archive-ext": { "contains_refs": contains_refs } } }));
+        let existing = objects.iter_mut().find_map(|object| match object {
+            StixObject::File(file) if file.id == id => Some(file),
+            _ => None,
+        });
+        match existing {
+            Some(file) => file.x_rustylog4jguard_paths.push(path.to_string()),
+            None => objects.push(StixObject::File(File {
+                spec_version: SPEC_VERSION,
+                id,
+                name,
+                size: None,
+                hashes: None,
+                extensions: Some(Extensions { archive: ArchiveExtension { contains_refs } }),
+                x_rustylog4jguard_paths: vec![path.to_string()],
+            })),
+        }
+    }
+
+    let ids: Vec<&str> = objects.iter().map(StixObject::id).collect();
+    Bundle {
+        object_type: "bundle",
+        id: object_id("bundle", &ids.join("|")),
+        objects,
+    }
+}
+
+impl StixObject {
+    fn id(&self) -> &str {
+        match self {
+            StixObject::Identity(object) => &object.id,
+            StixObject::File(object) => &object.id,
+            StixObject::Vulnerability(object) => &object.id,
+            StixObject::Relationship(object) => &object.id,
+        }
+    }
+}
+
+/// The last component of a path, or of an archive entry name
+fn file_name(path: &str) -> &str {
+    path.rsplit(['/', '\\', '!']).next().unwrap_or(path)
+}
+
+/// A `file` identifier as the specification derives it for SCOs, from the
+/// canonical JSON of its identifying properties: the SHA-256 hash, or the
+/// name and contents for a file that was not hashed
+fn file_id(identity: &Value) -> String {
+    format!("file--{}", Uuid::new_v5(&SCO_NAMESPACE, identity.to_string().as_bytes()))
+}
+
+/// A deterministic identifier for an object created by this tool
+fn object_id(object_type: &str, name: &str) -> String {
+    let namespace = Uuid::new_v5(&Uuid::NAMESPACE_URL, b"https://github.com/yezzfusl/RustyLog4jGuard");
+    format!("{}--{}", object_type, Uuid::new_v5(&namespace, name.as_bytes()))
+}
@@ -0,0 +1,206 @@
+use super::{DEDUP_HASHES, TABLE_HASH_LENGTH};
+use crate::annotations;
+use crate::config::Config;
+use crate::scanner::{ScanResult, Severity};
+use std::io::{self, IsTerminal, Write};
+
+/// Columns `--columns` can select, in no particular order
+const COLUMNS: [&str; 8] = ["path", "severity", "cve", "version", "confidence", "reason", "hash", "annotations"];
+/// Columns shown without `--columns`
+const DEFAULT_COLUMNS: [&str; 6] = ["path", "severity", "cve", "version", "confidence", "reason"];
+/// Narrowest the path column may be cut to; a terminal that leaves it less
+/// gets plain lines instead
+const MIN_PATH_WIDTH: usize = 20;
+/// Characters of the reason shown before it is cut short
+const REASON_WIDTH: usize = 40;
+const GAP: &str = "  ";
+const RESET: &str = "\x1b[0m";
+
+/// Write vulnerable results as a table fitted to the terminal width, file
+/// paths shortened in the middle to fit and severities colored. When the
+/// report does not go to a terminal, or the terminal is too narrow for the
+/// columns, the same columns are written as tab-separated lines instead.
+pub fn report_table(results: &[ScanResult], mut output: Box<dyn Write>, config: &Config) -> io::Result<()> {
+    let columns = selected_columns(config)?;
+    let terminal = config.output.is_none() && io::stdout().is_terminal();
+    let width = terminal_size::terminal_size()
+        .filter(|_| terminal)
+        .map(|(terminal_size::Width(width), _)| width as usize);
+    let color = terminal && std::env::var_os("NO_COLOR").is_none();
+    output.write_all(render(results, &columns, width, color).as_bytes())
+}
+
+fn selected_columns(config: &Config) -> io::Result<Vec<&str>> {
+    if config.columns.is_empty() {
+        return Ok(DEFAULT_COLUMNS.to_vec());
+    }
+    match config.columns.iter().find(|column| !COLUMNS.contains(&column.as_str())) {
+        Some(unknown) => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("unknown table column '{}' (expected {})", unknown, COLUMNS.join(", ")),
+        )),
+        None => Ok(config.columns.iter().map(String::as_str).collect()),
+    }
+}
+
+/// The table at `width` characters, or tab-separated lines without a width
+/// or when the columns do not fit in it
+pub fn render(results: &[ScanResult], columns: &[&str], width: Option<usize>, color: bool) -> String {
+    let findings: Vec<&ScanResult> = results.iter().filter(|r| r.vulnerable).collect();
+    let rows: Vec<Vec<String>> = findings.iter()
+        .map(|finding| columns.iter().map(|column| cell(finding, column)).collect())
+        .collect();
+
+    let mut rendered = String::new();
+    let widths = match width.and_then(|width| fit(columns, &rows, width)) {
+        Some(widths) => widths,
+        None => {
+            rendered.push_str(&columns.join("\t"));
+            rendered.push('\n');
+            for row in &rows {
+                rendered.push_str(&row.join("\t"));
+                rendered.push('\n');
+            }
+            return rendered;
+        }
+    };
+
+    let header: Vec<String> = columns.iter().map(|column| column.to_ascii_uppercase()).collect();
+    push_line(&mut rendered, &header, &widths, None);
+    for (finding, row) in findings.iter().zip(&rows) {
+        let cells: Vec<String> = columns.iter().zip(row).zip(&widths)
+            .map(|((column, cell), &width)| if *column == "path" { truncate_middle(cell, width) } else { cell.clone() })
+            .collect();
+        let severity = columns.iter().position(|column| *column == "severity")
+            .zip(finding.severity.as_ref())
+            .filter(|_| color);
+        push_line(&mut rendered, &cells, &widths, severity);
+    }
+    rendered
+}
+
+/// Add one line of padded cells, coloring the cell at the given index by severity
+fn push_line(rendered: &mut String, cells: &[String], widths: &[usize], colored: Option<(usize, &Severity)>) {
+    for (index, (cell, &width)) in cells.iter().zip(widths).enumerate() {
+        if index > 0 {
+            rendered.push_str(GAP);
+        }
+        // The last column is not padded, so lines have no trailing spaces
+        let padding = if index + 1 < cells.len() { width.saturating_sub(cell.chars().count()) } else { 0 };
+        match colored {
+            Some((colored, severity)) if colored == index => {
+                rendered.push_str(severity_color(severity));
+                rendered.push_str(cell);
+                rendered.push_str(RESET);
+            }
+            _ => rendered.push_str(cell),
+        }
+        rendered.push_str(&" ".repeat(padding));
+    }
+    rendered.push('\n');
+}
+
+fn cell(finding: &ScanResult, column: &str) -> String {
+    let text = match column {
+        "path" => finding.file_path.clone(),
+        "severity" => finding.severity.as_ref().map_or_else(|| "-".to_string(), |severity| severity.to_string()),
+        "cve" => finding.cve.clone().unwrap_or_else(|| "-".to_string()),
+        "version" => finding.maven_coordinates.as_ref().map_or_else(|| "-".to_string(), |coordinates| coordinates.version.clone()),
+        "confidence" => finding.confidence.map_or_else(|| "-".to_string(), |confidence| format!("{:?}", confidence)),
+        "reason" => truncate_end(finding.reason.as_deref().unwrap_or("-"), REASON_WIDTH),
+        "hash" => DEDUP_HASHES.iter()
+            .find_map(|&algorithm| finding.hash(algorithm))
+            .map_or_else(|| "-".to_string(), |hash| hash[..hash.len().min(TABLE_HASH_LENGTH)].to_string()),
+        "annotations" => finding.annotations.as_ref().map_or_else(|| "-".to_string(), annotations::describe),
+        _ => "-".to_string(),
+    };
+    // Line breaks and tabs in a file name would break the layout; show them as `ls` does
+    text.chars().map(|c| if c.is_control() { '?' } else { c }).collect()
+}
+
+/// Column widths for `rows` in `width` characters, taking what the other
+/// columns leave for the path column when the widest paths do not fit
+fn fit(columns: &[&str], rows: &[Vec<String>], width: usize) -> Option<Vec<usize>> {
+    let mut widths: Vec<usize> = columns.iter().enumerate()
+        .map(|(index, column)| rows.iter().map(|row| row[index].chars().count()).fold(column.len(), usize::max))
+        .collect();
+    let total = widths.iter().sum::<usize>() + GAP.len() * columns.len().saturating_sub(1);
+    if total <= width {
+        return Some(widths);
+    }
+    let path = columns.iter().position(|column| *column == "path")?;
+    let available = (width + widths[path]).checked_sub(total).filter(|&available| available >= MIN_PATH_WIDTH)?;
+    widths[path] = available;
+    Some(widths)
+}
+
+/// `text` cut to `width` characters by replacing its middle with an
+/// ellipsis, keeping more of the end, where the file name is
+fn truncate_middle(text: &str, width: usize) -> String {
+    let chars: Vec<char> = text.chars().collect();
+    if chars.len() <= width {
+        return text.to_string();
+    }
+    let kept = width.saturating_sub(1);
+    let head = kept / 3;
+    let tail = kept - head;
+    chars[..head].iter().chain(['…'].iter()).chain(chars[chars.len() - tail..].iter()).collect()
+}
+
+fn truncate_end(text: &str, width: usize) -> String {
+    if text.chars().count() <= width {
+        return text.to_string();
+    }
+    text.chars().take(width.saturating_sub(1)).chain(['…']).collect()
+}
+
+fn severity_color(severity: &Severity) -> &'static str {
+    match severity {
+        Severity::Critical => "\x1b[1;31m",
+        Severity::High => "\x1b[31m",
+        Severity::Medium => "\x1b[33m",
+        Severity::Low => "\x1b[36m",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn finding(path: &str, severity: &str) -> ScanResult {
+        serde_json::from_value(serde_json::json!({
+            "file_path": path, "vulnerable": true, "status": "Vulnerable", "severity": severity,
+            "file_size": 1, "entropy": 0.0, "fourier_coefficient": [0.0, 0.0], "markov_probability": 0.0,
+        })).unwrap()
+    }
+
+    #[test]
+    fn truncate_middle_keeps_the_file_name() {
+        assert_eq!(truncate_middle("/opt/app/lib/log4j-core-2.14.1.jar", 20), "/opt/a…re-2.14.1.jar");
+        assert_eq!(truncate_middle("/opt/app/lib/log4j-core-2.14.1.jar", 20).chars().count(), 20);
+        assert_eq!(truncate_middle("/opt/app.jar", 20), "/opt/app.jar");
+        assert_eq!(truncate_end("log4j configuration: JMS appender", 10), "log4j con…");
+        assert_eq!(truncate_end("short", 10), "short");
+    }
+
+    /// Colors wrap only the severity cell, and the padding stays outside
+    /// them so the columns still line up
+    #[test]
+    fn severity_cells_are_colored() {
+        let results = [finding("/opt/a.jar", "Critical"), finding("/opt/b.jar", "Low")];
+        assert_eq!(render(&results, &["severity", "path"], Some(80), true), "\
+SEVERITY  PATH
+\x1b[1;31mcritical\x1b[0m  /opt/a.jar
+\x1b[36mlow\x1b[0m       /opt/b.jar
+");
+    }
+
+    #[test]
+    fn unknown_columns_are_an_error() {
+        let config = Config { columns: vec!["path".to_string(), "owner".to_string()], ..Default::default() };
+        let error = selected_columns(&config).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+        assert!(error.to_string().contains("'owner'"), "{}", error);
+        assert_eq!(selected_columns(&Config::default()).unwrap(), DEFAULT_COLUMNS);
+    }
+}
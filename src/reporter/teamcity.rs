@@ -0,0 +1,137 @@
+use super::report_filter::ReportFilter;
+use crate::config::Config;
+use crate::scanner::{ScanResult, ScanSummary, Severity};
+use std::io::{self, Write};
+
+/// Inspection type of findings without a rule id
+const DEFAULT_INSPECTION_TYPE: &str = "log4shell";
+
+/// Write vulnerable results as TeamCity service messages: an `inspectionType`
+/// per rule that matched, then an `inspection` per finding. A `buildProblem`
+/// is added for each condition that makes the scanner exit with an error, so
+/// the build fails with a reason rather than just an exit code. Scan coverage
+/// is reported as build statistics.
+pub fn report_teamcity(results: &[ScanResult], summary: &ScanSummary, mut output: Box<dyn Write>, config: &Config) -> io::Result<()> {
+    // Inspections for what --report-filter keeps; build problems for the whole scan
+    let filter = ReportFilter::of(config);
+    let findings: Vec<&ScanResult> = results.iter()
+        .filter(|r| r.vulnerable && filter.as_ref().is_none_or(|filter| filter.matches(r)))
+        .collect();
+    if let Some(filter) = &filter {
+        writeln!(output, "##teamcity[message text='{}']", escape(&filter.note(results).line()))?;
+    }
+
+    let mut types: Vec<&str> = Vec::new();
+    for finding in &findings {
+        let type_id = inspection_type(finding);
+        if !types.contains(&type_id) {
+            types.push(type_id);
+            writeln!(
+                output,
+                "##teamcity[inspectionType id='{}' name='{}' category='{}' description='{}']",
+                escape(type_id),
+                escape(type_id),
+                escape(finding.cve.as_deref().unwrap_or("log4j")),
+                escape(finding.reason.as_deref().unwrap_or("Vulnerable log4j artifact"))
+            )?;
+        }
+    }
+
+    for finding in &findings {
+        let severity = match finding.severity {
+            Some(Severity::Critical) | Some(Severity::High) => "ERROR",
+            Some(Severity::Low) => "INFO",
+            Some(Severity::Medium) | None => "WARNING",
+        };
+        let mut message = finding.reason.clone().unwrap_or_else(|| "Vulnerable log4j artifact".to_string());
+        if let Some(cve) = &finding.cve {
+            message = format!("{} ({})", message, cve);
+        }
+        writeln!(
+            output,
+            "##teamcity[inspection typeId='{}' message='{}' file='{}' SEVERITY='{}']",
+            escape(inspection_type(finding)),
+            escape(&message),
+            escape(&finding.file_path),
+            severity
+        )?;
+    }
+
+    if let Some(coverage) = &summary.coverage {
+        for (key, value) in [
+            ("log4jScanEntriesWalked", coverage.entries_walked as f64),
+            ("log4jScanCoveragePercent", coverage.scanned_percent),
+            ("log4jScanSkippedPercent", coverage.skipped_percent),
+            ("log4jScanErroredPercent", coverage.errored_percent),
+        ] {
+            writeln!(output, "##teamcity[buildStatisticValue key='{}' value='{}']", key, value)?;
+        }
+    }
+
+    let unscannable = results.iter().filter(|r| r.unscannable()).count();
+    let mut problems = Vec::new();
+    if summary.timed_out {
+        problems.push(("log4j-scan-timed-out", format!("Scan timed out after examining {} files; results are incomplete", summary.files_scanned)));
+    }
+    if summary.max_duration_reached {
+        problems.push(("log4j-scan-max-duration", format!("Scan reached --max-duration after examining {} files, {} not reached; results are incomplete", summary.files_scanned, summary.files_not_reached())));
+    }
+    if config.fail_on_permission_denied && !summary.permission_denied.is_empty() {
+        problems.push(("log4j-scan-permission-denied", format!("Permission denied for {} files", summary.permission_denied.len())));
+    }
+    if config.fail_on_unscannable && unscannable > 0 {
+        problems.push(("log4j-scan-unscannable", format!("{} files could not be scanned", unscannable)));
+    }
+    for (identity, description) in problems {
+        writeln!(output, "##teamcity[buildProblem description='{}' identity='{}']", escape(&description), identity)?;
+    }
+    Ok(())
+}
+
+fn inspection_type(finding: &ScanResult) -> &str {
+    finding.rule_id.as_deref().unwrap_or(DEFAULT_INSPECTION_TYPE)
+}
+
+/// Escape a service message attribute value: `|` is the escape character,
+/// and quotes, brackets and line breaks would otherwise end the message early
+fn escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '|' => escaped.push_str("||"),
+            '\'' => escaped.push_str("|'"),
+            '[' => escaped.push_str("|["),
+            ']' => escaped.push_str("|]"),
+            '\n' => escaped.push_str("|n"),
+            '\r' => escaped.push_str("|r"),
+            '\u{0085}' => escaped.push_str("|x"),
+            '\u{2028}' => escaped.push_str("|l"),
+            '\u{2029}' => escaped.push_str("|p"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escape_doubles_pipes_and_escapes_quotes_and_brackets() {
+        assert_eq!(escape("/srv/a|b/it's [old]/log4j-core.jar"), "/srv/a||b/it|'s |[old|]/log4j-core.jar");
+        assert_eq!(escape("||"), "||||");
+        assert_eq!(escape("]']"), "|]|'|]");
+    }
+
+    #[test]
+    fn escape_encodes_every_line_break() {
+        assert_eq!(escape("a\nb\rc\u{0085}d\u{2028}e\u{2029}f"), "a|nb|rc|xd|le|pf");
+    }
+
+    #[test]
+    fn escape_leaves_other_text_alone() {
+        assert_eq!(escape("C:\\apps\\log4j \"core\" ünïcode"), "C:\\apps\\log4j \"core\" ünïcode");
+        assert_eq!(escape(""), "");
+    }
+}
@@ -0,0 +1,61 @@
+use crate::scanner::{ScanResult, Severity};
+use std::io::{self, Write};
+
+/// Keys of every record, in the order they are written. Keys are never
+/// renamed, removed or reordered; new keys may only be appended.
+const KEYS: [&str; 5] = ["path", "severity", "cve", "sha256", "reason_code"];
+
+/// Write one record per finding for log scrapers, in a grammar that does not
+/// change when the `text` report's wording does:
+///
+/// ```text
+/// report  = *(record LF)
+/// record  = 1*(key "=" value LF)
+/// key     = "path" / "severity" / "cve" / "sha256" / "reason_code"
+/// value   = *(escaped / char)     ; char is anything but LF, CR and "\"
+/// escaped = "\\" / "\n" / "\r"
+/// ```
+///
+/// Every record has all of `KEYS` in that order, with an empty value when
+/// one is unknown. `severity` is `low`, `medium`, `high` or `critical` and
+/// `reason_code` is the id of the rule that matched. Output is never colored.
+pub fn report_text_stable(results: &[ScanResult], mut output: Box<dyn Write>) -> io::Result<()> {
+    for result in results.iter().filter(|r| r.vulnerable) {
+        let values = [
+            result.file_path.as_str(),
+            result.severity.as_ref().map(severity_code).unwrap_or_default(),
+            result.cve.as_deref().unwrap_or_default(),
+            result.file_hash.as_deref().unwrap_or_default(),
+            result.rule_id.as_deref().unwrap_or_default(),
+        ];
+        for (key, value) in KEYS.iter().zip(values) {
+            writeln!(output, "{}={}", key, escape(value))?;
+        }
+        writeln!(output)?;
+    }
+    Ok(())
+}
+
+/// Severity spelled independently of the enum's variant names
+fn severity_code(severity: &Severity) -> &'static str {
+    match severity {
+        Severity::Low => "low",
+        Severity::Medium => "medium",
+        Severity::High => "high",
+        Severity::Critical => "critical",
+    }
+}
+
+/// Escape what would break a line-based record: backslashes and line breaks
+fn escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
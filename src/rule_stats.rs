@@ -0,0 +1,71 @@
+use crate::config::Config;
+use crate::rules::{RuleOrigin, RuleSet};
+use once_cell::sync::Lazy;
+use std::collections::{HashMap, HashSet};
+use std::sync::Mutex;
+
+/// How much one rule or built-in check found during a scan
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct RuleStats {
+    pub id: String,
+    /// Where the rule was defined; checks built into the scanner, such as
+    /// the log4j configuration checks, have none
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub origin: Option<RuleOrigin>,
+    pub enabled: bool,
+    /// Files with at least one finding, counting an archive and everything in it once
+    pub files_matched: usize,
+    /// Findings, including each nested archive or configuration line of a file
+    pub total_matches: usize,
+    /// Distinct digests of the matched files and entries, with `--stats`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub unique_hashes: Option<usize>,
+}
+
+#[derive(Default)]
+struct Hits {
+    matches: usize,
+    files: HashSet<String>,
+    hashes: HashSet<String>,
+}
+
+/// Hits of the running scan by rule id, shared by every worker thread. Only
+/// findings touch it, so one lock is cheap enough.
+static HITS: Lazy<Mutex<HashMap<String, Hits>>> = Lazy::new(Default::default);
+
+/// Count a finding of `rule_id` at `path`, which for archive entries is
+/// `outer.jar!inner.jar` and counts towards the outer file
+pub fn record(rule_id: &str, path: &str, hash: Option<&str>, config: &Config) {
+    let file = path.split('!').next().unwrap_or(path);
+    let mut hits = HITS.lock().unwrap_or_else(|e| e.into_inner());
+    let hits = hits.entry(rule_id.to_string()).or_default();
+    hits.matches += 1;
+    hits.files.insert(file.to_string());
+    if let Some(hash) = hash.filter(|_| config.stats) {
+        hits.hashes.insert(hash.to_string());
+    }
+}
+
+/// Statistics of every rule in `rules`, in rule order and including those
+/// that found nothing, followed by the built-in checks that found something.
+/// Resets the counts for the next scan.
+pub fn take(rules: &RuleSet, config: &Config) -> Vec<RuleStats> {
+    let mut hits = std::mem::take(&mut *HITS.lock().unwrap_or_else(|e| e.into_inner()));
+    let unique_hashes = config.stats;
+    let stats = |id: String, origin: Option<RuleOrigin>, enabled: bool, hits: Hits| RuleStats {
+        id,
+        origin,
+        enabled,
+        files_matched: hits.files.len(),
+        total_matches: hits.matches,
+        unique_hashes: unique_hashes.then_some(hits.hashes.len()),
+    };
+
+    let mut all: Vec<RuleStats> = rules.all().iter()
+        .map(|rule| stats(rule.id.clone(), Some(rule.origin.clone()), rule.enabled, hits.remove(&rule.id).unwrap_or_default()))
+        .collect();
+    let mut checks: Vec<(String, Hits)> = hits.into_iter().collect();
+    checks.sort_by(|a, b| a.0.cmp(&b.0));
+    all.extend(checks.into_iter().map(|(id, hits)| stats(id, None, true, hits)));
+    all
+}
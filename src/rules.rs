@@ -0,0 +1,732 @@
+use crate::config::{validate_custom_patterns, Config};
+use crate::maven::MavenCoordinates;
+use crate::patterns_update;
+use crate::scanner::{decode_contents, parse_version, visit_scannable_contents, Confidence, Severity};
+use log::warn;
+use once_cell::sync::Lazy;
+use regex::Regex;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+
+/// Characters of context shown on each side of a match
+const SNIPPET_CONTEXT: usize = 24;
+
+pub const LOG4SHELL_CVE: &str = "CVE-2021-44228";
+/// log4j 1.x `JMSSink` deserializes untrusted JMS messages
+const LOG4J1_JMS_SINK_CVE: &str = "CVE-2022-23302";
+/// log4j 1.x Chainsaw deserializes untrusted log events
+const LOG4J1_CHAINSAW_CVE: &str = "CVE-2022-23307";
+/// logback's `JMXConfigurator` lets JMX users load a JNDI-backed configuration
+const LOGBACK_JMX_CVE: &str = "CVE-2021-42550";
+
+/// Short names of the CVEs built-in rules report, so findings that are not
+/// Log4Shell are not mistaken for it
+pub fn cve_name(cve: &str) -> Option<&'static str> {
+    match cve {
+        LOG4SHELL_CVE => Some("Log4Shell"),
+        LOG4J1_JMS_SINK_CVE => Some("log4j 1.x JMSSink deserialization"),
+        LOG4J1_CHAINSAW_CVE => Some("log4j 1.x Chainsaw deserialization"),
+        LOGBACK_JMX_CVE => Some("logback JMXConfigurator JNDI"),
+        _ => None,
+    }
+}
+
+/// What a rule's pattern is matched against
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum RuleTarget {
+    /// The name of an entry inside an archive
+    EntryName,
+    /// The decoded contents of a file or archive entry
+    Content,
+}
+
+/// Where a rule was defined
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RuleOrigin {
+    BuiltIn,
+    RulesFile(String),
+    CustomPattern,
+}
+
+impl fmt::Display for RuleOrigin {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RuleOrigin::BuiltIn => write!(f, "built-in"),
+            RuleOrigin::RulesFile(path) => write!(f, "file:{}", path),
+            RuleOrigin::CustomPattern => write!(f, "custom-pattern"),
+        }
+    }
+}
+
+impl serde::Serialize for RuleOrigin {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+/// The releases a rule applies to: it only matches inside an archive whose
+/// Maven coordinates name one of `artifact_ids` at a version below `fixed_in`
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct AffectedVersions {
+    pub artifact_ids: Vec<String>,
+    pub fixed_in: String,
+}
+
+impl AffectedVersions {
+    /// Whether `coordinates` name an affected release. Unknown or
+    /// unparseable versions are not affected, so such rules stay quiet
+    /// rather than guess.
+    fn contain(&self, coordinates: Option<&MavenCoordinates>) -> bool {
+        let coordinates = match coordinates {
+            Some(coordinates) if self.artifact_ids.contains(&coordinates.artifact_id) => coordinates,
+            _ => return false,
+        };
+        match (parse_version(&coordinates.version), parse_version(&self.fixed_in)) {
+            (Some(version), Some(fixed_in)) => version < fixed_in,
+            _ => false,
+        }
+    }
+}
+
+/// A detection rule with a stable id
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct Rule {
+    pub id: String,
+    pub target: RuleTarget,
+    #[serde(with = "serde_regex")]
+    pub pattern: Regex,
+    pub severity: Severity,
+    pub cve: Option<String>,
+    pub origin: RuleOrigin,
+    /// The pack of the rules file the rule came from
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pack: Option<String>,
+    pub enabled: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub affected_versions: Option<AffectedVersions>,
+}
+
+impl Rule {
+    fn built_in(id: &str, target: RuleTarget, pattern: &str, severity: Severity) -> Self {
+        Rule {
+            id: id.to_string(),
+            target,
+            pattern: Regex::new(pattern).unwrap(),
+            severity,
+            cve: Some(LOG4SHELL_CVE.to_string()),
+            origin: RuleOrigin::BuiltIn,
+            pack: None,
+            enabled: true,
+            affected_versions: None,
+        }
+    }
+
+    /// This rule reporting `cve` instead of Log4Shell
+    fn with_cve(self, cve: &str) -> Self {
+        Rule { cve: Some(cve.to_string()), ..self }
+    }
+
+    /// This rule limited to `artifact_ids` released before `fixed_in`
+    fn affecting(self, artifact_ids: &[&str], fixed_in: &str) -> Self {
+        let affected_versions = AffectedVersions {
+            artifact_ids: artifact_ids.iter().map(|id| id.to_string()).collect(),
+            fixed_in: fixed_in.to_string(),
+        };
+        Rule { affected_versions: Some(affected_versions), ..self }
+    }
+
+    /// Confidence of findings produced by this rule: entry names are
+    /// structural evidence, content patterns are circumstantial
+    pub fn confidence(&self) -> Confidence {
+        match self.target {
+            RuleTarget::EntryName => Confidence::High,
+            RuleTarget::Content => Confidence::Tentative,
+        }
+    }
+
+    /// Human-readable reason recorded on findings produced by this rule,
+    /// naming the issue when it is a known one other than Log4Shell
+    pub fn reason(&self) -> String {
+        let reason = match (&self.origin, self.target) {
+            (RuleOrigin::CustomPattern, _) => format!("Custom vulnerability pattern found: {}", self.pattern),
+            (_, RuleTarget::EntryName) => format!("Vulnerable entry found: {}", self.pattern),
+            (_, RuleTarget::Content) => format!("Vulnerable pattern found: {}", self.pattern),
+        };
+        match self.cve.as_deref().filter(|&cve| cve != LOG4SHELL_CVE).and_then(cve_name) {
+            Some(name) => format!("{} ({}, not Log4Shell)", reason, name),
+            None => reason,
+        }
+    }
+
+    /// Where the rule was defined, with its pack: `pack internal (file:internal.json)`
+    fn source(&self) -> String {
+        match &self.pack {
+            Some(pack) => format!("pack {} ({})", pack, self.origin),
+            None => self.origin.to_string(),
+        }
+    }
+
+    /// Whether the rule applies inside an archive with these coordinates
+    fn applies_to(&self, coordinates: Option<&MavenCoordinates>) -> bool {
+        self.affected_versions.as_ref().is_none_or(|affected| affected.contain(coordinates))
+    }
+}
+
+mod serde_regex {
+    use regex::Regex;
+
+    pub fn serialize<S: serde::Serializer>(regex: &Regex, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(regex.as_str())
+    }
+}
+
+static BUILTIN_RULES: Lazy<Vec<Rule>> = Lazy::new(|| {
+    vec![
+        Rule::built_in(
+            "log4j-jndi-lookup-entry",
+            RuleTarget::EntryName,
+            r"(^|/)org/apache/logging/log4j/core/lookup/JndiLookup\.class$",
+            Severity::Critical,
+        ),
+        Rule::built_in(
+            "log4j-jndi-lookup-class",
+            RuleTarget::Content,
+            r"org/apache/logging/log4j/core/lookup/JndiLookup",
+            Severity::Critical,
+        ),
+        Rule::built_in("jndi-initial-context", RuleTarget::Content, r"javax/naming/InitialContext", Severity::High),
+        Rule::built_in("jndi-context", RuleTarget::Content, r"javax/naming/Context", Severity::High),
+        Rule::built_in("jndi-lookup-string", RuleTarget::Content, r"\$\{jndi:", Severity::Critical),
+        Rule::built_in(
+            "log4j1-jms-sink",
+            RuleTarget::EntryName,
+            r"(^|/)org/apache/log4j/net/JMSSink\.class$",
+            Severity::High,
+        ).with_cve(LOG4J1_JMS_SINK_CVE),
+        Rule::built_in(
+            "log4j1-chainsaw",
+            RuleTarget::EntryName,
+            r"(^|/)org/apache/log4j/chainsaw/LoggingReceiver\.class$",
+            Severity::High,
+        ).with_cve(LOG4J1_CHAINSAW_CVE),
+        Rule::built_in(
+            "logback-jmx-configurator",
+            RuleTarget::EntryName,
+            r"(^|/)ch/qos/logback/classic/jmx/JMXConfigurator\.class$",
+            Severity::Medium,
+        ).with_cve(LOGBACK_JMX_CVE).affecting(&["logback-classic", "logback-core"], "1.2.8"),
+    ]
+});
+
+/// A rules file and what it says about itself, recorded in reports so a
+/// finding can be traced to the version of the pack that produced it
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct RulePack {
+    pub name: String,
+    /// The rules file
+    pub source: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub author: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub min_scanner_version: Option<String>,
+    /// Number of rules in the file
+    pub rules: usize,
+}
+
+/// The rules in effect for a scan: built-ins, then rules files, then custom patterns
+#[derive(Debug, Clone)]
+pub struct RuleSet {
+    rules: Vec<Rule>,
+    packs: Vec<RulePack>,
+}
+
+impl RuleSet {
+    /// Build the rule set for a configuration, failing if a rules file cannot
+    /// be loaded or two rules share an id
+    pub fn from_config(config: &Config) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut rules = BUILTIN_RULES.clone();
+
+        let mut packs = Vec::new();
+        if let Some((pack, saved_rules)) = load_saved_patterns()? {
+            // Saved patterns replace the built-in rules they share an id with
+            rules.retain(|rule| !saved_rules.iter().any(|saved| saved.id == rule.id));
+            rules.extend(saved_rules);
+            packs.push(pack);
+        }
+        for (pack, pack_rules) in load_packs(config)? {
+            rules.extend(pack_rules);
+            packs.push(pack);
+        }
+
+        let custom_patterns = validate_custom_patterns(&config.custom_patterns)
+            .map_err(|invalid| format!("invalid custom pattern: {}", invalid[0].0))?;
+        rules.extend(custom_patterns.into_iter()
+            .enumerate()
+            .map(|(i, pattern)| Rule {
+                id: format!("custom-{}", i + 1),
+                target: RuleTarget::Content,
+                pattern,
+                severity: Severity::High,
+                cve: None,
+                origin: RuleOrigin::CustomPattern,
+                pack: None,
+                enabled: true,
+                affected_versions: None,
+            }));
+        check_unique_ids(&rules)?;
+
+        for id in &config.disable_rules {
+            match rules.iter_mut().find(|rule| &rule.id == id) {
+                Some(rule) => rule.enabled = false,
+                None => warn!("Unknown rule id in --disable-rule: {}", id),
+            }
+        }
+
+        Ok(RuleSet { rules, packs })
+    }
+
+    /// The built-in rules alone, without saved patterns, rules files or
+    /// custom patterns
+    pub fn built_in() -> Self {
+        RuleSet { rules: BUILTIN_RULES.clone(), packs: Vec::new() }
+    }
+
+    /// The rules files in effect, as packs
+    pub fn packs(&self) -> &[RulePack] {
+        &self.packs
+    }
+
+    /// SHA-256 of the id, target and pattern of every enabled rule, in order:
+    /// equal digests mean scans matched with the same rules
+    pub fn digest(&self) -> String {
+        let mut hasher = Sha256::new();
+        for rule in self.enabled() {
+            hasher.update(format!("{}\t{:?}\t{}\n", rule.id, rule.target, rule.pattern).as_bytes());
+        }
+        format!("{:x}", hasher.finalize())
+    }
+
+    /// Every rule, including disabled ones
+    pub fn all(&self) -> &[Rule] {
+        &self.rules
+    }
+
+    /// Whether every enabled content rule is built in, so contents that
+    /// cannot match a built-in rule cannot match any rule
+    pub fn content_rules_are_builtin(&self) -> bool {
+        self.enabled()
+            .filter(|rule| rule.target == RuleTarget::Content)
+            .all(|rule| rule.origin == RuleOrigin::BuiltIn)
+    }
+
+    fn enabled(&self) -> impl Iterator<Item = &Rule> {
+        self.rules.iter().filter(|rule| rule.enabled)
+    }
+
+    /// First enabled rule matching one of the forms of an archive entry's
+    /// name, none outside an archive, or the content. `coordinates` are
+    /// those of the archive holding the entry, if any.
+    pub fn find_match(&self, entry_names: &[&str], contents: &[u8], coordinates: Option<&MavenCoordinates>) -> Option<&Rule> {
+        let entry_match = self.enabled()
+            .filter(|rule| rule.target == RuleTarget::EntryName && rule.applies_to(coordinates))
+            .find(|rule| entry_names.iter().any(|entry_name| rule.pattern.is_match(entry_name)));
+        if entry_match.is_some() {
+            return entry_match;
+        }
+
+        let text = decode_contents(contents);
+        self.enabled()
+            .filter(|rule| rule.target == RuleTarget::Content && rule.applies_to(coordinates))
+            .find(|rule| rule.pattern.is_match(&text))
+    }
+}
+
+/// Id of the built-in rule that already finds everything `pattern` finds:
+/// an identical content pattern, or a literal containing a built-in match
+pub fn redundant_builtin_rule(pattern: &Regex) -> Option<&'static str> {
+    let source = pattern.as_str();
+    let literal = literal_text(source);
+
+    BUILTIN_RULES.iter()
+        .filter(|rule| rule.target == RuleTarget::Content)
+        .find(|rule| {
+            rule.pattern.as_str() == source
+                || literal.as_deref().is_some_and(|text| rule.pattern.is_match(text))
+        })
+        .map(|rule| rule.id.as_str())
+}
+
+/// The text a pattern matches if it has no metacharacters other than
+/// escaped punctuation, e.g. `JndiLookup\.class` is `JndiLookup.class`
+fn literal_text(pattern: &str) -> Option<String> {
+    let mut literal = String::with_capacity(pattern.len());
+    let mut chars = pattern.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => literal.push(chars.next().filter(|c| c.is_ascii_punctuation())?),
+            '.' | '+' | '*' | '?' | '(' | ')' | '|' | '[' | ']' | '{' | '}' | '^' | '$' => return None,
+            c => literal.push(c),
+        }
+    }
+    Some(literal)
+}
+
+/// A rule as written in an external rules file
+#[derive(serde::Deserialize)]
+struct RuleDefinition {
+    id: String,
+    pattern: String,
+    #[serde(default = "default_severity")]
+    severity: Severity,
+    #[serde(default)]
+    cve: Option<String>,
+    #[serde(default)]
+    target: Option<String>,
+}
+
+#[derive(serde::Deserialize)]
+struct RulesFile {
+    /// Name `--packs` selects the file by; the file name without its
+    /// extension when missing
+    #[serde(default)]
+    pack: Option<String>,
+    #[serde(default)]
+    version: Option<String>,
+    #[serde(default)]
+    author: Option<String>,
+    /// Oldest scanner release the rules work with, e.g. for a target added later
+    #[serde(default)]
+    min_scanner_version: Option<String>,
+    rules: Vec<RuleDefinition>,
+}
+
+fn default_severity() -> Severity {
+    Severity::High
+}
+
+/// A rules file's pack and the rules in it
+pub type LoadedPack = (RulePack, Vec<Rule>);
+
+/// The `--rules` files of `config` as packs with their rules, only those
+/// named in `--packs` when it is given
+fn load_packs(config: &Config) -> Result<Vec<LoadedPack>, Box<dyn std::error::Error>> {
+    let mut packs = config.rules.iter()
+        .map(|path| load_rules_file(Path::new(path)))
+        .collect::<Result<Vec<_>, _>>()?;
+    if config.packs.is_empty() {
+        return Ok(packs);
+    }
+    if let Some(unknown) = config.packs.iter().find(|name| !packs.iter().any(|(pack, _)| &pack.name == *name)) {
+        let loaded: Vec<&str> = packs.iter().map(|(pack, _)| pack.name.as_str()).collect();
+        let loaded = if loaded.is_empty() { "none".to_string() } else { loaded.join(", ") };
+        return Err(format!("unknown rule pack '{}' in --packs (the --rules files define: {})", unknown, loaded).into());
+    }
+    packs.retain(|(pack, _)| config.packs.contains(&pack.name));
+    Ok(packs)
+}
+
+/// The patterns `update-patterns` saved, if any
+fn load_saved_patterns() -> Result<Option<LoadedPack>, Box<dyn std::error::Error>> {
+    match patterns_update::saved_path() {
+        Some(path) if path.is_file() => load_rules_file(&path)
+            .map(Some)
+            .map_err(|e| format!("{} (run update-patterns again or delete the file)", e).into()),
+        _ => Ok(None),
+    }
+}
+
+/// Reject a rule set in which two rules, from whichever sources, share an
+/// id: `--disable-rule` and findings' `rule_id` name rules by it
+fn check_unique_ids(rules: &[Rule]) -> Result<(), String> {
+    let mut seen: HashMap<&str, &Rule> = HashMap::new();
+    for rule in rules {
+        if let Some(first) = seen.insert(&rule.id, rule) {
+            return Err(format!("rule id {} is defined by both {} and {}", rule.id, first.source(), rule.source()));
+        }
+    }
+    Ok(())
+}
+
+/// Load a rules pack from a JSON file of the form `{"pack": ..., "version": ...,
+/// "rules": [{"id": ..., "pattern": ..., "severity": ...}]}`
+pub fn load_rules_file(path: &Path) -> Result<LoadedPack, Box<dyn std::error::Error>> {
+    let contents = fs::read_to_string(path)
+        .map_err(|e| format!("unable to read rules file {}: {}", path.display(), e))?;
+    parse_rules_file(&contents, path)
+}
+
+/// Parse and compile the rules of a rules file read from `path`, or
+/// downloaded to be saved there
+pub fn parse_rules_file(contents: &str, path: &Path) -> Result<LoadedPack, Box<dyn std::error::Error>> {
+    let file: RulesFile = serde_json::from_str(contents)
+        .map_err(|e| format!("invalid rules file {}: {}", path.display(), e))?;
+
+    let name = file.pack.clone()
+        .unwrap_or_else(|| path.file_stem().unwrap_or_default().to_string_lossy().to_string());
+    if let Some(required) = &file.min_scanner_version {
+        let required_version = parse_version(required)
+            .ok_or_else(|| format!("invalid min_scanner_version {} in {}", required, path.display()))?;
+        if parse_version(env!("CARGO_PKG_VERSION")).is_some_and(|version| version < required_version) {
+            return Err(format!(
+                "rule pack {} in {} needs scanner version {} or later; this is {}",
+                name, path.display(), required, env!("CARGO_PKG_VERSION"),
+            ).into());
+        }
+    }
+    let pack = RulePack {
+        name,
+        source: path.display().to_string(),
+        version: file.version,
+        author: file.author,
+        min_scanner_version: file.min_scanner_version,
+        rules: file.rules.len(),
+    };
+
+    let rules = file.rules.into_iter()
+        .map(|definition| {
+            let pattern = Regex::new(&definition.pattern)
+                .map_err(|e| format!("invalid pattern for rule {} in {}: {}", definition.id, path.display(), e))?;
+            let target = match definition.target.as_deref() {
+                None | Some("content") => RuleTarget::Content,
+                Some("entry-name") => RuleTarget::EntryName,
+                Some(other) => return Err(format!("invalid target for rule {}: {}", definition.id, other).into()),
+            };
+            Ok(Rule {
+                id: definition.id,
+                target,
+                pattern,
+                severity: definition.severity,
+                cve: definition.cve,
+                origin: RuleOrigin::RulesFile(path.display().to_string()),
+                pack: Some(pack.name.clone()),
+                enabled: true,
+                affected_versions: None,
+            })
+        })
+        .collect::<Result<Vec<_>, Box<dyn std::error::Error>>>()?;
+    Ok((pack, rules))
+}
+
+/// Print every rule in effect for `config`, in registry order
+pub fn list(config: &Config) -> Result<(), Box<dyn std::error::Error>> {
+    let rule_set = RuleSet::from_config(config)?;
+    let mut output = io::stdout().lock();
+
+    if config.format == "json" {
+        serde_json::to_writer_pretty(&mut output, rule_set.all())?;
+        writeln!(output)?;
+        return Ok(());
+    }
+
+    writeln!(output, "{:<26} {:<11} {:<9} {:<15} {:<9} {:<12} {:<20} PATTERN", "ID", "TARGET", "SEVERITY", "CVE", "STATUS", "PACK", "ORIGIN")?;
+    for rule in rule_set.all() {
+        let target = match rule.target {
+            RuleTarget::EntryName => "entry-name",
+            RuleTarget::Content => "content",
+        };
+        writeln!(
+            output,
+            "{:<26} {:<11} {:<9} {:<15} {:<9} {:<12} {:<20} {}",
+            rule.id,
+            target,
+            rule.severity.to_string(),
+            rule.cve.as_deref().unwrap_or("-"),
+            if rule.enabled { "enabled" } else { "disabled" },
+            rule.pack.as_deref().unwrap_or("-"),
+            rule.origin.to_string(),
+            match &rule.affected_versions {
+                Some(affected) => format!("{} (in {} before {})", rule.pattern, affected.artifact_ids.join(", "), affected.fixed_in),
+                None => rule.pattern.to_string(),
+            }
+        )?;
+    }
+    Ok(())
+}
+
+/// Run `--pattern` and/or the rules files in `config` against `target` the
+/// same way the scanner would, printing every match with its location and context
+pub fn test(pattern: Option<&str>, config: &Config, target: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    let mut rules: Vec<Rule> = Vec::new();
+    if let Some(pattern) = pattern {
+        rules.push(Rule {
+            id: pattern.to_string(),
+            target: RuleTarget::Content,
+            pattern: Regex::new(pattern).map_err(|e| format!("invalid pattern: {}", e))?,
+            severity: Severity::High,
+            cve: None,
+            origin: RuleOrigin::CustomPattern,
+            pack: None,
+            enabled: true,
+            affected_versions: None,
+        });
+    }
+    for (_, pack_rules) in load_packs(config)? {
+        rules.extend(pack_rules);
+    }
+
+    let mut hits = vec![0usize; rules.len()];
+    visit_scannable_contents(target, config, |location, entry, contents| {
+        let text = decode_contents(contents);
+        for (rule, hits) in rules.iter().zip(hits.iter_mut()) {
+            let haystack = match rule.target {
+                RuleTarget::EntryName => match entry {
+                    Some(entry) => entry,
+                    None => continue,
+                },
+                RuleTarget::Content => &text,
+            };
+            for m in rule.pattern.find_iter(haystack) {
+                *hits += 1;
+                println!("{} @ {} [{}]: {}", location, m.start(), rule.id, snippet(haystack, m.start(), m.end()));
+            }
+        }
+    })?;
+
+    if hits.iter().all(|&count| count == 0) {
+        println!("no matches");
+    }
+
+    if !config.rules.is_empty() {
+        for (heading, fired) in [("Rules fired:", true), ("Rules not fired:", false)] {
+            println!("\n{}", heading);
+            for (rule, count) in rules.iter().zip(&hits).filter(|(_, &count)| (count > 0) == fired) {
+                println!("  {} ({}): {} matches", rule.id, rule.severity, count);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// How one rule fared in `pattern-test`
+#[derive(serde::Serialize)]
+struct PatternResult<'a> {
+    id: &'a str,
+    target: RuleTarget,
+    severity: &'a Severity,
+    enabled: bool,
+    matches: usize,
+    /// Where the rule first matched: the file, or `file!entry` inside an archive
+    first_match: Option<String>,
+}
+
+/// Run every rule in effect for `config`, including disabled ones, against
+/// `target` whatever its extension, and print a row per rule whether it
+/// matched or not. Returns whether any enabled rule matched.
+pub fn pattern_test(config: &Config, target: &Path) -> Result<bool, Box<dyn std::error::Error>> {
+    let rule_set = RuleSet::from_config(config)?;
+    let mut results: Vec<PatternResult> = rule_set.all().iter()
+        .map(|rule| PatternResult {
+            id: &rule.id,
+            target: rule.target,
+            severity: &rule.severity,
+            enabled: rule.enabled,
+            matches: 0,
+            first_match: None,
+        })
+        .collect();
+
+    visit_scannable_contents(target, config, |location, entry, contents| {
+        let text = decode_contents(contents);
+        for (rule, result) in rule_set.all().iter().zip(results.iter_mut()) {
+            let haystack = match rule.target {
+                RuleTarget::EntryName => match entry {
+                    Some(entry) => entry,
+                    None => continue,
+                },
+                RuleTarget::Content => &text,
+            };
+            let matches = rule.pattern.find_iter(haystack).count();
+            if matches > 0 && result.first_match.is_none() {
+                result.first_match = Some(location.to_string());
+            }
+            result.matches += matches;
+        }
+    })?;
+    let matched = results.iter().any(|result| result.enabled && result.matches > 0);
+
+    let mut output = io::stdout().lock();
+    if config.format == "json" {
+        serde_json::to_writer_pretty(&mut output, &results)?;
+        writeln!(output)?;
+        return Ok(matched);
+    }
+
+    writeln!(output, "{:<26} {:<11} {:<9} {:<9} {:>7}  FIRST MATCH", "ID", "TARGET", "SEVERITY", "STATUS", "MATCHES")?;
+    for result in &results {
+        let target = match result.target {
+            RuleTarget::EntryName => "entry-name",
+            RuleTarget::Content => "content",
+        };
+        writeln!(
+            output,
+            "{:<26} {:<11} {:<9} {:<9} {:>7}  {}",
+            result.id,
+            target,
+            result.severity.to_string(),
+            if result.enabled { "enabled" } else { "disabled" },
+            result.matches,
+            result.first_match.as_deref().unwrap_or("-")
+        )?;
+    }
+    let fired = results.iter().filter(|result| result.enabled && result.matches > 0).count();
+    writeln!(output, "\n{} of {} enabled rules matched", fired, results.iter().filter(|result| result.enabled).count())?;
+    Ok(matched)
+}
+
+/// Text around a match with non-printable characters replaced by `.`
+fn snippet(text: &str, start: usize, end: usize) -> String {
+    let before: Vec<char> = text[..start].chars().rev().take(SNIPPET_CONTEXT).collect();
+    let after = text[end..].chars().take(SNIPPET_CONTEXT);
+
+    before.into_iter().rev()
+        .chain(text[start..end].chars())
+        .chain(after)
+        .map(|c| if c.is_control() || c == char::REPLACEMENT_CHARACTER { '.' } else { c })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Inputs covering a match of each built-in rule, near misses, and text
+    /// that is not UTF-8 once decoded
+    const SAMPLES: &[&str] = &[
+        "org/apache/logging/log4j/core/lookup/JndiLookup.class",
+        "BOOT-INF/lib/org/apache/logging/log4j/core/lookup/JndiLookup.class",
+        "org/apache/logging/log4j/core/lookup/JndiLookup.class.bak",
+        "xorg/apache/logging/log4j/core/lookup/JndiLookup.class",
+        "\u{1}\u{7}Lorg/apache/logging/log4j/core/lookup/JndiLookup;\u{0}",
+        "javax/naming/InitialContext",
+        "javax/naming/Context",
+        "javax.naming.Context",
+        "${jndi:ldap://example.com/a}",
+        "$ {jndi:",
+        "org/apache/log4j/net/JMSSink.class",
+        "org/apache/log4j/chainsaw/LoggingReceiver.class",
+        "ch/qos/logback/classic/jmx/JMXConfigurator.class",
+        "\u{fffd}\u{fffd}clean",
+        "",
+    ];
+
+    /// The shared, compiled-once patterns find exactly what compiling each
+    /// pattern afresh would
+    #[test]
+    fn built_in_patterns_match_like_freshly_compiled_ones() {
+        for rule in BUILTIN_RULES.iter() {
+            let fresh = Regex::new(rule.pattern.as_str()).unwrap();
+            for sample in SAMPLES {
+                assert_eq!(rule.pattern.is_match(sample), fresh.is_match(sample), "{} on {:?}", rule.id, sample);
+            }
+            assert!(SAMPLES.iter().any(|sample| rule.pattern.is_match(sample)), "no sample matches {}", rule.id);
+        }
+    }
+}
@@ -0,0 +1,133 @@
+use log::{debug, warn};
+use once_cell::sync::Lazy;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use zip::result::ZipError;
+
+/// Warnings logged per kind of error in each directory; later ones are only
+/// counted, so one unreadable subtree cannot bury every other warning
+const WARNINGS_PER_DIRECTORY: usize = 3;
+/// Paths kept per kind of error to show in the digest
+const DIGEST_EXAMPLES: usize = 3;
+
+/// What went wrong reading a file, the key errors are counted under
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ScanErrorKind {
+    PermissionDenied,
+    NotFound,
+    /// Not a valid archive, or one that could not be unpacked
+    InvalidArchive,
+    /// Any other I/O failure
+    Read,
+}
+
+impl ScanErrorKind {
+    pub fn of_io(error: &io::Error) -> Self {
+        match error.kind() {
+            io::ErrorKind::PermissionDenied => ScanErrorKind::PermissionDenied,
+            io::ErrorKind::NotFound => ScanErrorKind::NotFound,
+            _ => ScanErrorKind::Read,
+        }
+    }
+
+    pub fn of_zip(error: &ZipError) -> Self {
+        match error {
+            ZipError::Io(error) => Self::of_io(error),
+            _ => ScanErrorKind::InvalidArchive,
+        }
+    }
+}
+
+impl fmt::Display for ScanErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ScanErrorKind::PermissionDenied => write!(f, "permission denied"),
+            ScanErrorKind::NotFound => write!(f, "not found"),
+            ScanErrorKind::InvalidArchive => write!(f, "invalid archive"),
+            ScanErrorKind::Read => write!(f, "read error"),
+        }
+    }
+}
+
+/// How often one kind of error occurred during a scan, with the first paths,
+/// in path order, it occurred at
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct ErrorDigestEntry {
+    pub kind: ScanErrorKind,
+    pub count: usize,
+    pub examples: Vec<String>,
+}
+
+#[derive(Default)]
+struct Collector {
+    kinds: BTreeMap<ScanErrorKind, ErrorDigestEntry>,
+    per_directory: HashMap<(PathBuf, ScanErrorKind), usize>,
+    /// The first error message of each file, until the scan takes it
+    messages: HashMap<PathBuf, String>,
+}
+
+/// Errors of the running scan, shared by every worker thread
+static COLLECTOR: Lazy<Mutex<Collector>> = Lazy::new(Default::default);
+
+/// Count an error reading `path` and log `message` as a warning, unless
+/// enough errors of this kind were already logged for its directory. Paths
+/// inside archives (`outer.jar!inner.jar`) count towards the outer file.
+pub fn record(path: impl AsRef<Path>, kind: ScanErrorKind, message: fmt::Arguments) {
+    let label = path.as_ref().to_string_lossy();
+    let file = PathBuf::from(label.split('!').next().unwrap_or_default());
+    COLLECTOR.lock().unwrap_or_else(|e| e.into_inner()).messages.entry(file).or_insert_with(|| message.to_string());
+    if count(path, kind) <= WARNINGS_PER_DIRECTORY {
+        warn!("{}", message);
+    } else {
+        debug!("{}", message);
+    }
+}
+
+/// Count an error reading `path` without logging it, returning how many
+/// errors of this kind its directory has had
+pub fn count(path: impl AsRef<Path>, kind: ScanErrorKind) -> usize {
+    let label = path.as_ref().to_string_lossy();
+    let file = Path::new(label.split('!').next().unwrap_or_default());
+    let directory = file.parent().unwrap_or(Path::new("")).to_path_buf();
+
+    let mut collector = COLLECTOR.lock().unwrap_or_else(|e| e.into_inner());
+    let entry = collector.kinds.entry(kind).or_insert_with(|| ErrorDigestEntry { kind, count: 0, examples: Vec::new() });
+    entry.count += 1;
+    // Path order rather than the order worker threads got there, so the
+    // same tree always gives the same examples
+    if let Err(index) = entry.examples.binary_search_by(|example| example.as_str().cmp(&label)) {
+        if index < DIGEST_EXAMPLES {
+            entry.examples.insert(index, label.to_string());
+            entry.examples.truncate(DIGEST_EXAMPLES);
+        }
+    }
+    let in_directory = collector.per_directory.entry((directory, kind)).or_default();
+    *in_directory += 1;
+    *in_directory
+}
+
+/// The first error recorded while reading `path` or anything inside it
+pub fn take_message(path: &Path) -> Option<String> {
+    COLLECTOR.lock().unwrap_or_else(|e| e.into_inner()).messages.remove(path)
+}
+
+/// The errors counted since the last call, most frequent first, and log
+/// them as a digest. Resets the counts for the next scan.
+pub fn take_digest() -> Vec<ErrorDigestEntry> {
+    let collector = std::mem::take(&mut *COLLECTOR.lock().unwrap_or_else(|e| e.into_inner()));
+    let mut digest: Vec<ErrorDigestEntry> = collector.kinds.into_values().collect();
+    digest.sort_by(|a, b| b.count.cmp(&a.count).then(a.kind.cmp(&b.kind)));
+    for entry in &digest {
+        warn!("{}", digest_line(entry));
+    }
+    digest
+}
+
+/// `1204 files: permission denied (examples: /a, /b, /c)`
+pub fn digest_line(entry: &ErrorDigestEntry) -> String {
+    format!("{} files: {} (examples: {})", entry.count, entry.kind, entry.examples.join(", "))
+}
@@ -1,39 +1,186 @@
+use crate::audit_log::{self, AuditReference, Decision};
+use crate::benchmark::{self, Phase};
+use crate::class_path;
 use crate::config::Config;
-use crate::utils::{is_jar_file, is_class_file, calculate_file_hash};
-use blake3::Hasher as Blake3Hasher;
+use crate::coverage::{self, Coverage, SkipReason};
+use crate::dex;
+use crate::discovery::DiscoveredRoot;
+use crate::maven::MavenCoordinates;
+use crate::object_storage::StorageObject;
+use crate::jimage::scan_jimage;
+use crate::log4j_config::{find_issues, is_log4j_config};
+use crate::package::{scan_package, scan_tar};
+use crate::rule_stats::{self, RuleStats};
+use crate::rules::{RulePack, RuleSet, LOG4SHELL_CVE};
+use crate::scan_errors::{self, ErrorDigestEntry, ScanErrorKind};
+use crossbeam_channel::{SendTimeoutError, Sender};
+use crate::utils::{is_jar_file, is_class_file, is_pom_file, is_pom_properties_entry, is_apk_file, is_bundle_file, is_python_package, is_rpm_file, is_deb_file, is_jmod_file, is_jimage_file, is_readable, is_hidden, is_link, is_virtual_fs, directory_id, system_dirs, virtual_fs_dirs, calculate_file_hashes, calculate_hashes, format_rfc3339, nested_path, escape_invalid_utf8, has_class_magic_bytes, has_zip_magic_bytes, read_file, bytes_read, lower_thread_priority, CountingSemaphore, Deadline, IoBudget, SharedFile, ThrottledReader, FileHashes, HashAlgorithm, CLASS_MAGIC};
 use fftw::array::AlignedVec;
 use fftw::plan::*;
 use fftw::types::*;
 use glob::Pattern;
 use indicatif::{ProgressBar, ProgressStyle};
 use log::{debug, info, warn};
+use memchr::memmem;
 use nalgebra::DMatrix;
 use num_complex::Complex;
+use once_cell::sync::Lazy;
 use rayon::prelude::*;
 use regex::Regex;
-use sha3::{Sha3_256, Digest};
+use std::borrow::Cow;
+use std::cell::Cell;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
 use std::fs::File;
-use std::io::{BufReader, Read};
-use std::path::Path;
-use std::sync::Arc;
-use walkdir::WalkDir;
+use std::io::{self, BufReader, Cursor, Read, Seek, Write};
+use std::ops::{ControlFlow, Range};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+use walkdir::{DirEntry, WalkDir};
+use zip::result::ZipError;
+use zip::read::ZipFile;
 use zip::ZipArchive;
 
-#[derive(Debug, serde::Serialize)]
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct ScanResult {
     pub file_path: String,
+    /// Path of the entry the finding is in, within a war, ear, zip, egg or wheel bundle
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub inner_path: Option<String>,
     pub vulnerable: bool,
+    pub status: ScanStatus,
     pub reason: Option<String>,
     pub severity: Option<Severity>,
-    pub file_hash: String,
-    pub sha3_hash: String,
-    pub blake3_hash: String,
+    pub confidence: Option<Confidence>,
+    pub cve: Option<String>,
+    /// Stable id of the rule or check behind a finding
+    pub rule_id: Option<String>,
+    pub maven_coordinates: Option<MavenCoordinates>,
+    /// Java release the matching class was compiled for, e.g. `Java 8 (52.0)`;
+    /// only for findings in class files and class entries
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub class_version: Option<String>,
+    /// Notes from the `--annotations` file, such as a ticket or an owner
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub annotations: Option<BTreeMap<String, String>>,
+    /// Stored and inflated size of the class entry a finding in an archive is in
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub compressed_size_bytes: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub uncompressed_size_bytes: Option<u64>,
+    /// SHA-256 of the file; this and the other digests are omitted unless selected with `--hashes`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub file_hash: Option<String>,
+    pub file_size: u64,
+    pub modified: Option<String>,
+    /// SHA3-256 and BLAKE3 of the whole file too, even for a finding in an
+    /// archive entry; in schema version 1 reports they were of the matched entry
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sha3_hash: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub blake3_hash: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub xxh3_hash: Option<String>,
+    /// Lookup URLs such as `ldap://host/a` in `${jndi:...}` strings of the matching contents
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub jndi_urls: Vec<String>,
+    #[serde(serialize_with = "metric_precision::serialize")]
     pub entropy: f64,
+    /// Share of each byte value 0–255 in the contents, scaled so 255 is 100%;
+    /// only with `--include-byte-histogram`
+    #[serde(default, skip_serializing_if = "Option::is_none", with = "byte_histogram")]
+    pub byte_frequency: Option<Box<[u8; 256]>>,
+    /// ETag and storage class of the `--s3` or `--gcs` object the result is from
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub storage_object: Option<StorageObject>,
+    /// Found through a scanned jar's `Class-Path` manifest header rather than
+    /// under a scan root; only with `--manifest-scan`
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub discovered_via_classpath: bool,
+    #[serde(serialize_with = "metric_precision::serialize_complex")]
     pub fourier_coefficient: Complex<f64>,
+    #[serde(serialize_with = "metric_precision::serialize")]
     pub markov_probability: f64,
 }
 
-#[derive(Debug, serde::Serialize, Clone)]
+/// The statistical metrics are written to 6 decimal places, enough to compare
+/// files by, so floating-point noise never changes a report
+mod metric_precision {
+    use num_complex::Complex;
+    use serde::{Serialize, Serializer};
+
+    const SCALE: f64 = 1e6;
+
+    fn round(value: f64) -> f64 {
+        // Adding 0.0 turns a -0.0 left by rounding into 0.0
+        (value * SCALE).round() / SCALE + 0.0
+    }
+
+    pub fn serialize<S: Serializer>(value: &f64, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_f64(round(*value))
+    }
+
+    /// As num-complex serializes it: `[re, im]`
+    pub fn serialize_complex<S: Serializer>(value: &Complex<f64>, serializer: S) -> Result<S::Ok, S::Error> {
+        (round(value.re), round(value.im)).serialize(serializer)
+    }
+}
+
+/// serde has no impls for arrays this long; a histogram is a plain array of 256 integers
+mod byte_histogram {
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(histogram: &Option<Box<[u8; 256]>>, serializer: S) -> Result<S::Ok, S::Error> {
+        match histogram {
+            Some(histogram) => serializer.collect_seq(histogram.iter()),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Box<[u8; 256]>>, D::Error> {
+        Option::<Vec<u8>>::deserialize(deserializer)?
+            .map(|values| {
+                let len = values.len();
+                values.into_boxed_slice().try_into()
+                    .map_err(|_| serde::de::Error::invalid_length(len, &"256 byte frequencies"))
+            })
+            .transpose()
+    }
+}
+
+impl ScanResult {
+    /// The file's digest for `algorithm`, if it was computed
+    pub fn hash(&self, algorithm: HashAlgorithm) -> Option<&str> {
+        match algorithm {
+            HashAlgorithm::Sha256 => self.file_hash.as_deref(),
+            HashAlgorithm::Sha3 => self.sha3_hash.as_deref(),
+            HashAlgorithm::Blake3 => self.blake3_hash.as_deref(),
+            HashAlgorithm::Xxh3 => self.xxh3_hash.as_deref(),
+        }
+    }
+
+    /// Whether the result is of a file the scan could not fully examine, as
+    /// counted by `--fail-on-unscannable`. A file named like a class file but
+    /// without the class magic was read whole and is simply not a class file.
+    pub fn unscannable(&self) -> bool {
+        self.status != ScanStatus::Vulnerable && self.reason.as_deref() != Some(NOT_A_CLASS_FILE_REASON)
+    }
+}
+
+/// Whether a result is a finding or a file the scanner could not fully examine
+#[derive(Debug, serde::Serialize, serde::Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum ScanStatus {
+    Vulnerable,
+    /// Not scanned at all, e.g. encrypted entries or an unsupported package format
+    Skipped,
+    /// A corrupted file; any readable entries were still scanned
+    Error,
+}
+
+#[derive(Debug, serde::Serialize, serde::Deserialize, Clone, PartialEq, Eq, PartialOrd, Ord)]
 pub enum Severity {
     Low,
     Medium,
@@ -41,201 +188,2067 @@ pub enum Severity {
     Critical,
 }
 
-pub fn scan_directory(config: &Config) -> Result<Vec<ScanResult>, Box<dyn std::error::Error>> {
-    if !config.quiet {
-        info!("Scanning directory: {}", config.path);
+impl FromStr for Severity {
+    type Err = SeverityParseError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.to_ascii_lowercase().as_str() {
+            "low" => Ok(Severity::Low),
+            "medium" => Ok(Severity::Medium),
+            "high" => Ok(Severity::High),
+            "critical" => Ok(Severity::Critical),
+            _ => Err(SeverityParseError { value: value.to_string() }),
+        }
+    }
+}
+
+/// A name `Severity::from_str` does not accept
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SeverityParseError {
+    value: String,
+}
+
+impl fmt::Display for SeverityParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "invalid severity '{}' (expected low, medium, high or critical)", self.value)
+    }
+}
+
+impl std::error::Error for SeverityParseError {}
+
+/// The lowercase name `from_str` accepts, e.g. `critical`
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Severity::Low => write!(f, "low"),
+            Severity::Medium => write!(f, "medium"),
+            Severity::High => write!(f, "high"),
+            Severity::Critical => write!(f, "critical"),
+        }
+    }
+}
+
+/// How certain a finding is, from circumstantial to certain
+#[derive(Debug, serde::Serialize, serde::Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Confidence {
+    /// Content patterns and heuristics
+    Tentative,
+    /// Structural matches such as a JndiLookup class entry
+    High,
+    /// Exact version or hash matches
+    Confirmed,
+}
+
+impl FromStr for Confidence {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.to_ascii_lowercase().as_str() {
+            "tentative" => Ok(Confidence::Tentative),
+            "high" => Ok(Confidence::High),
+            "confirmed" => Ok(Confidence::Confirmed),
+            _ => Err(format!("invalid confidence '{}' (expected tentative, high or confirmed)", value)),
+        }
+    }
+}
+
+/// A match produced by the detection layer
+struct Detection {
+    /// Stable id of the rule or check, unlike `reason`, which is prose
+    rule_id: String,
+    reason: String,
+    severity: Severity,
+    confidence: Confidence,
+    cve: Option<String>,
+}
+
+/// Put results in path order, and by entry within a path, so reports do not
+/// depend on the order worker threads finished in. The sort is stable, so an
+/// archive's own result stays ahead of the findings in its entries.
+pub fn sort_results(results: &mut [ScanResult], summary: &mut ScanSummary) {
+    results.sort_by(|a, b| a.file_path.cmp(&b.file_path).then_with(|| a.inner_path.cmp(&b.inner_path)));
+    summary.permission_denied.sort();
+}
+
+/// Keep the first `max` findings. Skipped and corrupted files don't count
+/// towards `--max-findings` and are kept wherever they are in the results.
+pub fn limit_findings(results: &mut Vec<ScanResult>, max: usize) {
+    let mut kept = 0;
+    results.retain(|r| r.status != ScanStatus::Vulnerable || {
+        kept += 1;
+        kept <= max
+    });
+}
+
+/// Drop findings below `min_confidence`; results without a confidence are kept
+pub fn retain_min_confidence(results: &mut Vec<ScanResult>, min_confidence: Option<Confidence>) {
+    if let Some(min_confidence) = min_confidence {
+        results.retain(|r| !matches!(r.confidence, Some(c) if c < min_confidence));
+    }
+}
+
+const ZIP_MAGIC: &[u8] = b"PK\x03\x04";
+/// Header of a `.jmod` file, followed by a zip archive
+const JMOD_MAGIC: &[u8] = b"JM\x01\x00";
+const ENCRYPTED_ENTRIES_REASON: &str = "encrypted entries — cannot scan";
+/// Reason of the result listed for a `.class` file without the class magic
+const NOT_A_CLASS_FILE_REASON: &str = "Not a valid class file";
+/// Directory of Java service loader registrations, one file per service interface
+const SERVICES_DIR: &str = "META-INF/services/";
+/// Terms marking a service registration as JNDI related: log4j, JNDI, or the JNDI API's package
+const SERVICE_JNDI_TERMS: [&str; 3] = ["log4j", "jndi", "javax.naming"];
+/// Searched for in the string table of Dalvik bytecode, which has no JVM
+/// class entries to match rules against: the type descriptor of log4j's
+/// JndiLookup class, compiled in when log4j-core is, and the lookup string
+const DEX_JNDI_LOOKUP_DESCRIPTOR: &[u8] = b"Lorg/apache/logging/log4j/core/lookup/JndiLookup;";
+const DEX_JNDI_PATTERN: &[u8] = b"${jndi:";
+/// Characters of a matched dex string shown in the reason
+const DEX_STRING_SHOWN: usize = 80;
+/// URLs kept per finding; an exploit kit may embed many
+const MAX_JNDI_URLS: usize = 20;
+const MEGABYTE: u64 = 1024 * 1024;
+/// Contents larger than this build their Markov transition matrix in
+/// parallel with `--markov-parallel`
+const MARKOV_PARALLEL_THRESHOLD: usize = 100 * 1024;
+const MARKOV_CHUNK_SIZE: usize = 64 * 1024;
+/// Jars at least this large have their entries scanned in parallel
+const PARALLEL_ARCHIVE_THRESHOLD: u64 = 64 * MEGABYTE;
+/// Streamed zip archives up to this size are scanned in memory
+const STREAM_SPOOL_THRESHOLD: u64 = 64 * MEGABYTE;
+/// Label of `--stdin` results without `--filename`
+const STDIN_NAME: &str = "<stdin>";
+/// How often a walker waiting on a full queue checks whether the scan was stopped
+const QUEUE_POLL_INTERVAL: Duration = Duration::from_millis(50);
+/// Entry chunks per pool thread when scanning one archive in parallel, so
+/// threads that finish early can take work from slower ones
+const ARCHIVE_CHUNKS_PER_THREAD: usize = 4;
+
+#[derive(Debug, Default, serde::Serialize)]
+pub struct ScanSummary {
+    pub files_scanned: usize,
+    pub truncated: bool,
+    /// The `--timeout` deadline passed before every file was scanned
+    pub timed_out: bool,
+    /// `--max-duration` passed before every file was handed to a worker,
+    /// so the report is partial; the coverage counts the files not reached
+    pub max_duration_reached: bool,
+    pub since: Option<String>,
+    pub permission_denied: Vec<PathBuf>,
+    /// Errors reading files, counted by kind
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub error_digest: Vec<ErrorDigestEntry>,
+    /// Fingerprint of the key the report will be signed with
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub signing_key_fingerprint: Option<String>,
+    /// The `--audit-log` file and the id of this scan's events in it
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub audit_log: Option<AuditReference>,
+    /// Installations found by `--discover-java` and what was found in each
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub discovered_roots: Vec<DiscoveredRoot>,
+    /// The most files waiting for a worker at once, at most `--queue-capacity`
+    #[serde(skip)]
+    pub peak_queue_depth: usize,
+    /// Directories the scan walked
+    #[serde(skip)]
+    pub roots: Vec<PathBuf>,
+    /// Every file examined, kept only for `--summary-by-dir`
+    #[serde(skip)]
+    pub scanned_files: Vec<PathBuf>,
+    /// What each rule found, rules that found nothing included
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub rule_stats: Vec<RuleStats>,
+    /// The `--rules` files in effect, with what they say about themselves
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub rule_packs: Vec<RulePack>,
+    /// What the walk reached and what became of it, for scans of directories
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub coverage: Option<Coverage>,
+    /// The deepest archive nesting the scan came across: 1 for a jar in a
+    /// war, 2 for a jar in that jar, and so on; archives deeper than
+    /// `--max-archive-depth` count too, though they were not opened
+    pub archive_depth_reached: usize,
+    /// Keys of the `--annotations` file that matched no result
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub stale_annotations: Vec<String>,
+}
+
+impl ScanSummary {
+    /// Files the walk found but `--max-duration` left unscanned
+    pub fn files_not_reached(&self) -> usize {
+        self.coverage.as_ref()
+            .and_then(|coverage| coverage.skipped_by_reason.get(&SkipReason::MaxDuration).copied())
+            .unwrap_or(0)
+    }
+}
+
+pub fn scan_directory(config: &Config) -> Result<(Vec<ScanResult>, ScanSummary), Box<dyn std::error::Error>> {
+    info!("Scanning directory: {}", config.path);
+
+    scan_paths(config, &[PathBuf::from(&config.path)])
+}
+
+/// Scan every file under each of `roots` as one scan, sharing the thread pool,
+/// limits and summary
+pub fn scan_paths(config: &Config, roots: &[PathBuf]) -> Result<(Vec<ScanResult>, ScanSummary), Box<dyn std::error::Error>> {
+    let config = &Config { io_budget: IoBudget::new(config.io_limit), ..config.clone() };
+
+    let timed_out = Arc::new(AtomicBool::new(false));
+    let _deadline = config.timeout.map(|seconds| Deadline::start(Duration::from_secs(seconds), Arc::clone(&timed_out)));
+    let out_of_time = Arc::new(AtomicBool::new(false));
+    let _max_duration = config.max_duration.map(|duration| Deadline::start(duration, Arc::clone(&out_of_time)));
+
+    let nice = config.nice;
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(config.scan_threads())
+        .start_handler(move |_| {
+            if nice {
+                lower_thread_priority();
+            }
+        })
+        .build()?;
+
+    let rules = RuleSet::from_config(config)?;
+    let archives = archive_formats(config)?;
+
+    // The walker adds each file to the length as it queues it
+    let progress_bar = if !config.quiet() {
+        Some(Arc::new(ProgressBar::new(0)))
+    } else {
+        None
+    };
+
+    if let Some(pb) = &progress_bar {
+        pb.set_style(ProgressStyle::default_bar()
+            .template("[{elapsed_precise}] {bar:40.cyan/blue} {pos:>7}/{len:7} {msg}")
+            .unwrap()
+            .progress_chars("##-"));
+    }
+
+    let files_scanned = AtomicUsize::new(0);
+    let findings = AtomicUsize::new(0);
+    let stop = AtomicBool::new(false);
+    let permission_denied = Mutex::new(Vec::new());
+    let scanned_files = config.summary_by_dir.map(|_| Mutex::new(Vec::new()));
+    let memory_limit = config.threads_max_memory.map(CountingSemaphore::new);
+    let class_path = Mutex::new(Vec::new());
+    let (queue, queued) = crossbeam_channel::bounded(config.queue_capacity);
+
+    let scan_entry = |entry: &DirEntry| {
+        let path = entry.path();
+        if stop.load(Ordering::Relaxed) || timed_out.load(Ordering::Relaxed) {
+            audit_log::file(path, Decision::Skipped, format_args!("scan stopped early"));
+            coverage::skipped(path, if timed_out.load(Ordering::Relaxed) { SkipReason::Timeout } else { SkipReason::Stopped }, config);
+            return Vec::new();
+        }
+        // Queued before --max-duration passed, but not yet handed to a worker
+        if out_of_time.load(Ordering::Relaxed) {
+            not_reached(path, config);
+            return Vec::new();
+        }
+
+        let pb = progress_bar.as_ref().map(Arc::clone);
+        let scannable = is_scannable(path, config, &archives);
+        let _permit = memory_limit.as_ref()
+            .filter(|_| scannable)
+            .map(|semaphore| {
+                let size = entry.metadata().map(|m| m.len()).unwrap_or(0);
+                semaphore.acquire(size.div_ceil(MEGABYTE).max(1) as usize)
+            });
+        let results: Vec<ScanResult> = if scannable && !is_readable(path) {
+            // Listed in the report, so only counted towards the error digest
+            debug!("Permission denied: {:?}", path);
+            scan_errors::count(path, ScanErrorKind::PermissionDenied);
+            permission_denied.lock().unwrap().push(path.to_path_buf());
+            audit_log::file(path, Decision::Error, format_args!("permission denied"));
+            coverage::skipped(path, SkipReason::PermissionDenied, config);
+            Vec::new()
+        } else if !scannable {
+            audit_log::file(path, Decision::Skipped, format_args!("not a file type the scanner examines"));
+            coverage::skipped(path, SkipReason::UnsupportedType, config);
+            Vec::new()
+        } else {
+            let results = scan_file(path, config, &rules, &archives);
+            match scan_errors::take_message(path) {
+                Some(message) => {
+                    audit_log::file(path, Decision::Error, format_args!("{}", message));
+                    coverage::errored();
+                }
+                None => {
+                    audit_log::file(path, Decision::Scanned, format_args!("findings: {}", results.iter().filter(|r| r.vulnerable).count()));
+                    record_coverage(path, &results, config);
+                }
+            }
+            results
+        };
+        if config.manifest_scan && is_jar_file(path) {
+            class_path.lock().unwrap().extend(class_path::referenced_paths(path));
+        }
+        files_scanned.fetch_add(1, Ordering::Relaxed);
+        if let Some(scanned_files) = &scanned_files {
+            scanned_files.lock().unwrap().push(path.to_path_buf());
+        }
+        if let Some(pb) = pb {
+            pb.inc(1);
+            let mut message = format!("queue {}", queued.len());
+            if config.io_limit.is_some() {
+                let elapsed = pb.elapsed().as_secs_f64().max(0.001);
+                message.push_str(&format!(", {:.1} MB/s", bytes_read() as f64 / MEGABYTE as f64 / elapsed));
+            }
+            pb.set_message(message);
+        }
+
+        for result in results.iter().filter(|r| r.vulnerable) {
+            let found = findings.fetch_add(1, Ordering::Relaxed) + 1;
+            let reached_max = config.max_findings.is_some_and(|max| found >= max);
+            let critical = config.fail_fast && matches!(result.severity, Some(Severity::Critical));
+            if reached_max || critical {
+                stop.store(true, Ordering::Relaxed);
+            }
+        }
+        results
+    };
+
+    // Workers take files off the queue one at a time, in the order the
+    // walker sent them, while it keeps walking on a thread of its own
+    let (mut results, (mut scanned, peak_queue_depth)) = std::thread::scope(|scope| {
+        let walker = scope.spawn(|| stream_walk(config, roots, &timed_out, &stop, &out_of_time, queue, progress_bar.as_deref()));
+        let results: Vec<ScanResult> = pool.install(|| queued.iter().par_bridge().flat_map_iter(|entry| scan_entry(&entry)).collect());
+        (results, walker.join().unwrap_or_else(|e| std::panic::resume_unwind(e)))
+    });
+    debug!("At most {} files were queued for the workers", peak_queue_depth);
+
+    // Jars the scanned jars' Class-Path headers reference, and the jars
+    // those reference in turn, until no new files turn up
+    loop {
+        let referenced = std::mem::take(&mut *class_path.lock().unwrap());
+        if referenced.is_empty() || stop.load(Ordering::Relaxed) || timed_out.load(Ordering::Relaxed) || out_of_time.load(Ordering::Relaxed) {
+            break;
+        }
+        // Directory references are walked like scan roots
+        let discovered: Vec<DirEntry> = walk(config, &referenced, &timed_out).entries.into_iter()
+            .filter(|entry| scanned.insert(canonical_path(entry.path())))
+            .collect();
+        if discovered.is_empty() {
+            break;
+        }
+        info!("Scanning {} files referenced by Class-Path manifest headers", discovered.len());
+        if let Some(pb) = &progress_bar {
+            pb.inc_length(discovered.len() as u64);
+        }
+        results.extend(pool.install(|| {
+            discovered.par_iter()
+                .flat_map_iter(scan_entry)
+                .map(|result| ScanResult { discovered_via_classpath: true, ..result })
+                .collect::<Vec<_>>()
+        }));
+    }
+
+    let truncated = stop.load(Ordering::Relaxed);
+    let timed_out = timed_out.load(Ordering::Relaxed);
+    let max_duration_reached = out_of_time.load(Ordering::Relaxed);
+    if let Some(max) = config.max_findings {
+        limit_findings(&mut results, max);
+    }
+
+    if let Some(pb) = progress_bar {
+        if timed_out {
+            pb.abandon_with_message("Scan timed out");
+        } else if max_duration_reached {
+            pb.abandon_with_message("Scan reached --max-duration");
+        } else if truncated {
+            pb.abandon_with_message("Scan terminated early");
+        } else {
+            pb.finish_with_message("Scan complete");
+        }
+    }
+
+    let checked = PREFILTER_CHECKED.swap(0, Ordering::Relaxed);
+    if checked > 0 {
+        debug!("Prefilter passed {} of {} contents to pattern matching", PREFILTER_PASSED.swap(0, Ordering::Relaxed), checked);
+    }
+
+    let summary = ScanSummary {
+        files_scanned: files_scanned.into_inner(),
+        truncated,
+        timed_out,
+        max_duration_reached,
+        since: config.since.map(format_rfc3339),
+        permission_denied: permission_denied.into_inner().unwrap(),
+        error_digest: scan_errors::take_digest(),
+        rule_stats: rule_stats::take(&rules, config),
+        archive_depth_reached: take_deepest_archive(),
+        rule_packs: rules.packs().to_vec(),
+        coverage: Some(coverage::take()),
+        peak_queue_depth,
+        roots: roots.to_vec(),
+        scanned_files: scanned_files.map(|files| files.into_inner().unwrap()).unwrap_or_default(),
+        ..Default::default()
+    };
+
+    Ok((results, summary))
+}
+
+/// Scan one file with the scanner its name calls for; files no scanner
+/// handles give no results
+pub fn scan_file(path: &Path, config: &Config, rules: &RuleSet, archives: &[&ArchiveFormat]) -> Vec<ScanResult> {
+    if let Some(format) = archive_format(path, archives) {
+        (format.scanner)(path, config, rules)
+    } else if is_class_file(path) {
+        scan_class(path, config, rules).into_iter().collect()
+    } else if is_jmod_file(path) {
+        scan_jmod(path, config, rules)
+    } else if is_log4j_config(path) {
+        scan_config(path, config)
+    } else if is_jimage_file(path) {
+        scan_jimage(path, config, rules)
+    } else if is_apk_file(path) {
+        scan_apk(path, config, rules)
+    } else if is_python_package(path) {
+        scan_bundle(path, config, rules)
+    } else if is_rpm_file(path) || is_deb_file(path) {
+        scan_package(path, config, rules)
+    } else if config.scan_pom_xml && is_pom_file(path) {
+        scan_pom(path, config).into_iter().collect()
+    } else if config.content_type_detection && has_zip_magic_bytes(path) {
+        debug!("Scanning {:?} as a JAR file by its contents", path);
+        scan_jar(path, config, rules)
+    } else if config.content_type_detection && has_class_magic_bytes(path) {
+        debug!("Scanning {:?} as a class file by its contents", path);
+        scan_class(path, config, rules).into_iter().collect()
+    } else {
+        Vec::new()
+    }
+}
+
+/// Count a file scanned without errors towards coverage, unless its own
+/// result says it could not be examined, like an archive of encrypted entries
+fn record_coverage(path: &Path, results: &[ScanResult], config: &Config) {
+    let label = path.to_string_lossy();
+    match results.iter().find(|r| r.status == ScanStatus::Skipped && r.file_path == label) {
+        Some(result) if result.reason.as_deref() == Some(ENCRYPTED_ENTRIES_REASON) => coverage::skipped(path, SkipReason::Encrypted, config),
+        Some(_) => coverage::skipped(path, SkipReason::UnsupportedType, config),
+        None => coverage::scanned(),
+    }
+}
+
+/// Record a file `--max-duration` left unscanned
+fn not_reached(path: &Path, config: &Config) {
+    audit_log::file(path, Decision::Skipped, format_args!("not reached before --max-duration"));
+    coverage::skipped(path, SkipReason::MaxDuration, config);
+}
+
+/// Links and `..` resolved, so a file referenced under several paths is scanned once
+fn canonical_path(path: &Path) -> PathBuf {
+    path.canonicalize().unwrap_or_else(|_| path.to_path_buf())
+}
+
+/// The files under the scan roots that pass the `--exclude` and `--since`
+/// filters, in the order they will be scanned
+struct Walk {
+    entries: Vec<DirEntry>,
+    filtered: FilterCounts,
+}
+
+/// What the filters of a walk kept and left out
+struct FilterCounts {
+    /// Files that passed every filter
+    kept: usize,
+    /// Files matching an `--exclude` pattern
+    excluded: usize,
+    /// Files excluded by each pattern, counted against the first that matched
+    excluded_by: Vec<(String, usize)>,
+    /// Files not modified since `--since`
+    unmodified: usize,
+}
+
+/// Walk `roots` and filter the files found, without opening any of them.
+/// Stops early once `timed_out` is set.
+fn walk(config: &Config, roots: &[PathBuf], timed_out: &AtomicBool) -> Walk {
+    let mut entries = Vec::new();
+    let filtered = walk_with(config, roots, timed_out, |entry| {
+        entries.push(entry);
+        true
+    });
+    if config.prioritize {
+        entries.sort_by_cached_key(priority_key);
+    }
+    Walk { entries, filtered }
+}
+
+/// Walk `roots` and hand each file that passes the filters to `keep` as it
+/// is found, without opening any of them. Stops early once `timed_out` is
+/// set or `keep` returns false.
+fn walk_with(config: &Config, roots: &[PathBuf], timed_out: &AtomicBool, mut keep: impl FnMut(DirEntry) -> bool) -> FilterCounts {
+    let exclude_patterns: Vec<Pattern> = config.exclude_patterns().into_iter()
+        .filter_map(|p| Pattern::new(p).ok())
+        .collect();
+
+    let files = roots.iter()
+        .flat_map(|root| walk_root(root, config))
+        .take_while(|_| !timed_out.load(Ordering::Relaxed))
+        .filter_map(|entry| match entry {
+            Ok(entry) => Some(entry),
+            Err(e) => {
+                let path = e.path().unwrap_or(Path::new("")).to_path_buf();
+                let kind = e.io_error().map(ScanErrorKind::of_io).unwrap_or(ScanErrorKind::Read);
+                audit_log::file(&path, Decision::Error, format_args!("{}", e));
+                if kind == ScanErrorKind::PermissionDenied {
+                    coverage::skipped(&path, SkipReason::PermissionDenied, config);
+                } else {
+                    coverage::errored();
+                }
+                scan_errors::record(&path, kind, format_args!("Error walking directory: {:?} - {}", path, e));
+                None
+            }
+        })
+        .filter(|e| e.file_type().is_file());
+
+    let mut filtered = FilterCounts {
+        kept: 0,
+        excluded: 0,
+        excluded_by: exclude_patterns.iter().map(|pattern| (pattern.to_string(), 0)).collect(),
+        unmodified: 0,
+    };
+    for entry in files {
+        if let Some(index) = exclude_patterns.iter().position(|pattern| pattern.matches_path(entry.path())) {
+            audit_log::file(entry.path(), Decision::Excluded, format_args!("matches exclude pattern {}", exclude_patterns[index]));
+            coverage::skipped(entry.path(), SkipReason::ExcludedByGlob, config);
+            filtered.excluded += 1;
+            filtered.excluded_by[index].1 += 1;
+        } else if !is_modified_since(&entry, config.since) {
+            audit_log::file(entry.path(), Decision::Skipped, format_args!("not modified since --since"));
+            coverage::skipped(entry.path(), SkipReason::NotModified, config);
+            filtered.unmodified += 1;
+        } else {
+            filtered.kept += 1;
+            if !keep(entry) {
+                break;
+            }
+        }
+    }
+
+    let excluded_by: serde_json::Map<String, serde_json::Value> = filtered.excluded_by.iter()
+        .map(|(pattern, count)| (pattern.clone(), (*count).into()))
+        .collect();
+    audit_log::event("filter-summary", serde_json::json!({
+        "roots": roots,
+        "files_kept": filtered.kept,
+        "excluded": filtered.excluded,
+        "excluded_by_pattern": excluded_by,
+        "not_modified_since": filtered.unmodified,
+        "skip_hidden": config.skip_hidden,
+        "vfs_filter": !config.no_vfs_filter,
+        "loop_detection": !config.no_loop_detection,
+    }));
+    filtered
+}
+
+/// Walk `roots` and send each file to scan through the bounded `queue`, so
+/// the walk waits whenever the workers fall behind rather than listing the
+/// whole tree ahead of them. A full queue is retried every
+/// `QUEUE_POLL_INTERVAL` until the scan is stopped or times out, so
+/// cancellation never leaves the walker blocked. Once `out_of_time` is set
+/// by `--max-duration` the walk goes on but sends nothing, counting each
+/// file it finds as not reached. With `--prioritize`, every file is found
+/// and sorted before the first is sent. Returns the canonical paths sent,
+/// for `--manifest-scan`, and the most files queued at once.
+fn stream_walk(
+    config: &Config,
+    roots: &[PathBuf],
+    timed_out: &AtomicBool,
+    stop: &AtomicBool,
+    out_of_time: &AtomicBool,
+    queue: Sender<DirEntry>,
+    progress_bar: Option<&ProgressBar>,
+) -> (HashSet<PathBuf>, usize) {
+    let started = Instant::now();
+    let mut waited = Duration::ZERO;
+    let mut sent = HashSet::new();
+    let mut peak_depth = 0;
+    let mut send = |mut entry: DirEntry| {
+        if out_of_time.load(Ordering::Relaxed) {
+            not_reached(entry.path(), config);
+            return true;
+        }
+        let path = config.manifest_scan.then(|| canonical_path(entry.path()));
+        if let Some(pb) = progress_bar {
+            pb.inc_length(1);
+        }
+        loop {
+            if stop.load(Ordering::Relaxed) || timed_out.load(Ordering::Relaxed) {
+                return false;
+            }
+            let sending = Instant::now();
+            let sent = queue.send_timeout(entry, QUEUE_POLL_INTERVAL);
+            waited += sending.elapsed();
+            match sent {
+                Ok(()) => break,
+                Err(SendTimeoutError::Timeout(unsent)) => entry = unsent,
+                Err(SendTimeoutError::Disconnected(_)) => return false,
+            }
+        }
+        peak_depth = peak_depth.max(queue.len());
+        sent.extend(path);
+        true
+    };
+
+    if config.prioritize {
+        for entry in walk(config, roots, timed_out).entries {
+            if !send(entry) {
+                break;
+            }
+        }
+    } else {
+        walk_with(config, roots, timed_out, send);
+    }
+    if benchmark::enabled() {
+        benchmark::add(Phase::Walk, started.elapsed().saturating_sub(waited));
+    }
+    (sent, peak_depth)
+}
+
+/// Walk `root` without descending into links, hidden files and directories
+/// unless `--skip-hidden=false`, system directories, virtual filesystems
+/// unless `--no-vfs-filter`, or directories already walked under another
+/// path unless `--no-loop-detection`. The root itself is always walked, even
+/// when hidden or virtual.
+fn walk_root<'a>(root: &Path, config: &'a Config) -> impl Iterator<Item = walkdir::Result<DirEntry>> + 'a {
+    let skip_hidden = config.skip_hidden;
+    let mut system_dirs = system_dirs(root);
+    // A scan of /proc itself asked for what is in it
+    let skip_virtual = !config.no_vfs_filter && !is_virtual_fs(root);
+    if skip_virtual {
+        system_dirs.extend(virtual_fs_dirs(root));
+    }
+    // Directories only, so the set stays small however many files there are
+    let mut visited = (!config.no_loop_detection).then(HashSet::new);
+    WalkDir::new(root).into_iter().filter_entry(move |entry| {
+        let filtered = if entry.depth() == 0 {
+            None
+        } else if is_link(entry) {
+            Some((SkipReason::SymlinkNotFollowed, "link, not followed"))
+        } else if skip_hidden && is_hidden(entry) {
+            Some((SkipReason::Hidden, "hidden"))
+        } else if system_dirs.iter().any(|dir| entry.path() == dir) {
+            Some((SkipReason::SystemDirectory, "system or virtual filesystem directory"))
+        } else if skip_virtual && entry.file_type().is_dir() && is_virtual_fs(entry.path()) {
+            Some((SkipReason::SystemDirectory, "virtual filesystem"))
+        } else {
+            None
+        };
+        if let Some((reason, detail)) = filtered {
+            audit_log::file(entry.path(), Decision::Skipped, format_args!("{}", detail));
+            coverage::skipped(entry.path(), reason, config);
+            return false;
+        }
+        // Links are not followed, but a bind mount can still lead back to
+        // a directory being walked, endlessly
+        if let Some(visited) = visited.as_mut().filter(|_| entry.file_type().is_dir()) {
+            if directory_id(entry).is_some_and(|id| !visited.insert(id)) {
+                warn!("Skipping {:?}: the same directory was already walked under another path, e.g. through a bind mount", entry.path());
+                audit_log::file(entry.path(), Decision::Skipped, format_args!("directory already walked under another path"));
+                coverage::skipped(entry.path(), SkipReason::AlreadyWalked, config);
+                return false;
+            }
+        }
+        true
+    })
+}
+
+#[derive(serde::Serialize)]
+struct DryRun {
+    files_walked: usize,
+    excluded: usize,
+    not_modified_since: usize,
+    /// Files the scanner does not examine, e.g. `.txt` or `.so`
+    not_scannable: usize,
+    would_scan: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    files: Option<Vec<PathBuf>>,
+}
+
+/// Walk `roots` with every filter a scan applies and list the files it would
+/// scan, in scan order, or with `summary_only` just count them. No file is
+/// hashed, or opened except for `--content-type-detection` to read its start.
+pub fn dry_run(config: &Config, roots: &[PathBuf], summary_only: bool) -> Result<(), Box<dyn std::error::Error>> {
+    for root in roots {
+        std::fs::metadata(root).map_err(|e| format!("unable to read {}: {}", root.display(), e))?;
+    }
+    let archives = archive_formats(config)?;
+
+    let walk = walk(config, roots, &AtomicBool::new(false));
+    scan_errors::take_digest();
+    coverage::take();
+    let files: Vec<PathBuf> = walk.entries.iter()
+        .map(DirEntry::path)
+        .filter(|path| is_scannable(path, config, &archives))
+        .map(Path::to_path_buf)
+        .collect();
+    let dry_run = DryRun {
+        files_walked: walk.entries.len() + walk.filtered.excluded + walk.filtered.unmodified,
+        excluded: walk.filtered.excluded,
+        not_modified_since: walk.filtered.unmodified,
+        not_scannable: walk.entries.len() - files.len(),
+        would_scan: files.len(),
+        files: (!summary_only).then_some(files),
+    };
+
+    let mut output = io::stdout().lock();
+    if config.format == "json" {
+        serde_json::to_writer_pretty(&mut output, &dry_run)?;
+        writeln!(output)?;
+        return Ok(());
+    }
+
+    for path in dry_run.files.iter().flatten() {
+        writeln!(output, "{}", path.display())?;
+    }
+    writeln!(output, "Files walked: {}", dry_run.files_walked)?;
+    writeln!(output, "Excluded: {}", dry_run.excluded)?;
+    if config.since.is_some() {
+        writeln!(output, "Not modified since --since: {}", dry_run.not_modified_since)?;
+    }
+    writeln!(output, "Not scannable: {}", dry_run.not_scannable)?;
+    writeln!(output, "Would scan: {}", dry_run.would_scan)?;
+    Ok(())
+}
+
+/// An archive format scanned by file extension
+pub struct ArchiveFormat {
+    pub ext: &'static str,
+    pub scanner: fn(&Path, &Config, &RuleSet) -> Vec<ScanResult>,
+}
+
+impl ArchiveFormat {
+    fn matches(&self, path: &Path) -> bool {
+        path.file_name()
+            .map(|name| name.to_string_lossy().to_ascii_lowercase())
+            .is_some_and(|name| name.strip_suffix(self.ext).is_some_and(|stem| stem.ends_with('.')))
+    }
+}
+
+/// Every archive format `--include-archives` can enable
+pub const ARCHIVE_FORMATS: [ArchiveFormat; 11] = [
+    ArchiveFormat { ext: "jar", scanner: scan_jar },
+    ArchiveFormat { ext: "war", scanner: scan_bundle },
+    ArchiveFormat { ext: "ear", scanner: scan_bundle },
+    // Android libraries keep their code in classes.jar and libs/*.jar
+    ArchiveFormat { ext: "aar", scanner: scan_bundle },
+    ArchiveFormat { ext: "zip", scanner: scan_bundle },
+    ArchiveFormat { ext: "tar", scanner: scan_tar },
+    ArchiveFormat { ext: "tar.gz", scanner: scan_tar },
+    ArchiveFormat { ext: "tgz", scanner: scan_tar },
+    ArchiveFormat { ext: "tar.bz2", scanner: scan_tar },
+    ArchiveFormat { ext: "tar.xz", scanner: scan_tar },
+    ArchiveFormat { ext: "tar.zst", scanner: scan_tar },
+];
+
+/// The JAR family, scanned unless `--include-archives` removes them
+pub const DEFAULT_ARCHIVES: [&str; 4] = ["jar", "war", "ear", "aar"];
+
+/// Parse one `--include-archives` entry: a registered extension to add, or
+/// one prefixed with `-` to remove
+pub fn parse_archive_extension(value: &str) -> Result<String, String> {
+    let value = value.trim().to_ascii_lowercase();
+    let (removed, ext) = match value.strip_prefix('-') {
+        Some(ext) => (true, ext.trim_start_matches('.')),
+        None => (false, value.trim_start_matches('.')),
+    };
+    if !ARCHIVE_FORMATS.iter().any(|format| format.ext == ext) {
+        let known: Vec<&str> = ARCHIVE_FORMATS.iter().map(|format| format.ext).collect();
+        return Err(format!("unknown archive format '{}' (expected one of {})", value, known.join(", ")));
+    }
+    Ok(if removed { format!("-{}", ext) } else { ext.to_string() })
+}
+
+/// The archive formats to scan: `DEFAULT_ARCHIVES` with the `--include-archives`
+/// additions and removals applied in order
+pub fn archive_formats(config: &Config) -> Result<Vec<&'static ArchiveFormat>, String> {
+    let mut enabled: Vec<String> = DEFAULT_ARCHIVES.iter().map(|ext| ext.to_string()).collect();
+    for entry in &config.include_archives {
+        let ext = parse_archive_extension(entry)?;
+        match ext.strip_prefix('-') {
+            Some(removed) => enabled.retain(|ext| ext != removed),
+            None => enabled.push(ext),
+        }
+    }
+    Ok(ARCHIVE_FORMATS.iter().filter(|format| enabled.iter().any(|ext| ext == format.ext)).collect())
+}
+
+fn archive_format<'a>(path: &Path, archives: &[&'a ArchiveFormat]) -> Option<&'a ArchiveFormat> {
+    archives.iter().copied().find(|format| format.matches(path))
+}
+
+fn is_scannable(path: &Path, config: &Config, archives: &[&ArchiveFormat]) -> bool {
+    archive_format(path, archives).is_some() || is_class_file(path) || is_apk_file(path) || is_python_package(path) || is_rpm_file(path) || is_deb_file(path)
+        || is_jmod_file(path) || is_jimage_file(path) || is_log4j_config(path)
+        || (config.scan_pom_xml && is_pom_file(path))
+        || (config.content_type_detection && (has_zip_magic_bytes(path) || has_class_magic_bytes(path)))
+}
+
+#[derive(serde::Serialize)]
+struct ExtensionCount {
+    extension: String,
+    count: usize,
+    /// `Yes`, `No`, or `Some` when only files with certain names are scanned, e.g. `log4j2.xml`
+    would_scan: &'static str,
+}
+
+/// Count the files under `path` by extension, most common first, with whether a scan
+/// would examine them, to help choose `--exclude` patterns. Nothing is scanned.
+pub fn list_extensions(path: &Path, config: &Config) -> Result<(), Box<dyn std::error::Error>> {
+    std::fs::metadata(path).map_err(|e| format!("unable to read {}: {}", path.display(), e))?;
+    let archives = archive_formats(config)?;
+
+    let mut counts: HashMap<String, (usize, usize)> = HashMap::new();
+    for entry in walk_root(path, config).filter_map(|e| e.ok()).filter(|e| e.file_type().is_file()) {
+        let extension = entry.path().extension()
+            .map(|extension| format!(".{}", extension.to_string_lossy().to_ascii_lowercase()))
+            .unwrap_or_else(|| "(none)".to_string());
+        let (count, scannable) = counts.entry(extension).or_default();
+        *count += 1;
+        if is_scannable(entry.path(), config, &archives) {
+            *scannable += 1;
+        }
+    }
+
+    let mut extensions: Vec<ExtensionCount> = counts.into_iter()
+        .map(|(extension, (count, scannable))| ExtensionCount {
+            extension,
+            count,
+            would_scan: match scannable {
+                0 => "No",
+                n if n == count => "Yes",
+                _ => "Some",
+            },
+        })
+        .collect();
+    extensions.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.extension.cmp(&b.extension)));
+
+    let mut output = io::stdout().lock();
+    if config.format == "json" {
+        serde_json::to_writer_pretty(&mut output, &extensions)?;
+        writeln!(output)?;
+        return Ok(());
+    }
+
+    writeln!(output, "{:<16} | {:>9} | Would Scan", "Extension", "Count")?;
+    for extension in &extensions {
+        writeln!(output, "{:<16} | {:>9} | {}", extension.extension, extension.count, extension.would_scan)?;
+    }
+    Ok(())
+}
+
+/// Sort key placing likely-vulnerable files first: log4j in the file name,
+/// library directories, then jars before classes before other files, then
+/// smaller files before larger ones.
+fn priority_key(entry: &DirEntry) -> (bool, bool, u8, u64) {
+    let path = entry.path();
+    let name = entry.file_name().to_string_lossy().to_ascii_lowercase();
+    let in_lib_dir = path.parent()
+        .and_then(|parent| parent.file_name())
+        .map(|dir| dir.eq_ignore_ascii_case("lib"))
+        .unwrap_or(false);
+    let kind = if is_jar_file(path) {
+        0
+    } else if is_class_file(path) {
+        1
+    } else {
+        2
+    };
+    let size = entry.metadata().map(|m| m.len()).unwrap_or(u64::MAX);
+
+    (!name.contains("log4j"), !in_lib_dir, kind, size)
+}
+
+fn is_modified_since(entry: &DirEntry, since: Option<SystemTime>) -> bool {
+    let cutoff = match since {
+        Some(cutoff) => cutoff,
+        None => return true,
+    };
+
+    match entry.metadata().map_err(io::Error::from).and_then(|m| m.modified()) {
+        Ok(modified) => modified >= cutoff,
+        Err(e) => {
+            debug!("Unable to read modification time, scanning anyway: {:?} - {}", entry.path(), e);
+            true
+        }
+    }
+}
+
+fn scan_jar(path: &Path, config: &Config, rules: &RuleSet) -> Vec<ScanResult> {
+    debug!("Scanning JAR file: {:?}", path);
+
+    let name = path.to_string_lossy();
+    let mut archive_info = ArchiveInfo::default();
+    let size = std::fs::metadata(path).map(|m| m.len()).unwrap_or(0);
+    let scanned = if size >= PARALLEL_ARCHIVE_THRESHOLD {
+        scan_archive_parallel(path, &name, config, rules, &mut archive_info)
+    } else {
+        let file = match File::open(path).map(|file| ThrottledReader::new(file, &config.io_budget)) {
+            Ok(file) => file,
+            Err(e) => {
+                scan_errors::record(path, ScanErrorKind::of_io(&e), format_args!("Error opening JAR file: {:?} - {}", path, e));
+                return Vec::new();
+            }
+        };
+        scan_archive(&name, file, config, rules, &mut archive_info)
+    };
+    let result = match scanned {
+        Some(found) => Some(entry_match_result(FileInfo::from_path(path, config), found, config)),
+        None => unscanned_archive_result(path, &archive_info, config),
+    };
+    archive_results(&name, result, archive_info, config)
+}
+
+/// What was learned about a zip archive besides its findings: its Maven
+/// coordinates, the log4j configuration files it bundles, and the entries
+/// that could not be read
+#[derive(Default)]
+struct ArchiveInfo {
+    maven_coordinates: Option<MavenCoordinates>,
+    config_files: Vec<(String, Vec<u8>)>,
+    /// Service loader files naming log4j or JNDI, e.g. a registered `InitialContextFactory`
+    service_files: Vec<(String, Vec<u8>)>,
+    encrypted: usize,
+    errors: Vec<String>,
+    /// Class entries compressed more than `--suspicious-ratio`
+    suspicious_entries: Vec<(String, EntrySizes)>,
+}
+
+/// The archive's own result, carrying its Maven coordinates, followed by
+/// the findings in its log4j configuration files and service registrations
+fn archive_results(name: &str, result: Option<ScanResult>, archive_info: ArchiveInfo, config: &Config) -> Vec<ScanResult> {
+    let mut results: Vec<ScanResult> = result.into_iter()
+        .map(|result| ScanResult {
+            maven_coordinates: archive_info.maven_coordinates.clone(),
+            ..result
+        })
+        .collect();
+    for (entry, contents) in &archive_info.config_files {
+        let nested_name = nested_path(name, entry);
+        results.extend(scan_config_contents(FileInfo::from_bytes(&nested_name, contents, config), contents, config));
+    }
+    for (entry, contents) in &archive_info.service_files {
+        let nested_name = nested_path(name, entry);
+        // Metrics need some bytes; an empty registration falls back to its name
+        let contents = if contents.is_empty() { entry.as_bytes() } else { contents };
+        let detection = Detection {
+            rule_id: "jndi-service-provider".to_string(),
+            reason: "JNDI service provider registered".to_string(),
+            severity: Severity::Medium,
+            confidence: Confidence::Tentative,
+            cve: None,
+        };
+        results.push(create_scan_result(FileInfo::from_bytes(&nested_name, contents, config), contents, detection, config));
+    }
+    for (entry, sizes) in &archive_info.suspicious_entries {
+        let nested_name = nested_path(name, entry);
+        // The entry is not read again; its name stands in for the contents
+        let detection = Detection {
+            rule_id: "suspicious-compression-ratio".to_string(),
+            reason: format!("Suspicious compression ratio: {:.1}:1", sizes.ratio().unwrap_or_default()),
+            severity: Severity::Low,
+            confidence: Confidence::Tentative,
+            cve: None,
+        };
+        results.push(ScanResult {
+            compressed_size_bytes: Some(sizes.compressed),
+            uncompressed_size_bytes: Some(sizes.uncompressed),
+            ..create_scan_result(FileInfo::from_bytes(&nested_name, entry.as_bytes(), config), entry.as_bytes(), detection, config)
+        });
+    }
+    results
+}
+
+impl ArchiveInfo {
+    /// Status, reason and severity to report for an archive without a finding.
+    /// Corruption outranks encryption, since a corrupt archive may hide anything.
+    fn outcome(&self) -> Option<(ScanStatus, String, Option<Severity>)> {
+        if !self.errors.is_empty() {
+            Some((ScanStatus::Error, format!("Corrupted archive: {}", self.errors.join("; ")), None))
+        } else if self.encrypted > 0 {
+            Some((ScanStatus::Skipped, ENCRYPTED_ENTRIES_REASON.to_string(), Some(Severity::Medium)))
+        } else {
+            None
+        }
+    }
+}
+
+/// Contents and sizes of a matching archive entry along with the match
+type EntryMatch = (Vec<u8>, Detection, EntrySizes);
+
+/// Stored and inflated size of a zip entry
+#[derive(Debug, Clone, Copy)]
+struct EntrySizes {
+    compressed: u64,
+    uncompressed: u64,
+}
+
+impl EntrySizes {
+    /// Inflated size over stored size; None for an empty entry
+    fn ratio(self) -> Option<f64> {
+        (self.compressed > 0).then(|| self.uncompressed as f64 / self.compressed as f64)
+    }
+}
+
+/// The result for a matching archive entry, with the entry's sizes
+fn entry_match_result(file: FileInfo, (contents, detection, sizes): EntryMatch, config: &Config) -> ScanResult {
+    ScanResult {
+        compressed_size_bytes: Some(sizes.compressed),
+        uncompressed_size_bytes: Some(sizes.uncompressed),
+        ..create_scan_result(file, &contents, detection, config)
+    }
+}
+
+/// Class entries inflating to more than this many times their stored size
+/// are reported, unless `--suspicious-ratio` says otherwise
+pub const DEFAULT_SUSPICIOUS_RATIO: f64 = 100.0;
+
+/// Scan the class entries of a zip archive, returning the first match
+fn scan_archive<R: Read + Seek>(name: &str, reader: R, config: &Config, rules: &RuleSet, archive_info: &mut ArchiveInfo) -> Option<EntryMatch> {
+    visit_archive_entries(name, reader, config, archive_info, |entry_names, contents, sizes, coordinates| {
+        match is_vulnerable(&entry_names.all(), contents, rules, coordinates) {
+            Some(detection) => ControlFlow::Break((contents.to_vec(), detection, sizes)),
+            None => ControlFlow::Continue(()),
+        }
+    })
+}
+
+/// `scan_archive` for large jars: the class entries are split into chunks
+/// scanned in parallel on the current pool. The central directory is read
+/// once and each chunk reads through its own clone of the archive, since zip
+/// readers cannot be shared. The finding is the first matching entry in
+/// archive order, as with a sequential scan.
+fn scan_archive_parallel(path: &Path, name: &str, config: &Config, rules: &RuleSet, archive_info: &mut ArchiveInfo) -> Option<EntryMatch> {
+    let opened = SharedFile::open(path)
+        .map_err(|e| (ScanErrorKind::of_io(&e), e.to_string()))
+        .and_then(|file| ZipArchive::new(ThrottledReader::new(file, &config.io_budget)).map_err(|e| (ScanErrorKind::of_zip(&e), e.to_string())));
+    let mut archive = match opened {
+        Ok(archive) => archive,
+        Err((kind, e)) => {
+            scan_errors::record(name, kind, format_args!("Error reading JAR file: {} - {}", name, e));
+            archive_info.errors.push(e);
+            return None;
+        }
+    };
+    archive_info.maven_coordinates = read_maven_coordinates(name, &mut archive);
+    archive_info.config_files = read_config_files(name, &mut archive);
+    archive_info.service_files = read_service_files(name, &mut archive);
+
+    let len = archive.len();
+    let chunk_size = len.div_ceil(rayon::current_num_threads() * ARCHIVE_CHUNKS_PER_THREAD).max(1);
+    debug!("Scanning {} entries of {} in chunks of {}", len, name, chunk_size);
+    // Lowest chunk with a match; later chunks stop early since their match would not be first
+    let first_match = AtomicUsize::new(usize::MAX);
+    let chunks: Vec<(Option<EntryMatch>, ArchiveInfo)> = (0..len).step_by(chunk_size)
+        .collect::<Vec<_>>()
+        .into_par_iter()
+        .enumerate()
+        .map(|(chunk, start)| {
+            let mut chunk_info = ArchiveInfo {
+                maven_coordinates: archive_info.maven_coordinates.clone(),
+                ..ArchiveInfo::default()
+            };
+            let mut archive = archive.clone();
+            let found = visit_entries(name, &mut archive, start..(start + chunk_size).min(len), config, &mut chunk_info, &mut |entry_names, contents, sizes, coordinates| {
+                if first_match.load(Ordering::Relaxed) < chunk {
+                    return ControlFlow::Break(None);
+                }
+                match is_vulnerable(&entry_names.all(), contents, rules, coordinates) {
+                    Some(detection) => {
+                        first_match.fetch_min(chunk, Ordering::Relaxed);
+                        ControlFlow::Break(Some((contents.to_vec(), detection, sizes)))
+                    }
+                    None => ControlFlow::Continue(()),
+                }
+            });
+            (found.flatten(), chunk_info)
+        })
+        .collect();
+
+    // Merge in entry order up to the first match, as a sequential scan would have stopped there
+    for (found, chunk_info) in chunks {
+        archive_info.encrypted += chunk_info.encrypted;
+        archive_info.errors.extend(chunk_info.errors);
+        archive_info.suspicious_entries.extend(chunk_info.suspicious_entries);
+        if found.is_some() {
+            return found;
+        }
+    }
+    None
+}
+
+/// Call `visit` with the names, contents and sizes of each class entry in a
+/// zip archive, and the archive's Maven coordinates, until it breaks,
+/// returning the break value. Encrypted and unreadable entries, and entries
+/// compressed more than `--suspicious-ratio`, are recorded in `archive_info`.
+fn visit_archive_entries<R, T, F>(name: &str, reader: R, config: &Config, archive_info: &mut ArchiveInfo, mut visit: F) -> Option<T>
+where
+    R: Read + Seek,
+    F: FnMut(&EntryNames, &[u8], EntrySizes, Option<&MavenCoordinates>) -> ControlFlow<T>,
+{
+    let mut archive = match ZipArchive::new(reader) {
+        Ok(archive) => archive,
+        Err(e) => {
+            scan_errors::record(name, ScanErrorKind::of_zip(&e), format_args!("Error reading JAR file: {} - {}", name, e));
+            archive_info.errors.push(e.to_string());
+            return None;
+        }
+    };
+    archive_info.maven_coordinates = read_maven_coordinates(name, &mut archive);
+    archive_info.config_files = read_config_files(name, &mut archive);
+    archive_info.service_files = read_service_files(name, &mut archive);
+
+    let len = archive.len();
+    visit_entries(name, &mut archive, 0..len, config, archive_info, &mut visit)
+}
+
+/// `visit_archive_entries` over the entries at `indices` of an open archive
+fn visit_entries<R, T, F>(name: &str, archive: &mut ZipArchive<R>, indices: Range<usize>, config: &Config, archive_info: &mut ArchiveInfo, visit: &mut F) -> Option<T>
+where
+    R: Read + Seek,
+    F: FnMut(&EntryNames, &[u8], EntrySizes, Option<&MavenCoordinates>) -> ControlFlow<T>,
+{
+    let suspicious_ratio = config.suspicious_ratio.unwrap_or(DEFAULT_SUSPICIOUS_RATIO);
+    for i in indices {
+        let mut file = match archive.by_index(i) {
+            Ok(file) => file,
+            Err(ZipError::UnsupportedArchive(ZipError::PASSWORD_REQUIRED)) => {
+                debug!("Skipping encrypted entry {} in JAR: {}", i, name);
+                archive_info.encrypted += 1;
+                continue;
+            }
+            Err(e) => {
+                scan_errors::record(name, ScanErrorKind::of_zip(&e), format_args!("Error reading file in JAR: {} - {}", name, e));
+                archive_info.errors.push(format!("entry {}: {}", i, e));
+                continue;
+            }
+        };
+
+        let entry_names = EntryNames::of(&file);
+        if is_class_file(Path::new(&entry_names.decoded)) {
+            let sizes = EntrySizes { compressed: file.compressed_size(), uncompressed: file.size() };
+            if sizes.ratio().is_some_and(|ratio| ratio > suspicious_ratio) {
+                archive_info.suspicious_entries.push((entry_names.decoded.clone(), sizes));
+            }
+            let mut contents = Vec::new();
+            if let Err(e) = file.read_to_end(&mut contents) {
+                scan_errors::record(name, ScanErrorKind::of_io(&e), format_args!("Error reading class file in JAR: {} - {}", name, e));
+                archive_info.errors.push(format!("{}: {}", entry_names.decoded, e));
+                continue;
+            }
+
+            if let ControlFlow::Break(value) = visit(&entry_names, &contents, sizes, archive_info.maven_coordinates.as_ref()) {
+                return Some(value);
+            }
+        }
+    }
+
+    None
+}
+
+/// The names of a zip entry: the one it is reported by, and the forms of it
+/// entry-name rules are matched against
+struct EntryNames {
+    /// As the zip crate decodes it: UTF-8 when the entry's language encoding
+    /// flag is set and CP437, as the zip spec says, when it is not. Bytes
+    /// that are not UTF-8 despite the flag are written `%XX`, not replaced.
+    decoded: String,
+    /// The raw name read as UTF-8, when that reads differently: tools that
+    /// wrote UTF-8 names without setting the flag leave names that CP437
+    /// decoding garbles
+    raw: Option<String>,
+}
+
+impl EntryNames {
+    fn of(file: &ZipFile) -> Self {
+        let raw = std::str::from_utf8(file.name_raw());
+        let decoded = match raw {
+            Err(_) if file.name().contains(char::REPLACEMENT_CHARACTER) => escape_invalid_utf8(file.name_raw()),
+            _ => file.name().to_string(),
+        };
+        let raw = raw.ok().filter(|&raw| raw != decoded).map(String::from);
+        EntryNames { decoded, raw }
+    }
+
+    /// The decoded name, then the raw one if it differs
+    fn all(&self) -> Vec<&str> {
+        std::iter::once(self.decoded.as_str()).chain(self.raw.as_deref()).collect()
+    }
+}
+
+/// Coordinates from the archive's `pom.properties`. Shaded jars bundle one per
+/// dependency, so prefer the one whose artifactId appears in the archive name.
+fn read_maven_coordinates<R: Read + Seek>(name: &str, archive: &mut ZipArchive<R>) -> Option<MavenCoordinates> {
+    let mut entries: Vec<String> = archive.file_names()
+        .filter(|entry| is_pom_properties_entry(entry))
+        .map(String::from)
+        .collect();
+    entries.sort();
+
+    let mut candidates = Vec::new();
+    for entry in entries {
+        let mut contents = String::new();
+        match archive.by_name(&entry).map_err(io::Error::from).and_then(|mut file| file.read_to_string(&mut contents)) {
+            Ok(_) => candidates.extend(MavenCoordinates::from_pom_properties(&contents)),
+            Err(e) => debug!("Unable to read {} in JAR: {} - {}", entry, name, e),
+        }
+    }
+
+    let file_name = name.rsplit(['/', '\\', '!']).next().unwrap_or(name);
+    let preferred = candidates.iter().position(|c| file_name.contains(&c.artifact_id)).unwrap_or(0);
+    (!candidates.is_empty()).then(|| candidates.swap_remove(preferred))
+}
+
+/// Name and contents of each log4j configuration file in the archive
+fn read_config_files<R: Read + Seek>(name: &str, archive: &mut ZipArchive<R>) -> Vec<(String, Vec<u8>)> {
+    read_entries(name, archive, |entry| is_log4j_config(Path::new(entry)))
+}
+
+/// Name and contents of each `META-INF/services/` file in the archive whose
+/// name or contents mention log4j or JNDI
+fn read_service_files<R: Read + Seek>(name: &str, archive: &mut ZipArchive<R>) -> Vec<(String, Vec<u8>)> {
+    read_entries(name, archive, |entry| entry.len() > SERVICES_DIR.len() && entry.starts_with(SERVICES_DIR) && !entry.ends_with('/'))
+        .into_iter()
+        .filter(|(entry, contents)| {
+            let text = format!("{}\n{}", entry, String::from_utf8_lossy(contents)).to_ascii_lowercase();
+            SERVICE_JNDI_TERMS.iter().any(|term| text.contains(term))
+        })
+        .collect()
+}
+
+/// Name and contents of each archive entry whose name passes `filter`, in name order
+fn read_entries<R: Read + Seek>(name: &str, archive: &mut ZipArchive<R>, filter: impl Fn(&str) -> bool) -> Vec<(String, Vec<u8>)> {
+    let mut entries: Vec<String> = archive.file_names()
+        .filter(|entry| filter(entry))
+        .map(String::from)
+        .collect();
+    entries.sort();
+
+    entries.into_iter()
+        .filter_map(|entry| {
+            let mut contents = Vec::new();
+            match archive.by_name(&entry).map_err(io::Error::from).and_then(|mut file| file.read_to_end(&mut contents)) {
+                Ok(_) => Some((entry, contents)),
+                Err(e) => {
+                    debug!("Unable to read {} in JAR: {} - {}", entry, name, e);
+                    None
+                }
+            }
+        })
+        .collect()
+}
+
+/// Call `visit` with each piece of content the scanner would match patterns
+/// against: every class entry of a jar or jmod, and of the jars a war, ear or
+/// Python package bundles, or the whole file otherwise. `visit` gets the
+/// content's label, e.g. `app.war!WEB-INF/lib/inner.jar!Entry.class`, and its
+/// entry name, which is `None` for plain files.
+pub fn visit_scannable_contents<F>(path: &Path, config: &Config, mut visit: F) -> io::Result<()>
+where
+    F: FnMut(&str, Option<&str>, &[u8]),
+{
+    let data = std::fs::read(path)?;
+    let name = path.to_string_lossy();
+    let zip = if data.starts_with(JMOD_MAGIC) { &data[JMOD_MAGIC.len()..] } else { &data[..] };
+    if zip.starts_with(ZIP_MAGIC) {
+        visit_zip_contents(&name, zip, config, &mut visit);
+    } else {
+        visit(&name, None, &data);
+    }
+    Ok(())
+}
+
+/// `visit_scannable_contents` of the zip archive `zip` labelled `name`
+fn visit_zip_contents<F>(name: &str, zip: &[u8], config: &Config, visit: &mut F)
+where
+    F: FnMut(&str, Option<&str>, &[u8]),
+{
+    if is_bundle_file(Path::new(name)) || is_python_package(Path::new(name)) {
+        visit_bundled_archives(name, zip, config, visit);
+    }
+    visit_archive_entries(name, Cursor::new(zip), config, &mut ArchiveInfo::default(), |entry_names, contents, _, _| {
+        visit(&nested_path(name, &entry_names.decoded), Some(&entry_names.decoded), contents);
+        ControlFlow::<()>::Continue(())
+    });
+}
+
+/// `visit_zip_contents` of each jar, jmod and bundle inside the bundle `name`
+fn visit_bundled_archives<F>(name: &str, zip: &[u8], config: &Config, visit: &mut F)
+where
+    F: FnMut(&str, Option<&str>, &[u8]),
+{
+    let mut archive = match ZipArchive::new(Cursor::new(zip)) {
+        Ok(archive) => archive,
+        Err(e) => {
+            scan_errors::record(name, ScanErrorKind::of_zip(&e), format_args!("Error reading bundle: {} - {}", name, e));
+            return;
+        }
+    };
+    for i in 0..archive.len() {
+        let Ok(mut entry) = archive.by_index(i) else { continue };
+        let entry_name = EntryNames::of(&entry).decoded;
+        let entry_path = Path::new(&entry_name);
+        if !(is_jar_file(entry_path) || is_jmod_file(entry_path) || is_bundle_file(entry_path)) {
+            continue;
+        }
+        let mut data = Vec::new();
+        if let Err(e) = entry.read_to_end(&mut data) {
+            scan_errors::record(name, ScanErrorKind::of_io(&e), format_args!("Error reading file in bundle: {} - {}", name, e));
+            continue;
+        }
+        let nested_name = nested_path(name, &entry_name);
+        let zip = if data.starts_with(JMOD_MAGIC) { &data[JMOD_MAGIC.len()..] } else { &data[..] };
+        if zip.starts_with(ZIP_MAGIC) {
+            visit_zip_contents(&nested_name, zip, config, visit);
+        }
+    }
+}
+
+/// Scan an in-memory jar, jmod, class or log4j configuration file, labelling the results with `name`
+pub fn scan_bytes(name: &str, data: &[u8], config: &Config, rules: &RuleSet) -> Vec<ScanResult> {
+    debug!("Scanning in-memory file: {}", name);
+
+    if is_log4j_config(Path::new(name)) {
+        scan_config_contents(FileInfo::from_bytes(name, data, config), data, config)
+    } else if let Some(skipped) = past_max_archive_depth(name, data, config) {
+        vec![skipped]
+    } else if data.starts_with(ZIP_MAGIC) && (is_bundle_file(Path::new(name)) || is_python_package(Path::new(name))) {
+        scan_bundle_data(name, data, config, rules)
+    } else if data.starts_with(ZIP_MAGIC) {
+        scan_zip_data(FileInfo::from_bytes(name, data, config), data, data, config, rules)
+    } else if data.starts_with(JMOD_MAGIC) {
+        scan_zip_data(FileInfo::from_bytes(name, data, config), data, &data[JMOD_MAGIC.len()..], config, rules)
+    } else {
+        is_vulnerable(&[], data, rules, None)
+            .map(|detection| create_scan_result(FileInfo::from_bytes(name, data, config), data, detection, config))
+            .into_iter()
+            .collect()
+    }
+}
+
+/// `scan_bytes` for an entry of the archive being scanned, one level of
+/// nesting deeper than the archive
+pub fn scan_nested(name: &str, data: &[u8], config: &Config, rules: &RuleSet) -> Vec<ScanResult> {
+    let _level = NestingLevel::enter();
+    scan_bytes(name, data, config, rules)
+}
+
+/// Archives nested deeper than this below the file scanned are not opened,
+/// unless `--max-archive-depth` says otherwise
+pub const DEFAULT_MAX_ARCHIVE_DEPTH: usize = 3;
+
+/// The deepest nested archive come across since the last scan
+static DEEPEST_ARCHIVE: AtomicUsize = AtomicUsize::new(0);
+
+thread_local! {
+    /// How many archives enclose the entry this thread is scanning; 0 for a
+    /// file on disk or downloaded
+    static ARCHIVE_DEPTH: Cell<usize> = const { Cell::new(0) };
+}
+
+/// The deepest nested archive the scan came across, opened or not. Resets it
+/// for the next scan.
+pub fn take_deepest_archive() -> usize {
+    DEEPEST_ARCHIVE.swap(0, Ordering::Relaxed)
+}
+
+/// One more archive enclosing what this thread scans, until dropped
+struct NestingLevel(usize);
+
+impl NestingLevel {
+    fn enter() -> Self {
+        NestingLevel(ARCHIVE_DEPTH.with(|depth| depth.replace(depth.get() + 1)))
+    }
+}
+
+impl Drop for NestingLevel {
+    fn drop(&mut self) {
+        ARCHIVE_DEPTH.with(|depth| depth.set(self.0));
+    }
+}
+
+/// The Skipped result for the archive `name` when it is nested deeper than
+/// `--max-archive-depth`, None when it may be opened or is no archive
+fn past_max_archive_depth(name: &str, data: &[u8], config: &Config) -> Option<ScanResult> {
+    if !(data.starts_with(ZIP_MAGIC) || data.starts_with(JMOD_MAGIC)) {
+        return None;
+    }
+    let depth = ARCHIVE_DEPTH.with(Cell::get);
+    DEEPEST_ARCHIVE.fetch_max(depth, Ordering::Relaxed);
+    let limit = config.max_archive_depth.unwrap_or(DEFAULT_MAX_ARCHIVE_DEPTH);
+    if depth <= limit {
+        return None;
+    }
+    debug!("Not opening archive nested {} deep: {}", depth, name);
+    let reason = format!("Archive nesting depth limit {} reached: not opened at depth {}", limit, depth);
+    Some(unscanned_result(FileInfo::from_bytes(name, data, config), data, ScanStatus::Skipped, reason, None, config))
+}
+
+/// Scan `--stdin` as a single file labelled with `--filename`
+pub fn scan_stdin(config: &Config) -> Result<(Vec<ScanResult>, ScanSummary), Box<dyn std::error::Error>> {
+    let rules = RuleSet::from_config(config)?;
+    let name = config.stdin_filename.as_deref().unwrap_or(STDIN_NAME);
+    info!("Scanning standard input as {}", name);
+
+    let results = scan_stream(name, io::stdin().lock(), config, &rules)
+        .map_err(|e| format!("Error reading standard input: {}", e))?;
+    let summary = ScanSummary {
+        files_scanned: 1,
+        error_digest: scan_errors::take_digest(),
+        rule_stats: rule_stats::take(&rules, config),
+        archive_depth_reached: take_deepest_archive(),
+        rule_packs: rules.packs().to_vec(),
+        ..Default::default()
+    };
+    Ok((results, summary))
+}
+
+/// Scan a file read from a stream, labelling the results with `name`. As
+/// with `scan_bytes`, the contents decide between a jar, jmod or class file.
+/// Zip archives larger than `STREAM_SPOOL_THRESHOLD` are spooled to a
+/// temporary file, which the zip reader can seek without holding it all in memory.
+pub fn scan_stream<R: Read>(name: &str, mut reader: R, config: &Config, rules: &RuleSet) -> io::Result<Vec<ScanResult>> {
+    let mut data = Vec::new();
+    (&mut reader).take(STREAM_SPOOL_THRESHOLD).read_to_end(&mut data)?;
+    if (data.len() as u64) < STREAM_SPOOL_THRESHOLD || !data.starts_with(ZIP_MAGIC) || is_log4j_config(Path::new(name)) {
+        reader.read_to_end(&mut data)?;
+        return Ok(scan_bytes(name, &data, config, rules));
+    }
+
+    let spool_path = std::env::temp_dir().join(format!("rustylog4jguard-stream-{}", std::process::id()));
+    let mut spool = File::options().read(true).write(true).create_new(true).open(&spool_path)?;
+    let spool_path = scopeguard::guard(spool_path, |path| {
+        if let Err(e) = std::fs::remove_file(&path) {
+            warn!("Unable to remove spool file: {:?} - {}", path, e);
+        }
+    });
+    spool.write_all(&data)?;
+    drop(data);
+    let size = io::copy(&mut reader, &mut spool)? + STREAM_SPOOL_THRESHOLD;
+    spool.rewind()?;
+    debug!("Spooled {} ({} bytes) to {:?}", name, size, *spool_path);
+
+    let spool_info = || FileInfo { path: name.to_string(), modified: None, ..FileInfo::from_path(&spool_path, config) };
+    let unscanned = |archive_info: &ArchiveInfo| {
+        let (status, reason, severity) = archive_info.outcome()?;
+        let data = read_file(&spool_path, &config.io_budget).ok()?;
+        Some(unscanned_result(spool_info(), &data, status, reason, severity, config))
+    };
+    let mut archive_info = ArchiveInfo::default();
+    if is_bundle_file(Path::new(name)) || is_python_package(Path::new(name)) {
+        let mut results = scan_bundle_entries(name, spool, config, rules, &mut archive_info);
+        if results.is_empty() {
+            results.extend(unscanned(&archive_info));
+        }
+        return Ok(results);
+    }
+    let result = match scan_archive(name, spool, config, rules, &mut archive_info) {
+        Some(found) => Some(entry_match_result(spool_info(), found, config)),
+        None => unscanned(&archive_info),
+    };
+    Ok(archive_results(name, result, archive_info, config))
+}
+
+/// Scan a `.jmod` file: a zip archive behind a 4-byte header, with classes under `classes/`
+fn scan_jmod(path: &Path, config: &Config, rules: &RuleSet) -> Vec<ScanResult> {
+    debug!("Scanning JMOD file: {:?}", path);
+
+    let data = match read_file(path, &config.io_budget) {
+        Ok(data) => data,
+        Err(e) => {
+            scan_errors::record(path, ScanErrorKind::of_io(&e), format_args!("Error reading JMOD file: {:?} - {}", path, e));
+            return Vec::new();
+        }
+    };
+
+    if !data.starts_with(JMOD_MAGIC) {
+        return vec![skipped_scan_result(path, &data, "Skipped JMOD: missing JM header".to_string(), config)];
     }
+    scan_zip_data(FileInfo::from_path(path, config), &data, &data[JMOD_MAGIC.len()..], config, rules)
+}
 
-    let pool = rayon::ThreadPoolBuilder::new()
-        .num_threads(config.threads.unwrap_or_else(num_cpus::get))
-        .build()?;
+/// Scan the zip archive `zip` held in `data`, which is the whole file
+/// (equal to `zip` unless the archive sits behind a header)
+fn scan_zip_data(file: FileInfo, data: &[u8], zip: &[u8], config: &Config, rules: &RuleSet) -> Vec<ScanResult> {
+    let name = file.path.clone();
+    let mut archive_info = ArchiveInfo::default();
+    let result = match scan_archive(&name, Cursor::new(zip), config, rules, &mut archive_info) {
+        Some(found) => Some(entry_match_result(file, found, config)),
+        None => archive_info.outcome()
+            .map(|(status, reason, severity)| unscanned_result(file, data, status, reason, severity, config)),
+    };
+    archive_results(&name, result, archive_info, config)
+}
 
-    let exclude_patterns: Vec<Pattern> = config.exclude.iter()
-        .filter_map(|p| Pattern::new(p).ok())
-        .collect();
+/// Match an entry of a container whose entries are only listed, not
+/// extracted, by its name alone. `contents` only feeds the hashes and
+/// metrics of the result, which is labelled with `name`.
+pub fn scan_entry_name(name: &str, entry_name: &str, contents: &[u8], config: &Config, rules: &RuleSet) -> Option<ScanResult> {
+    is_vulnerable(&[entry_name], &[], rules, None)
+        .map(|detection| create_scan_result(FileInfo::from_bytes(name, contents, config), contents, detection, config))
+}
 
-    let custom_patterns: Vec<Regex> = config.custom_patterns.iter()
-        .filter_map(|p| Regex::new(p).ok())
-        .collect();
+/// Scan an Android package: jars bundled under `assets/` or `lib/` get the
+/// usual jar scan, `.dex` entries have their string tables searched for the
+/// JndiLookup class and the JNDI lookup string
+fn scan_apk(path: &Path, config: &Config, rules: &RuleSet) -> Vec<ScanResult> {
+    debug!("Scanning APK file: {:?}", path);
 
-    let entries: Vec<_> = WalkDir::new(&config.path)
-        .into_iter()
-        .filter_map(|e| e.ok())
-        .filter(|e| e.file_type().is_file())
-        .filter(|e| !is_excluded(e.path(), &exclude_patterns))
-        .collect();
+    let file = match File::open(path).map(|file| ThrottledReader::new(file, &config.io_budget)) {
+        Ok(file) => file,
+        Err(e) => {
+            scan_errors::record(path, ScanErrorKind::of_io(&e), format_args!("Error opening APK file: {:?} - {}", path, e));
+            return Vec::new();
+        }
+    };
 
-    let progress_bar = if !config.quiet {
-        Some(Arc::new(ProgressBar::new(entries.len() as u64)))
-    } else {
-        None
+    let mut archive_info = ArchiveInfo::default();
+    let mut archive = match ZipArchive::new(file) {
+        Ok(archive) => archive,
+        Err(e) => {
+            scan_errors::record(path, ScanErrorKind::of_zip(&e), format_args!("Error reading APK file: {:?} - {}", path, e));
+            archive_info.errors.push(e.to_string());
+            return unscanned_archive_result(path, &archive_info, config).into_iter().collect();
+        }
     };
 
-    if let Some(pb) = &progress_bar {
-        pb.set_style(ProgressStyle::default_bar()
-            .template("[{elapsed_precise}] {bar:40.cyan/blue} {pos:>7}/{len:7} {msg}")
-            .unwrap()
-            .progress_chars("##-"));
-    }
+    let mut results = Vec::new();
 
-    let results: Vec<ScanResult> = pool.install(|| {
-        entries.par_iter()
-            .filter_map(|entry| {
-                let pb = progress_bar.as_ref().map(Arc::clone);
-                let path = entry.path();
-                let result = if is_jar_file(path) {
-                    scan_jar(path, &custom_patterns)
-                } else if is_class_file(path) {
-                    scan_class(path, &custom_patterns)
-                } else {
-                    None
-                };
-                if let Some(pb) = pb {
-                    pb.inc(1);
-                }
-                result
-            })
-            .collect()
-    });
+    for i in 0..archive.len() {
+        let mut entry = match archive.by_index(i) {
+            Ok(entry) => entry,
+            Err(ZipError::UnsupportedArchive(ZipError::PASSWORD_REQUIRED)) => {
+                debug!("Skipping encrypted entry {} in APK: {:?}", i, path);
+                archive_info.encrypted += 1;
+                continue;
+            }
+            Err(e) => {
+                scan_errors::record(path, ScanErrorKind::of_zip(&e), format_args!("Error reading file in APK: {:?} - {}", path, e));
+                archive_info.errors.push(format!("entry {}: {}", i, e));
+                continue;
+            }
+        };
 
-    if let Some(pb) = progress_bar {
-        pb.finish_with_message("Scan complete");
+        let name = EntryNames::of(&entry).decoded;
+        let is_bundled_jar = (name.starts_with("assets/") || name.starts_with("lib/")) && is_jar_file(Path::new(&name));
+        let is_dex = name.ends_with(".dex");
+        if !is_bundled_jar && !is_dex {
+            continue;
+        }
+
+        let mut data = Vec::new();
+        if let Err(e) = entry.read_to_end(&mut data) {
+            scan_errors::record(path, ScanErrorKind::of_io(&e), format_args!("Error reading file in APK: {:?} - {}", path, e));
+            archive_info.errors.push(format!("{}: {}", name, e));
+            continue;
+        }
+
+        let nested_name = nested_path(&path.to_string_lossy(), &name);
+        if is_bundled_jar {
+            results.extend(scan_nested(&nested_name, &data, config, rules));
+            continue;
+        }
+        match dex::strings(&data) {
+            Ok(strings) => results.extend(dex_detections(&strings).into_iter()
+                .map(|detection| create_scan_result(FileInfo::from_bytes(&nested_name, &data, config), &data, detection, config))),
+            Err(e) => {
+                scan_errors::record(path, ScanErrorKind::InvalidArchive, format_args!("Error reading dex file in APK: {} - {}", nested_name, e));
+                archive_info.errors.push(format!("{}: {}", name, e));
+            }
+        }
     }
 
-    Ok(results)
+    if results.is_empty() {
+        results.extend(unscanned_archive_result(path, &archive_info, config));
+    }
+    results
 }
 
-fn is_excluded(path: &Path, patterns: &[Pattern]) -> bool {
-    patterns.iter().any(|pattern| pattern.matches_path(path))
+/// The JndiLookup class descriptor and the first JNDI lookup string in a
+/// dex string table, each with the index it was found at
+fn dex_detections(strings: &[&[u8]]) -> Vec<Detection> {
+    let shown = |index: usize, string: &[u8]| {
+        let string: String = String::from_utf8_lossy(string).chars().take(DEX_STRING_SHOWN).collect();
+        format!("{} at dex string index {}", string, index)
+    };
+    let mut detections = Vec::new();
+    if let Some(index) = strings.iter().position(|string| *string == DEX_JNDI_LOOKUP_DESCRIPTOR) {
+        detections.push(Detection {
+            rule_id: "dex-jndi-lookup-class".to_string(),
+            reason: format!("JndiLookup class in Dalvik bytecode: {}", shown(index, strings[index])),
+            severity: Severity::Critical,
+            confidence: Confidence::High,
+            cve: Some(LOG4SHELL_CVE.to_string()),
+        });
+    }
+    if let Some(index) = strings.iter().position(|string| memmem::find(string, DEX_JNDI_PATTERN).is_some()) {
+        detections.push(Detection {
+            rule_id: "dex-jndi-string".to_string(),
+            reason: format!("JNDI lookup string in Dalvik bytecode: {}", shown(index, strings[index])),
+            severity: Severity::High,
+            confidence: Confidence::Tentative,
+            cve: Some(LOG4SHELL_CVE.to_string()),
+        });
+    }
+    detections
 }
 
-fn scan_jar(path: &Path, custom_patterns: &[Regex]) -> Option<ScanResult> {
-    debug!("Scanning JAR file: {:?}", path);
+/// Scan a zip-based bundle of Java code: a web or enterprise application
+/// archive, a plain zip, or a Python egg or wheel such as PySpark's. Findings
+/// record the entry they were found in as `inner_path`.
+fn scan_bundle(path: &Path, config: &Config, rules: &RuleSet) -> Vec<ScanResult> {
+    debug!("Scanning bundle: {:?}", path);
 
-    let file = match File::open(path) {
+    let file = match File::open(path).map(|file| ThrottledReader::new(file, &config.io_budget)) {
         Ok(file) => file,
         Err(e) => {
-            warn!("Error opening JAR file: {:?} - {}", path, e);
-            return None;
+            scan_errors::record(path, ScanErrorKind::of_io(&e), format_args!("Error opening bundle: {:?} - {}", path, e));
+            return Vec::new();
         }
     };
 
-    let mut archive = match ZipArchive::new(file) {
+    let name = path.to_string_lossy();
+    let mut archive_info = ArchiveInfo::default();
+    let mut results = scan_bundle_entries(&name, file, config, rules, &mut archive_info);
+    if results.is_empty() {
+        results.extend(unscanned_archive_result(path, &archive_info, config));
+    }
+    results
+}
+
+/// `scan_bundle` for a bundle nested in another archive or downloaded
+fn scan_bundle_data(name: &str, data: &[u8], config: &Config, rules: &RuleSet) -> Vec<ScanResult> {
+    let mut archive_info = ArchiveInfo::default();
+    let mut results = scan_bundle_entries(name, Cursor::new(data), config, rules, &mut archive_info);
+    if results.is_empty() {
+        results.extend(archive_info.outcome()
+            .map(|(status, reason, severity)| unscanned_result(FileInfo::from_bytes(name, data, config), data, status, reason, severity, config)));
+    }
+    results
+}
+
+/// Scan the jars, nested bundles, class files and log4j configuration files in a bundle
+fn scan_bundle_entries<R: Read + Seek>(name: &str, reader: R, config: &Config, rules: &RuleSet, archive_info: &mut ArchiveInfo) -> Vec<ScanResult> {
+    let mut archive = match ZipArchive::new(reader) {
         Ok(archive) => archive,
         Err(e) => {
-            warn!("Error reading JAR file: {:?} - {}", path, e);
-            return None;
+            scan_errors::record(name, ScanErrorKind::of_zip(&e), format_args!("Error reading bundle: {} - {}", name, e));
+            archive_info.errors.push(e.to_string());
+            return Vec::new();
         }
     };
 
+    let mut results = Vec::new();
+
     for i in 0..archive.len() {
-        let mut file = match archive.by_index(i) {
-            Ok(file) => file,
+        let mut entry = match archive.by_index(i) {
+            Ok(entry) => entry,
+            Err(ZipError::UnsupportedArchive(ZipError::PASSWORD_REQUIRED)) => {
+                debug!("Skipping encrypted entry {} in bundle: {}", i, name);
+                archive_info.encrypted += 1;
+                continue;
+            }
             Err(e) => {
-                warn!("Error reading file in JAR: {:?} - {}", path, e);
+                scan_errors::record(name, ScanErrorKind::of_zip(&e), format_args!("Error reading file in bundle: {} - {}", name, e));
+                archive_info.errors.push(format!("entry {}: {}", i, e));
                 continue;
             }
         };
 
-        if file.name().ends_with(".class") {
-            let mut contents = Vec::new();
-            if let Err(e) = file.read_to_end(&mut contents) {
-                warn!("Error reading class file in JAR: {:?} - {}", path, e);
-                continue;
-            }
+        let entry_name = EntryNames::of(&entry).decoded;
+        let entry_path = Path::new(&entry_name);
+        if !(is_jar_file(entry_path) || is_bundle_file(entry_path) || is_class_file(entry_path)
+            || is_jmod_file(entry_path) || is_log4j_config(entry_path)) {
+            continue;
+        }
 
-            if let Some((vulnerable, reason, severity)) = is_vulnerable(&contents, custom_patterns) {
-                return Some(create_scan_result(path, &contents, vulnerable, Some(reason), Some(severity)));
-            }
+        let mut data = Vec::new();
+        if let Err(e) = entry.read_to_end(&mut data) {
+            scan_errors::record(name, ScanErrorKind::of_io(&e), format_args!("Error reading file in bundle: {} - {}", name, e));
+            archive_info.errors.push(format!("{}: {}", entry_name, e));
+            continue;
         }
+
+        let nested_name = nested_path(name, &entry_name);
+        let prefix = format!("{}!", name);
+        results.extend(scan_nested(&nested_name, &data, config, rules).into_iter().map(|result| ScanResult {
+            inner_path: result.file_path.strip_prefix(&prefix).map(String::from),
+            ..result
+        }));
     }
+    results
+}
 
-    None
+
+/// The Skipped or Error result for an archive file with unreadable entries and no findings
+fn unscanned_archive_result(path: &Path, archive_info: &ArchiveInfo, config: &Config) -> Option<ScanResult> {
+    let (status, reason, severity) = archive_info.outcome()?;
+    let data = read_file(path, &config.io_budget).ok()?;
+    Some(unscanned_result(FileInfo::from_path(path, config), &data, status, reason, severity, config))
 }
 
-fn scan_class(path: &Path, custom_patterns: &[Regex]) -> Option<ScanResult> {
+fn scan_class(path: &Path, config: &Config, rules: &RuleSet) -> Option<ScanResult> {
     debug!("Scanning class file: {:?}", path);
 
-    let file = match File::open(path) {
+    let file = match File::open(path).map(|file| ThrottledReader::new(file, &config.io_budget)) {
         Ok(file) => file,
         Err(e) => {
-            warn!("Error opening class file: {:?} - {}", path, e);
+            scan_errors::record(path, ScanErrorKind::of_io(&e), format_args!("Error opening class file: {:?} - {}", path, e));
             return None;
         }
     };
 
-    let mut reader = BufReader::new(file);
+    let mut reader = BufReader::with_capacity(config.read_buffer_size(), file);
     let mut contents = Vec::new();
     if let Err(e) = reader.read_to_end(&mut contents) {
-        warn!("Error reading class file: {:?} - {}", path, e);
+        scan_errors::record(path, ScanErrorKind::of_io(&e), format_args!("Error reading class file: {:?} - {}", path, e));
         return None;
     }
+    // A text file or a truncated copy named like a class file would only
+    // match rules by accident. The full report lists it as skipped; quiet
+    // ones, which list only findings, leave it out.
+    if !contents.starts_with(&CLASS_MAGIC) {
+        debug!("Not a class file, no 0xCAFEBABE magic: {:?}", path);
+        return (!config.quiet()).then(|| skipped_scan_result(path, &contents, NOT_A_CLASS_FILE_REASON.to_string(), config));
+    }
 
-    if let Some((vulnerable, reason, severity)) = is_vulnerable(&contents, custom_patterns) {
-        Some(create_scan_result(path, &contents, vulnerable, Some(reason), Some(severity)))
-    } else {
-        None
+    is_vulnerable(&[], &contents, rules, None)
+        .map(|detection| create_scan_result(FileInfo::from_path(path, config), &contents, detection, config))
+}
+
+/// The Java release a class targets, from the major and minor version
+/// following its magic: `Java 8 (52.0)`. None for contents without the class
+/// magic; an error when the header is cut short or has a major version no
+/// Java release uses.
+pub fn class_version(contents: &[u8]) -> Option<Result<String, String>> {
+    if !contents.starts_with(&CLASS_MAGIC) {
+        return None;
     }
+    let header = match contents.get(4..8) {
+        Some(header) => header,
+        None => return Some(Err("class file header truncated".to_string())),
+    };
+    let minor = u16::from_be_bytes([header[0], header[1]]);
+    let major = u16::from_be_bytes([header[2], header[3]]);
+    let release = match major {
+        45..=48 => format!("1.{}", major - 44),
+        49..=66 => (major - 44).to_string(),
+        _ => return Some(Err(format!("unknown class file version {}.{}", major, minor))),
+    };
+    Some(Ok(format!("Java {} ({}.{})", release, major, minor)))
 }
 
-fn is_vulnerable(contents: &[u8], custom_patterns: &[Regex]) -> Option<(bool, String, Severity)> {
-    let vulnerable_patterns = [
-        (r"org/apache/logging/log4j/core/lookup/JndiLookup", Severity::Critical),
-        (r"javax/naming/InitialContext", Severity::High),
-        (r"javax/naming/Context", Severity::High),
-        (r"\$\{jndi:", Severity::Critical),
-    ];
+static POM_LOG4J_CORE_PATTERN: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"<artifactId>\s*log4j-core\s*</artifactId>(?:[^\n]*\n){0,3}?[^\n]*?<version>\s*([^<]+?)\s*</version>").unwrap()
+});
+
+fn scan_pom(path: &Path, config: &Config) -> Option<ScanResult> {
+    debug!("Scanning POM file: {:?}", path);
 
-    for (pattern, severity) in vulnerable_patterns.iter() {
-        let re = Regex::new(pattern).unwrap();
-        if re.is_match(&String::from_utf8_lossy(contents)) {
-            return Some((true, format!("Vulnerable pattern found: {}", pattern), severity.clone()));
+    let contents = match read_file(path, &config.io_budget) {
+        Ok(contents) => contents,
+        Err(e) => {
+            scan_errors::record(path, ScanErrorKind::of_io(&e), format_args!("Error reading POM file: {:?} - {}", path, e));
+            return None;
         }
-    }
+    };
 
-    for pattern in custom_patterns {
-        if pattern.is_match(&String::from_utf8_lossy(contents)) {
-            return Some((true, format!("Custom vulnerability pattern found: {}", pattern), Severity::High));
+    let text = String::from_utf8_lossy(&contents);
+    for captures in POM_LOG4J_CORE_PATTERN.captures_iter(&text) {
+        let version = &captures[1];
+        match parse_version(version) {
+            Some(parsed) if is_vulnerable_log4j_version(parsed) => {
+                let detection = Detection {
+                    rule_id: "pom-log4j-core-dependency".to_string(),
+                    reason: format!("log4j-core {} declared as a Maven dependency", version),
+                    severity: Severity::Critical,
+                    confidence: Confidence::Confirmed,
+                    cve: Some(LOG4SHELL_CVE.to_string()),
+                };
+                return Some(create_scan_result(FileInfo::from_path(path, config), &contents, detection, config));
+            }
+            Some(_) => {}
+            None => debug!("Unable to parse log4j-core version in {:?}: {}", path, version),
         }
     }
 
     None
 }
 
-fn create_scan_result(path: &Path, contents: &[u8], vulnerable: bool, reason: Option<String>, severity: Option<Severity>) -> ScanResult {
+fn scan_config(path: &Path, config: &Config) -> Vec<ScanResult> {
+    debug!("Scanning log4j configuration: {:?}", path);
+
+    match read_file(path, &config.io_budget) {
+        Ok(contents) => scan_config_contents(FileInfo::from_path(path, config), &contents, config),
+        Err(e) => {
+            scan_errors::record(path, ScanErrorKind::of_io(&e), format_args!("Error reading log4j configuration: {:?} - {}", path, e));
+            Vec::new()
+        }
+    }
+}
+
+/// A Medium finding for each risky setting in a log4j configuration file,
+/// quoting the offending line; a file that cannot be parsed gets a
+/// Tentative finding saying so, and its lines are still checked
+fn scan_config_contents(file: FileInfo, contents: &[u8], config: &Config) -> Vec<ScanResult> {
+    let text = String::from_utf8_lossy(contents);
+    find_issues(&file.path, &text).into_iter()
+        .map(|issue| {
+            let reason = match issue.line {
+                Some(line) => format!("log4j configuration: {} at line {}: {}", issue.description, line, issue.excerpt),
+                None => format!("log4j configuration: {}", issue.description),
+            };
+            let detection = Detection {
+                rule_id: issue.id.to_string(),
+                reason,
+                severity: Severity::Medium,
+                confidence: if issue.parse_failure { Confidence::Tentative } else { Confidence::High },
+                cve: issue.cve.map(String::from),
+            };
+            create_scan_result(file.clone(), contents, detection, config)
+        })
+        .collect()
+}
+
+/// Parse the leading numeric components of a version such as `2.14.1` or `2.0-beta9`
+pub fn parse_version(version: &str) -> Option<(u32, u32, u32)> {
+    let mut parts = version
+        .split(|c: char| !c.is_ascii_digit())
+        .take_while(|part| !part.is_empty())
+        .map(|part| part.parse::<u32>().ok());
+
+    let major = parts.next()??;
+    let minor = parts.next().flatten().unwrap_or(0);
+    let patch = parts.next().flatten().unwrap_or(0);
+    Some((major, minor, patch))
+}
+
+/// Log4Shell affects log4j-core versions in `[2.0.0, 2.17.1)`
+fn is_vulnerable_log4j_version(version: (u32, u32, u32)) -> bool {
+    ((2, 0, 0)..(2, 17, 1)).contains(&version)
+}
+
+/// Decode file contents into the text that patterns are matched against
+pub fn decode_contents(contents: &[u8]) -> Cow<'_, str> {
+    String::from_utf8_lossy(contents)
+}
+
+static JNDI_URL_PATTERN: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"\$\{jndi:((?:ldaps?|rmi|dns)://[^}\s]+)\}").unwrap()
+});
+
+/// The distinct URLs of the `${jndi:...}` lookups in `contents`, in order of
+/// appearance, which may point at attacker infrastructure
+fn extract_jndi_urls(contents: &[u8]) -> Vec<String> {
+    if memmem::find(contents, b"${jndi:").is_none() {
+        return Vec::new();
+    }
+    let text = decode_contents(contents);
+    let mut urls: Vec<String> = Vec::new();
+    for captures in JNDI_URL_PATTERN.captures_iter(&text) {
+        let url = &captures[1];
+        if !urls.iter().any(|known| known == url) {
+            urls.push(url.to_string());
+        }
+        if urls.len() == MAX_JNDI_URLS {
+            break;
+        }
+    }
+    urls
+}
+
+/// Host name or IP address of a JNDI lookup URL, lowercased, without user
+/// info or port: `ldap://user@Evil.example:1389/a` is `evil.example`
+pub fn jndi_host(url: &str) -> Option<String> {
+    let (_, rest) = url.split_once("://")?;
+    let authority = rest.split(['/', '?', '#']).next().unwrap_or_default();
+    let host_port = authority.rsplit_once('@').map_or(authority, |(_, host_port)| host_port);
+    let host = match host_port.strip_prefix('[') {
+        // An IPv6 address keeps its brackets
+        Some(ipv6) => &host_port[..ipv6.find(']').map_or(host_port.len(), |end| end + 2)],
+        None => host_port.split(':').next().unwrap_or_default(),
+    };
+    (!host.is_empty()).then(|| host.to_ascii_lowercase())
+}
+
+/// Byte sequences of which every built-in content rule needs at least one:
+/// `jndi` for `${jndi:`, `JndiLookup` for the log4j class reference and
+/// `javax/naming` for `InitialContext` and `Context`
+static PREFILTER_FINDERS: Lazy<Vec<memmem::Finder<'static>>> = Lazy::new(|| {
+    [&b"jndi"[..], b"JndiLookup", b"javax/naming"].into_iter().map(memmem::Finder::new).collect()
+});
+static PREFILTER_CHECKED: AtomicUsize = AtomicUsize::new(0);
+static PREFILTER_PASSED: AtomicUsize = AtomicUsize::new(0);
+
+/// Fast check ahead of regex matching: whether `contents` could match a
+/// built-in content rule at all
+pub fn quick_prefilter(contents: &[u8]) -> bool {
+    let passed = PREFILTER_FINDERS.iter().any(|finder| finder.find(contents).is_some());
+    PREFILTER_CHECKED.fetch_add(1, Ordering::Relaxed);
+    if passed {
+        PREFILTER_PASSED.fetch_add(1, Ordering::Relaxed);
+    }
+    passed
+}
+
+/// `entry_names` are the forms of an archive entry's name, empty outside an
+/// archive. `coordinates` are those of the archive holding the entry, for
+/// rules limited to some releases.
+fn is_vulnerable(entry_names: &[&str], contents: &[u8], rules: &RuleSet, coordinates: Option<&MavenCoordinates>) -> Option<Detection> {
+    let _timer = benchmark::time(Phase::Match);
+    // Entry-name rules still run on contents the prefilter rules out
+    let contents = if !contents.is_empty() && rules.content_rules_are_builtin() && !quick_prefilter(contents) {
+        &[]
+    } else {
+        contents
+    };
+    rules.find_match(entry_names, contents, coordinates)
+        .map(|rule| Detection {
+            rule_id: rule.id.clone(),
+            reason: rule.reason(),
+            severity: rule.severity.clone(),
+            confidence: rule.confidence(),
+            cve: rule.cve.clone(),
+        })
+}
+
+/// Identity of the scanned file, independent of where its bytes came from
+#[derive(Clone)]
+struct FileInfo {
+    path: String,
+    hashes: FileHashes,
+    size: u64,
+    modified: Option<String>,
+}
+
+impl FileInfo {
+    fn from_path(path: &Path, config: &Config) -> Self {
+        let metadata = match std::fs::metadata(path) {
+            Ok(metadata) => Some(metadata),
+            Err(e) => {
+                scan_errors::record(path, ScanErrorKind::of_io(&e), format_args!("Error reading metadata: {:?} - {}", path, e));
+                None
+            }
+        };
+
+        let hashes = calculate_file_hashes(path, config.hashes, config.read_buffer_size(), &config.io_budget).unwrap_or_else(|e| {
+            scan_errors::record(path, ScanErrorKind::of_io(&e), format_args!("Error hashing file: {:?} - {}", path, e));
+            FileHashes::default()
+        });
+
+        FileInfo {
+            path: path.to_string_lossy().to_string(),
+            hashes,
+            size: metadata.as_ref().map(|m| m.len()).unwrap_or(0),
+            modified: metadata.and_then(|m| m.modified().ok()).map(format_rfc3339),
+        }
+    }
+
+    fn from_bytes(name: &str, data: &[u8], config: &Config) -> Self {
+        FileInfo {
+            path: name.to_string(),
+            hashes: calculate_hashes(data, config.hashes),
+            size: data.len() as u64,
+            modified: None,
+        }
+    }
+}
+
+fn create_scan_result(file: FileInfo, contents: &[u8], detection: Detection, config: &Config) -> ScanResult {
+    let hashes = &file.hashes;
+    let hash = hashes.sha256.as_deref().or(hashes.blake3.as_deref()).or(hashes.sha3.as_deref()).or(hashes.xxh3.as_deref());
+    rule_stats::record(&detection.rule_id, &file.path, hash, config);
+    // A class whose header cannot be read is not quite what the rule expects
+    let (class_version, detection) = match class_version(contents) {
+        Some(Ok(version)) => (Some(version), detection),
+        Some(Err(e)) => (None, Detection {
+            reason: format!("{} ({})", detection.reason, e),
+            confidence: Confidence::Tentative,
+            ..detection
+        }),
+        None => (None, detection),
+    };
     ScanResult {
-        file_path: path.to_string_lossy().to_string(),
-        vulnerable,
-        reason,
-        severity,
-        file_hash: calculate_file_hash(path),
-        sha3_hash: calculate_sha3_hash(contents),
-        blake3_hash: calculate_blake3_hash(contents),
-        entropy: calculate_entropy(contents),
-        fourier_coefficient: calculate_fourier_coefficient(contents),
-        markov_probability: calculate_markov_probability(contents),
+        class_version,
+        reason: Some(detection.reason),
+        severity: Some(detection.severity),
+        confidence: Some(detection.confidence),
+        cve: detection.cve,
+        rule_id: Some(detection.rule_id),
+        jndi_urls: extract_jndi_urls(contents),
+        ..metrics_scan_result(file, contents, config)
     }
 }
 
-fn calculate_sha3_hash(contents: &[u8]) -> String {
-    let mut hasher = Sha3_256::new();
-    hasher.update(contents);
-    format!("{:x}", hasher.finalize())
+/// A non-vulnerable result recording why a file could not be scanned
+pub fn skipped_scan_result(path: &Path, contents: &[u8], reason: String, config: &Config) -> ScanResult {
+    unscanned_result(FileInfo::from_path(path, config), contents, ScanStatus::Skipped, reason, None, config)
+}
+
+/// A non-vulnerable result for a file whose contents were never read, such
+/// as a remote file that failed to download or was too large to
+pub fn unread_scan_result(name: &str, status: ScanStatus, reason: String, config: &Config) -> ScanResult {
+    let file = FileInfo { path: name.to_string(), hashes: FileHashes::default(), size: 0, modified: None };
+    unscanned_result(file, &[], status, reason, None, config)
+}
+
+fn unscanned_result(file: FileInfo, contents: &[u8], status: ScanStatus, reason: String, severity: Option<Severity>, config: &Config) -> ScanResult {
+    ScanResult {
+        vulnerable: false,
+        status,
+        reason: Some(reason),
+        severity,
+        ..metrics_scan_result(file, contents, config)
+    }
 }
 
-fn calculate_blake3_hash(contents: &[u8]) -> String {
-    let mut hasher = Blake3Hasher::new();
-    hasher.update(contents);
-    format!("{}", hasher.finalize().to_hex())
+fn metrics_scan_result(file: FileInfo, contents: &[u8], config: &Config) -> ScanResult {
+    let byte_counts = count_bytes(contents);
+    ScanResult {
+        file_path: file.path,
+        inner_path: None,
+        vulnerable: true,
+        status: ScanStatus::Vulnerable,
+        reason: None,
+        severity: None,
+        confidence: None,
+        cve: None,
+        rule_id: None,
+        maven_coordinates: None,
+        class_version: None,
+        annotations: None,
+        compressed_size_bytes: None,
+        uncompressed_size_bytes: None,
+        file_hash: file.hashes.sha256,
+        file_size: file.size,
+        modified: file.modified,
+        sha3_hash: file.hashes.sha3,
+        blake3_hash: file.hashes.blake3,
+        xxh3_hash: file.hashes.xxh3,
+        jndi_urls: Vec::new(),
+        entropy: calculate_entropy(&byte_counts, contents.len()),
+        byte_frequency: config.include_byte_histogram.then(|| byte_frequency(&byte_counts, contents.len())),
+        storage_object: None,
+        discovered_via_classpath: false,
+        fourier_coefficient: calculate_fourier_coefficient(contents),
+        markov_probability: calculate_markov_probability(contents, config.markov_parallel),
+    }
 }
 
-fn calculate_entropy(contents: &[u8]) -> f64 {
+/// Occurrences of each byte value in `contents`
+fn count_bytes(contents: &[u8]) -> [u32; 256] {
     let mut byte_counts = [0u32; 256];
     for &byte in contents {
         byte_counts[byte as usize] += 1;
     }
+    byte_counts
+}
 
-    let total_bytes = contents.len() as f64;
+fn calculate_entropy(byte_counts: &[u32; 256], total_bytes: usize) -> f64 {
+    let total_bytes = total_bytes as f64;
     byte_counts.iter()
         .filter(|&&count| count > 0)
         .map(|&count| {
@@ -245,36 +2258,50 @@ fn calculate_entropy(contents: &[u8]) -> f64 {
         .sum()
 }
 
+/// Each byte value's share of `total_bytes`, rounded to 0–255 for 0–100%
+fn byte_frequency(byte_counts: &[u32; 256], total_bytes: usize) -> Box<[u8; 256]> {
+    let mut frequency = Box::new([0u8; 256]);
+    if total_bytes > 0 {
+        for (share, &count) in frequency.iter_mut().zip(byte_counts) {
+            *share = (count as f64 * 255.0 / total_bytes as f64).round() as u8;
+        }
+    }
+    frequency
+}
+
 fn calculate_fourier_coefficient(contents: &[u8]) -> Complex<f64> {
     let n = contents.len();
+    if n == 0 {
+        return Complex::new(0.0, 0.0);
+    }
     let mut input: AlignedVec<c64> = contents.iter()
         .map(|&x| c64::new(x as f64, 0.0))
         .collect();
 
     let mut output = AlignedVec::new(n);
-    let plan = C2CPlan64::aligned(&[n], Sign::Forward, Flag::MEASURE).unwrap();
+    // MEASURE would pick the algorithm by timing trial runs, and with it the
+    // rounding of the result; ESTIMATE picks the same one every run
+    let plan = C2CPlan64::aligned(&[n], Sign::Forward, Flag::ESTIMATE).unwrap();
     plan.c2c(&mut input, &mut output).unwrap();
 
     // Return the first non-DC coefficient
     output.get(1).map(|&x| Complex::new(x.re, x.im)).unwrap_or(Complex::new(0.0, 0.0))
 }
 
-fn calculate_markov_probability(contents: &[u8]) -> f64 {
-    let transition_matrix = calculate_transition_matrix(contents);
-    let initial_state = contents[0] as usize;
-    
+fn calculate_markov_probability(contents: &[u8], parallel: bool) -> f64 {
+    let transition_matrix = calculate_transition_matrix(contents, parallel);
+
     contents.windows(2)
         .map(|window| transition_matrix[(window[0] as usize, window[1] as usize)])
         .fold(1.0, |acc, prob| acc * prob)
 }
 
-fn calculate_transition_matrix(contents: &[u8]) -> DMatrix<f64> {
-    let mut counts = DMatrix::zeros(256, 256);
-    
-    for window in contents.windows(2) {
-        let (from, to) = (window[0] as usize, window[1] as usize);
-        counts[(from, to)] += 1.0;
-    }
+fn calculate_transition_matrix(contents: &[u8], parallel: bool) -> DMatrix<f64> {
+    let mut counts = if parallel && contents.len() > MARKOV_PARALLEL_THRESHOLD {
+        count_transitions_parallel(contents)
+    } else {
+        count_transitions(contents)
+    };
 
     for row in 0..256 {
         let row_sum: f64 = counts.row(row).sum();
@@ -287,3 +2314,336 @@ fn calculate_transition_matrix(contents: &[u8]) -> DMatrix<f64> {
 
     counts
 }
+
+fn count_transitions(contents: &[u8]) -> DMatrix<f64> {
+    let mut counts = DMatrix::zeros(256, 256);
+
+    for window in contents.windows(2) {
+        let (from, to) = (window[0] as usize, window[1] as usize);
+        counts[(from, to)] += 1.0;
+    }
+
+    counts
+}
+
+/// Count transitions per chunk in parallel and sum the partial matrices.
+/// Chunks overlap by one byte so windows spanning a boundary are counted once.
+fn count_transitions_parallel(contents: &[u8]) -> DMatrix<f64> {
+    let windows = contents.len().saturating_sub(1);
+
+    (0..windows.div_ceil(MARKOV_CHUNK_SIZE))
+        .into_par_iter()
+        .map(|chunk| {
+            let start = chunk * MARKOV_CHUNK_SIZE;
+            let end = (start + MARKOV_CHUNK_SIZE).min(windows);
+            count_transitions(&contents[start..=end])
+        })
+        .reduce(|| DMatrix::zeros(256, 256), |a, b| a + b)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::OutputLevel;
+    use crate::utils::HashAlgorithms;
+
+    const CONTENTS: &[u8] = b"PK\x03\x04";
+
+    fn result(name: &str, status: ScanStatus) -> ScanResult {
+        let config = Config::default();
+        match status {
+            ScanStatus::Vulnerable => metrics_scan_result(FileInfo::from_bytes(name, CONTENTS, &config), CONTENTS, &config),
+            _ => unscanned_result(FileInfo::from_bytes(name, CONTENTS, &config), CONTENTS, status, ENCRYPTED_ENTRIES_REASON.to_string(), None, &config),
+        }
+    }
+
+    #[test]
+    fn cloned_result_is_independent() {
+        let original = result("a.jar", ScanStatus::Vulnerable);
+        let coefficient = original.fourier_coefficient;
+        let mut clone = original.clone();
+        clone.file_path.push_str("!inner.jar");
+        clone.severity = Some(Severity::Low);
+        clone.jndi_urls.push("ldap://example.com/a".to_string());
+        clone.fourier_coefficient = Complex::new(1.0, 2.0);
+
+        assert_eq!(original.file_path, "a.jar");
+        assert_eq!(original.severity, None);
+        assert!(original.jndi_urls.is_empty());
+        assert_eq!(original.fourier_coefficient.to_string(), coefficient.to_string());
+    }
+
+    /// Two scans in one process keep their own settings, as the socket
+    /// server's requests do
+    #[test]
+    fn scans_keep_their_own_settings() {
+        let plain = Config::default();
+        let detailed = Config {
+            hashes: HashAlgorithms::from(vec![HashAlgorithm::Xxh3]),
+            include_byte_histogram: true,
+            max_archive_depth: Some(0),
+            ..Default::default()
+        };
+
+        let result = metrics_scan_result(FileInfo::from_bytes("a.jar", CONTENTS, &detailed), CONTENTS, &detailed);
+        assert!(result.xxh3_hash.is_some() && result.file_hash.is_none());
+        assert!(result.byte_frequency.is_some());
+        let result = metrics_scan_result(FileInfo::from_bytes("a.jar", CONTENTS, &plain), CONTENTS, &plain);
+        assert!(result.file_hash.is_some() && result.xxh3_hash.is_none());
+        assert!(result.byte_frequency.is_none());
+
+        let _level = NestingLevel::enter();
+        assert!(past_max_archive_depth("a.jar", CONTENTS, &detailed).is_some());
+        assert!(past_max_archive_depth("a.jar", CONTENTS, &plain).is_none());
+    }
+
+    /// The counts are whole numbers, so summing chunks in any order gives
+    /// exactly the matrix of one pass
+    #[test]
+    fn parallel_transition_matrix_matches_serial() {
+        let mut state: u64 = 0x9e37_79b9_7f4a_7c15;
+        let contents: Vec<u8> = (0..1024 * 1024)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                state as u8
+            })
+            .collect();
+
+        assert_eq!(count_transitions_parallel(&contents), count_transitions(&contents));
+        assert_eq!(calculate_transition_matrix(&contents, true), calculate_transition_matrix(&contents, false));
+        assert_eq!(count_transitions(&contents).sum(), (contents.len() - 1) as f64);
+    }
+
+    /// With `--prioritize` a log4j-core jar is handed out first, then jars in
+    /// a `lib` directory, then other jars before class files and the rest
+    #[test]
+    fn prioritized_walk_puts_log4j_first() {
+        let dir = tempfile::tempdir().unwrap();
+        for (name, size) in [("a-app.jar", 1), ("b.class", 1), ("c.txt", 1), ("lib/d.jar", 1), ("z-log4j-core-2.14.1.jar", 10), ("e.jar", 100)] {
+            let path = dir.path().join(name);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, vec![0; size]).unwrap();
+        }
+        let config = Config { prioritize: true, ..Default::default() };
+
+        let walked = walk(&config, &[dir.path().to_path_buf()], &AtomicBool::new(false));
+        let names: Vec<String> = walked.entries.iter()
+            .map(|entry| entry.path().strip_prefix(dir.path()).unwrap().to_string_lossy().replace('\\', "/"))
+            .collect();
+        assert_eq!(names, ["z-log4j-core-2.14.1.jar", "lib/d.jar", "a-app.jar", "e.jar", "b.class", "c.txt"]);
+    }
+
+    fn jar_of(entries: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut jar = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        for (name, contents) in entries {
+            jar.start_file(*name, zip::write::FileOptions::default()).unwrap();
+            jar.write_all(contents).unwrap();
+        }
+        jar.finish().unwrap().into_inner()
+    }
+
+    /// A backup copy of JndiLookup left in a jar is read as a class
+    #[test]
+    fn class_backups_in_a_jar_are_scanned() {
+        let class = include_bytes!("../fixtures/JndiLookup.class");
+        let (config, rules) = (Config::default(), RuleSet::built_in());
+        for suffix in [".bak", ".orig", ".tmp", "~"] {
+            let entry = format!("org/apache/logging/log4j/core/lookup/JndiLookup.class{}", suffix);
+            let results = scan_bytes("app.jar", &jar_of(&[(&entry, class)]), &config, &rules);
+            assert!(results.iter().any(|r| r.vulnerable && r.rule_id.as_deref() == Some("log4j-jndi-lookup-class")), "{}: {:?}", entry, results);
+        }
+        let results = scan_bytes("app.jar", &jar_of(&[("org/apache/logging/log4j/core/lookup/JndiLookup.txt", class)]), &config, &rules);
+        assert!(results.iter().all(|r| !r.vulnerable), "{:?}", results);
+    }
+
+    /// A file named like a class file, or a backup of one, is only scanned
+    /// with the class magic; the full report lists it as not a class file
+    #[test]
+    fn class_files_without_the_magic_are_not_scanned() {
+        let dir = tempfile::tempdir().unwrap();
+        let class = include_bytes!("../fixtures/JndiLookup.class");
+        let rules = RuleSet::built_in();
+        let quiet = Config { output_level: OutputLevel::Quiet, ..Default::default() };
+        let archives = archive_formats(&quiet).unwrap();
+        for name in ["Foo.class", "Foo.class~"] {
+            let path = dir.path().join(name);
+            std::fs::write(&path, [&[0; 4], &class[4..]].concat()).unwrap();
+            assert!(scan_file(&path, &quiet, &rules, &archives).is_empty(), "{}", name);
+
+            let results = scan_file(&path, &Config::default(), &rules, &archives);
+            assert_eq!(results.len(), 1, "{}: {:?}", name, results);
+            assert_eq!((results[0].status, results[0].reason.as_deref()), (ScanStatus::Skipped, Some("Not a valid class file")));
+            assert!(!results[0].unscannable());
+        }
+    }
+
+    #[test]
+    fn severity_parses_any_case() {
+        assert_eq!("Critical".parse::<Severity>(), Ok(Severity::Critical));
+        assert_eq!("low".parse::<Severity>(), Ok(Severity::Low));
+    }
+
+    #[test]
+    fn unknown_severity_is_an_error() {
+        let error = "severe".parse::<Severity>().unwrap_err();
+        assert_eq!(error.to_string(), "invalid severity 'severe' (expected low, medium, high or critical)");
+        let _: Box<dyn std::error::Error + Send + Sync> = Box::new(error);
+    }
+
+    #[test]
+    fn max_findings_only_counts_findings() {
+        let mut results: Vec<ScanResult> = (1..=5)
+            .map(|i| result(&format!("a{}.jar", i), ScanStatus::Skipped))
+            .chain([result("z.jar", ScanStatus::Vulnerable)])
+            .collect();
+        limit_findings(&mut results, 1);
+        let statuses: Vec<(&str, ScanStatus)> = results.iter().map(|r| (r.file_path.as_str(), r.status)).collect();
+        assert_eq!(statuses, [
+            ("a1.jar", ScanStatus::Skipped),
+            ("a2.jar", ScanStatus::Skipped),
+            ("a3.jar", ScanStatus::Skipped),
+            ("a4.jar", ScanStatus::Skipped),
+            ("a5.jar", ScanStatus::Skipped),
+            ("z.jar", ScanStatus::Vulnerable),
+        ]);
+    }
+
+    #[test]
+    fn max_findings_drops_findings_past_the_limit() {
+        let mut results = vec![
+            result("a.jar", ScanStatus::Vulnerable),
+            result("b.jar", ScanStatus::Error),
+            result("c.jar", ScanStatus::Vulnerable),
+        ];
+        limit_findings(&mut results, 1);
+        let names: Vec<&str> = results.iter().map(|r| r.file_path.as_str()).collect();
+        assert_eq!(names, ["a.jar", "b.jar"]);
+    }
+
+    fn walked_dirs(root: &str, config: &Config) -> Vec<PathBuf> {
+        walk_root(Path::new(root), config)
+            .filter_map(|e| e.ok())
+            .filter(|e| e.depth() == 1 && e.file_type().is_dir())
+            .map(|e| e.into_path())
+            .collect()
+    }
+
+    /// A scan from `/` leaves out `/proc` and the other virtual mount points
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn root_scans_exclude_proc() {
+        assert!(is_virtual_fs(Path::new("/proc")));
+        let excluded = virtual_fs_dirs(Path::new("/"));
+        for dir in ["/proc", "/sys", "/dev", "/run"] {
+            assert!(excluded.contains(&PathBuf::from(dir)), "{}", dir);
+        }
+        assert!(virtual_fs_dirs(Path::new("/srv")).is_empty());
+    }
+
+    /// Below any root, a directory with a virtual filesystem mounted on it is
+    /// skipped unless `--no-vfs-filter`, and a virtual root is walked
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn virtual_filesystems_are_skipped_unless_unfiltered() {
+        let pts = PathBuf::from("/dev/pts");
+        if !is_virtual_fs(&pts) || is_virtual_fs(Path::new("/dev")) {
+            eprintln!("skipped: /dev/pts is not devpts mounted on a real /dev");
+            return;
+        }
+        assert!(!walked_dirs("/dev", &Config::default()).contains(&pts));
+        let unfiltered = Config { no_vfs_filter: true, ..Default::default() };
+        assert!(walked_dirs("/dev", &unfiltered).contains(&pts));
+
+        assert!(walked_dirs("/proc", &Config::default()).contains(&PathBuf::from("/proc/sys")));
+        assert!(!is_virtual_fs(tempfile::tempdir().unwrap().path()));
+    }
+
+    /// Paths walked below `root`, relative to it
+    fn walked(root: &Path, config: &Config) -> Vec<String> {
+        let mut walked: Vec<String> = walk_root(root, config)
+            .map(|entry| entry.unwrap().path().strip_prefix(root).unwrap().to_string_lossy().to_string())
+            .filter(|path| !path.is_empty())
+            .collect();
+        walked.sort();
+        walked
+    }
+
+    /// A hidden root is walked, hidden entries below it only with
+    /// `--skip-hidden=false`
+    #[cfg(unix)]
+    #[test]
+    fn hidden_entries_are_skipped_below_the_root() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().join(".m2");
+        std::fs::create_dir_all(root.join(".cache")).unwrap();
+        std::fs::create_dir_all(root.join("repository")).unwrap();
+        for file in [".cache/log4j.jar", "repository/log4j.jar", "repository/.log4j.jar"] {
+            std::fs::write(root.join(file), b"").unwrap();
+        }
+
+        let skipping = Config { skip_hidden: true, ..Default::default() };
+        assert_eq!(walked(&root, &skipping), ["repository", "repository/log4j.jar"]);
+        let scanning = Config { skip_hidden: false, ..Default::default() };
+        assert_eq!(walked(&root, &scanning), [".cache", ".cache/log4j.jar", "repository", "repository/.log4j.jar", "repository/log4j.jar"]);
+    }
+
+    /// Links below the root are neither walked into nor listed, whatever
+    /// the hidden setting
+    #[cfg(unix)]
+    #[test]
+    fn links_are_not_followed() {
+        let dir = tempfile::tempdir().unwrap();
+        let outside = dir.path().join("outside");
+        let root = dir.path().join("root");
+        std::fs::create_dir_all(&outside).unwrap();
+        std::fs::create_dir_all(&root).unwrap();
+        std::fs::write(outside.join("log4j.jar"), b"").unwrap();
+        std::fs::write(root.join("app.jar"), b"").unwrap();
+        std::os::unix::fs::symlink(&outside, root.join("lib")).unwrap();
+        std::os::unix::fs::symlink(outside.join("log4j.jar"), root.join("log4j.jar")).unwrap();
+
+        for skip_hidden in [true, false] {
+            assert_eq!(walked(&root, &Config { skip_hidden, ..Default::default() }), ["app.jar"]);
+        }
+    }
+
+    fn class_header(major: u16) -> Vec<u8> {
+        [&CLASS_MAGIC[..], &[0, 3], &major.to_be_bytes()].concat()
+    }
+
+    #[test]
+    fn class_versions_name_the_java_release() {
+        for (major, expected) in [(45, "Java 1.1 (45.3)"), (48, "Java 1.4 (48.3)"), (49, "Java 5 (49.3)"), (52, "Java 8 (52.3)"), (61, "Java 17 (61.3)"), (66, "Java 22 (66.3)")] {
+            assert_eq!(class_version(&class_header(major)), Some(Ok(expected.to_string())));
+        }
+    }
+
+    #[test]
+    fn corrupt_class_headers_have_no_version() {
+        assert_eq!(class_version(&CLASS_MAGIC), Some(Err("class file header truncated".to_string())));
+        assert_eq!(class_version(&class_header(52)[..7]), Some(Err("class file header truncated".to_string())));
+        assert_eq!(class_version(&class_header(44)), Some(Err("unknown class file version 44.3".to_string())));
+        assert_eq!(class_version(&class_header(67)), Some(Err("unknown class file version 67.3".to_string())));
+        assert_eq!(class_version(b"PK\x03\x04\x14\x00\x00\x00"), None);
+    }
+
+    /// A finding on a class whose header cannot be read has no version and
+    /// is tentative, saying why
+    #[test]
+    fn findings_carry_the_class_version() {
+        let config = Config::default();
+        let rules = RuleSet::built_in();
+        let class = [class_header(52), b" org/apache/logging/log4j/core/lookup/JndiLookup".to_vec()].concat();
+        let results = scan_bytes("JndiLookup.class", &class, &config, &rules);
+        assert_eq!(results[0].class_version.as_deref(), Some("Java 8 (52.3)"));
+
+        let class = [class_header(99), b" org/apache/logging/log4j/core/lookup/JndiLookup".to_vec()].concat();
+        let results = scan_bytes("JndiLookup.class", &class, &config, &rules);
+        assert!(results[0].vulnerable);
+        assert_eq!(results[0].class_version, None);
+        assert_eq!(results[0].confidence, Some(Confidence::Tentative));
+        assert!(results[0].reason.as_deref().unwrap().ends_with(" (unknown class file version 99.3)"), "{:?}", results[0].reason);
+    }
+}
@@ -0,0 +1,199 @@
+use serde_json::{json, Value};
+use std::fs;
+use std::path::Path;
+
+/// Version of the JSON report structure. Version 1 reports, from before
+/// reports had `schema_version`, are a bare array of findings. Bump it once in
+/// a release that changes `ScanResult` or the report envelope, update the
+/// schema to match and list the changes in CHANGELOG.md.
+pub const SCHEMA_VERSION: u64 = 2;
+
+/// JSON Schema (draft-07) describing the JSON report
+pub const REPORT_SCHEMA: &str = include_str!("../schema/report.schema.json");
+
+/// Print the embedded report schema
+pub fn print() {
+    println!("{}", REPORT_SCHEMA.trim_end());
+}
+
+/// Validate a JSON report against the embedded schema, printing each violation.
+/// Reports without `schema_version` are read as version 1.
+pub fn validate(report_path: &Path) -> Result<bool, Box<dyn std::error::Error>> {
+    let contents = fs::read_to_string(report_path)
+        .map_err(|e| format!("unable to read report {}: {}", report_path.display(), e))?;
+    let report: Value = serde_json::from_str(&contents)
+        .map_err(|e| format!("invalid JSON in {}: {}", report_path.display(), e))?;
+
+    let errors = validate_report(&report);
+    for error in &errors {
+        println!("{}", error);
+    }
+    if errors.is_empty() {
+        println!("{}: valid (schema version {})", report_path.display(), report_version(&report));
+    }
+    Ok(errors.is_empty())
+}
+
+/// Every way `report` violates the embedded schema
+pub fn validate_report(report: &Value) -> Vec<String> {
+    let schema: Value = serde_json::from_str(REPORT_SCHEMA).expect("embedded report schema is valid JSON");
+
+    let mut errors = Vec::new();
+    let version = report_version(report);
+    if version > SCHEMA_VERSION {
+        errors.push(format!("schema_version {} is newer than this scanner supports ({})", version, SCHEMA_VERSION));
+    }
+    check(&schema, &schema, report, "$", &mut errors);
+    errors
+}
+
+fn report_version(report: &Value) -> u64 {
+    report.get("schema_version").and_then(Value::as_u64).unwrap_or(1)
+}
+
+/// A report of any supported version in the current structure, for the
+/// commands that read reports back. A version 1 report becomes the `results`
+/// of a report without a summary: each finding gains its `status` and a
+/// `file_size` of 0, as the size is unknown, and loses `sha3_hash` and
+/// `blake3_hash`, which were digests of the matched entry, not of the file.
+pub fn upgrade(report: Value) -> Value {
+    let Value::Array(results) = report else { return report };
+    let results: Vec<Value> = results
+        .into_iter()
+        .map(|mut result| {
+            if let Value::Object(fields) = &mut result {
+                fields.insert("status".to_string(), json!("Vulnerable"));
+                fields.entry("file_size").or_insert(json!(0));
+                fields.remove("sha3_hash");
+                fields.remove("blake3_hash");
+            }
+            result
+        })
+        .collect();
+    json!({ "schema_version": 1, "results": results })
+}
+
+/// Check `instance` against the subset of JSON Schema the report schema uses:
+/// `$ref` into `definitions`, `type`, `enum`, `required`, `properties` and `items`
+fn check(root: &Value, schema: &Value, instance: &Value, path: &str, errors: &mut Vec<String>) {
+    if let Some(reference) = schema.get("$ref").and_then(Value::as_str) {
+        match reference.strip_prefix("#/").and_then(|pointer| root.pointer(&format!("/{}", pointer))) {
+            Some(target) => check(root, target, instance, path, errors),
+            None => errors.push(format!("{}: unresolvable schema reference {}", path, reference)),
+        }
+        return;
+    }
+
+    if let Some(types) = schema.get("type") {
+        let allowed: Vec<&str> = match types {
+            Value::String(name) => vec![name.as_str()],
+            Value::Array(names) => names.iter().filter_map(Value::as_str).collect(),
+            _ => Vec::new(),
+        };
+        if !allowed.iter().any(|name| has_type(instance, name)) {
+            errors.push(format!("{}: expected {}, found {}", path, allowed.join(" or "), type_name(instance)));
+            return;
+        }
+    }
+
+    if let Some(values) = schema.get("enum").and_then(Value::as_array) {
+        if !values.contains(instance) {
+            errors.push(format!("{}: {} is not one of {}", path, instance, Value::Array(values.clone())));
+        }
+    }
+
+    if let Value::Object(object) = instance {
+        for field in schema.get("required").and_then(Value::as_array).into_iter().flatten().filter_map(Value::as_str) {
+            if !object.contains_key(field) {
+                errors.push(format!("{}: missing required field {}", path, field));
+            }
+        }
+        if let Some(properties) = schema.get("properties").and_then(Value::as_object) {
+            for (name, value) in object {
+                if let Some(property) = properties.get(name) {
+                    check(root, property, value, &format!("{}.{}", path, name), errors);
+                }
+            }
+        }
+    }
+
+    if let (Value::Array(items), Some(item_schema)) = (instance, schema.get("items")) {
+        for (i, item) in items.iter().enumerate() {
+            check(root, item_schema, item, &format!("{}[{}]", path, i), errors);
+        }
+    }
+}
+
+fn has_type(instance: &Value, name: &str) -> bool {
+    match name {
+        "integer" => instance.is_u64() || instance.is_i64(),
+        _ => type_name(instance) == name || (name == "number" && instance.is_number()),
+    }
+}
+
+fn type_name(instance: &Value) -> &'static str {
+    match instance {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scanner::{ScanResult, ScanStatus};
+
+    /// A report as written before reports had `schema_version`
+    fn v1_report() -> Value {
+        json!([{
+            "file_path": "/srv/app/lib/log4j-core-2.14.1.jar",
+            "vulnerable": true,
+            "reason": "Vulnerable pattern found: org/apache/logging/log4j/core/lookup/JndiLookup",
+            "severity": "Critical",
+            "file_hash": "9f86d081884c7d659a2feaa0c55ad015a3bf4f1b2b0b822cd15d6c15b0f00a08",
+            "sha3_hash": "36f028580bb02cc8272a9a020f4200e346e276ae664e45ee80745574e2f5ab80",
+            "blake3_hash": "4878ca0425c739fa427f7eda20fe845f6b2e46ba5fe2a14df5b1e32f50603215",
+            "entropy": 5.42,
+            "fourier_coefficient": [12.5, -3.25],
+            "markov_probability": 0.0012
+        }])
+    }
+
+    #[test]
+    fn version_1_reports_are_valid() {
+        assert_eq!(validate_report(&v1_report()), Vec::<String>::new());
+    }
+
+    #[test]
+    fn version_1_findings_lose_their_entry_digests() {
+        let mut report = upgrade(v1_report());
+        assert_eq!(report["schema_version"], 1);
+        let results: Vec<ScanResult> = serde_json::from_value(report["results"].take()).unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].status, ScanStatus::Vulnerable);
+        assert_eq!(results[0].file_size, 0);
+        assert!(results[0].file_hash.is_some());
+        assert_eq!((&results[0].sha3_hash, &results[0].blake3_hash), (&None, &None));
+    }
+
+    #[test]
+    fn current_reports_are_not_upgraded() {
+        let report = json!({ "schema_version": SCHEMA_VERSION, "summary": {}, "results": [] });
+        assert_eq!(upgrade(report.clone()), report);
+    }
+
+    #[test]
+    fn newer_reports_are_refused() {
+        let report = json!({
+            "schema_version": SCHEMA_VERSION + 1,
+            "summary": { "files_scanned": 0, "truncated": false, "permission_denied": [] },
+            "results": []
+        });
+        let errors = validate_report(&report);
+        assert!(errors.iter().any(|e| e.contains("newer than this scanner supports")), "{:?}", errors);
+    }
+}
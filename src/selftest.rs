@@ -0,0 +1,320 @@
+use crate::config::{Config, OutputLevel};
+use crate::reporter;
+use crate::rules::{Rule, RuleSet, RuleTarget};
+use crate::schema;
+use crate::scanner::{self, scan_directory, ScanResult, Severity};
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+/// Minimal class file whose constant pool names the JndiLookup class
+const FIXTURE_CLASS: &[u8] = include_bytes!("../fixtures/JndiLookup.class");
+/// Jar wrapping `FIXTURE_CLASS` at its real log4j-core entry path, with the
+/// `pom.properties` of log4j-core 2.14.1
+const FIXTURE_JAR: &[u8] = include_bytes!("../fixtures/log4j-fixture.jar");
+/// RPM (gzip cpio payload) and Debian (xz data.tar) packages bundling `FIXTURE_JAR`
+const FIXTURE_RPM: &[u8] = include_bytes!("../fixtures/log4j-fixture.rpm");
+const FIXTURE_DEB: &[u8] = include_bytes!("../fixtures/log4j-fixture.deb");
+const PACKAGED_JAR: &str = "usr/share/java/log4j-fixture.jar";
+/// Python wheel bundling `FIXTURE_JAR` the way PySpark bundles its jars
+const FIXTURE_WHEEL: &[u8] = include_bytes!("../fixtures/log4j-fixture.whl");
+const WHEEL_JAR: &str = "pyspark/jars/log4j-fixture.jar";
+/// Web application archive with `FIXTURE_JAR` under `WEB-INF/lib/`
+const FIXTURE_WAR: &[u8] = include_bytes!("../fixtures/log4j-fixture.war");
+const WAR_JAR: &str = "WEB-INF/lib/log4j-fixture.jar";
+/// Java module with `FIXTURE_CLASS` under `classes/`, and a jrt image listing it as a resource
+const FIXTURE_JMOD: &[u8] = include_bytes!("../fixtures/log4j-fixture.jmod");
+const FIXTURE_JIMAGE: &[u8] = include_bytes!("../fixtures/log4j-fixture.jimage");
+const JIMAGE_RESOURCE: &str = "org.apache.logging.log4j.core/org/apache/logging/log4j/core/lookup/JndiLookup.class";
+/// Android package whose hand-built `classes.dex` names the JndiLookup class
+/// in its string table, and an Android library with `FIXTURE_JAR` as its classes.jar
+const FIXTURE_APK: &[u8] = include_bytes!("../fixtures/log4j-fixture.apk");
+const APK_DEX: &str = "classes.dex";
+const FIXTURE_AAR: &[u8] = include_bytes!("../fixtures/log4j-fixture.aar");
+const AAR_JAR: &str = "classes.jar";
+/// Zip whose only entry is AES encrypted, and the first half of `FIXTURE_JAR`
+const FIXTURE_ENCRYPTED_JAR: &[u8] = include_bytes!("../fixtures/encrypted-fixture.jar");
+const FIXTURE_TRUNCATED_JAR: &[u8] = include_bytes!("../fixtures/truncated-fixture.jar");
+
+const OUTPUT_FORMATS: [&str; 7] = ["text", "table", "json", "checkstyle", "stix", "text-stable", "teamcity"];
+/// Keys of a `text-stable` record, which scrapers rely on never changing
+const TEXT_STABLE_KEYS: [&str; 5] = ["path", "severity", "cve", "sha256", "reason_code"];
+
+/// Run the scan and report pipeline against embedded fixtures, printing
+/// PASS/FAIL per check. Returns whether every check passed.
+pub fn run() -> bool {
+    let mut checks = SelfTest::default();
+
+    run_pattern_checks(&mut checks);
+
+    // Created with a random name and owner-only permissions, so another
+    // user can't plant files in it ahead of the scan
+    let temp_dir = match tempfile::Builder::new().prefix("rustylog4jguard-self-test-").tempdir() {
+        Ok(temp_dir) => temp_dir,
+        Err(e) => {
+            checks.record("create temporary directory", Err(e.to_string()));
+            return false;
+        }
+    };
+    let dir = temp_dir.path();
+    let scan_dir = dir.join("scan");
+    let setup = fs::create_dir_all(&scan_dir)
+        .and_then(|_| fs::write(scan_dir.join("JndiLookup.class"), FIXTURE_CLASS))
+        .and_then(|_| fs::write(scan_dir.join("log4j-fixture.jar"), FIXTURE_JAR))
+        .and_then(|_| fs::write(scan_dir.join("log4j-fixture.rpm"), FIXTURE_RPM))
+        .and_then(|_| fs::write(scan_dir.join("log4j-fixture.deb"), FIXTURE_DEB))
+        .and_then(|_| fs::write(scan_dir.join("log4j-fixture.jmod"), FIXTURE_JMOD))
+        .and_then(|_| fs::write(scan_dir.join("log4j-fixture.whl"), FIXTURE_WHEEL))
+        .and_then(|_| fs::write(scan_dir.join("log4j-fixture.war"), FIXTURE_WAR))
+        .and_then(|_| fs::write(scan_dir.join("log4j-fixture.apk"), FIXTURE_APK))
+        .and_then(|_| fs::write(scan_dir.join("log4j-fixture.aar"), FIXTURE_AAR))
+        .and_then(|_| fs::create_dir_all(scan_dir.join("lib")))
+        .and_then(|_| fs::write(scan_dir.join("lib").join("modules"), FIXTURE_JIMAGE))
+        .and_then(|_| fs::write(scan_dir.join("encrypted-fixture.jar"), FIXTURE_ENCRYPTED_JAR))
+        .and_then(|_| fs::write(scan_dir.join("truncated-fixture.jar"), FIXTURE_TRUNCATED_JAR))
+        .and_then(|_| fs::write(scan_dir.join("Clean.class"), b"\xca\xfe\xba\xbe clean"))
+        // Names JndiLookup like FIXTURE_CLASS, but is not a class file
+        .and_then(|_| fs::write(scan_dir.join("NoMagic.class"), [b"\0\0\0\0", &FIXTURE_CLASS[4..]].concat()));
+    checks.record("write fixtures", setup.map_err(|e| e.to_string()));
+
+    if checks.all_passed() {
+        run_scan_checks(&scan_dir, dir, &mut checks);
+    }
+
+    let shown = dir.display().to_string();
+    if let Err(e) = temp_dir.close() {
+        println!("warning: unable to remove {}: {}", shown, e);
+    }
+
+    checks.all_passed()
+}
+
+fn run_scan_checks(scan_dir: &Path, dir: &Path, checks: &mut SelfTest) {
+    let config = Config {
+        path: scan_dir.to_string_lossy().to_string(),
+        output_level: OutputLevel::Quiet,
+        ..Default::default()
+    };
+
+    let (results, summary) = match scan_directory(&config) {
+        Ok(scan) => scan,
+        Err(e) => {
+            checks.record("scan fixtures", Err(e.to_string()));
+            return;
+        }
+    };
+    checks.record("scan fixtures", Ok(()));
+
+    let mut found: Vec<PathBuf> = results.iter()
+        .filter(|r| r.vulnerable)
+        .map(|r| PathBuf::from(&r.file_path))
+        .collect();
+    found.sort();
+    let expected = vec![
+        scan_dir.join("JndiLookup.class"),
+        scan_dir.join("lib").join(format!("modules!{}", JIMAGE_RESOURCE)),
+        scan_dir.join(format!("log4j-fixture.aar!{}", AAR_JAR)),
+        scan_dir.join(format!("log4j-fixture.apk!{}", APK_DEX)),
+        scan_dir.join(format!("log4j-fixture.deb!{}", PACKAGED_JAR)),
+        scan_dir.join("log4j-fixture.jar"),
+        scan_dir.join("log4j-fixture.jmod"),
+        scan_dir.join(format!("log4j-fixture.rpm!{}", PACKAGED_JAR)),
+        scan_dir.join(format!("log4j-fixture.war!{}", WAR_JAR)),
+        scan_dir.join(format!("log4j-fixture.whl!{}", WHEEL_JAR)),
+    ];
+    checks.record("exactly the expected findings", if found == expected {
+        Ok(())
+    } else {
+        Err(format!("expected {:?}, found {:?}", expected, found))
+    });
+
+    let all_critical = results.iter().filter(|r| r.vulnerable).all(|r| matches!(r.severity, Some(Severity::Critical)));
+    checks.record("findings are Critical", if all_critical {
+        Ok(())
+    } else {
+        Err("a finding had a severity other than Critical".to_string())
+    });
+
+    for format in OUTPUT_FORMATS {
+        let output = dir.join(format!("report.{}", format));
+        let config = Config {
+            format: format.to_string(),
+            output: Some(output.to_string_lossy().to_string()),
+            ..config.clone()
+        };
+        let check = reporter::report_results(&results, &summary, &config)
+            .map_err(|e| e.to_string())
+            .and_then(|_| fs::read_to_string(&output).map_err(|e| e.to_string()))
+            .and_then(|report| check_report(format, &report, &expected));
+        checks.record(&format!("{} report", format), check);
+    }
+}
+
+/// Header of the synthetic class files: the magic and Java 8's class version
+const SAMPLE_CLASS_HEADER: &[u8] = b"\xca\xfe\xba\xbe\x00\x00\x00\x34";
+
+/// Scan a synthetic sample made for each built-in rule, which must be found
+/// with the rule's severity and reason, then benign samples, which must not
+/// be found at all. Prints a table with a row per sample.
+fn run_pattern_checks(checks: &mut SelfTest) {
+    let config = Config::default();
+    let rules = RuleSet::built_in();
+    let mut rows: Vec<(String, String, Result<(), String>)> = rules.all().iter()
+        .map(|rule| {
+            let outcome = rule_sample(rule).and_then(|(name, data)| expect_rule(&scanner::scan_bytes(&name, &data, &config, &rules), rule));
+            (rule.id.clone(), rule.severity.to_string(), outcome)
+        })
+        .collect();
+    for (name, data) in benign_samples() {
+        let outcome = match data.map(|data| scanner::scan_bytes(name, &data, &config, &rules)) {
+            Ok(results) => match results.iter().find(|result| result.vulnerable) {
+                Some(result) => Err(format!("found by {}", result.rule_id.as_deref().unwrap_or("unknown rule"))),
+                None => Ok(()),
+            },
+            Err(e) => Err(e),
+        };
+        rows.push((format!("benign {}", name), "-".to_string(), outcome));
+    }
+
+    println!("{:<32} {:<9} Result", "Pattern", "Severity");
+    for (pattern, severity, outcome) in rows {
+        match outcome {
+            Ok(()) => println!("{:<32} {:<9} PASS", pattern, severity),
+            Err(e) => {
+                checks.failures += 1;
+                println!("{:<32} {:<9} FAIL: {}", pattern, severity, e);
+            }
+        }
+    }
+}
+
+/// A file name and contents made to match `rule`: a jar with an entry of
+/// the matching name, from an affected release when the rule is limited to
+/// some, or a class file holding the matching text
+fn rule_sample(rule: &Rule) -> Result<(String, Vec<u8>), String> {
+    // The built-in patterns are literal paths and strings once their anchors and escapes are dropped
+    let text = rule.pattern.as_str().trim_start_matches("(^|/)").trim_end_matches('$').replace('\\', "");
+    if !rule.pattern.is_match(&text) {
+        return Err(format!("no sample matches {}", rule.pattern));
+    }
+    match rule.target {
+        RuleTarget::EntryName => {
+            let mut entries = vec![(text, SAMPLE_CLASS_HEADER.to_vec())];
+            if let Some(affected) = &rule.affected_versions {
+                let artifact_id = affected.artifact_ids.first().ok_or("no affected artifact")?;
+                let pom = format!("groupId=sample\nartifactId={}\nversion=0.1.0\n", artifact_id);
+                entries.push((format!("META-INF/maven/sample/{}/pom.properties", artifact_id), pom.into_bytes()));
+            }
+            Ok(("sample.jar".to_string(), zip_of(&entries)?))
+        }
+        RuleTarget::Content => Ok(("Sample.class".to_string(), [SAMPLE_CLASS_HEADER, text.as_bytes()].concat())),
+    }
+}
+
+/// Files no built-in rule may match, though they come close: a class and a
+/// jar of ordinary code, and text naming JNDI and javax.naming
+fn benign_samples() -> Vec<(&'static str, Result<Vec<u8>, String>)> {
+    let class = [SAMPLE_CLASS_HEADER, b"org/example/Hello java/lang/Object javax/naming/NamingException"].concat();
+    vec![
+        ("Hello.class", Ok(class.clone())),
+        ("hello.jar", zip_of(&[("org/example/Hello.class".to_string(), class)])),
+        ("notes.txt", Ok(b"JNDI and jndi lookups are disabled; see javax/naming/spi".to_vec())),
+    ]
+}
+
+/// Check that `results` hold one finding, by `rule`, with its severity and reason
+fn expect_rule(results: &[ScanResult], rule: &Rule) -> Result<(), String> {
+    let findings: Vec<&ScanResult> = results.iter().filter(|result| result.vulnerable).collect();
+    let finding = match findings.as_slice() {
+        [finding] => finding,
+        _ => return Err(format!("expected one finding, got {}", findings.len())),
+    };
+    if finding.rule_id.as_deref() != Some(rule.id.as_str()) {
+        return Err(format!("found by {}", finding.rule_id.as_deref().unwrap_or("unknown rule")));
+    }
+    if finding.severity.as_ref() != Some(&rule.severity) {
+        return Err(format!("severity {:?}", finding.severity));
+    }
+    let reason = rule.reason();
+    if finding.reason.as_deref() != Some(reason.as_str()) {
+        return Err(format!("reason {:?}, expected {:?}", finding.reason, reason));
+    }
+    Ok(())
+}
+
+/// An in-memory zip archive of `entries`
+fn zip_of(entries: &[(String, Vec<u8>)]) -> Result<Vec<u8>, String> {
+    let mut writer = zip::ZipWriter::new(io::Cursor::new(Vec::new()));
+    for (name, contents) in entries {
+        writer.start_file(name.as_str(), zip::write::FileOptions::default()).map_err(|e| e.to_string())?;
+        writer.write_all(contents).map_err(|e| e.to_string())?;
+    }
+    Ok(writer.finish().map_err(|e| e.to_string())?.into_inner())
+}
+
+fn check_report(format: &str, report: &str, expected: &[PathBuf]) -> Result<(), String> {
+    if format == "json" {
+        let report = serde_json::from_str::<serde_json::Value>(report).map_err(|e| e.to_string())?;
+        let errors = schema::validate_report(&report);
+        if !errors.is_empty() {
+            return Err(format!("report does not match the schema: {}", errors.join("; ")));
+        }
+    }
+    if format == "stix" {
+        let bundle = serde_json::from_str::<serde_json::Value>(report).map_err(|e| e.to_string())?;
+        let objects = bundle["objects"].as_array().filter(|_| bundle["type"] == "bundle").ok_or("report is not a STIX bundle")?;
+        if let Some(object) = objects.iter().find(|o| o["spec_version"] != "2.1" || !o["id"].as_str().is_some_and(|id| id.starts_with(o["type"].as_str().unwrap_or("?")))) {
+            return Err(format!("invalid STIX object {}", object));
+        }
+    }
+    if format == "text-stable" {
+        let records: Vec<&str> = report.split_terminator("\n\n").collect();
+        if records.len() != expected.len() {
+            return Err(format!("expected {} records, found {}", expected.len(), records.len()));
+        }
+        if let Some(record) = records.iter().find(|record| {
+            let keys: Vec<&str> = record.lines().map(|line| line.split('=').next().unwrap_or_default()).collect();
+            keys != TEXT_STABLE_KEYS
+        }) {
+            return Err(format!("record does not have the keys {:?} in order: {:?}", TEXT_STABLE_KEYS, record));
+        }
+    }
+    if format == "teamcity" {
+        if let Some(line) = report.lines().find(|line| !(line.starts_with("##teamcity[") && line.ends_with(']'))) {
+            return Err(format!("not a service message: {:?}", line));
+        }
+        let inspections = report.lines().filter(|line| line.starts_with("##teamcity[inspection ")).count();
+        if inspections != expected.len() {
+            return Err(format!("expected {} inspections, found {}", expected.len(), inspections));
+        }
+    }
+    if format == "checkstyle" && !(report.starts_with("<?xml") && report.trim_end().ends_with("</checkstyle>")) {
+        return Err("report is not a checkstyle document".to_string());
+    }
+
+    match expected.iter().find(|path| !report.contains(&*path.to_string_lossy())) {
+        Some(missing) => Err(format!("report does not mention {}", missing.display())),
+        None => Ok(()),
+    }
+}
+
+#[derive(Default)]
+struct SelfTest {
+    failures: usize,
+}
+
+impl SelfTest {
+    fn record(&mut self, name: &str, result: Result<(), String>) {
+        match result {
+            Ok(()) => println!("PASS  {}", name),
+            Err(e) => {
+                self.failures += 1;
+                println!("FAIL  {}: {}", name, e);
+            }
+        }
+    }
+
+    fn all_passed(&self) -> bool {
+        self.failures == 0
+    }
+}
@@ -0,0 +1,248 @@
+use crate::config::{Config, OutputLevel};
+use crate::reporter;
+use crate::scanner::{retain_min_confidence, scan_directory, sort_results};
+use axum::body::Bytes;
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::routing::post;
+use axum::{Json, Router};
+use log::{error, info};
+use serde_json::{json, Value};
+use std::fs;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+const INTERNAL_ERROR: i64 = -32603;
+const SCAN_IN_PROGRESS: i64 = -32000;
+
+/// Options a client may set per scan; everything else comes from the server's command line
+#[derive(serde::Deserialize)]
+struct ScanParams {
+    path: String,
+    #[serde(default = "default_format")]
+    format: String,
+    #[serde(default)]
+    exclude: Vec<String>,
+}
+
+fn default_format() -> String {
+    "json".to_string()
+}
+
+#[derive(serde::Deserialize)]
+struct RpcRequest {
+    jsonrpc: String,
+    #[serde(default)]
+    id: Value,
+    method: String,
+    #[serde(default)]
+    params: Value,
+}
+
+struct ServerState {
+    config: Config,
+    /// Canonical directories clients may scan inside
+    roots: Vec<PathBuf>,
+    busy: AtomicBool,
+}
+
+/// Clears the busy flag when a scan finishes, even if it panics
+struct BusyGuard<'a>(&'a AtomicBool);
+
+impl Drop for BusyGuard<'_> {
+    fn drop(&mut self) {
+        self.0.store(false, Ordering::Release);
+    }
+}
+
+/// Serve `POST /scan` JSON-RPC 2.0 requests on `listen` until the process is stopped.
+/// Only paths inside `roots` are scanned. Only one scan runs at a time; concurrent
+/// requests get a "Scan in progress" error with status 429.
+pub fn run(config: &Config, listen: &str, roots: &[PathBuf]) -> Result<(), Box<dyn std::error::Error>> {
+    let roots = roots.iter()
+        .map(|root| fs::canonicalize(root).map_err(|e| format!("allowed root {}: {}", root.display(), e)))
+        .collect::<Result<Vec<_>, _>>()?;
+    let state = Arc::new(ServerState {
+        config: config.clone(),
+        roots,
+        busy: AtomicBool::new(false),
+    });
+    let app = router(state);
+
+    let runtime = tokio::runtime::Builder::new_multi_thread().enable_all().build()?;
+    runtime.block_on(async {
+        let listener = tokio::net::TcpListener::bind(listen).await?;
+        info!("Listening on {}", listener.local_addr()?);
+        axum::serve(listener, app).await
+    })?;
+    Ok(())
+}
+
+fn router(state: Arc<ServerState>) -> Router {
+    Router::new()
+        .route("/scan", post(handle_scan))
+        .with_state(state)
+}
+
+/// `path` resolved, if it is inside one of `roots`. Paths that do not exist get
+/// the same error, so clients cannot probe outside the roots.
+fn allowed_path(path: &str, roots: &[PathBuf]) -> Result<PathBuf, String> {
+    fs::canonicalize(path).ok()
+        .filter(|resolved| roots.iter().any(|root| resolved.starts_with(root)))
+        .ok_or_else(|| format!("not inside an allowed root: {}", path))
+}
+
+async fn handle_scan(State(state): State<Arc<ServerState>>, body: Bytes) -> Response {
+    let request: RpcRequest = match serde_json::from_slice::<Value>(&body) {
+        Ok(value) => match serde_json::from_value(value) {
+            Ok(request) => request,
+            Err(e) => return rpc_error(Value::Null, INVALID_REQUEST, &e.to_string()),
+        },
+        Err(e) => return rpc_error(Value::Null, PARSE_ERROR, &e.to_string()),
+    };
+
+    if request.jsonrpc != "2.0" {
+        return rpc_error(request.id, INVALID_REQUEST, "jsonrpc must be \"2.0\"");
+    }
+    if request.method != "scan" {
+        return rpc_error(request.id, METHOD_NOT_FOUND, &format!("unknown method: {}", request.method));
+    }
+    let params: ScanParams = match serde_json::from_value(request.params) {
+        Ok(params) => params,
+        Err(e) => return rpc_error(request.id, INVALID_PARAMS, &e.to_string()),
+    };
+    if params.format != "json" {
+        return rpc_error(request.id, INVALID_PARAMS, &format!("unsupported format: {}", params.format));
+    }
+    let path = match allowed_path(&params.path, &state.roots) {
+        Ok(path) => path,
+        Err(e) => return rpc_error(request.id, INVALID_PARAMS, &e),
+    };
+
+    if state.busy.compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed).is_err() {
+        return rpc_error(request.id, SCAN_IN_PROGRESS, "Scan in progress");
+    }
+
+    let scan_state = Arc::clone(&state);
+    let scan = tokio::task::spawn_blocking(move || {
+        let _guard = BusyGuard(&scan_state.busy);
+        let config = Config {
+            path: path.to_string_lossy().to_string(),
+            exclude: params.exclude,
+            output_level: OutputLevel::Quiet,
+            ..scan_state.config.clone()
+        };
+        info!("Scanning {} for JSON-RPC request", config.path);
+        scan_directory(&config)
+            .map_err(|e| e.to_string())
+            .and_then(|(mut results, mut summary)| {
+                sort_results(&mut results, &mut summary);
+                retain_min_confidence(&mut results, config.min_confidence);
+                reporter::json_report_value(&results, &summary, &config).map_err(|e| e.to_string())
+            })
+    }).await;
+
+    match scan {
+        Ok(Ok(report)) => Json(json!({ "jsonrpc": "2.0", "id": request.id, "result": report })).into_response(),
+        Ok(Err(e)) => rpc_error(request.id, INTERNAL_ERROR, &e),
+        Err(e) => {
+            error!("Scan task failed: {}", e);
+            rpc_error(request.id, INTERNAL_ERROR, "scan failed")
+        }
+    }
+}
+
+/// A JSON-RPC error response; a busy server also answers 429 so HTTP clients can back off
+fn rpc_error(id: Value, code: i64, message: &str) -> Response {
+    let status = if code == SCAN_IN_PROGRESS { StatusCode::TOO_MANY_REQUESTS } else { StatusCode::OK };
+    (status, Json(json!({ "jsonrpc": "2.0", "id": id, "error": { "code": code, "message": message } }))).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::blocking::Client;
+
+    fn state(roots: Vec<PathBuf>, busy: bool) -> ServerState {
+        ServerState { config: Config::default(), roots, busy: AtomicBool::new(busy) }
+    }
+
+    /// Serve `state` on an ephemeral port from a thread of its own and return the scan URL
+    fn serve(state: ServerState) -> String {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/scan", listener.local_addr().unwrap());
+        listener.set_nonblocking(true).unwrap();
+        std::thread::spawn(move || {
+            let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+            runtime.block_on(async {
+                let listener = tokio::net::TcpListener::from_std(listener).unwrap();
+                axum::serve(listener, router(Arc::new(state))).await.unwrap();
+            });
+        });
+        url
+    }
+
+    /// The reply's status code and body; reqwest and axum use different versions of `http`
+    fn post(url: &str, body: &str) -> (u16, Value) {
+        let response = Client::new().post(url).header("Content-Type", "application/json").body(body.to_string()).send().unwrap();
+        (response.status().as_u16(), response.json().unwrap())
+    }
+
+    #[test]
+    fn malformed_json_is_a_parse_error() {
+        let (status, reply) = post(&serve(state(Vec::new(), false)), "{\"jsonrpc\": \"2.0\",");
+        assert_eq!(status, StatusCode::OK.as_u16());
+        assert_eq!(reply["error"]["code"], PARSE_ERROR);
+        assert_eq!(reply["id"], Value::Null);
+    }
+
+    #[test]
+    fn malformed_requests_are_invalid() {
+        let url = serve(state(Vec::new(), false));
+        let (_, reply) = post(&url, r#"{"jsonrpc": "2.0", "id": 1}"#);
+        assert_eq!(reply["error"]["code"], INVALID_REQUEST);
+        let (_, reply) = post(&url, r#"{"jsonrpc": "1.0", "id": 2, "method": "scan", "params": {"path": "/"}}"#);
+        assert_eq!((reply["id"].clone(), reply["error"]["code"].clone()), (json!(2), json!(INVALID_REQUEST)));
+        let (_, reply) = post(&url, r#"{"jsonrpc": "2.0", "id": 3, "method": "delete", "params": {}}"#);
+        assert_eq!(reply["error"]["code"], METHOD_NOT_FOUND);
+        let (_, reply) = post(&url, r#"{"jsonrpc": "2.0", "id": 4, "method": "scan", "params": {"exclude": []}}"#);
+        assert_eq!(reply["error"]["code"], INVALID_PARAMS);
+    }
+
+    #[test]
+    fn busy_server_answers_429() {
+        let root = tempfile::tempdir().unwrap();
+        let url = serve(state(vec![fs::canonicalize(root.path()).unwrap()], true));
+        let body = json!({ "jsonrpc": "2.0", "id": 7, "method": "scan", "params": { "path": root.path() } });
+        let (status, reply) = post(&url, &body.to_string());
+        assert_eq!(status, StatusCode::TOO_MANY_REQUESTS.as_u16());
+        assert_eq!(reply, json!({ "jsonrpc": "2.0", "id": 7, "error": { "code": SCAN_IN_PROGRESS, "message": "Scan in progress" } }));
+    }
+
+    #[test]
+    fn paths_outside_the_roots_are_refused() {
+        let root = tempfile::tempdir().unwrap();
+        let url = serve(state(vec![fs::canonicalize(root.path()).unwrap()], false));
+        for path in [root.path().join(".."), root.path().join("missing"), PathBuf::from("/")] {
+            let body = json!({ "jsonrpc": "2.0", "id": 1, "method": "scan", "params": { "path": path } });
+            let (_, reply) = post(&url, &body.to_string());
+            assert_eq!(reply["error"]["code"], INVALID_PARAMS, "{}", path.display());
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlinks_out_of_the_roots_are_refused() {
+        let root = tempfile::tempdir().unwrap();
+        std::os::unix::fs::symlink("/", root.path().join("escape")).unwrap();
+        let roots = vec![fs::canonicalize(root.path()).unwrap()];
+        assert!(allowed_path(&root.path().join("escape").to_string_lossy(), &roots).is_err());
+        assert!(allowed_path(&root.path().to_string_lossy(), &roots).is_ok());
+    }
+}
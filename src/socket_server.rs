@@ -0,0 +1,231 @@
+use crate::config::Config;
+use crate::rule_stats;
+use crate::rules::RuleSet;
+use crate::scan_errors;
+use crate::scanner::{archive_formats, retain_min_confidence, scan_bytes, scan_file, ArchiveFormat, ScanResult};
+use crate::utils::{is_readable, IoBudget};
+use base64::Engine;
+use log::{debug, error, info, warn};
+use serde_json::Value;
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::sync::{mpsc, oneshot, Semaphore};
+
+/// Longest request line accepted, enough for a 48 MiB inline file
+const MAX_REQUEST_BYTES: usize = 64 * 1024 * 1024;
+/// Label of inline requests without a name
+const INLINE_NAME: &str = "<inline>";
+
+/// One line of a client's request stream: a file to scan by path, or one
+/// sent inline. `id`, when given, is echoed in the reply.
+#[derive(serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Request {
+    #[serde(default)]
+    id: Value,
+    path: Option<String>,
+    inline_b64: Option<String>,
+    name: Option<String>,
+}
+
+#[derive(serde::Serialize)]
+struct Reply {
+    #[serde(skip_serializing_if = "Value::is_null")]
+    id: Value,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    results: Option<Vec<ScanResult>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+impl Reply {
+    fn error(id: Value, message: impl Into<String>) -> Self {
+        Reply { id, results: None, error: Some(message.into()) }
+    }
+}
+
+/// What every request shares: the rules, compiled once, and the pool scans run on
+struct Scanner {
+    config: Config,
+    rules: RuleSet,
+    archives: Vec<&'static ArchiveFormat>,
+    pool: rayon::ThreadPool,
+    /// Requests being scanned or waiting for the pool, across all clients
+    in_flight: Arc<Semaphore>,
+}
+
+impl Scanner {
+    fn scan(&self, request: Request) -> Reply {
+        let mut results = match (request.path, request.inline_b64) {
+            (Some(path), None) => {
+                let path = Path::new(&path);
+                if !path.is_file() {
+                    return Reply::error(request.id, format!("not a file: {}", path.display()));
+                }
+                if !is_readable(path) {
+                    return Reply::error(request.id, format!("permission denied: {}", path.display()));
+                }
+                scan_file(path, &self.config, &self.rules, &self.archives)
+            }
+            (None, Some(inline)) => match base64::engine::general_purpose::STANDARD.decode(inline) {
+                Ok(data) => scan_bytes(request.name.as_deref().unwrap_or(INLINE_NAME), &data, &self.config, &self.rules),
+                Err(e) => return Reply::error(request.id, format!("invalid inline_b64: {}", e)),
+            },
+            _ => return Reply::error(request.id, "a request needs either path or inline_b64"),
+        };
+        retain_min_confidence(&mut results, self.config.min_confidence);
+        Reply { id: request.id, results: Some(results), error: None }
+    }
+}
+
+/// Answer newline-delimited JSON scan requests on a Unix domain socket (a
+/// named pipe such as `\\.\pipe\rustylog4jguard` on Windows) until the
+/// process is stopped. Each request gets one reply line, in request order;
+/// up to `max_in_flight` requests from all clients are scanned at once.
+pub fn run(config: &Config, path: &str, max_in_flight: Option<usize>) -> Result<(), Box<dyn std::error::Error>> {
+    let threads = config.scan_threads();
+    let scanner = Arc::new(Scanner {
+        config: Config { io_budget: IoBudget::new(config.io_limit), ..config.clone() },
+        rules: RuleSet::from_config(config)?,
+        archives: archive_formats(config)?,
+        pool: rayon::ThreadPoolBuilder::new().num_threads(threads).build()?,
+        in_flight: Arc::new(Semaphore::new(max_in_flight.unwrap_or(threads * 2).max(1))),
+    });
+
+    let runtime = tokio::runtime::Builder::new_multi_thread().enable_all().build()?;
+    runtime.block_on(listen(scanner, path))?;
+    Ok(())
+}
+
+#[cfg(unix)]
+async fn listen(scanner: Arc<Scanner>, path: &str) -> io::Result<()> {
+    use std::fs;
+    use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+
+    // A socket left behind by an earlier run would make bind fail
+    if fs::symlink_metadata(path).is_ok_and(|metadata| metadata.file_type().is_socket()) {
+        fs::remove_file(path)?;
+    }
+    let listener = tokio::net::UnixListener::bind(path)?;
+    // Only the user the scanner runs as may ask it to read files
+    fs::set_permissions(path, fs::Permissions::from_mode(0o600))?;
+    info!("Listening on {}", path);
+    loop {
+        let (stream, _) = listener.accept().await?;
+        tokio::spawn(serve(Arc::clone(&scanner), stream));
+    }
+}
+
+#[cfg(windows)]
+async fn listen(scanner: Arc<Scanner>, path: &str) -> io::Result<()> {
+    use tokio::net::windows::named_pipe::ServerOptions;
+
+    let mut server = ServerOptions::new().first_pipe_instance(true).reject_remote_clients(true).create(path)?;
+    info!("Listening on {}", path);
+    loop {
+        server.connect().await?;
+        // The next client connects to a new instance of the pipe
+        let connected = std::mem::replace(&mut server, ServerOptions::new().reject_remote_clients(true).create(path)?);
+        tokio::spawn(serve(Arc::clone(&scanner), connected));
+    }
+}
+
+/// Read a client's requests and hand them to the pool as they arrive, while
+/// a writer task sends the replies back in the order the requests came in
+async fn serve<S: AsyncRead + AsyncWrite + Send + 'static>(scanner: Arc<Scanner>, stream: S) {
+    debug!("Client connected");
+    let (reader, writer) = tokio::io::split(stream);
+    let mut reader = BufReader::new(reader);
+    let (replies, pending) = mpsc::unbounded_channel();
+    let writer = tokio::spawn(write_replies(writer, pending));
+
+    loop {
+        let line = match next_line(&mut reader).await {
+            Ok(Some(line)) => line,
+            Ok(None) => break,
+            Err(e) => {
+                warn!("Error reading from client: {}", e);
+                break;
+            }
+        };
+        let (reply, receiver) = oneshot::channel();
+        let request = match line {
+            Some(line) => serde_json::from_slice::<Request>(&line).map_err(|e| format!("invalid request: {}", e)),
+            None => Err(format!("request longer than {} bytes", MAX_REQUEST_BYTES)),
+        };
+        match request {
+            Ok(request) => {
+                // Waiting for a permit stops reading, so a client cannot queue unbounded work
+                let permit = match Arc::clone(&scanner.in_flight).acquire_owned().await {
+                    Ok(permit) => permit,
+                    Err(_) => break,
+                };
+                let scanner_ref = Arc::clone(&scanner);
+                scanner.pool.spawn(move || {
+                    let _ = reply.send(scanner_ref.scan(request));
+                    drop(permit);
+                });
+            }
+            Err(message) => {
+                let _ = reply.send(Reply::error(Value::Null, message));
+            }
+        }
+        if replies.send(receiver).is_err() {
+            break;
+        }
+    }
+
+    drop(replies);
+    if let Err(e) = writer.await {
+        error!("Client reply task failed: {}", e);
+    }
+    scan_errors::take_digest();
+    rule_stats::take(&scanner.rules, &scanner.config);
+    debug!("Client disconnected");
+}
+
+async fn write_replies<W: AsyncWrite>(writer: W, mut pending: mpsc::UnboundedReceiver<oneshot::Receiver<Reply>>) {
+    tokio::pin!(writer);
+    while let Some(receiver) = pending.recv().await {
+        let reply = receiver.await.unwrap_or_else(|_| Reply::error(Value::Null, "scan failed"));
+        let mut line = match serde_json::to_vec(&reply) {
+            Ok(line) => line,
+            Err(e) => serde_json::to_vec(&Reply::error(reply.id, format!("unable to encode results: {}", e))).unwrap_or_default(),
+        };
+        line.push(b'\n');
+        if let Err(e) = writer.write_all(&line).await {
+            debug!("Client went away: {}", e);
+            return;
+        }
+    }
+}
+
+/// The next line, without its newline, or `None` at the end of the stream.
+/// A line longer than `MAX_REQUEST_BYTES` is read to its end and comes back
+/// as `Some(None)`, so the client gets an error instead of losing the connection.
+async fn next_line<R: AsyncBufRead + Unpin>(reader: &mut R) -> io::Result<Option<Option<Vec<u8>>>> {
+    let mut line = Some(Vec::new());
+    let mut read_any = false;
+    loop {
+        let available = reader.fill_buf().await?;
+        if available.is_empty() {
+            return Ok(read_any.then_some(line));
+        }
+        read_any = true;
+        let newline = memchr::memchr(b'\n', available);
+        let chunk = &available[..newline.unwrap_or(available.len())];
+        if line.as_ref().is_some_and(|line| line.len() + chunk.len() > MAX_REQUEST_BYTES) {
+            line = None;
+        }
+        if let Some(line) = line.as_mut() {
+            line.extend_from_slice(chunk);
+        }
+        let consumed = newline.map_or(available.len(), |newline| newline + 1);
+        reader.consume(consumed);
+        if newline.is_some() {
+            return Ok(Some(line));
+        }
+    }
+}
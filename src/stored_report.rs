@@ -0,0 +1,49 @@
+use crate::config::Config;
+use crate::reporter::report_results;
+use crate::scanner::{ScanResult, ScanSummary};
+use crate::schema::{self, SCHEMA_VERSION};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// The parts of a JSON report's summary a report can be rendered again from
+#[derive(Default, serde::Deserialize)]
+#[serde(default)]
+struct StoredSummary {
+    files_scanned: usize,
+    truncated: bool,
+    timed_out: bool,
+    max_duration_reached: bool,
+    since: Option<String>,
+    permission_denied: Vec<PathBuf>,
+    archive_depth_reached: usize,
+}
+
+/// Render the results of an earlier JSON report again, in `--format` and
+/// with `--report-filter`, without scanning. Totals are recomputed from the
+/// results the report holds; summary sections other than the scan counts,
+/// such as coverage and rule packs, are not carried over.
+pub fn run(report: &Path, config: &Config) -> Result<(), Box<dyn std::error::Error>> {
+    let contents = fs::read(report).map_err(|e| format!("unable to read report {}: {}", report.display(), e))?;
+    let parsed: serde_json::Value = serde_json::from_slice(&contents)
+        .map_err(|e| format!("invalid JSON report {}: {}", report.display(), e))?;
+    let mut parsed = schema::upgrade(parsed);
+    if let Some(version) = parsed["schema_version"].as_u64().filter(|&version| version > SCHEMA_VERSION) {
+        return Err(format!("report {} has schema_version {}, newer than this scanner supports ({})", report.display(), version, SCHEMA_VERSION).into());
+    }
+    let results: Vec<ScanResult> = serde_json::from_value(parsed["results"].take())
+        .map_err(|e| format!("invalid results in report {}: {}", report.display(), e))?;
+    let stored: StoredSummary = serde_json::from_value(parsed["summary"].take()).unwrap_or_default();
+
+    let summary = ScanSummary {
+        files_scanned: stored.files_scanned,
+        truncated: stored.truncated,
+        timed_out: stored.timed_out,
+        max_duration_reached: stored.max_duration_reached,
+        since: stored.since,
+        permission_denied: stored.permission_denied,
+        archive_depth_reached: stored.archive_depth_reached,
+        ..Default::default()
+    };
+    report_results(&results, &summary, config)?;
+    Ok(())
+}
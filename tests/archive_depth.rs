@@ -0,0 +1,93 @@
+use serde_json::Value;
+use std::fs;
+use std::io::{Cursor, Write};
+use std::path::Path;
+use std::process::Command;
+use zip::write::FileOptions;
+use zip::ZipWriter;
+
+const SCANNER: &str = env!("CARGO_BIN_EXE_cve_2021_44228_scanner");
+
+/// The fixture jar wrapped in zips so that it is nested `depth` archives
+/// deep in the outermost one, which this returns. Each level is named with a
+/// `!`, which nested paths must escape.
+fn nested_jar(depth: usize) -> Vec<u8> {
+    let mut contents = fs::read(Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures/log4j-fixture.jar")).unwrap();
+    let mut name = "log4j-fixture.jar".to_string();
+    for level in (0..depth).rev() {
+        let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+        writer.start_file(name, FileOptions::default()).unwrap();
+        writer.write_all(&contents).unwrap();
+        contents = writer.finish().unwrap().into_inner();
+        name = format!("level!{}.zip", level);
+    }
+    contents
+}
+
+/// The JSON report of `dir` holding a jar nested at each of `depths`
+fn scan_nested(dir: &Path, depths: &[usize], args: &[&str]) -> Value {
+    for depth in depths {
+        fs::write(dir.join(format!("depth-{}.war", depth)), nested_jar(*depth)).unwrap();
+    }
+    let output = Command::new(SCANNER)
+        .arg("--path").arg(dir)
+        .args(["--format", "json"])
+        .args(args)
+        .output()
+        .unwrap();
+    serde_json::from_slice(&output.stdout).unwrap()
+}
+
+/// The result for the jar nested `depth` deep, its path relative to `dir`
+fn result_for<'a>(report: &'a Value, dir: &Path, depth: usize) -> (String, &'a Value) {
+    let prefix = format!("{}/", dir.display());
+    report["results"].as_array().unwrap().iter()
+        .map(|r| (r["file_path"].as_str().unwrap().strip_prefix(&prefix).unwrap().to_string(), r))
+        .find(|(path, _)| path.starts_with(&format!("depth-{}.war!", depth)))
+        .unwrap_or_else(|| panic!("no result for depth {}", depth))
+}
+
+/// Right at the default limit of 3 the jar is found, one past it skipped
+/// with the depth it was at, and the summary has the deepest reached
+#[test]
+fn default_limit_boundary() {
+    let dir = tempfile::tempdir().unwrap();
+    let report = scan_nested(dir.path(), &[3, 4], &[]);
+
+    let (path, at_limit) = result_for(&report, dir.path(), 3);
+    assert_eq!(at_limit["status"], "Vulnerable");
+    assert_eq!(path, "depth-3.war!level%211.zip!level%212.zip!log4j-fixture.jar");
+    assert_eq!(at_limit["inner_path"], "level%211.zip!level%212.zip!log4j-fixture.jar");
+
+    let (path, past_limit) = result_for(&report, dir.path(), 4);
+    assert_eq!(past_limit["status"], "Skipped");
+    assert_eq!(past_limit["reason"], "Archive nesting depth limit 3 reached: not opened at depth 4");
+    assert_eq!(path, "depth-4.war!level%211.zip!level%212.zip!level%213.zip!log4j-fixture.jar");
+    assert_eq!(report["summary"]["archive_depth_reached"], 4);
+}
+
+#[test]
+fn max_archive_depth_sets_the_limit() {
+    let dir = tempfile::tempdir().unwrap();
+    let report = scan_nested(dir.path(), &[1, 2], &["--max-archive-depth", "1"]);
+
+    assert_eq!(result_for(&report, dir.path(), 1).1["status"], "Vulnerable");
+    let (_, past_limit) = result_for(&report, dir.path(), 2);
+    assert_eq!(past_limit["status"], "Skipped");
+    assert_eq!(past_limit["reason"], "Archive nesting depth limit 1 reached: not opened at depth 2");
+    assert_eq!(report["summary"]["archive_depth_reached"], 2);
+}
+
+/// A pathologically deep archive stops at the limit rather than recursing
+/// through every level
+#[test]
+fn fifty_levels_stop_at_the_limit() {
+    let dir = tempfile::tempdir().unwrap();
+    let report = scan_nested(dir.path(), &[50], &[]);
+
+    let (path, result) = result_for(&report, dir.path(), 50);
+    assert_eq!(result["status"], "Skipped");
+    assert_eq!(result["reason"], "Archive nesting depth limit 3 reached: not opened at depth 4");
+    assert_eq!(path.matches('!').count(), 4, "{}", path);
+    assert_eq!(report["summary"]["archive_depth_reached"], 4);
+}
@@ -0,0 +1,91 @@
+use serde_json::Value;
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+const SCANNER: &str = env!("CARGO_BIN_EXE_cve_2021_44228_scanner");
+
+fn fixture(name: &str) -> Vec<u8> {
+    fs::read(Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures").join(name)).unwrap()
+}
+
+/// One file of each decision, and a hidden directory that is not walked
+fn write_tree(root: &Path) {
+    fs::create_dir_all(root.join(".cache")).unwrap();
+    fs::write(root.join("app.jar"), fixture("log4j-fixture.jar")).unwrap();
+    fs::write(root.join("app-sources.jar"), fixture("log4j-fixture.jar")).unwrap();
+    fs::write(root.join("app.jar.bak"), fixture("log4j-fixture.jar")).unwrap();
+    fs::write(root.join(".cache/app.jar"), fixture("log4j-fixture.jar")).unwrap();
+    fs::write(root.join("notes.txt"), "not scanned").unwrap();
+    fs::write(root.join("broken.jar"), fixture("truncated-fixture.jar")).unwrap();
+}
+
+/// Scan `root` with an audit log, returning the JSON report
+fn scan(root: &Path, audit_log: &Path) -> Value {
+    let output = Command::new(SCANNER)
+        .arg("--path").arg(root)
+        .arg("--audit-log").arg(audit_log)
+        .args(["--exclude", "*.bak", "--format", "json"])
+        .output()
+        .unwrap();
+    serde_json::from_slice(&output.stdout).unwrap()
+}
+
+fn events(audit_log: &Path) -> Vec<Value> {
+    fs::read_to_string(audit_log).unwrap().lines().map(|line| serde_json::from_str(line).unwrap()).collect()
+}
+
+/// The decision and reason recorded for the file named `name`
+fn decision<'a>(events: &'a [Value], name: &str) -> (&'a str, &'a str) {
+    let found: Vec<&Value> = events.iter()
+        .filter(|event| event["event"] == "file" && Path::new(event["path"].as_str().unwrap()).ends_with(name))
+        .collect();
+    assert_eq!(found.len(), 1, "{}: {:?}", name, found);
+    (found[0]["decision"].as_str().unwrap(), found[0]["reason"].as_str().unwrap())
+}
+
+#[test]
+fn every_file_walked_has_a_decision() {
+    let dir = tempfile::tempdir().unwrap();
+    let root = dir.path().join("scan");
+    write_tree(&root);
+    let audit_log = dir.path().join("audit.ndjson");
+    scan(&root, &audit_log);
+    let events = events(&audit_log);
+
+    assert_eq!(decision(&events, "app.jar"), ("scanned", "findings: 1"));
+    assert_eq!(decision(&events, "app-sources.jar"), ("excluded", "matches exclude pattern *-sources.jar"));
+    assert_eq!(decision(&events, "app.jar.bak"), ("excluded", "matches exclude pattern *.bak"));
+    assert_eq!(decision(&events, "notes.txt"), ("skipped", "not a file type the scanner examines"));
+    assert_eq!(decision(&events, ".cache"), ("skipped", "hidden"));
+    let (broken, reason) = decision(&events, "broken.jar");
+    assert_eq!(broken, "error");
+    assert!(reason.contains("invalid Zip archive"), "{}", reason);
+    assert_eq!(events.iter().filter(|event| event["event"] == "file").count(), 6);
+}
+
+/// Every run appends its own events, from `start` to `finish`, under the
+/// scan id its report names
+#[test]
+fn runs_are_appended_under_their_own_scan_id() {
+    let dir = tempfile::tempdir().unwrap();
+    let root = dir.path().join("scan");
+    write_tree(&root);
+    let audit_log = dir.path().join("audit.ndjson");
+    let first = scan(&root, &audit_log);
+    let second = scan(&root, &audit_log);
+    let events = events(&audit_log);
+
+    let mut scan_ids: Vec<&Value> = events.iter().map(|event| &event["scan_id"]).collect();
+    scan_ids.dedup();
+    assert_eq!(scan_ids, [&first["summary"]["audit_log"]["scan_id"], &second["summary"]["audit_log"]["scan_id"]]);
+    for scan_id in scan_ids {
+        let run: Vec<&Value> = events.iter().filter(|event| &event["scan_id"] == scan_id).collect();
+        assert_eq!(run.first().unwrap()["event"], "start");
+        assert_eq!(run.last().unwrap()["event"], "finish");
+        assert_eq!(run.last().unwrap()["exit_code"], 0);
+        for event in ["config-resolved", "filter-summary"] {
+            assert_eq!(run.iter().filter(|e| e["event"] == event).count(), 1, "{}", event);
+        }
+    }
+}
@@ -0,0 +1,73 @@
+#![cfg(target_os = "linux")]
+
+use std::fs;
+use std::path::Path;
+use std::process::{Command, Output};
+
+const SCANNER: &str = env!("CARGO_BIN_EXE_cve_2021_44228_scanner");
+
+/// Whether this system lets an unprivileged user namespace make its own
+/// mounts; some kernels and containers forbid it
+fn user_namespaces_available() -> bool {
+    Command::new("unshare")
+        .args(["--user", "--map-root-user", "--mount", "true"])
+        .output()
+        .is_ok_and(|output| output.status.success())
+}
+
+/// Scan `root` in a user namespace where `root/loop` is a bind mount of
+/// `root` itself
+fn scan_bind_mount_loop(root: &Path, args: &[&str]) -> Output {
+    Command::new("unshare")
+        .args(["--user", "--map-root-user", "--mount", "sh", "-c"])
+        .arg(r#"root="$1"; scanner="$2"; shift 2; mount --bind "$root" "$root/loop" && exec "$scanner" --path "$root" --format json "$@""#)
+        .arg("sh")
+        .arg(root)
+        .arg(SCANNER)
+        .args(args)
+        .env("RUST_LOG", "warn")
+        .output()
+        .unwrap()
+}
+
+fn finding_count(output: &Output) -> usize {
+    let report: serde_json::Value = serde_json::from_slice(&output.stdout)
+        .unwrap_or_else(|e| panic!("{}: {}", e, String::from_utf8_lossy(&output.stderr)));
+    report["results"].as_array().unwrap().iter().filter(|r| r["vulnerable"] == true).count()
+}
+
+fn write_tree(root: &Path) {
+    fs::create_dir(root.join("loop")).unwrap();
+    fs::copy(Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures/log4j-fixture.jar"), root.join("app.jar")).unwrap();
+}
+
+#[test]
+fn bind_mount_loop_is_walked_once() {
+    if !user_namespaces_available() {
+        eprintln!("skipped: user namespaces are not available");
+        return;
+    }
+    let dir = tempfile::tempdir().unwrap();
+    write_tree(dir.path());
+
+    let output = scan_bind_mount_loop(dir.path(), &[]);
+    assert_eq!(finding_count(&output), 1);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(stderr.matches("the same directory was already walked").count(), 1, "{}", stderr);
+    assert!(stderr.contains(&format!("{:?}", dir.path().join("loop"))), "{}", stderr);
+}
+
+/// `--no-loop-detection` walks into the mount as well, finding the jar a
+/// second time through it
+#[test]
+fn no_loop_detection_walks_the_loop() {
+    if !user_namespaces_available() {
+        eprintln!("skipped: user namespaces are not available");
+        return;
+    }
+    let dir = tempfile::tempdir().unwrap();
+    write_tree(dir.path());
+
+    let output = scan_bind_mount_loop(dir.path(), &["--no-loop-detection"]);
+    assert_eq!(finding_count(&output), 2);
+}
@@ -0,0 +1,84 @@
+use serde_json::Value;
+use std::fs;
+use std::io::Read;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+const SCANNER: &str = env!("CARGO_BIN_EXE_cve_2021_44228_scanner");
+const FILES: usize = 2000;
+
+/// Run the scanner, failing the test rather than hanging if it never
+/// finishes, and return its JSON report
+fn scan_within(timeout: Duration, args: &[&str], root: &Path) -> Value {
+    let mut child = Command::new(SCANNER)
+        .arg("--path").arg(root)
+        .args(["--format", "json"])
+        .args(args)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap();
+    let mut stdout = child.stdout.take().unwrap();
+    let reader = thread::spawn(move || {
+        let mut output = Vec::new();
+        stdout.read_to_end(&mut output).unwrap();
+        output
+    });
+    let started = Instant::now();
+    while child.try_wait().unwrap().is_none() {
+        if started.elapsed() > timeout {
+            let _ = child.kill();
+            panic!("scan did not finish within {:?}", timeout);
+        }
+        thread::sleep(Duration::from_millis(20));
+    }
+    serde_json::from_slice(&reader.join().unwrap()).unwrap()
+}
+
+/// The `peak_queue_depth` an audit log's `finish` event records
+fn peak_queue_depth(audit_log: &Path) -> u64 {
+    let finish: Value = fs::read_to_string(audit_log).unwrap().lines()
+        .map(|line| serde_json::from_str::<Value>(line).unwrap())
+        .find(|event| event["event"] == "finish")
+        .unwrap();
+    finish["scan"]["peak_queue_depth"].as_u64().unwrap()
+}
+
+/// With room for one file between the walker and the workers, every file
+/// is still scanned and no more than one is ever queued
+#[test]
+fn tiny_queue_scans_every_file() {
+    let dir = tempfile::tempdir().unwrap();
+    let root = dir.path().join("scan");
+    fs::create_dir(&root).unwrap();
+    for index in 0..FILES {
+        fs::write(root.join(format!("Tiny{}.class", index)), b"\xca\xfe\xba\xbe tiny").unwrap();
+    }
+    let audit_log = dir.path().join("audit.ndjson");
+
+    let report = scan_within(Duration::from_secs(120), &["--queue-capacity", "1", "--threads", "4", "--audit-log", audit_log.to_str().unwrap()], &root);
+    assert_eq!(report["summary"]["files_scanned"], FILES);
+    assert_eq!(peak_queue_depth(&audit_log), 1);
+}
+
+/// Stopping early while the walker waits on a full queue ends the walk and
+/// the scan rather than leaving the walker blocked
+#[test]
+fn stopping_with_a_full_queue_finishes() {
+    let dir = tempfile::tempdir().unwrap();
+    let root = dir.path().join("scan");
+    fs::create_dir(&root).unwrap();
+    let jar = fs::read(Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures/log4j-fixture.jar")).unwrap();
+    for index in 0..FILES {
+        fs::write(root.join(format!("app{}.jar", index)), &jar).unwrap();
+    }
+
+    for stop in [&["--max-findings", "1"][..], &["--fail-fast"][..]] {
+        let report = scan_within(Duration::from_secs(120), &[&["--queue-capacity", "1", "--threads", "2"][..], stop].concat(), &root);
+        let coverage = &report["summary"]["coverage"];
+        assert!(coverage["skipped_by_reason"]["stopped"].as_u64().unwrap() > 0, "{:?}: {}", stop, coverage);
+        assert!(report["summary"]["files_scanned"].as_u64().unwrap() < FILES as u64, "{:?}: {}", stop, report["summary"]);
+    }
+}
@@ -0,0 +1,197 @@
+use cve_2021_44228_scanner::config::{self, Config, HyperlinkMode, OutputLevel, Profile, ThreadMode};
+use cve_2021_44228_scanner::scanner::{Confidence, Severity};
+use cve_2021_44228_scanner::utils::{HashAlgorithm, HashAlgorithms};
+use proptest::option;
+use proptest::prelude::*;
+use proptest::sample::subsequence;
+use std::fs;
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+fn text() -> impl Strategy<Value = String> {
+    "\\PC{0,12}"
+}
+
+fn optional_text() -> impl Strategy<Value = Option<String>> {
+    option::of(text())
+}
+
+fn texts() -> impl Strategy<Value = Vec<String>> {
+    prop::collection::vec(text(), 0..3)
+}
+
+/// Counts fit in a TOML integer, which is signed 64-bit
+fn count() -> impl Strategy<Value = usize> {
+    0..1_000_000usize
+}
+
+fn optional_count() -> impl Strategy<Value = Option<usize>> {
+    option::of(count())
+}
+
+fn duration() -> impl Strategy<Value = Duration> {
+    (0..4_000_000_000u64, 0..1_000_000_000u32).prop_map(|(secs, nanos)| Duration::new(secs, nanos))
+}
+
+fn time() -> impl Strategy<Value = SystemTime> {
+    duration().prop_map(|since_epoch| UNIX_EPOCH + since_epoch)
+}
+
+fn output_level() -> impl Strategy<Value = OutputLevel> {
+    prop_oneof![Just(OutputLevel::Normal), Just(OutputLevel::Quiet), Just(OutputLevel::Silent)]
+}
+
+fn confidence() -> impl Strategy<Value = Confidence> {
+    prop_oneof![Just(Confidence::Tentative), Just(Confidence::High), Just(Confidence::Confirmed)]
+}
+
+fn severity() -> impl Strategy<Value = Severity> {
+    prop_oneof![Just(Severity::Low), Just(Severity::Medium), Just(Severity::High), Just(Severity::Critical)]
+}
+
+fn profile() -> impl Strategy<Value = Profile> {
+    prop_oneof![Just(Profile::Fast), Just(Profile::Default), Just(Profile::Thorough), Just(Profile::Paranoid)]
+}
+
+fn hyperlink_mode() -> impl Strategy<Value = HyperlinkMode> {
+    prop_oneof![Just(HyperlinkMode::Auto), Just(HyperlinkMode::Always), Just(HyperlinkMode::Never)]
+}
+
+fn thread_mode() -> impl Strategy<Value = ThreadMode> {
+    prop_oneof![Just(ThreadMode::Auto), count().prop_map(ThreadMode::Fixed), Just(ThreadMode::IoOptimized)]
+}
+
+fn hashes() -> impl Strategy<Value = HashAlgorithms> {
+    subsequence(HashAlgorithm::ALL.to_vec(), 0..=HashAlgorithm::ALL.len()).prop_map(HashAlgorithms::from)
+}
+
+/// Finite ratios and rates; TOML has no NaN in serde's float mapping
+fn ratio() -> impl Strategy<Value = Option<f64>> {
+    option::of(0.0..1e9f64)
+}
+
+/// Every field of `Config`, so a field added without round-trip support
+/// fails to compile here
+fn any_config() -> impl Strategy<Value = Config> {
+    (
+        (text(), text(), optional_count(), texts(), texts(), output_level(), optional_text(), option::of(time()), any::<bool>(), any::<bool>(), optional_count(), any::<bool>()),
+        (any::<bool>(), any::<bool>(), optional_text(), optional_text(), optional_text(), optional_count(), any::<bool>(), texts(), texts(), option::of(confidence()), any::<bool>(), any::<bool>()),
+        (optional_text(), text(), ratio(), any::<bool>(), option::of(0..1_000_000_000u64), any::<bool>(), count(), optional_text(), optional_text(), optional_count(), hashes(), optional_count()),
+        (texts(), texts(), optional_text(), optional_text(), text(), option::of(severity()), any::<bool>(), any::<bool>(), optional_text(), option::of(profile()), any::<bool>(), any::<bool>()),
+        (optional_text(), hyperlink_mode(), optional_text(), any::<bool>(), any::<bool>(), optional_text(), optional_text(), optional_count(), any::<bool>(), any::<bool>(), optional_text(), any::<bool>()),
+        (any::<bool>(), option::of(0..1_000_000u64), any::<bool>(), any::<bool>(), any::<bool>(), texts(), any::<bool>(), texts(), any::<bool>(), count(), texts(), thread_mode()),
+        (any::<bool>(), 0..10u32, optional_count(), any::<bool>(), optional_text(), ratio(), option::of(duration()), any::<bool>()),
+    )
+        .prop_map(|(a, b, c, d, e, f, g)| Config {
+            path: a.0,
+            format: a.1,
+            threads: a.2,
+            exclude: a.3,
+            custom_patterns: a.4,
+            output_level: a.5,
+            output: a.6,
+            since: a.7,
+            scan_pom_xml: a.8,
+            prioritize: a.9,
+            max_findings: a.10,
+            fail_fast: a.11,
+            fail_on_permission_denied: b.0,
+            fail_on_unscannable: b.1,
+            maven_artifact: b.2,
+            maven_repo_url: b.3,
+            maven_cache_dir: b.4,
+            threads_max_memory: b.5,
+            syslog: b.6,
+            rules: b.7,
+            disable_rules: b.8,
+            min_confidence: b.9,
+            group_duplicates: b.10,
+            no_summary: b.11,
+            sign_key: c.0,
+            encoding: c.1,
+            io_limit: c.2,
+            nice: c.3,
+            timeout: c.4,
+            discover_java: c.5,
+            io_buffer_size: c.6,
+            ssh: c.7,
+            ssh_key: c.8,
+            ssh_connections: c.9,
+            hashes: c.10,
+            summary_by_dir: c.11,
+            include_archives: d.0,
+            email_to: d.1,
+            email_from: d.2,
+            smtp_server: d.3,
+            smtp_tls: d.4,
+            email_min_severity: d.5,
+            email_always: d.6,
+            email_required: d.7,
+            exit_code_manifest: d.8,
+            profile: d.9,
+            no_table: d.10,
+            skip_hidden: d.11,
+            audit_log: e.0,
+            ansi_hyperlinks: e.1,
+            registry_image: e.2,
+            registry_plain_http: e.3,
+            include_byte_histogram: e.4,
+            s3: e.5,
+            gcs: e.6,
+            bucket_connections: e.7,
+            manifest_scan: e.8,
+            stdin: e.9,
+            stdin_filename: e.10,
+            reproducible: e.11,
+            no_vfs_filter: f.0,
+            output_rotate: f.1,
+            stats: f.2,
+            content_type_detection: f.3,
+            no_loop_detection: f.4,
+            columns: f.5,
+            scan_source_jars: f.6,
+            packs: f.7,
+            list_skipped: f.8,
+            queue_capacity: f.9,
+            report_filter: f.10,
+            thread_mode: f.11,
+            output_compress: g.0,
+            output_compress_level: g.1,
+            max_archive_depth: g.2,
+            no_summary_histogram: g.3,
+            annotations: g.4,
+            suspicious_ratio: g.5,
+            max_duration: g.6,
+            markov_parallel: g.7,
+            io_budget: Default::default(),
+        })
+}
+
+/// Configs compared by their serialized form; `Config` has no `PartialEq`
+fn value(config: &Config) -> serde_json::Value {
+    serde_json::to_value(config).unwrap()
+}
+
+fn read_back(toml: &str, dir: &Path) -> Config {
+    let path = dir.join("config.toml");
+    fs::write(&path, toml).unwrap();
+    config::apply_file(Config::default(), &path, &[]).unwrap()
+}
+
+proptest! {
+    /// The profile is printed as a comment, its settings being resolved already
+    #[test]
+    fn printed_config_reads_back_unchanged(config in any_config()) {
+        let dir = tempfile::tempdir().unwrap();
+        let toml = config::to_toml(&config).unwrap();
+        let expected = Config { profile: None, ..config };
+        prop_assert_eq!(value(&read_back(&toml, dir.path())), value(&expected), "{}", toml);
+    }
+
+    #[test]
+    fn json_scan_config_reads_back_unchanged(config in any_config()) {
+        let json = serde_json::to_string(&config).unwrap();
+        let decoded: Config = serde_json::from_str(&json).unwrap();
+        prop_assert_eq!(value(&decoded), value(&config));
+    }
+}
@@ -0,0 +1,81 @@
+use serde_json::{json, Value};
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+const SCANNER: &str = env!("CARGO_BIN_EXE_cve_2021_44228_scanner");
+
+fn fixture(name: &str) -> Vec<u8> {
+    fs::read(Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures").join(name)).unwrap()
+}
+
+/// A scanned jar, a source jar and a backup excluded by pattern, a file type
+/// the scanner does not examine, a truncated jar and a hidden directory
+fn write_tree(root: &Path) {
+    fs::create_dir_all(root.join(".cache")).unwrap();
+    fs::write(root.join(".cache/app.jar"), fixture("log4j-fixture.jar")).unwrap();
+    fs::write(root.join("app.jar"), fixture("log4j-fixture.jar")).unwrap();
+    fs::write(root.join("app-sources.jar"), fixture("log4j-fixture.jar")).unwrap();
+    fs::write(root.join("app.jar.bak"), fixture("log4j-fixture.jar")).unwrap();
+    fs::write(root.join("notes.txt"), "not scanned").unwrap();
+    fs::write(root.join("broken.jar"), fixture("truncated-fixture.jar")).unwrap();
+}
+
+fn scan(root: &Path, args: &[&str]) -> String {
+    let output = Command::new(SCANNER)
+        .arg("--path").arg(root)
+        .args(["--exclude", "*.bak"])
+        .args(args)
+        .output()
+        .unwrap();
+    String::from_utf8(output.stdout).unwrap()
+}
+
+#[test]
+fn coverage_counts_every_entry_walked_by_outcome() {
+    let dir = tempfile::tempdir().unwrap();
+    write_tree(dir.path());
+
+    let report: Value = serde_json::from_str(&scan(dir.path(), &["--format", "json"])).unwrap();
+    let coverage = &report["summary"]["coverage"];
+    assert_eq!(coverage["entries_walked"], 6);
+    assert_eq!((&coverage["scanned"], &coverage["skipped"], &coverage["errored"]), (&json!(1), &json!(4), &json!(1)));
+    assert_eq!((&coverage["scanned_percent"], &coverage["skipped_percent"], &coverage["errored_percent"]), (&json!(16.67), &json!(66.67), &json!(16.67)));
+    assert_eq!(coverage["skipped_by_reason"], json!({"excluded-by-glob": 2, "unsupported-type": 1, "hidden": 1}));
+    assert!(coverage.get("skipped_entries").is_none(), "{}", coverage);
+}
+
+#[test]
+fn list_skipped_names_each_skipped_entry() {
+    let dir = tempfile::tempdir().unwrap();
+    write_tree(dir.path());
+
+    let report: Value = serde_json::from_str(&scan(dir.path(), &["--format", "json", "--list-skipped"])).unwrap();
+    let skipped: Vec<(String, &str)> = report["summary"]["coverage"]["skipped_entries"].as_array().unwrap().iter()
+        .map(|entry| {
+            let path = Path::new(entry["path"].as_str().unwrap());
+            (path.strip_prefix(dir.path()).unwrap().to_string_lossy().into_owned(), entry["reason"].as_str().unwrap())
+        })
+        .collect();
+    assert_eq!(skipped, [
+        (".cache".to_string(), "hidden"),
+        ("app-sources.jar".to_string(), "excluded-by-glob"),
+        ("app.jar.bak".to_string(), "excluded-by-glob"),
+        ("notes.txt".to_string(), "unsupported-type"),
+    ]);
+
+    let text = scan(dir.path(), &["--no-table", "--list-skipped"]);
+    assert!(text.contains(&format!("- {} [unsupported-type]\n", dir.path().join("notes.txt").display())), "{}", text);
+}
+
+#[test]
+fn text_and_teamcity_summaries_show_the_coverage() {
+    let dir = tempfile::tempdir().unwrap();
+    write_tree(dir.path());
+
+    let text = scan(dir.path(), &["--no-table"]);
+    assert!(text.contains("Coverage: 6 entries walked, 1 scanned (16.67%), 4 skipped (66.67%), 1 errored (16.67%)\n"), "{}", text);
+    let teamcity = scan(dir.path(), &["--format", "teamcity"]);
+    assert!(teamcity.contains("##teamcity[buildStatisticValue key='log4jScanEntriesWalked' value='6']"), "{}", teamcity);
+    assert!(teamcity.contains("##teamcity[buildStatisticValue key='log4jScanCoveragePercent' value='16.67']"), "{}", teamcity);
+}
@@ -0,0 +1,56 @@
+use serde_json::Value;
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+const SCANNER: &str = env!("CARGO_BIN_EXE_cve_2021_44228_scanner");
+
+fn put(path: &Path, contents: &[u8]) {
+    fs::create_dir_all(path.parent().unwrap()).unwrap();
+    fs::write(path, contents).unwrap();
+}
+
+/// A JDK named by `JAVA_HOME` and a Tomcat named by `CATALINA_HOME` are
+/// found, and only their library and deployment directories scanned: the
+/// vulnerable jar in Tomcat's `lib` is found, the one in its `logs` is not
+#[test]
+fn installations_named_by_the_environment_are_scanned() {
+    let dir = tempfile::tempdir().unwrap();
+    let jar = fs::read(Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures/log4j-fixture.jar")).unwrap();
+    let java = if cfg!(windows) { "java.exe" } else { "java" };
+    let (jdk, tomcat) = (dir.path().join("jdk"), dir.path().join("tomcat"));
+    put(&jdk.join("bin").join(java), b"");
+    put(&jdk.join("lib/tools.jar"), b"");
+    put(&tomcat.join("lib/catalina.jar"), b"");
+    put(&tomcat.join("lib/log4j-core-2.14.1.jar"), &jar);
+    put(&tomcat.join("logs/log4j-core-2.14.1.jar"), &jar);
+
+    let output = Command::new(SCANNER)
+        .args(["--discover-java", "--format", "json"])
+        .env_clear()
+        .env("JAVA_HOME", &jdk)
+        .env("CATALINA_HOME", &tomcat)
+        .env("HOME", dir.path())
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let report: Value = serde_json::from_slice(&output.stdout).unwrap();
+
+    let (jdk, tomcat) = (fs::canonicalize(jdk).unwrap(), fs::canonicalize(tomcat).unwrap());
+    let roots = report["summary"]["discovered_roots"].as_array().unwrap();
+    let root = |path: &Path| roots.iter()
+        .find(|root| root["path"] == path.to_str().unwrap())
+        .unwrap_or_else(|| panic!("{} not discovered: {:?}", path.display(), roots));
+    assert_eq!((&root(&jdk)["kind"], &root(&jdk)["found_via"]), (&"java".into(), &"JAVA_HOME".into()));
+    assert_eq!(root(&jdk)["scanned"], serde_json::json!([jdk.join("lib")]));
+    assert_eq!((&root(&tomcat)["kind"], &root(&tomcat)["found_via"]), (&"tomcat".into(), &"CATALINA_HOME".into()));
+    assert_eq!(root(&tomcat)["scanned"], serde_json::json!([tomcat.join("lib")]));
+    assert_eq!(root(&tomcat)["findings"], 1);
+
+    let findings: Vec<&str> = report["results"].as_array().unwrap().iter()
+        .filter(|r| r["vulnerable"] == true)
+        .map(|r| r["file_path"].as_str().unwrap())
+        .filter(|path| path.starts_with(tomcat.to_str().unwrap()))
+        .collect();
+    assert_eq!(findings, [tomcat.join("lib/log4j-core-2.14.1.jar").to_str().unwrap()]);
+}
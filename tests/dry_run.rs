@@ -0,0 +1,109 @@
+use serde_json::Value;
+use std::fs::{self, File, FileTimes};
+use std::path::Path;
+use std::process::Command;
+use std::time::{Duration, SystemTime};
+
+const SCANNER: &str = env!("CARGO_BIN_EXE_cve_2021_44228_scanner");
+
+/// Cutoff of `--since`: `lib/old.jar` was last modified before it
+const SINCE: &str = "2021-12-10T00:00:00Z";
+
+/// A tree with one file for each way the walk can count it: scanned,
+/// excluded by `--exclude` or as a source jar, not modified since `SINCE`,
+/// and not scannable
+fn scan_tree() -> tempfile::TempDir {
+    let dir = tempfile::tempdir().unwrap();
+    let jar = fs::read(Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures/log4j-fixture.jar")).unwrap();
+    fs::create_dir_all(dir.path().join("lib")).unwrap();
+    for name in ["lib/log4j-core.jar", "lib/log4j-core-sources.jar", "lib/vendored.jar", "lib/old.jar", "NOTES.txt"] {
+        fs::write(dir.path().join(name), &jar).unwrap();
+    }
+    let accessed = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000_000);
+    for name in ["lib/log4j-core.jar", "lib/log4j-core-sources.jar", "lib/vendored.jar", "NOTES.txt"] {
+        set_times(&dir.path().join(name), accessed, SystemTime::now());
+    }
+    set_times(&dir.path().join("lib/old.jar"), accessed, accessed);
+    dir
+}
+
+fn set_times(path: &Path, accessed: SystemTime, modified: SystemTime) {
+    File::options().write(true).open(path).unwrap()
+        .set_times(FileTimes::new().set_accessed(accessed).set_modified(modified)).unwrap();
+}
+
+fn accessed(path: &Path) -> SystemTime {
+    fs::metadata(path).unwrap().accessed().unwrap()
+}
+
+fn dry_run(dir: &Path, args: &[&str]) -> String {
+    let output = Command::new(SCANNER)
+        .arg("--path").arg(dir)
+        .args(["--exclude", "**/vendored.jar", "--since", SINCE])
+        .args(args)
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    String::from_utf8(output.stdout).unwrap()
+}
+
+#[test]
+fn dry_run_lists_the_files_a_scan_would_examine() {
+    let dir = scan_tree();
+    let report = dry_run(dir.path(), &["--dry-run"]);
+    assert_eq!(report, format!(
+        "{}\nFiles walked: 5\nExcluded: 2\nNot modified since --since: 1\nNot scannable: 1\nWould scan: 1\n",
+        dir.path().join("lib/log4j-core.jar").display(),
+    ));
+
+    let report: Value = serde_json::from_str(&dry_run(dir.path(), &["--dry-run", "--format", "json"])).unwrap();
+    assert_eq!(report, serde_json::json!({
+        "files_walked": 5,
+        "excluded": 2,
+        "not_modified_since": 1,
+        "not_scannable": 1,
+        "would_scan": 1,
+        "files": [dir.path().join("lib/log4j-core.jar")],
+    }));
+}
+
+#[test]
+fn dry_run_summary_only_counts() {
+    let dir = scan_tree();
+    assert_eq!(
+        dry_run(dir.path(), &["--dry-run-summary"]),
+        "Files walked: 5\nExcluded: 2\nNot modified since --since: 1\nNot scannable: 1\nWould scan: 1\n",
+    );
+    let report: Value = serde_json::from_str(&dry_run(dir.path(), &["--dry-run-summary", "--format", "json"])).unwrap();
+    assert_eq!(report, serde_json::json!({
+        "files_walked": 5,
+        "excluded": 2,
+        "not_modified_since": 1,
+        "not_scannable": 1,
+        "would_scan": 1,
+    }));
+}
+
+/// A dry run reads the walk's metadata only: the access time of every file,
+/// older than its modification time, stays as it was
+#[test]
+fn dry_run_opens_no_file() {
+    let dir = scan_tree();
+    let files: Vec<_> = ["lib/log4j-core.jar", "lib/log4j-core-sources.jar", "lib/vendored.jar", "lib/old.jar", "NOTES.txt"].iter()
+        .map(|name| dir.path().join(name))
+        .collect();
+    let before: Vec<SystemTime> = files.iter().map(|path| accessed(path)).collect();
+
+    let control = tempfile::NamedTempFile::new_in(dir.path()).unwrap();
+    set_times(control.path(), SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000_000), SystemTime::now());
+    let untouched = accessed(control.path());
+    fs::read(control.path()).unwrap();
+    if accessed(control.path()) == untouched {
+        eprintln!("skipped: the filesystem does not record access times");
+        return;
+    }
+
+    dry_run(dir.path(), &["--dry-run"]);
+    dry_run(dir.path(), &["--dry-run-summary", "--format", "json"]);
+    assert_eq!(files.iter().map(|path| accessed(path)).collect::<Vec<_>>(), before);
+}
@@ -0,0 +1,129 @@
+use serde_json::Value;
+use std::fs;
+use std::io::{Cursor, Write};
+use std::path::Path;
+use std::process::Command;
+use zip::write::FileOptions;
+use zip::{ZipArchive, ZipWriter};
+
+const SCANNER: &str = env!("CARGO_BIN_EXE_cve_2021_44228_scanner");
+
+const JNDI_LOOKUP_ENTRY: &[u8] = b"org/apache/logging/log4j/core/lookup/JndiLookup.class";
+const CLASS_MAGIC: &[u8] = &[0xCA, 0xFE, 0xBA, 0xBE];
+
+/// An entry-name rule whose pattern only the raw UTF-8 form of the name
+/// `café/Exploit.class` matches, not its CP437 decoding `caf├⌐/Exploit.class`
+const UNFLAGGED_UTF8_RULES: &str = r#"{"rules": [{"id": "cafe-exploit", "target": "entry-name", "pattern": "(^|/)café/Exploit\\.class$"}]}"#;
+
+fn fixture_jar() -> Vec<u8> {
+    fs::read(Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures/log4j-fixture.jar")).unwrap()
+}
+
+/// A zip of `(name, utf8_flag, contents)` entries named by raw bytes. It is
+/// written with ASCII names of the same length, which leave the language
+/// encoding flag clear, and each name and flag is then patched into the
+/// local and central headers: the name at 30 and 46 bytes into them, the
+/// flags at 6 and 8.
+fn with_raw_names(entries: &[(&[u8], bool, &[u8])]) -> Vec<u8> {
+    let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+    for (index, (name, _, contents)) in entries.iter().enumerate() {
+        writer.start_file(format!("{:0>1$}", index, name.len()), FileOptions::default()).unwrap();
+        writer.write_all(contents).unwrap();
+    }
+    let mut zip = writer.finish().unwrap().into_inner();
+    let headers: Vec<(usize, usize)> = {
+        let mut archive = ZipArchive::new(Cursor::new(&zip)).unwrap();
+        (0..archive.len())
+            .map(|index| archive.by_index_raw(index).map(|entry| (entry.header_start() as usize, entry.central_header_start() as usize)).unwrap())
+            .collect()
+    };
+    for ((name, utf8, _), (local, central)) in entries.iter().zip(headers) {
+        for (header, name_offset, flags_offset) in [(local, 30, 6), (central, 46, 8)] {
+            zip[header + name_offset..header + name_offset + name.len()].copy_from_slice(name);
+            let flags = u16::from_le_bytes([zip[header + flags_offset], zip[header + flags_offset + 1]]);
+            let flags = if *utf8 { flags | 1 << 11 } else { flags & !(1 << 11) };
+            zip[header + flags_offset..header + flags_offset + 2].copy_from_slice(&flags.to_le_bytes());
+        }
+    }
+    zip
+}
+
+/// The findings of a scan of `archives` written to `dir`, as paths relative
+/// to it with their rule ids. A match in a jar is reported on the jar, and
+/// a jar bundled in another archive by its entry name.
+fn findings(dir: &Path, archives: &[(&str, Vec<u8>)], args: &[&str]) -> Vec<(String, String)> {
+    for (name, contents) in archives {
+        fs::write(dir.join(name), contents).unwrap();
+    }
+    let output = Command::new(SCANNER)
+        .arg("--path").arg(dir)
+        .args(["--format", "json"])
+        .args(args)
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let report: Value = serde_json::from_slice(&output.stdout).unwrap();
+    let prefix = format!("{}/", dir.display());
+    report["results"].as_array().unwrap().iter()
+        .filter(|r| r["vulnerable"] == true)
+        .map(|r| (
+            r["file_path"].as_str().unwrap().strip_prefix(&prefix).unwrap().to_string(),
+            r["rule_id"].as_str().unwrap_or_default().to_string(),
+        ))
+        .collect()
+}
+
+fn paths(findings: &[(String, String)]) -> Vec<&str> {
+    findings.iter().map(|(path, _)| path.as_str()).collect()
+}
+
+/// A name without the flag is CP437, with control characters escaped; a
+/// flagged name that is not UTF-8 keeps its stray bytes as `%XX`
+#[test]
+fn bundled_jar_names_are_decoded_and_escaped() {
+    let dir = tempfile::tempdir().unwrap();
+    let jar = fixture_jar();
+    let war = with_raw_names(&[(b"WEB-INF/lib/\x81ber\x01.jar", false, &jar), (b"WEB-INF/lib/\xFF.jar", true, &jar)]);
+    let findings = findings(dir.path(), &[("bundle.war", war)], &[]);
+
+    let mut paths = paths(&findings);
+    paths.sort();
+    assert_eq!(paths, ["bundle.war!WEB-INF/lib/%FF.jar", "bundle.war!WEB-INF/lib/über%01.jar"]);
+}
+
+/// Two flagged names differing only in their invalid bytes stay apart
+#[test]
+fn invalid_utf8_names_stay_distinct() {
+    let dir = tempfile::tempdir().unwrap();
+    let jar = fixture_jar();
+    let war = with_raw_names(&[(b"WEB-INF/lib/\xFE.jar", true, &jar), (b"WEB-INF/lib/\xFF.jar", true, &jar)]);
+    let findings = findings(dir.path(), &[("bundle.war", war)], &[]);
+
+    let mut paths = paths(&findings);
+    paths.sort();
+    assert_eq!(paths, ["bundle.war!WEB-INF/lib/%FE.jar", "bundle.war!WEB-INF/lib/%FF.jar"]);
+}
+
+#[test]
+fn cp437_directories_do_not_hide_the_lookup_class() {
+    let dir = tempfile::tempdir().unwrap();
+    let entry = [b"\x81ber/".as_slice(), JNDI_LOOKUP_ENTRY].concat();
+    let findings = findings(dir.path(), &[("cp437.jar", with_raw_names(&[(&entry, false, CLASS_MAGIC)]))], &[]);
+
+    assert_eq!(findings, [("cp437.jar".to_string(), "log4j-jndi-lookup-entry".to_string())]);
+}
+
+/// UTF-8 written without the flag decodes as CP437, so the rule only
+/// matches it by its raw form
+#[test]
+fn unflagged_utf8_names_match_by_their_raw_form() {
+    let dir = tempfile::tempdir().unwrap();
+    let rules = dir.path().join("unflagged-utf8.json");
+    fs::write(&rules, UNFLAGGED_UTF8_RULES).unwrap();
+    let scan_dir = dir.path().join("scan");
+    fs::create_dir(&scan_dir).unwrap();
+    let jar = with_raw_names(&[("café/Exploit.class".as_bytes(), false, CLASS_MAGIC)]);
+    let findings = findings(&scan_dir, &[("unflagged.jar", jar)], &["--rules", rules.to_str().unwrap()]);
+
+    assert_eq!(findings, [("unflagged.jar".to_string(), "cafe-exploit".to_string())]);
+}
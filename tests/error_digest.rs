@@ -0,0 +1,71 @@
+use serde_json::{json, Value};
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+const SCANNER: &str = env!("CARGO_BIN_EXE_cve_2021_44228_scanner");
+
+fn fixture(name: &str) -> Vec<u8> {
+    fs::read(Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures").join(name)).unwrap()
+}
+
+/// Five truncated jars in one directory and one in another are counted in
+/// the digest, with the first three paths as examples. Only the first three
+/// warnings of a directory are logged, then the digest line.
+#[test]
+fn truncated_jars_are_counted_in_the_digest() {
+    let dir = tempfile::tempdir().unwrap();
+    let broken = dir.path().join("broken");
+    fs::create_dir(&broken).unwrap();
+    for i in 1..=5 {
+        fs::write(broken.join(format!("t{}.jar", i)), fixture("truncated-fixture.jar")).unwrap();
+    }
+    fs::write(dir.path().join("other.jar"), fixture("truncated-fixture.jar")).unwrap();
+
+    let output = Command::new(SCANNER)
+        .arg("--path").arg(dir.path())
+        .args(["--format", "json"])
+        .env("RUST_LOG", "warn")
+        .output()
+        .unwrap();
+    let report: Value = serde_json::from_slice(&output.stdout).unwrap();
+    let example = |name: &str| broken.join(name).to_string_lossy().to_string();
+    assert_eq!(report["summary"]["error_digest"], json!([
+        {"kind": "invalid-archive", "count": 6, "examples": [example("t1.jar"), example("t2.jar"), example("t3.jar")]},
+    ]));
+
+    let log = String::from_utf8(output.stderr).unwrap();
+    let warned = |path: &Path| log.lines().filter(|line| line.contains(&format!("Error reading JAR file: {}", path.display()))).count();
+    assert_eq!((1..=5).map(|i| warned(&broken.join(format!("t{}.jar", i)))).sum::<usize>(), 3, "{}", log);
+    assert_eq!(warned(&dir.path().join("other.jar")), 1, "{}", log);
+    assert!(log.contains(&format!("6 files: invalid archive (examples: {}, {}, {})", example("t1.jar"), example("t2.jar"), example("t3.jar"))), "{}", log);
+}
+
+/// A directory the scan cannot read is counted as permission denied. Root
+/// reads it anyway, so there is nothing to count then.
+#[cfg(unix)]
+#[test]
+fn unreadable_directory_is_counted_in_the_digest() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = tempfile::tempdir().unwrap();
+    let locked = dir.path().join("locked");
+    fs::create_dir(&locked).unwrap();
+    fs::write(locked.join("log4j-core.jar"), fixture("log4j-fixture.jar")).unwrap();
+    fs::set_permissions(&locked, fs::Permissions::from_mode(0o000)).unwrap();
+    let enforced = fs::read_dir(&locked).is_err();
+
+    let output = Command::new(SCANNER)
+        .arg("--path").arg(dir.path())
+        .args(["--format", "json"])
+        .output()
+        .unwrap();
+    fs::set_permissions(&locked, fs::Permissions::from_mode(0o755)).unwrap();
+    let report: Value = serde_json::from_slice(&output.stdout).unwrap();
+    let digest = &report["summary"]["error_digest"];
+    if enforced {
+        assert_eq!(digest, &json!([{"kind": "permission-denied", "count": 1, "examples": [locked.to_string_lossy()]}]));
+    } else {
+        assert!(digest.is_null(), "{}", digest);
+    }
+}
@@ -0,0 +1,46 @@
+use std::fs;
+use std::io::{Cursor, Write};
+use std::path::Path;
+use std::process::Command;
+use std::time::{Duration, Instant};
+use zip::write::FileOptions;
+use zip::{CompressionMethod, ZipWriter};
+
+const SCANNER: &str = env!("CARGO_BIN_EXE_cve_2021_44228_scanner");
+const MEGABYTE: usize = 1024 * 1024;
+
+/// A stored jar of one class entry of `size` bytes
+fn write_jar(path: &Path, size: usize) {
+    let mut jar = ZipWriter::new(Cursor::new(Vec::new()));
+    jar.start_file("com/example/Large.class", FileOptions::default().compression_method(CompressionMethod::Stored)).unwrap();
+    let mut class = vec![0xca, 0xfe, 0xba, 0xbe];
+    class.extend((0..size - 4).map(|i| (i % 251) as u8));
+    jar.write_all(&class).unwrap();
+    fs::write(path, jar.finish().unwrap().into_inner()).unwrap();
+}
+
+fn timed_scan(dir: &Path, args: &[&str]) -> Duration {
+    let started = Instant::now();
+    let status = Command::new(SCANNER)
+        .arg("--path").arg(dir)
+        .args(["--format", "json", "--output"]).arg(dir.join("report.json"))
+        .args(args)
+        .status()
+        .unwrap();
+    assert!(status.success());
+    started.elapsed()
+}
+
+/// The bucket starts with one second's worth of bytes, so at 1 MB/s a 3 MB
+/// jar takes about two seconds to read, where the unlimited scan is quick
+#[test]
+fn io_limit_slows_a_scan_to_the_rate() {
+    let dir = tempfile::tempdir().unwrap();
+    write_jar(&dir.path().join("large.jar"), 3 * MEGABYTE);
+
+    let unlimited = timed_scan(dir.path(), &[]);
+    let limited = timed_scan(dir.path(), &["--io-limit", "1"]);
+    assert!(unlimited < Duration::from_millis(1500), "{:?}", unlimited);
+    assert!(limited >= Duration::from_millis(1900), "{:?}", limited);
+    assert!(limited < Duration::from_secs(6), "{:?}", limited);
+}
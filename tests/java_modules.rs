@@ -0,0 +1,78 @@
+use serde_json::Value;
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+const SCANNER: &str = env!("CARGO_BIN_EXE_cve_2021_44228_scanner");
+const JIMAGE_RESOURCE: &str = "org.apache.logging.log4j.core/org/apache/logging/log4j/core/lookup/JndiLookup.class";
+
+fn fixture(name: &str) -> Vec<u8> {
+    fs::read(Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures").join(name)).unwrap()
+}
+
+/// The JSON report of a scan of `dir`
+fn scan(dir: &Path) -> Value {
+    let output = Command::new(SCANNER)
+        .arg("--path").arg(dir)
+        .args(["--format", "json"])
+        .output()
+        .unwrap();
+    serde_json::from_slice(&output.stdout).unwrap()
+}
+
+/// The results for `path`, as (status, rule id)
+fn results_for(report: &Value, path: &Path) -> Vec<(String, Option<String>)> {
+    let path = path.to_string_lossy();
+    report["results"].as_array().unwrap().iter()
+        .filter(|r| r["file_path"] == path.as_ref())
+        .map(|r| (r["status"].as_str().unwrap().to_string(), r["rule_id"].as_str().map(String::from)))
+        .collect()
+}
+
+/// The JndiLookup class under the module's `classes/` is matched like a jar entry
+#[test]
+fn jmod_finding_is_reported_for_the_module() {
+    let dir = tempfile::tempdir().unwrap();
+    let jmod = dir.path().join("log4j-fixture.jmod");
+    fs::write(&jmod, fixture("log4j-fixture.jmod")).unwrap();
+
+    let report = scan(dir.path());
+    assert_eq!(results_for(&report, &jmod), [("Vulnerable".to_string(), Some("log4j-jndi-lookup-entry".to_string()))]);
+}
+
+/// A `.jmod` without the `JM` header is not read as a zip
+#[test]
+fn jmod_without_its_header_is_skipped() {
+    let dir = tempfile::tempdir().unwrap();
+    let jmod = dir.path().join("broken.jmod");
+    fs::write(&jmod, fixture("log4j-fixture.jar")).unwrap();
+
+    let report = scan(dir.path());
+    let result = &report["results"][0];
+    assert_eq!(result["status"], "Skipped");
+    assert_eq!(result["reason"], "Skipped JMOD: missing JM header");
+}
+
+/// A runtime image is matched by the resource names it lists, each reported
+/// at its nested path in `lib/modules`
+#[test]
+fn jimage_resource_is_reported_at_its_nested_path() {
+    let dir = tempfile::tempdir().unwrap();
+    let lib = dir.path().join("jre").join("lib");
+    fs::create_dir_all(&lib).unwrap();
+    fs::write(lib.join("modules"), fixture("log4j-fixture.jimage")).unwrap();
+
+    let report = scan(dir.path());
+    let nested = lib.join(format!("modules!{}", JIMAGE_RESOURCE));
+    assert_eq!(results_for(&report, &nested), [("Vulnerable".to_string(), Some("log4j-jndi-lookup-entry".to_string()))]);
+}
+
+/// The same image outside a `lib` directory is not a runtime image
+#[test]
+fn jimage_is_only_read_as_lib_modules() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(dir.path().join("modules"), fixture("log4j-fixture.jimage")).unwrap();
+
+    let report = scan(dir.path());
+    assert!(report["results"].as_array().unwrap().iter().all(|r| r["vulnerable"] == false), "{}", report["results"]);
+}
@@ -0,0 +1,89 @@
+use std::fs;
+use std::io::{Cursor, Write};
+use std::path::Path;
+use std::process::Command;
+use zip::write::FileOptions;
+use zip::ZipWriter;
+
+const SCANNER: &str = env!("CARGO_BIN_EXE_cve_2021_44228_scanner");
+const JNDI_LOOKUP_ENTRY: &str = "org/apache/logging/log4j/core/lookup/JndiLookup.class";
+
+fn fixture(name: &str) -> Vec<u8> {
+    fs::read(Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures").join(name)).unwrap()
+}
+
+/// A shaded jar bundling JndiLookup with the `pom.properties` of log4j-core
+/// and of the application itself
+fn write_shaded_jar(path: &Path) {
+    let mut jar = ZipWriter::new(Cursor::new(Vec::new()));
+    for (name, contents) in [
+        ("META-INF/maven/com.example/shaded-app/pom.properties", b"#Generated by Maven\ngroupId=com.example\nartifactId=shaded-app\nversion=3.1.0\n".to_vec()),
+        ("META-INF/maven/org.apache.logging.log4j/log4j-core/pom.properties", b"version=2.14.1\ngroupId=org.apache.logging.log4j\nartifactId=log4j-core\n".to_vec()),
+        (JNDI_LOOKUP_ENTRY, fixture("JndiLookup.class")),
+    ] {
+        jar.start_file(name, FileOptions::default()).unwrap();
+        jar.write_all(&contents).unwrap();
+    }
+    fs::write(path, jar.finish().unwrap().into_inner()).unwrap();
+}
+
+fn scan(dir: &Path, args: &[&str]) -> Vec<u8> {
+    let output = Command::new(SCANNER)
+        .arg("--path").arg(dir)
+        .args(args)
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    output.stdout
+}
+
+/// Of the jar's two `pom.properties`, the one whose artifactId the file name
+/// holds names the finding, in the JSON report and in the text one
+#[test]
+fn finding_has_the_coordinates_of_its_jar() {
+    let dir = tempfile::tempdir().unwrap();
+    write_shaded_jar(&dir.path().join("shaded-app-3.1.0.jar"));
+
+    let report: serde_json::Value = serde_json::from_slice(&scan(dir.path(), &["--format", "json"])).unwrap();
+    let finding = &report["results"][0];
+    assert_eq!(finding["vulnerable"], true);
+    assert_eq!(finding["maven_coordinates"], serde_json::json!({
+        "group_id": "com.example",
+        "artifact_id": "shaded-app",
+        "version": "3.1.0",
+    }));
+
+    let text = String::from_utf8(scan(dir.path(), &["--no-table"])).unwrap();
+    assert!(text.contains("\n  Maven: com.example:shaded-app:3.1.0\n"), "{}", text);
+}
+
+#[test]
+fn fixture_jar_has_log4j_core_coordinates() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(dir.path().join("log4j-fixture.jar"), fixture("log4j-fixture.jar")).unwrap();
+
+    let report: serde_json::Value = serde_json::from_slice(&scan(dir.path(), &["--format", "json"])).unwrap();
+    assert_eq!(report["results"][0]["maven_coordinates"], serde_json::json!({
+        "group_id": "org.apache.logging.log4j",
+        "artifact_id": "log4j-core",
+        "version": "2.14.1",
+    }));
+}
+
+/// With the coordinates and the class version the report says which log4j
+/// release, compiled for which Java, was found, for jar entries and class files
+#[test]
+fn findings_name_the_java_release_they_were_compiled_for() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(dir.path().join("log4j-fixture.jar"), fixture("log4j-fixture.jar")).unwrap();
+    fs::write(dir.path().join("JndiLookup.class"), fixture("JndiLookup.class")).unwrap();
+
+    let report: serde_json::Value = serde_json::from_slice(&scan(dir.path(), &["--format", "json"])).unwrap();
+    let versions: Vec<(&str, &str)> = report["results"].as_array().unwrap().iter()
+        .map(|r| (Path::new(r["file_path"].as_str().unwrap()).file_name().unwrap().to_str().unwrap(), r["class_version"].as_str().unwrap()))
+        .collect();
+    assert_eq!(versions, [("JndiLookup.class", "Java 8 (52.0)"), ("log4j-fixture.jar", "Java 8 (52.0)")]);
+
+    let text = String::from_utf8(scan(dir.path(), &["--no-table"])).unwrap();
+    assert!(text.contains("\n  Maven: org.apache.logging.log4j:log4j-core:2.14.1\n  Compiled for: Java 8 (52.0)\n"), "{}", text);
+}
@@ -0,0 +1,81 @@
+use serde_json::Value;
+use std::fs;
+use std::path::Path;
+use std::process::{Command, Output};
+
+const SCANNER: &str = env!("CARGO_BIN_EXE_cve_2021_44228_scanner");
+
+/// Directories and class files per directory of the synthetic tree
+const TREE: (usize, usize) = (40, 50);
+const FILES: usize = TREE.0 * TREE.1;
+
+/// `TREE` of class files holding nothing but the class magic
+fn write_tree(root: &Path) {
+    let (dirs, files) = TREE;
+    for d in 0..dirs {
+        let sub = root.join(format!("dir{}", d));
+        fs::create_dir_all(&sub).unwrap();
+        for f in 0..files {
+            fs::write(sub.join(format!("File{}.class", f)), [0xCA, 0xFE, 0xBA, 0xBE]).unwrap();
+        }
+    }
+}
+
+fn scan(root: &Path, args: &[&str]) -> Output {
+    Command::new(SCANNER)
+        .arg("--path").arg(root)
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+/// A duration that has passed before the first file is handed out: the
+/// walk still lists every file, counting each as not reached, the report is
+/// marked partial and the scanner exits with 4
+#[test]
+fn tiny_duration_marks_the_report_partial() {
+    let dir = tempfile::tempdir().unwrap();
+    write_tree(dir.path());
+    let output = scan(dir.path(), &["--format", "json", "--max-duration", "0s", "--list-skipped"]);
+    assert_eq!(output.status.code(), Some(4), "{}", String::from_utf8_lossy(&output.stderr));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("files not reached; results are incomplete"));
+
+    let report: Value = serde_json::from_slice(&output.stdout).unwrap();
+    let summary = &report["summary"];
+    assert_eq!(summary["max_duration_reached"], true);
+    let coverage = &summary["coverage"];
+    let scanned = summary["files_scanned"].as_u64().unwrap() as usize;
+    let not_reached = coverage["skipped_by_reason"]["max-duration"].as_u64().unwrap() as usize;
+    assert_eq!(coverage["entries_walked"], FILES);
+    assert_eq!(scanned + not_reached, FILES);
+    assert!(not_reached > FILES / 2, "{} of {} not reached", not_reached, FILES);
+
+    let listed = coverage["skipped_entries"].as_array().unwrap();
+    assert_eq!(listed.iter().filter(|entry| entry["reason"] == "max-duration").count(), not_reached);
+}
+
+#[test]
+fn text_report_says_what_was_not_reached() {
+    let dir = tempfile::tempdir().unwrap();
+    write_tree(dir.path());
+    let output = scan(dir.path(), &["--no-table", "--max-duration", "0s"]);
+    assert_eq!(output.status.code(), Some(4));
+
+    let text = String::from_utf8(output.stdout).unwrap();
+    assert!(text.contains("- max-duration: "), "{}", text);
+    assert!(text.contains("not reached; results are incomplete"), "{}", text);
+}
+
+/// A window the scan fits in changes nothing
+#[test]
+fn scans_within_the_duration_are_complete() {
+    let dir = tempfile::tempdir().unwrap();
+    write_tree(dir.path());
+    let output = scan(dir.path(), &["--format", "json", "--max-duration", "1h"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+
+    let report: Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(report["summary"]["max_duration_reached"], false);
+    assert_eq!(report["summary"]["files_scanned"], FILES);
+    assert!(report["summary"]["coverage"]["skipped_by_reason"].get("max-duration").is_none());
+}
@@ -0,0 +1,38 @@
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+const SCANNER: &str = env!("CARGO_BIN_EXE_cve_2021_44228_scanner");
+
+fn fixture(name: &str) -> Vec<u8> {
+    fs::read(Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures").join(name)).unwrap()
+}
+
+/// Five encrypted jars sorting before the one vulnerable jar must not use up
+/// `--max-findings 1`
+#[test]
+fn skipped_files_do_not_hide_findings() {
+    let dir = tempfile::tempdir().unwrap();
+    for i in 1..=5 {
+        fs::write(dir.path().join(format!("a{}.jar", i)), fixture("encrypted-fixture.jar")).unwrap();
+    }
+    fs::write(dir.path().join("z.jar"), fixture("log4j-fixture.jar")).unwrap();
+    let report = dir.path().join("report.json");
+
+    let status = Command::new(SCANNER)
+        .arg("--path").arg(dir.path())
+        .args(["--max-findings", "1", "--threads", "1", "--format", "json", "--output"])
+        .arg(&report)
+        .status()
+        .unwrap();
+    assert!(status.success());
+
+    let report: serde_json::Value = serde_json::from_slice(&fs::read(&report).unwrap()).unwrap();
+    let results = report["results"].as_array().unwrap();
+    let vulnerable: Vec<&str> = results.iter()
+        .filter(|r| r["status"] == "Vulnerable")
+        .map(|r| r["file_path"].as_str().unwrap())
+        .collect();
+    assert_eq!(vulnerable, [dir.path().join("z.jar").to_string_lossy()]);
+    assert!(results.iter().all(|r| r["status"] == "Vulnerable" || r["status"] == "Skipped"));
+}
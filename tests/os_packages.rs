@@ -0,0 +1,85 @@
+use serde_json::Value;
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+const SCANNER: &str = env!("CARGO_BIN_EXE_cve_2021_44228_scanner");
+
+fn fixture(name: &str) -> Vec<u8> {
+    fs::read(Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures").join(name)).unwrap()
+}
+
+/// An `ar` archive of the members, as a Debian package is laid out
+fn ar_of(members: &[(&str, &[u8])]) -> Vec<u8> {
+    let mut archive = b"!<arch>\n".to_vec();
+    for (name, contents) in members {
+        archive.extend(format!("{:<16}{:<12}{:<6}{:<6}{:<8}{:<10}`\n", name, 0, 0, 0, 100644, contents.len()).as_bytes());
+        archive.extend(*contents);
+        if contents.len() % 2 == 1 {
+            archive.push(b'\n');
+        }
+    }
+    archive
+}
+
+/// Every result of a JSON report, as (file path, status, reason, CVE)
+fn results(dir: &Path) -> Vec<(String, String, String, Option<String>)> {
+    let output = Command::new(SCANNER)
+        .arg("--path").arg(dir)
+        .args(["--format", "json"])
+        .output()
+        .unwrap();
+    let report: Value = serde_json::from_slice(&output.stdout).unwrap();
+    let mut results: Vec<_> = report["results"].as_array().unwrap().iter()
+        .map(|r| (
+            r["file_path"].as_str().unwrap().to_string(),
+            r["status"].as_str().unwrap().to_string(),
+            r["reason"].as_str().unwrap_or_default().to_string(),
+            r["cve"].as_str().map(String::from),
+        ))
+        .collect();
+    results.sort();
+    results
+}
+
+/// The jar bundled in an RPM's cpio payload and in a Debian package's
+/// `data.tar` is reported at its path inside the package
+#[test]
+fn jar_bundled_in_an_os_package_is_found() {
+    let dir = tempfile::tempdir().unwrap();
+    for package in ["log4j-fixture.rpm", "log4j-fixture.deb"] {
+        fs::write(dir.path().join(package), fixture(package)).unwrap();
+    }
+
+    let results = results(dir.path());
+    let nested = |package: &str| dir.path().join(format!("{}!usr/share/java/log4j-fixture.jar", package)).to_string_lossy().to_string();
+    assert_eq!(results.iter().map(|(path, status, _, cve)| (path.as_str(), status.as_str(), cve.as_deref())).collect::<Vec<_>>(), [
+        (nested("log4j-fixture.deb").as_str(), "Vulnerable", Some("CVE-2021-44228")),
+        (nested("log4j-fixture.rpm").as_str(), "Vulnerable", Some("CVE-2021-44228")),
+    ]);
+    assert!(results.iter().all(|(_, _, reason, _)| reason.contains("JndiLookup")));
+}
+
+/// A package compressed with something the scanner cannot read is skipped
+/// with the reason, rather than passing as clean
+#[test]
+fn package_with_unsupported_compression_is_skipped() {
+    let dir = tempfile::tempdir().unwrap();
+    // The fixture RPM's headers, followed by an lz4 frame instead of its gzip payload
+    let rpm = fixture("log4j-fixture.rpm");
+    let payload_start = rpm.windows(3).position(|window| window == b"\x1f\x8b\x08").unwrap();
+    let mut lz4 = rpm[..payload_start].to_vec();
+    lz4.extend(b"\x04\x22\x4d\x18");
+    lz4.extend([0; 64]);
+    fs::write(dir.path().join("lz4.rpm"), lz4).unwrap();
+    fs::write(dir.path().join("lzma.deb"), ar_of(&[
+        ("debian-binary", b"2.0\n"),
+        ("data.tar.lzma", b"\x5d\x00\x00\x80\x00\xff\xff\xff\xff"),
+    ])).unwrap();
+
+    let path = |name: &str| dir.path().join(name).to_string_lossy().to_string();
+    assert_eq!(results(dir.path()), [
+        (path("lz4.rpm"), "Skipped".to_string(), "Skipped package: unsupported RPM payload compression".to_string(), None),
+        (path("lzma.deb"), "Skipped".to_string(), "Skipped package: unsupported data archive compression: data.tar.lzma".to_string(), None),
+    ]);
+}
@@ -0,0 +1,73 @@
+use cve_2021_44228_scanner::schema;
+use flate2::read::GzDecoder;
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::Path;
+use std::process::{Command, Output};
+
+const SCANNER: &str = env!("CARGO_BIN_EXE_cve_2021_44228_scanner");
+
+fn fixtures() -> &'static Path {
+    Path::new(concat!(env!("CARGO_MANIFEST_DIR"), "/fixtures"))
+}
+
+fn scan(output: &Path, args: &[&str]) -> Output {
+    Command::new(SCANNER)
+        .arg("--path").arg(fixtures())
+        .arg("--output").arg(output)
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+fn gunzip(path: &Path) -> String {
+    let mut contents = String::new();
+    GzDecoder::new(File::open(path).unwrap()).read_to_string(&mut contents).unwrap();
+    contents
+}
+
+/// The `.gz` name is added, and what `GzDecoder` reads back is the whole report
+#[test]
+fn compressed_report_is_valid_gzip_of_the_report() {
+    let dir = tempfile::tempdir().unwrap();
+    let output = dir.path().join("report.json");
+    assert!(scan(&output, &["--format", "json", "--output-compress"]).status.success());
+    assert!(!output.exists());
+
+    let report: serde_json::Value = serde_json::from_str(&gunzip(&dir.path().join("report.json.gz"))).unwrap();
+    assert!(schema::validate_report(&report).is_empty(), "{:?}", schema::validate_report(&report));
+    assert!(report["results"].as_array().unwrap().iter().any(|r| r["vulnerable"] == true));
+
+    let output = dir.path().join("named.json.gz");
+    assert!(scan(&output, &["--format", "json", "--output-compress"]).status.success());
+    assert!(!dir.path().join("named.json.gz.gz").exists());
+    serde_json::from_str::<serde_json::Value>(&gunzip(&output)).unwrap();
+}
+
+#[test]
+fn every_level_decodes_to_the_same_report() {
+    let dir = tempfile::tempdir().unwrap();
+    let reports: Vec<(u64, String)> = ["0", "6", "9"].iter()
+        .map(|level| {
+            let output = dir.path().join(format!("report-{}.txt.gz", level));
+            let status = scan(&output, &["--no-table", "--output-compress", "--output-compress-level", level]).status;
+            assert!(status.success());
+            (fs::metadata(&output).unwrap().len(), gunzip(&output))
+        })
+        .collect();
+    // The text report's own lines, less the timings that differ run to run
+    let stable = |report: &str| report.lines().filter(|line| !line.contains("Modified:") && !line.contains("Duration")).map(String::from).collect::<Vec<_>>();
+    assert!(reports[0].1.contains("Vulnerable files found:"), "{}", reports[0].1);
+    assert_eq!(stable(&reports[0].1), stable(&reports[2].1));
+    assert!(reports[0].0 > reports[2].0, "level 0 is {} bytes, level 9 {}", reports[0].0, reports[2].0);
+}
+
+#[test]
+fn compress_needs_a_plain_output_file() {
+    let dir = tempfile::tempdir().unwrap();
+    let output = dir.path().join("report.json");
+    assert!(!scan(&output, &["--format", "json", "--output-compress", "--output-rotate", "1"]).status.success());
+    assert!(!scan(&output, &["--output-compress", "--output-compress-level", "10"]).status.success());
+    let output = Command::new(SCANNER).arg("--path").arg(fixtures()).arg("--output-compress").output().unwrap();
+    assert!(!output.status.success());
+}
@@ -0,0 +1,144 @@
+use serde_json::Value;
+use std::fs;
+use std::path::Path;
+use std::process::{Command, Output};
+
+const SCANNER: &str = env!("CARGO_BIN_EXE_cve_2021_44228_scanner");
+/// Formats that list findings only, whatever the output level
+const FINDINGS_FORMATS: [&str; 4] = ["checkstyle", "stix", "text-stable", "teamcity"];
+
+fn fixture(name: &str) -> Vec<u8> {
+    fs::read(Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures").join(name)).unwrap()
+}
+
+/// A directory with a vulnerable jar, an encrypted jar, which is skipped, a
+/// truncated jar, which is an error and logs a warning, and a clean class
+fn scan_dir() -> tempfile::TempDir {
+    let dir = tempfile::tempdir().unwrap();
+    for name in ["log4j-fixture.jar", "encrypted-fixture.jar", "truncated-fixture.jar"] {
+        fs::write(dir.path().join(name), fixture(name)).unwrap();
+    }
+    fs::write(dir.path().join("Clean.class"), b"\xca\xfe\xba\xbe clean").unwrap();
+    dir
+}
+
+fn scan(dir: &Path, args: &[&str]) -> Output {
+    let output = Command::new(SCANNER)
+        .arg("--path").arg(dir)
+        .args(args)
+        .env("RUST_LOG", "info")
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    output
+}
+
+fn stdout(output: &Output) -> String {
+    String::from_utf8(output.stdout.clone()).unwrap()
+}
+
+fn stderr(output: &Output) -> String {
+    String::from_utf8(output.stderr.clone()).unwrap()
+}
+
+/// The statuses of the results a JSON report lists
+fn statuses(output: &Output) -> Vec<String> {
+    let report: Value = serde_json::from_slice(&output.stdout).unwrap();
+    let mut statuses: Vec<String> = report["results"].as_array().unwrap().iter()
+        .map(|r| r["status"].as_str().unwrap().to_string())
+        .collect();
+    statuses.sort();
+    statuses
+}
+
+#[test]
+fn normal_text_report_has_the_summary_and_info_logs() {
+    let dir = scan_dir();
+    let output = scan(dir.path(), &["--no-table"]);
+    let text = stdout(&output);
+    assert!(text.starts_with("Scan Results:\n"), "{}", text);
+    assert!(text.contains("\nSummary:\n"), "{}", text);
+    assert!(text.contains("log4j-fixture.jar"), "{}", text);
+    assert!(stderr(&output).contains(" INFO "), "{}", stderr(&output));
+    assert!(stderr(&output).contains(" WARN "), "{}", stderr(&output));
+}
+
+/// `--quiet` drops the summary and info logs, keeping the findings and warnings
+#[test]
+fn quiet_text_report_lists_only_findings() {
+    let dir = scan_dir();
+    let output = scan(dir.path(), &["--quiet", "--no-table"]);
+    let text = stdout(&output);
+    assert!(!text.contains("Scan Results:") && !text.contains("Summary:"), "{}", text);
+    assert!(text.contains("\nVulnerable Files:\n- "), "{}", text);
+    assert!(text.contains("log4j-fixture.jar") && !text.contains("encrypted-fixture.jar"), "{}", text);
+    assert!(!stderr(&output).contains(" INFO "), "{}", stderr(&output));
+    assert!(stderr(&output).contains(" WARN "), "{}", stderr(&output));
+}
+
+/// `--silent` with a human-readable format leaves the exit code as the only signal
+#[test]
+fn silent_text_and_table_reports_print_nothing() {
+    let dir = scan_dir();
+    for format in ["text", "table"] {
+        let output = scan(dir.path(), &["--silent", "--format", format]);
+        assert_eq!(stdout(&output), "", "{}", format);
+        assert_eq!(stderr(&output), "", "{}", format);
+    }
+}
+
+/// A silent scan still writes the text report to `--output`
+#[test]
+fn silent_text_report_is_still_written_to_output() {
+    let dir = scan_dir();
+    let output_dir = tempfile::tempdir().unwrap();
+    let report = output_dir.path().join("report.txt");
+    scan(dir.path(), &["--silent", "--no-table", "--output", report.to_str().unwrap()]);
+    assert!(fs::read_to_string(&report).unwrap().contains("log4j-fixture.jar"));
+}
+
+/// The JSON report lists every result normally, and only findings under
+/// `--quiet` and `--silent`, which still print it
+#[test]
+fn json_report_lists_only_findings_when_quiet_or_silent() {
+    let dir = scan_dir();
+    assert_eq!(statuses(&scan(dir.path(), &["--format", "json"])), ["Error", "Skipped", "Vulnerable"]);
+    for level in ["--quiet", "--silent"] {
+        let output = scan(dir.path(), &[level, "--format", "json"]);
+        assert_eq!(statuses(&output), ["Vulnerable"], "{}", level);
+        let report: Value = serde_json::from_slice(&output.stdout).unwrap();
+        assert_eq!(report["summary"]["files_scanned"], 4, "{}", level);
+    }
+    assert_eq!(stderr(&scan(dir.path(), &["--silent", "--format", "json"])), "");
+}
+
+#[test]
+fn msgpack_report_lists_only_findings_when_quiet() {
+    let dir = scan_dir();
+    let count = |args: &[&str]| rmp_serde::from_slice::<Vec<Value>>(&scan(dir.path(), args).stdout).unwrap().len();
+    assert_eq!(count(&["--format", "msgpack"]), 3);
+    assert_eq!(count(&["--quiet", "--format", "msgpack"]), 1);
+    assert_eq!(count(&["--silent", "--format", "msgpack"]), 1);
+}
+
+/// Formats that only ever list findings print the same report at every level
+#[test]
+fn findings_reports_are_the_same_at_every_level() {
+    let dir = scan_dir();
+    for format in FINDINGS_FORMATS {
+        let normal = stdout(&scan(dir.path(), &["--format", format]));
+        assert!(normal.contains("log4j-fixture.jar"), "{}: {}", format, normal);
+        for level in ["--quiet", "--silent"] {
+            assert_eq!(stdout(&scan(dir.path(), &[level, "--format", format])), normal, "{} {}", format, level);
+        }
+    }
+}
+
+/// The `table` format lists only findings, so `--quiet` leaves it as it is
+#[test]
+fn table_report_is_the_same_when_quiet() {
+    let dir = scan_dir();
+    let normal = stdout(&scan(dir.path(), &["--format", "table"]));
+    assert!(normal.contains("log4j-fixture.jar"), "{}", normal);
+    assert_eq!(stdout(&scan(dir.path(), &["--quiet", "--format", "table"])), normal);
+}
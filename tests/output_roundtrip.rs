@@ -0,0 +1,361 @@
+use cve_2021_44228_scanner::config::{Config, OutputLevel};
+use cve_2021_44228_scanner::scanner::{scan_directory, sort_results, Confidence, ScanResult, ScanStatus, ScanSummary, Severity};
+use cve_2021_44228_scanner::{reporter, schema};
+use jsonschema::JSONSchema;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::sync::OnceLock;
+use uuid::Uuid;
+
+/// The fixtures scanned once for every test: scans share process-wide
+/// counters, so concurrent scans would mix their summaries
+fn scan() -> &'static (Vec<ScanResult>, ScanSummary) {
+    static SCAN: OnceLock<(Vec<ScanResult>, ScanSummary)> = OnceLock::new();
+    SCAN.get_or_init(|| {
+        let config = Config {
+            path: Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures").to_string_lossy().to_string(),
+            output_level: OutputLevel::Quiet,
+            ..Default::default()
+        };
+        let (mut results, mut summary) = scan_directory(&config).unwrap();
+        sort_results(&mut results, &mut summary);
+        assert!(results.iter().any(|r| r.vulnerable), "the fixtures have findings");
+        assert!(results.iter().any(|r| !r.vulnerable), "the fixtures have skipped and corrupted files");
+        (results, summary)
+    })
+}
+
+/// Results as JSON values, to compare field by field; the metrics can be NaN,
+/// which is never equal to itself
+fn values(results: &[ScanResult]) -> Vec<Value> {
+    results.iter().map(|r| serde_json::to_value(r).unwrap()).collect()
+}
+
+fn compile(schema: &str) -> JSONSchema {
+    JSONSchema::compile(&serde_json::from_str(schema).unwrap()).unwrap()
+}
+
+fn assert_valid(schema: &JSONSchema, instance: &Value) {
+    if let Err(errors) = schema.validate(instance) {
+        let errors: Vec<String> = errors.map(|e| format!("{} at {}", e, e.instance_path)).collect();
+        panic!("invalid document: {}", errors.join("; "));
+    }
+}
+
+#[test]
+fn json_report_round_trips() {
+    let (results, summary) = scan();
+    let report = reporter::json_report_string(results, summary, &Config::default()).unwrap();
+    let report: Value = serde_json::from_str(&report).unwrap();
+    assert_eq!(report["schema_version"], schema::SCHEMA_VERSION);
+
+    let decoded: Vec<ScanResult> = serde_json::from_value(report["results"].clone()).unwrap();
+    assert_eq!(values(&decoded), values(results));
+}
+
+#[test]
+fn json_report_matches_its_schema() {
+    let (results, summary) = scan();
+    let report = reporter::json_report_string(results, summary, &Config::default()).unwrap();
+    assert_valid(&compile(schema::REPORT_SCHEMA), &serde_json::from_str(&report).unwrap());
+}
+
+/// Each digest is of the whole file, also for a finding in an archive entry
+#[test]
+fn digests_describe_the_whole_file() {
+    use sha2::{Digest, Sha256};
+    use sha3::Sha3_256;
+
+    let (results, _) = scan();
+    // The JndiLookup class entry of a jar on disk
+    let finding = results.iter()
+        .find(|r| r.vulnerable && r.file_path.ends_with("log4j-fixture.jar") && !r.file_path.contains('!'))
+        .expect("the fixture jar has a finding");
+    let contents = fs::read(&finding.file_path).unwrap();
+    assert_eq!(finding.file_hash.as_deref(), Some(format!("{:x}", Sha256::digest(&contents)).as_str()));
+    assert_eq!(finding.sha3_hash.as_deref(), Some(format!("{:x}", Sha3_256::digest(&contents)).as_str()));
+    assert_eq!(finding.blake3_hash.as_deref(), Some(blake3::hash(&contents).to_hex().as_str()));
+}
+
+/// `msgpack` is a MessagePack array of results, and `msgpack-base64` the
+/// same bytes in base64 on one line
+#[test]
+fn msgpack_reports_round_trip() {
+    use base64::Engine;
+
+    let (results, summary) = scan();
+    let dir = tempfile::tempdir().unwrap();
+    for format in ["msgpack", "msgpack-base64"] {
+        let output = dir.path().join(format!("report.{}", format));
+        let config = Config {
+            format: format.to_string(),
+            output: Some(output.to_string_lossy().to_string()),
+            ..Default::default()
+        };
+        reporter::report_results(results, summary, &config).unwrap();
+
+        let mut report = fs::read(&output).unwrap();
+        if format == "msgpack-base64" {
+            let text = String::from_utf8(report).unwrap();
+            report = base64::engine::general_purpose::STANDARD.decode(text.trim_end()).unwrap();
+        }
+        let decoded: Vec<ScanResult> = rmp_serde::from_read(report.as_slice()).unwrap();
+        assert_eq!(values(&decoded), values(results), "{}", format);
+    }
+}
+
+#[test]
+fn yaml_round_trips() {
+    let (results, _) = scan();
+    let yaml = serde_yaml::to_string(results).unwrap();
+    let decoded: Vec<ScanResult> = serde_yaml::from_str(&yaml).unwrap();
+    assert_eq!(values(&decoded), values(results));
+}
+
+/// The scalar fields of a result; CSV has no nesting, so the coordinates,
+/// metrics and other structured fields are left out
+#[derive(Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+struct Row {
+    file_path: String,
+    inner_path: Option<String>,
+    vulnerable: bool,
+    status: ScanStatus,
+    reason: Option<String>,
+    severity: Option<Severity>,
+    confidence: Option<Confidence>,
+    cve: Option<String>,
+    rule_id: Option<String>,
+    file_hash: Option<String>,
+    file_size: u64,
+    modified: Option<String>,
+}
+
+impl From<&ScanResult> for Row {
+    fn from(result: &ScanResult) -> Self {
+        Row {
+            file_path: result.file_path.clone(),
+            inner_path: result.inner_path.clone(),
+            vulnerable: result.vulnerable,
+            status: result.status,
+            reason: result.reason.clone(),
+            severity: result.severity.clone(),
+            confidence: result.confidence,
+            cve: result.cve.clone(),
+            rule_id: result.rule_id.clone(),
+            file_hash: result.file_hash.clone(),
+            file_size: result.file_size,
+            modified: result.modified.clone(),
+        }
+    }
+}
+
+#[test]
+fn csv_round_trips() {
+    let (results, _) = scan();
+    let rows: Vec<Row> = results.iter().map(Row::from).collect();
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    for row in &rows {
+        writer.serialize(row).unwrap();
+    }
+    let csv = writer.into_inner().unwrap();
+
+    let decoded: Vec<Row> = csv::Reader::from_reader(csv.as_slice()).deserialize().collect::<Result<_, _>>().unwrap();
+    assert_eq!(decoded, rows);
+}
+
+/// Check a report against Checkstyle's XML schema: a `checkstyle` root of
+/// `file` elements, each holding `error` elements with a line, a severity
+/// Checkstyle knows and a message
+fn assert_checkstyle_schema(report: &str) {
+    let document = roxmltree::Document::parse(report).unwrap();
+    let root = document.root_element();
+    assert_eq!(root.tag_name().name(), "checkstyle");
+    assert!(root.attributes().all(|a| a.name() == "version"), "{:?}", root);
+    for file in root.children().filter(|node| node.is_element()) {
+        assert_eq!(file.tag_name().name(), "file");
+        assert!(file.attribute("name").is_some_and(|name| !name.is_empty()), "{:?}", file);
+        assert!(file.attributes().all(|a| a.name() == "name"), "{:?}", file);
+        for error in file.children().filter(|node| node.is_element()) {
+            assert_eq!(error.tag_name().name(), "error");
+            assert!(error.attribute("line").is_some_and(|line| line.parse::<u32>().is_ok()), "{:?}", error);
+            assert!(error.attribute("column").is_none_or(|column| column.parse::<u32>().is_ok()), "{:?}", error);
+            assert!(matches!(error.attribute("severity"), Some("ignore" | "info" | "warning" | "error")), "{:?}", error);
+            assert!(error.attribute("message").is_some(), "{:?}", error);
+            assert!(error.attributes().all(|a| matches!(a.name(), "line" | "column" | "severity" | "message" | "source")), "{:?}", error);
+        }
+    }
+}
+
+fn checkstyle_report(results: &[ScanResult], summary: &ScanSummary) -> String {
+    let dir = tempfile::tempdir().unwrap();
+    let output = dir.path().join("report.xml");
+    let config = Config {
+        format: "checkstyle".to_string(),
+        output: Some(output.to_string_lossy().to_string()),
+        ..Default::default()
+    };
+    reporter::report_results(results, summary, &config).unwrap();
+    fs::read_to_string(&output).unwrap()
+}
+
+#[test]
+fn checkstyle_report_matches_the_checkstyle_schema() {
+    let (results, summary) = scan();
+    let report = checkstyle_report(results, summary);
+    assert_checkstyle_schema(&report);
+
+    let document = roxmltree::Document::parse(&report).unwrap();
+    let errors = document.descendants().filter(|node| node.has_tag_name("error")).count();
+    assert_eq!(errors, results.iter().filter(|r| r.vulnerable).count());
+}
+
+/// Each finding is an `error` of its file, with the severity mapped to
+/// Checkstyle's, and markup in paths and reasons escaped
+#[test]
+fn checkstyle_report_lists_each_finding_of_a_file() {
+    let results: Vec<ScanResult> = serde_json::from_str(r#"[
+        {"file_path": "/opt/<app> & co/log4j-core.jar", "vulnerable": true, "status": "Vulnerable",
+         "reason": "Vulnerable entry found: JndiLookup", "severity": "Critical", "cve": "CVE-2021-44228",
+         "file_size": 1, "entropy": 0.0, "fourier_coefficient": [0.0, 0.0], "markov_probability": 0.0},
+        {"file_path": "/opt/<app> & co/log4j-core.jar", "vulnerable": true, "status": "Vulnerable",
+         "reason": "Suspicious compression ratio \"1000\"", "severity": "Low",
+         "file_size": 1, "entropy": 0.0, "fourier_coefficient": [0.0, 0.0], "markov_probability": 0.0},
+        {"file_path": "/etc/log4j2.xml", "vulnerable": true, "status": "Vulnerable",
+         "reason": "log4j configuration: JMS appender at line 3", "severity": "Medium",
+         "file_size": 1, "entropy": 0.0, "fourier_coefficient": [0.0, 0.0], "markov_probability": 0.0},
+        {"file_path": "/opt/encrypted.jar", "vulnerable": false, "status": "Skipped",
+         "reason": "encrypted entries — cannot scan", "severity": "Medium",
+         "file_size": 1, "entropy": 0.0, "fourier_coefficient": [0.0, 0.0], "markov_probability": 0.0}
+    ]"#).unwrap();
+    let report = checkstyle_report(&results, &ScanSummary::default());
+    assert_checkstyle_schema(&report);
+
+    let document = roxmltree::Document::parse(&report).unwrap();
+    let files: Vec<(&str, Vec<(&str, &str)>)> = document.root_element().children()
+        .filter(|node| node.is_element())
+        .map(|file| (
+            file.attribute("name").unwrap(),
+            file.children().filter(|node| node.is_element()).map(|error| (error.attribute("severity").unwrap(), error.attribute("message").unwrap())).collect(),
+        ))
+        .collect();
+    assert_eq!(files, [
+        ("/opt/<app> & co/log4j-core.jar", vec![
+            ("error", "Vulnerable entry found: JndiLookup (CVE-2021-44228)"),
+            ("info", "Suspicious compression ratio \"1000\""),
+        ]),
+        ("/etc/log4j2.xml", vec![("warning", "log4j configuration: JMS appender at line 3")]),
+    ]);
+}
+
+fn stix_report(results: &[ScanResult], summary: &ScanSummary) -> Value {
+    let dir = tempfile::tempdir().unwrap();
+    let output = dir.path().join("report.stix.json");
+    let config = Config {
+        format: "stix".to_string(),
+        output: Some(output.to_string_lossy().to_string()),
+        ..Default::default()
+    };
+    reporter::report_results(results, summary, &config).unwrap();
+    serde_json::from_slice(&fs::read(&output).unwrap()).unwrap()
+}
+
+/// Objects of a STIX bundle by identifier
+fn stix_objects(bundle: &Value) -> HashMap<&str, &Value> {
+    bundle["objects"].as_array().unwrap().iter().map(|object| (object["id"].as_str().unwrap(), object)).collect()
+}
+
+#[test]
+fn stix_report_is_a_bundle_of_deterministic_objects() {
+    let (results, summary) = scan();
+    let bundle = stix_report(results, summary);
+    assert_eq!(bundle, stix_report(results, summary), "repeated scans produce the same bundle");
+    assert_eq!(bundle["type"], "bundle");
+    assert!(bundle["id"].as_str().unwrap().starts_with("bundle--"));
+
+    let objects = bundle["objects"].as_array().unwrap();
+    assert_eq!(stix_objects(&bundle).len(), objects.len(), "identifiers are unique");
+    for object in objects {
+        assert_eq!(object["spec_version"], "2.1");
+        let id = object["id"].as_str().unwrap();
+        let (prefix, uuid) = id.split_once("--").unwrap();
+        assert_eq!(prefix, object["type"].as_str().unwrap());
+        assert_eq!(Uuid::parse_str(uuid).unwrap().get_version_num(), 5, "{} is a UUIDv5", id);
+    }
+
+    // A hashed file is identified as the specification derives SCO identifiers
+    let sco_namespace = Uuid::parse_str("00abedb4-aa42-466c-9c01-fed23315a9b7").unwrap();
+    let files: Vec<&Value> = objects.iter().filter(|object| object["type"] == "file").collect();
+    let hashed: Vec<&&Value> = files.iter().filter(|file| file["hashes"].is_object()).collect();
+    assert!(!hashed.is_empty());
+    for file in hashed {
+        let identity = serde_json::json!({ "hashes": { "SHA-256": file["hashes"]["SHA-256"] } });
+        assert_eq!(file["id"], format!("file--{}", Uuid::new_v5(&sco_namespace, identity.to_string().as_bytes())));
+    }
+}
+
+/// Every finding with a CVE is related to its vulnerability through the
+/// `file` of the matched artifact, and an archive holding it contains that file
+#[test]
+fn stix_relationships_link_artifacts_to_their_vulnerabilities() {
+    let (results, summary) = scan();
+    let bundle = stix_report(results, summary);
+    let objects = stix_objects(&bundle);
+    let relationships: Vec<(&Value, &Value)> = objects.values()
+        .filter(|object| object["type"] == "relationship")
+        .map(|relationship| {
+            assert_eq!(relationship["relationship_type"], "related-to");
+            let source = objects[relationship["source_ref"].as_str().unwrap()];
+            let target = objects[relationship["target_ref"].as_str().unwrap()];
+            assert_eq!(source["type"], "file");
+            assert_eq!(target["type"], "vulnerability");
+            (source, target)
+        })
+        .collect();
+
+    let holds = |file: &Value, path: &str| file["x_rustylog4jguard_paths"].as_array().unwrap().iter().any(|p| p == path);
+    let findings: Vec<&ScanResult> = results.iter().filter(|r| r.vulnerable && r.cve.is_some()).collect();
+    assert!(findings.iter().any(|r| r.file_path.contains('!')), "the fixtures have findings in nested archives");
+    for finding in findings {
+        let (artifact, _) = relationships.iter()
+            .find(|(file, vulnerability)| holds(file, &finding.file_path) && vulnerability["name"] == finding.cve.as_deref().unwrap())
+            .unwrap_or_else(|| panic!("no relationship for {}", finding.file_path));
+        assert_eq!(artifact["hashes"]["SHA-256"], finding.file_hash.as_deref().unwrap());
+        assert_eq!(artifact["size"], finding.file_size);
+        // Identical copies are one file, named after the first path found
+        let name = artifact["name"].as_str().unwrap();
+        assert!(artifact["x_rustylog4jguard_paths"].as_array().unwrap().iter().any(|path| path.as_str().unwrap().ends_with(name)));
+
+        if let Some((archive_path, _)) = finding.file_path.split_once('!') {
+            let archive = objects.values()
+                .find(|object| object["type"] == "file" && holds(object, archive_path))
+                .unwrap_or_else(|| panic!("no archive for {}", finding.file_path));
+            assert!(archive["name"].as_str().is_some_and(|name| archive_path.ends_with(name)));
+            assert!(archive.get("hashes").is_none(), "the archive is not described by the entry's hashes");
+            assert!(archive["extensions"]["archive-ext"]["contains_refs"].as_array().unwrap().contains(&artifact["id"]));
+        }
+    }
+}
+
+/// `--encoding utf16le` reports start with the UTF-16LE byte order mark,
+/// followed by the same report in UTF-16LE
+#[test]
+fn utf16le_reports_start_with_a_bom() {
+    let (results, summary) = scan();
+    let dir = tempfile::tempdir().unwrap();
+    let output = dir.path().join("report.json");
+    let config = Config {
+        format: "json".to_string(),
+        encoding: "utf16le".to_string(),
+        output: Some(output.to_string_lossy().to_string()),
+        ..Default::default()
+    };
+    reporter::report_results(results, summary, &config).unwrap();
+
+    let report = fs::read(&output).unwrap();
+    assert_eq!(report[..2], [0xFF, 0xFE]);
+    let units: Vec<u16> = report[2..].chunks_exact(2).map(|pair| u16::from_le_bytes([pair[0], pair[1]])).collect();
+    let report: Value = serde_json::from_str(&String::from_utf16(&units).unwrap()).unwrap();
+    assert_eq!(report["schema_version"], schema::SCHEMA_VERSION);
+}
@@ -0,0 +1,80 @@
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+const SCANNER: &str = env!("CARGO_BIN_EXE_cve_2021_44228_scanner");
+
+/// The configuration `args` resolve to, as `--print-config` prints it, with
+/// `config_file` written for `--config` when given
+fn resolved(dir: &Path, config_file: Option<&str>, args: &[&str]) -> toml::Table {
+    let mut command = Command::new(SCANNER);
+    if let Some(contents) = config_file {
+        let path = dir.join("scanner.toml");
+        fs::write(&path, contents).unwrap();
+        command.arg("--config").arg(path);
+    }
+    let output = command.args(args).arg("--print-config").output().unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    toml::from_str(&String::from_utf8(output.stdout).unwrap()).unwrap()
+}
+
+fn hashes(config: &toml::Table) -> Vec<&str> {
+    config["hashes"].as_array().unwrap().iter().map(|hash| hash.as_str().unwrap()).collect()
+}
+
+#[test]
+fn profile_sets_its_defaults() {
+    let dir = tempfile::tempdir().unwrap();
+    let config = resolved(dir.path(), None, &["--profile", "fast"]);
+    assert_eq!(hashes(&config), ["sha256"]);
+    assert_eq!(config["group_duplicates"].as_bool(), Some(true));
+    assert_eq!(config["min_confidence"].as_str(), Some("High"));
+
+    let config = resolved(dir.path(), None, &["--profile", "paranoid"]);
+    assert_eq!(hashes(&config), ["sha256", "sha3", "blake3", "xxh3"]);
+    assert_eq!(config["fail_on_unscannable"].as_bool(), Some(true));
+}
+
+/// A config file overrides the keys it sets and keeps the profile's others
+#[test]
+fn config_file_overrides_the_profile() {
+    let dir = tempfile::tempdir().unwrap();
+    let config = resolved(dir.path(), Some("hashes = [\"blake3\"]\n"), &["--profile", "fast"]);
+    assert_eq!(hashes(&config), ["blake3"]);
+    assert_eq!(config["group_duplicates"].as_bool(), Some(true));
+}
+
+/// Command-line options override both the config file and the profile
+#[test]
+fn command_line_overrides_the_config_file_and_the_profile() {
+    let dir = tempfile::tempdir().unwrap();
+    let file = "hashes = [\"blake3\"]\nmin_confidence = \"Confirmed\"\n";
+    let config = resolved(dir.path(), Some(file), &["--profile", "fast", "--hashes", "xxh3", "--min-confidence", "tentative"]);
+    assert_eq!(hashes(&config), ["xxh3"]);
+    assert_eq!(config["min_confidence"].as_str(), Some("Tentative"));
+    assert_eq!(config["group_duplicates"].as_bool(), Some(true));
+}
+
+/// A profile named in the config file is applied before the file's other
+/// keys, and `--profile` replaces it
+#[test]
+fn profile_in_the_config_file_is_overridden_by_its_keys_and_the_command_line() {
+    let dir = tempfile::tempdir().unwrap();
+    let file = "profile = \"fast\"\nhashes = [\"sha3\"]\n";
+    let config = resolved(dir.path(), Some(file), &[]);
+    assert_eq!(hashes(&config), ["sha3"]);
+    assert_eq!(config["group_duplicates"].as_bool(), Some(true));
+
+    let config = resolved(dir.path(), Some(file), &["--profile", "default"]);
+    assert_eq!(hashes(&config), ["sha3"]);
+    assert_eq!(config["group_duplicates"].as_bool(), Some(false));
+}
+
+/// The printed configuration reads back as a config file giving the same one
+#[test]
+fn printed_config_reads_back() {
+    let dir = tempfile::tempdir().unwrap();
+    let printed = resolved(dir.path(), None, &["--profile", "thorough", "--threads", "3"]);
+    let read_back = resolved(dir.path(), Some(&toml::to_string(&printed).unwrap()), &[]);
+    assert_eq!(read_back, printed);
+}
@@ -0,0 +1,78 @@
+use serde_json::Value;
+use std::fs;
+use std::io::{Cursor, Write};
+use std::path::Path;
+use std::process::Command;
+use zip::write::FileOptions;
+use zip::ZipWriter;
+
+const SCANNER: &str = env!("CARGO_BIN_EXE_cve_2021_44228_scanner");
+
+fn fixture(name: &str) -> Vec<u8> {
+    fs::read(Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures").join(name)).unwrap()
+}
+
+fn zip_of(entries: &[(&str, &[u8])]) -> Vec<u8> {
+    let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+    for (name, contents) in entries {
+        zip.start_file(*name, FileOptions::default()).unwrap();
+        zip.write_all(contents).unwrap();
+    }
+    zip.finish().unwrap().into_inner()
+}
+
+/// The findings of a JSON report, as (file path, inner path)
+fn findings(dir: &Path) -> Vec<(String, Option<String>)> {
+    let output = Command::new(SCANNER)
+        .arg("--path").arg(dir)
+        .args(["--format", "json"])
+        .output()
+        .unwrap();
+    let report: Value = serde_json::from_slice(&output.stdout).unwrap();
+    let mut findings: Vec<(String, Option<String>)> = report["results"].as_array().unwrap().iter()
+        .filter(|r| r["vulnerable"] == true)
+        .map(|r| (r["file_path"].as_str().unwrap().to_string(), r["inner_path"].as_str().map(String::from)))
+        .collect();
+    findings.sort();
+    findings
+}
+
+/// A vulnerable jar bundled in a wheel or an egg is reported at its nested
+/// path, with the jar's path in the package as `inner_path`
+#[test]
+fn jar_bundled_in_a_python_package_is_found() {
+    let dir = tempfile::tempdir().unwrap();
+    let jar = fixture("log4j-fixture.jar");
+    let package = zip_of(&[
+        ("pyspark/__init__.py", b""),
+        ("pyspark/jars/log4j-core-2.14.1.jar", &jar),
+        ("pyspark/jars/clean.jar", &zip_of(&[("com/example/App.class", b"\xca\xfe\xba\xbe")])),
+    ]);
+    fs::write(dir.path().join("pyspark-3.2.0-py2.py3-none-any.whl"), &package).unwrap();
+    fs::write(dir.path().join("pyspark-3.2.0-py3.9.egg"), &package).unwrap();
+
+    let inner = "pyspark/jars/log4j-core-2.14.1.jar";
+    let nested = |package: &str| dir.path().join(format!("{}!{}", package, inner)).to_string_lossy().to_string();
+    assert_eq!(findings(dir.path()), [
+        (nested("pyspark-3.2.0-py2.py3-none-any.whl"), Some(inner.to_string())),
+        (nested("pyspark-3.2.0-py3.9.egg"), Some(inner.to_string())),
+    ]);
+}
+
+#[test]
+fn fixture_wheel_names_its_bundled_jar() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(dir.path().join("log4j-fixture.whl"), fixture("log4j-fixture.whl")).unwrap();
+
+    let inner = "pyspark/jars/log4j-fixture.jar";
+    let nested = dir.path().join(format!("log4j-fixture.whl!{}", inner)).to_string_lossy().to_string();
+    assert_eq!(findings(dir.path()), [(nested, Some(inner.to_string()))]);
+}
+
+/// A plain zip bundling the same jar is not read as a Python package
+#[test]
+fn other_zips_are_not_python_packages() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(dir.path().join("pyspark.zip"), zip_of(&[("pyspark/jars/log4j-core-2.14.1.jar", &fixture("log4j-fixture.jar"))])).unwrap();
+    assert_eq!(findings(dir.path()), []);
+}
@@ -0,0 +1,77 @@
+use cve_2021_44228_scanner::schema;
+use serde_json::{json, Value};
+use std::fs;
+use std::path::Path;
+use std::process::{Command, Output};
+
+const SCANNER: &str = env!("CARGO_BIN_EXE_cve_2021_44228_scanner");
+
+/// A vulnerable jar under `tomcat/` and a vulnerable war under `app/`
+fn write_tree(root: &Path) {
+    let fixtures = Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures");
+    for (dir, name) in [("tomcat/lib", "log4j-fixture.jar"), ("app", "log4j-fixture.war")] {
+        fs::create_dir_all(root.join(dir)).unwrap();
+        fs::copy(fixtures.join(name), root.join(dir).join(name)).unwrap();
+    }
+}
+
+fn run(args: &[&str]) -> Output {
+    Command::new(SCANNER).args(args).output().unwrap()
+}
+
+fn json(output: &Output) -> Value {
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    serde_json::from_slice(&output.stdout).unwrap()
+}
+
+fn paths(report: &Value) -> Vec<&str> {
+    report["results"].as_array().unwrap().iter().map(|r| r["file_path"].as_str().unwrap()).collect()
+}
+
+/// The filter picks the findings listed, while the totals still count the
+/// whole scan
+#[test]
+fn filtered_report_keeps_the_totals_of_the_scan() {
+    let dir = tempfile::tempdir().unwrap();
+    write_tree(dir.path());
+    let root = dir.path().to_str().unwrap();
+
+    let report = json(&run(&["--path", root, "--format", "json", "--report-filter", "/tomcat/"]));
+    assert!(schema::validate_report(&report).is_empty(), "{:?}", schema::validate_report(&report));
+    assert_eq!(paths(&report), [dir.path().join("tomcat/lib/log4j-fixture.jar").to_str().unwrap()]);
+    assert_eq!(report["summary"]["total_affected_paths"], 2);
+    assert_eq!(report["summary"]["report_filter"], json!({"patterns": ["/tomcat/"], "shown": 1, "total": 2}));
+
+    let report = json(&run(&["--path", root, "--format", "json", "--report-filter", "^/nowhere/"]));
+    assert!(paths(&report).is_empty());
+    assert_eq!(report["summary"]["report_filter"]["total"], 2);
+}
+
+#[test]
+fn stored_report_is_filtered_again() {
+    let dir = tempfile::tempdir().unwrap();
+    write_tree(dir.path());
+    let stored = dir.path().join("scan.json");
+    let output = run(&["--path", dir.path().to_str().unwrap(), "--format", "json", "--output", stored.to_str().unwrap()]);
+    assert!(output.status.success());
+
+    let report = json(&run(&["--format", "json", "--report-filter", "\\.war!", "report", stored.to_str().unwrap()]));
+    assert_eq!(paths(&report).len(), 1);
+    assert!(paths(&report)[0].contains("log4j-fixture.war!"), "{:?}", paths(&report));
+
+    let output = run(&["--no-table", "--report-filter", "/tomcat/", "--report-filter", "\\.war!", "report", stored.to_str().unwrap()]);
+    let text = String::from_utf8(output.stdout).unwrap();
+    assert!(text.contains("Vulnerable files found: 2\n"), "{}", text);
+    assert!(text.contains("Showing 2 of 2 findings (filter: /tomcat/, \\.war!)"), "{}", text);
+}
+
+#[test]
+fn invalid_filter_fails_before_scanning() {
+    let dir = tempfile::tempdir().unwrap();
+    write_tree(dir.path());
+
+    let output = run(&["--path", dir.path().to_str().unwrap(), "--format", "json", "--report-filter", "log4j-(core"]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(output.stdout.is_empty());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("invalid --report-filter log4j-(core"));
+}
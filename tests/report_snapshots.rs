@@ -0,0 +1,146 @@
+use cve_2021_44228_scanner::config::Config;
+use cve_2021_44228_scanner::reporter;
+use cve_2021_44228_scanner::scanner::{ScanResult, ScanSummary};
+use std::fs;
+
+/// A finding whose path needs escaping, one with no CVE or hash, and a
+/// skipped file, which the findings formats leave out
+const RESULTS: &str = r#"[
+    {"file_path": "C:\\apps\\log4j-core-2.14.1.jar", "vulnerable": true, "status": "Vulnerable",
+     "reason": "Vulnerable entry found: JndiLookup", "severity": "Critical", "confidence": "Confirmed",
+     "cve": "CVE-2021-44228", "rule_id": "log4j-jndi-lookup-entry",
+     "file_hash": "94510d662ac0d9825afd3080440f68a3c54ab8ce704c800c1e55742321ee6cad",
+     "file_size": 1, "entropy": 0.0, "fourier_coefficient": [0.0, 0.0], "markov_probability": 0.0},
+    {"file_path": "/etc/app/log4j2.xml\nrenamed", "vulnerable": true, "status": "Vulnerable",
+     "reason": "log4j configuration: JMS appender at line 3", "severity": "Medium", "confidence": "High",
+     "rule_id": "config-jms-appender",
+     "file_size": 1, "entropy": 0.0, "fourier_coefficient": [0.0, 0.0], "markov_probability": 0.0},
+    {"file_path": "/opt/encrypted.jar", "vulnerable": false, "status": "Skipped",
+     "reason": "Skipped 1 encrypted entries", "file_size": 1,
+     "entropy": 0.0, "fourier_coefficient": [0.0, 0.0], "markov_probability": 0.0}
+]"#;
+
+/// `RESULTS` in `text-stable`. Scrapers rely on this never changing: a change
+/// here is a breaking change to the format, not a snapshot to update.
+const TEXT_STABLE_SNAPSHOT: &str = "\
+path=C:\\\\apps\\\\log4j-core-2.14.1.jar
+severity=critical
+cve=CVE-2021-44228
+sha256=94510d662ac0d9825afd3080440f68a3c54ab8ce704c800c1e55742321ee6cad
+reason_code=log4j-jndi-lookup-entry
+
+path=/etc/app/log4j2.xml\\nrenamed
+severity=medium
+cve=
+sha256=
+reason_code=config-jms-appender
+
+";
+
+/// `RESULTS` as the `table` format lays them out at three widths: wide
+/// enough for every column, the paths shortened in the middle, then too
+/// narrow for any table
+const TABLE_SNAPSHOTS: [(usize, &str); 3] = [
+    (100, "\
+PATH                           SEVERITY  CVE             HASH
+C:\\apps\\log4j-core-2.14.1.jar  critical  CVE-2021-44228  94510d662ac0
+/etc/app/log4j2.xml?renamed    medium    -               -
+"),
+    (60, "\
+PATH                  SEVERITY  CVE             HASH
+C:\\app…re-2.14.1.jar  critical  CVE-2021-44228  94510d662ac0
+/etc/a…2.xml?renamed  medium    -               -
+"),
+    (39, "\
+path\tseverity\tcve\thash
+C:\\apps\\log4j-core-2.14.1.jar\tcritical\tCVE-2021-44228\t94510d662ac0
+/etc/app/log4j2.xml?renamed\tmedium\t-\t-
+"),
+];
+
+/// Paths with each character service messages must escape, and an
+/// unscannable file that fails the build under `--fail-on-unscannable`
+const TEAMCITY_RESULTS: &str = r#"[
+    {"file_path": "/srv/a|b/it's [old]/log4j-core.jar", "vulnerable": true, "status": "Vulnerable",
+     "reason": "Vulnerable entry found: JndiLookup", "severity": "Critical", "confidence": "High",
+     "cve": "CVE-2021-44228", "rule_id": "log4j-jndi-lookup-entry",
+     "file_size": 1, "entropy": 0.0, "fourier_coefficient": [0.0, 0.0], "markov_probability": 0.0},
+    {"file_path": "/etc/app/log4j2.xml\r\nrenamed\u2028", "vulnerable": true, "status": "Vulnerable",
+     "reason": "log4j configuration: JMS appender at line 3", "severity": "Low", "confidence": "High",
+     "rule_id": "config-jms-appender",
+     "file_size": 1, "entropy": 0.0, "fourier_coefficient": [0.0, 0.0], "markov_probability": 0.0},
+    {"file_path": "/opt/encrypted.jar", "vulnerable": false, "status": "Skipped",
+     "reason": "Skipped 1 encrypted entries", "file_size": 1,
+     "entropy": 0.0, "fourier_coefficient": [0.0, 0.0], "markov_probability": 0.0}
+]"#;
+const TEAMCITY_SNAPSHOT: &str = "\
+##teamcity[inspectionType id='log4j-jndi-lookup-entry' name='log4j-jndi-lookup-entry' category='CVE-2021-44228' description='Vulnerable entry found: JndiLookup']
+##teamcity[inspectionType id='config-jms-appender' name='config-jms-appender' category='log4j' description='log4j configuration: JMS appender at line 3']
+##teamcity[inspection typeId='log4j-jndi-lookup-entry' message='Vulnerable entry found: JndiLookup (CVE-2021-44228)' file='/srv/a||b/it|'s |[old|]/log4j-core.jar' SEVERITY='ERROR']
+##teamcity[inspection typeId='config-jms-appender' message='log4j configuration: JMS appender at line 3' file='/etc/app/log4j2.xml|r|nrenamed|l' SEVERITY='INFO']
+##teamcity[buildProblem description='1 files could not be scanned' identity='log4j-scan-unscannable']
+";
+
+fn results(json: &str) -> Vec<ScanResult> {
+    serde_json::from_str(json).unwrap()
+}
+
+/// The report of `results` under `config`, written to a file as `--output` does
+fn report(results: &[ScanResult], config: Config) -> String {
+    let dir = tempfile::tempdir().unwrap();
+    let output = dir.path().join("report");
+    let config = Config { output: Some(output.to_string_lossy().to_string()), ..config };
+    reporter::report_results(results, &ScanSummary::default(), &config).unwrap();
+    fs::read_to_string(&output).unwrap()
+}
+
+#[test]
+fn text_stable_matches_its_snapshot() {
+    let config = Config { format: "text-stable".to_string(), ..Default::default() };
+    assert_eq!(report(&results(RESULTS), config), TEXT_STABLE_SNAPSHOT);
+}
+
+/// Nothing to report is an empty report, not a blank record
+#[test]
+fn text_stable_without_findings_is_empty() {
+    let config = Config { format: "text-stable".to_string(), ..Default::default() };
+    let skipped: Vec<ScanResult> = results(RESULTS).into_iter().filter(|r| !r.vulnerable).collect();
+    assert_eq!(report(&skipped, config), "");
+}
+
+/// An unescaped character would end a message early
+#[test]
+fn teamcity_matches_its_snapshot() {
+    let config = Config { format: "teamcity".to_string(), fail_on_unscannable: true, ..Default::default() };
+    assert_eq!(report(&results(TEAMCITY_RESULTS), config), TEAMCITY_SNAPSHOT);
+}
+
+/// Without `--fail-on-unscannable` the skipped file is no build problem
+#[test]
+fn teamcity_reports_build_problems_only_for_failing_conditions() {
+    let config = Config { format: "teamcity".to_string(), ..Default::default() };
+    let report = report(&results(TEAMCITY_RESULTS), config);
+    assert!(!report.contains("buildProblem"), "{}", report);
+    assert_eq!(report.lines().filter(|line| line.starts_with("##teamcity[inspection ")).count(), 2);
+}
+
+#[test]
+fn table_matches_its_snapshots_at_fixed_widths() {
+    let results = results(RESULTS);
+    for (width, snapshot) in TABLE_SNAPSHOTS {
+        assert_eq!(reporter::table_at_width(&results, &["path", "severity", "cve", "hash"], width), snapshot, "at width {}", width);
+    }
+}
+
+/// A saved report is never fitted to a terminal, and lists the columns in
+/// the order given
+#[test]
+fn saved_table_is_tab_separated_in_column_order() {
+    let columns = ["confidence", "reason", "path"].map(String::from).to_vec();
+    let config = Config { format: "table".to_string(), columns, ..Default::default() };
+    assert_eq!(report(&results(RESULTS), config), "\
+confidence\treason\tpath
+Confirmed\tVulnerable entry found: JndiLookup\tC:\\apps\\log4j-core-2.14.1.jar
+High\tlog4j configuration: JMS appender at li…\t/etc/app/log4j2.xml?renamed
+");
+}
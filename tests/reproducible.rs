@@ -0,0 +1,70 @@
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+const SCANNER: &str = env!("CARGO_BIN_EXE_cve_2021_44228_scanner");
+
+fn fixture(name: &str) -> Vec<u8> {
+    fs::read(Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures").join(name)).unwrap()
+}
+
+/// A tree with enough findings across directories for the worker threads to
+/// finish them in a different order from run to run
+fn write_tree(root: &Path) {
+    for dir in ["a", "b/c", "b/d", "e/f/g"] {
+        let dir = root.join(dir);
+        fs::create_dir_all(&dir).unwrap();
+        for name in ["log4j-fixture.jar", "log4j-fixture.war", "log4j-fixture.whl", "JndiLookup.class"] {
+            fs::write(dir.join(name), fixture(name)).unwrap();
+        }
+    }
+}
+
+fn scan(root: &Path, audit_log: &Path, threads: &str, extra: &[&str]) -> Vec<u8> {
+    let output = Command::new(SCANNER)
+        .arg("--path").arg(root)
+        .arg("--audit-log").arg(audit_log)
+        .args(["--format", "json", "--hashes", "sha256", "--threads", threads])
+        .args(extra)
+        .output()
+        .unwrap();
+    assert!(!output.stdout.is_empty(), "{}", String::from_utf8_lossy(&output.stderr));
+    output.stdout
+}
+
+#[test]
+fn reproducible_reports_are_byte_identical_across_thread_counts() {
+    let dir = tempfile::tempdir().unwrap();
+    let root = dir.path().join("tree");
+    write_tree(&root);
+    let audit_log = dir.path().join("audit.log");
+
+    let one = scan(&root, &audit_log, "1", &["--reproducible"]);
+    let eight = scan(&root, &audit_log, "8", &["--reproducible"]);
+    assert_eq!(String::from_utf8_lossy(&one), String::from_utf8_lossy(&eight));
+
+    let report: serde_json::Value = serde_json::from_slice(&one).unwrap();
+    let paths: Vec<&str> = report["results"].as_array().unwrap().iter()
+        .map(|r| r["file_path"].as_str().unwrap())
+        .collect();
+    assert_eq!(paths.len(), 16);
+    assert!(paths.windows(2).all(|pair| pair[0] <= pair[1]), "{:?}", paths);
+    assert_eq!(report["summary"]["audit_log"]["scan_id"], "00000000-0000-0000-0000-000000000000");
+}
+
+/// Without `--reproducible` the scan id and thread count are kept, so the
+/// reports differ
+#[test]
+fn reports_record_the_scan_id_and_threads_by_default() {
+    let dir = tempfile::tempdir().unwrap();
+    let root = dir.path().join("tree");
+    write_tree(&root);
+    let audit_log = dir.path().join("audit.log");
+
+    let one: serde_json::Value = serde_json::from_slice(&scan(&root, &audit_log, "1", &[])).unwrap();
+    let eight: serde_json::Value = serde_json::from_slice(&scan(&root, &audit_log, "8", &[])).unwrap();
+    assert_ne!(one["summary"]["audit_log"]["scan_id"], eight["summary"]["audit_log"]["scan_id"]);
+    assert_eq!(one["scan_config"]["threads"], 1);
+    assert_eq!(eight["scan_config"]["threads"], 8);
+    assert_eq!(one["results"], eight["results"]);
+}
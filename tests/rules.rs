@@ -0,0 +1,202 @@
+use std::fs;
+use std::io::{Cursor, Write};
+use std::path::Path;
+use std::process::{Command, Output};
+use zip::write::FileOptions;
+use zip::ZipWriter;
+
+const SCANNER: &str = env!("CARGO_BIN_EXE_cve_2021_44228_scanner");
+
+/// Run the scanner in `dir` with no saved patterns, so only the rules the
+/// arguments name are in effect
+fn run(dir: &Path, args: &[&str]) -> Output {
+    Command::new(SCANNER)
+        .args(args)
+        .current_dir(dir)
+        .env("XDG_CONFIG_HOME", dir)
+        .output()
+        .unwrap()
+}
+
+fn stdout(output: &Output) -> String {
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    String::from_utf8(output.stdout.clone()).unwrap()
+}
+
+fn zip_of(entries: &[(&str, &[u8])]) -> Vec<u8> {
+    let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+    for (name, contents) in entries {
+        zip.start_file(*name, FileOptions::default()).unwrap();
+        zip.write_all(contents).unwrap();
+    }
+    zip.finish().unwrap().into_inner()
+}
+
+/// A war bundling a jar whose class holds `token=secret-token-abc`
+fn write_war(path: &Path) {
+    let jar = zip_of(&[("com/example/Config.class", b"\xca\xfe\xba\xbe\x00\x00\x00\x34 token=secret-token-abc; end")]);
+    fs::write(path, zip_of(&[("WEB-INF/lib/inner.jar", &jar), ("WEB-INF/classes/Main.class", b"\xca\xfe\xba\xbe plain")])).unwrap();
+}
+
+#[test]
+fn pattern_matches_in_a_bundled_jar_entry() {
+    let dir = tempfile::tempdir().unwrap();
+    write_war(&dir.path().join("app.war"));
+
+    let output = stdout(&run(dir.path(), &["rules", "test", "--pattern", "secret-token-[a-z]+", "app.war"]));
+    assert_eq!(output, "app.war!WEB-INF/lib/inner.jar!com/example/Config.class @ 23 [secret-token-[a-z]+]: .......4 token=secret-token-abc; end\n");
+}
+
+#[test]
+fn pattern_without_matches_says_so() {
+    let dir = tempfile::tempdir().unwrap();
+    write_war(&dir.path().join("app.war"));
+
+    assert_eq!(stdout(&run(dir.path(), &["rules", "test", "--pattern", "not-there", "app.war"])), "no matches\n");
+}
+
+#[test]
+fn rules_file_lists_the_rules_that_fired() {
+    let dir = tempfile::tempdir().unwrap();
+    write_war(&dir.path().join("app.war"));
+    fs::write(dir.path().join("team.json"), r#"{"pack": "team", "rules": [
+        {"id": "secret-token", "pattern": "secret-token-[a-z]+", "severity": "High"},
+        {"id": "inner-jar", "pattern": "inner\\.jar", "target": "entry-name", "severity": "Low"},
+        {"id": "plain-class", "pattern": "plain$", "severity": "Medium"}
+    ]}"#).unwrap();
+
+    let output = stdout(&run(dir.path(), &["rules", "test", "--rules", "team.json", "app.war"]));
+    assert!(output.contains("app.war!WEB-INF/lib/inner.jar!com/example/Config.class @ 23 [secret-token]"), "{}", output);
+    assert!(output.contains("app.war!WEB-INF/classes/Main.class @ 13 [plain-class]: .... plain"), "{}", output);
+    assert!(output.ends_with("\nRules fired:\n  secret-token (high): 1 matches\n  plain-class (medium): 1 matches\n\nRules not fired:\n  inner-jar (low): 0 matches\n"), "{}", output);
+}
+
+/// `rules list` with a rules file, a disabled rule and a custom pattern;
+/// scripts read this layout, so a change to it must be deliberate
+const RULES_LIST_SNAPSHOT: &str = r"ID                         TARGET      SEVERITY  CVE             STATUS    PACK         ORIGIN               PATTERN
+log4j-jndi-lookup-entry    entry-name  critical  CVE-2021-44228  enabled   -            built-in             (^|/)org/apache/logging/log4j/core/lookup/JndiLookup\.class$
+log4j-jndi-lookup-class    content     critical  CVE-2021-44228  enabled   -            built-in             org/apache/logging/log4j/core/lookup/JndiLookup
+jndi-initial-context       content     high      CVE-2021-44228  enabled   -            built-in             javax/naming/InitialContext
+jndi-context               content     high      CVE-2021-44228  disabled  -            built-in             javax/naming/Context
+jndi-lookup-string         content     critical  CVE-2021-44228  enabled   -            built-in             \$\{jndi:
+log4j1-jms-sink            entry-name  high      CVE-2022-23302  enabled   -            built-in             (^|/)org/apache/log4j/net/JMSSink\.class$
+log4j1-chainsaw            entry-name  high      CVE-2022-23307  enabled   -            built-in             (^|/)org/apache/log4j/chainsaw/LoggingReceiver\.class$
+logback-jmx-configurator   entry-name  medium    CVE-2021-42550  enabled   -            built-in             (^|/)ch/qos/logback/classic/jmx/JMXConfigurator\.class$ (in logback-classic, logback-core before 1.2.8)
+secret-token               content     high      -               enabled   team         file:team.json       secret-token-[a-z]+
+custom-1                   content     high      -               enabled   -            custom-pattern       evil\.example
+";
+
+fn write_rules_file(dir: &Path) {
+    fs::write(dir.join("team.json"), r#"{"pack": "team", "version": "1.2", "rules": [
+        {"id": "secret-token", "pattern": "secret-token-[a-z]+", "severity": "High"}
+    ]}"#).unwrap();
+}
+
+const LIST_ARGS: [&str; 8] = ["rules", "list", "--rules", "team.json", "--disable-rule", "jndi-context", "--custom-patterns", r"evil\.example"];
+
+#[test]
+fn rules_list_matches_its_snapshot() {
+    let dir = tempfile::tempdir().unwrap();
+    write_rules_file(dir.path());
+    assert_eq!(stdout(&run(dir.path(), &LIST_ARGS)), RULES_LIST_SNAPSHOT);
+}
+
+#[test]
+fn rules_list_json_marks_each_origin() {
+    let dir = tempfile::tempdir().unwrap();
+    write_rules_file(dir.path());
+    let output = stdout(&run(dir.path(), &[&LIST_ARGS[..], &["--format", "json"]].concat()));
+
+    let rules: Vec<serde_json::Value> = serde_json::from_str(&output).unwrap();
+    let listed: Vec<(&str, &str, bool, Option<&str>)> = rules.iter()
+        .map(|rule| (rule["id"].as_str().unwrap(), rule["origin"].as_str().unwrap(), rule["enabled"].as_bool().unwrap(), rule["pack"].as_str()))
+        .collect();
+    assert_eq!(listed, [
+        ("log4j-jndi-lookup-entry", "built-in", true, None),
+        ("log4j-jndi-lookup-class", "built-in", true, None),
+        ("jndi-initial-context", "built-in", true, None),
+        ("jndi-context", "built-in", false, None),
+        ("jndi-lookup-string", "built-in", true, None),
+        ("log4j1-jms-sink", "built-in", true, None),
+        ("log4j1-chainsaw", "built-in", true, None),
+        ("logback-jmx-configurator", "built-in", true, None),
+        ("secret-token", "file:team.json", true, Some("team")),
+        ("custom-1", "custom-pattern", true, None),
+    ]);
+    assert_eq!(rules[0]["target"], "entry-name");
+    assert_eq!(rules[0]["pattern"], r"(^|/)org/apache/logging/log4j/core/lookup/JndiLookup\.class$");
+    assert_eq!(rules[0]["cve"], "CVE-2021-44228");
+    assert_eq!(rules[7]["affected_versions"]["fixed_in"], "1.2.8");
+}
+
+/// Two packs with distinct ids, and one reusing an id of the first
+fn write_rule_packs(dir: &Path) {
+    fs::write(dir.join("internal.json"), r#"{"pack": "internal", "version": "1.2", "author": "Platform", "rules": [
+        {"id": "internal-jndi-helper", "pattern": "JndiHelper"}
+    ]}"#).unwrap();
+    fs::write(dir.join("miners.json"), r#"{"pack": "miners", "rules": [{"id": "miner-xmrig", "pattern": "xmrig"}]}"#).unwrap();
+    fs::write(dir.join("overlap.json"), r#"{"pack": "overlap", "rules": [{"id": "internal-jndi-helper", "pattern": "JndiHelper2"}]}"#).unwrap();
+    fs::create_dir(dir.join("scan")).unwrap();
+    fs::write(dir.join("scan/Helper.class"), b"\xca\xfe\xba\xbe JndiHelper").unwrap();
+    fs::write(dir.join("scan/Miner.class"), b"\xca\xfe\xba\xbe xmrig").unwrap();
+}
+
+/// The packs a JSON report of `dir/scan` records, and the rules that found something
+fn scan_with_packs(dir: &Path, args: &[&str]) -> (serde_json::Value, Vec<String>) {
+    let output = stdout(&run(dir, &[&["--path", "scan", "--format", "json"][..], args].concat()));
+    let report: serde_json::Value = serde_json::from_str(&output).unwrap();
+    let rule_ids = report["results"].as_array().unwrap().iter()
+        .map(|r| r["rule_id"].as_str().unwrap().to_string())
+        .collect();
+    (report["summary"]["rule_packs"].clone(), rule_ids)
+}
+
+fn stderr(output: &Output) -> String {
+    assert!(!output.status.success());
+    String::from_utf8_lossy(&output.stderr).into_owned()
+}
+
+#[test]
+fn packs_with_distinct_ids_are_recorded_in_the_report() {
+    let dir = tempfile::tempdir().unwrap();
+    write_rule_packs(dir.path());
+
+    let (packs, rule_ids) = scan_with_packs(dir.path(), &["--rules", "internal.json", "--rules", "miners.json"]);
+    assert_eq!(packs, serde_json::json!([
+        {"name": "internal", "source": "internal.json", "version": "1.2", "author": "Platform", "rules": 1},
+        {"name": "miners", "source": "miners.json", "rules": 1},
+    ]));
+    assert_eq!(rule_ids, ["internal-jndi-helper", "miner-xmrig"]);
+}
+
+#[test]
+fn packs_selects_the_packs_named() {
+    let dir = tempfile::tempdir().unwrap();
+    write_rule_packs(dir.path());
+
+    let (packs, rule_ids) = scan_with_packs(dir.path(), &["--rules", "internal.json", "--rules", "miners.json", "--packs", "miners"]);
+    assert_eq!(packs, serde_json::json!([{"name": "miners", "source": "miners.json", "rules": 1}]));
+    assert_eq!(rule_ids, ["miner-xmrig"]);
+
+    let error = stderr(&run(dir.path(), &["--path", "scan", "--rules", "internal.json", "--packs", "nope"]));
+    assert!(error.contains("unknown rule pack 'nope' in --packs (the --rules files define: internal)"), "{}", error);
+}
+
+#[test]
+fn packs_sharing_a_rule_id_are_rejected_naming_both() {
+    let dir = tempfile::tempdir().unwrap();
+    write_rule_packs(dir.path());
+
+    let error = stderr(&run(dir.path(), &["--path", "scan", "--rules", "internal.json", "--rules", "overlap.json"]));
+    assert!(error.contains("rule id internal-jndi-helper is defined by both pack internal (file:internal.json) and pack overlap (file:overlap.json)"), "{}", error);
+}
+
+#[test]
+fn pack_needing_a_newer_scanner_is_rejected() {
+    let dir = tempfile::tempdir().unwrap();
+    write_rule_packs(dir.path());
+    fs::write(dir.path().join("future.json"), r#"{"pack": "future", "min_scanner_version": "99.0", "rules": []}"#).unwrap();
+
+    let error = stderr(&run(dir.path(), &["--path", "scan", "--rules", "future.json"]));
+    assert!(error.contains("rule pack future in future.json needs scanner version 99.0 or later"), "{}", error);
+}
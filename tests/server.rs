@@ -0,0 +1,71 @@
+use serde_json::{json, Value};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+const SCANNER: &str = env!("CARGO_BIN_EXE_cve_2021_44228_scanner");
+
+/// A `server` process, stopped when dropped
+struct Server {
+    child: Child,
+    url: String,
+}
+
+impl Server {
+    fn start(allow_root: &Path) -> Self {
+        // Pick a free port; the server binds it again right after
+        let address: SocketAddr = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+        let child = Command::new(SCANNER)
+            .args(["server", "--listen", &address.to_string(), "--allow-root"])
+            .arg(allow_root)
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .unwrap();
+        let started = Instant::now();
+        while TcpStream::connect(address).is_err() {
+            assert!(started.elapsed() < Duration::from_secs(30), "server did not start listening");
+            thread::sleep(Duration::from_millis(50));
+        }
+        Server { child, url: format!("http://{}/scan", address) }
+    }
+
+    fn scan(&self, path: &Path) -> Value {
+        let request = json!({ "jsonrpc": "2.0", "id": 1, "method": "scan", "params": { "path": path, "format": "json" } });
+        let response = reqwest::blocking::Client::new().post(&self.url).json(&request).send().unwrap();
+        assert_eq!(response.status().as_u16(), 200);
+        response.json().unwrap()
+    }
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+fn fixtures() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures")
+}
+
+#[test]
+fn scans_paths_inside_the_allowed_root() {
+    let server = Server::start(&fixtures());
+    let reply = server.scan(&fixtures());
+    assert_eq!(reply["id"], 1);
+    assert!(reply.get("error").is_none(), "{}", reply);
+    let results = reply["result"]["results"].as_array().unwrap();
+    assert!(results.iter().any(|r| r["vulnerable"] == true && r["file_path"].as_str().unwrap().ends_with("log4j-fixture.jar")));
+}
+
+#[test]
+fn refuses_paths_outside_the_allowed_root() {
+    let root = tempfile::tempdir().unwrap();
+    let server = Server::start(root.path());
+    let reply = server.scan(&fixtures());
+    assert_eq!(reply["error"]["code"], -32602);
+    assert!(reply.get("result").is_none());
+}
@@ -0,0 +1,37 @@
+use std::fs::{self, File};
+use std::path::Path;
+use std::process::Command;
+use std::time::{Duration, SystemTime};
+
+const SCANNER: &str = env!("CARGO_BIN_EXE_cve_2021_44228_scanner");
+
+fn fixture(name: &str) -> Vec<u8> {
+    fs::read(Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures").join(name)).unwrap()
+}
+
+fn write_modified(path: &Path, contents: &[u8], modified: SystemTime) {
+    fs::write(path, contents).unwrap();
+    File::options().write(true).open(path).unwrap().set_modified(modified).unwrap();
+}
+
+/// Only the jar modified after the cut-off is scanned, and the report records
+/// the cut-off
+#[test]
+fn since_scans_only_recently_modified_files() {
+    let dir = tempfile::tempdir().unwrap();
+    let now = SystemTime::now();
+    write_modified(&dir.path().join("old.jar"), &fixture("log4j-fixture.jar"), now - Duration::from_secs(3 * 24 * 60 * 60));
+    write_modified(&dir.path().join("new.jar"), &fixture("log4j-fixture.jar"), now);
+
+    let output = Command::new(SCANNER)
+        .arg("--path").arg(dir.path())
+        .args(["--since", "24h", "--format", "json"])
+        .output()
+        .unwrap();
+    let report: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    let paths: Vec<&str> = report["results"].as_array().unwrap().iter()
+        .map(|r| r["file_path"].as_str().unwrap())
+        .collect();
+    assert_eq!(paths, [dir.path().join("new.jar").to_string_lossy()]);
+    assert!(report["summary"]["since"].is_string(), "{}", report["summary"]);
+}
@@ -0,0 +1,144 @@
+#![cfg(unix)]
+
+use base64::Engine;
+use serde_json::{json, Value};
+use std::fs;
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::fs::PermissionsExt;
+use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+const SCANNER: &str = env!("CARGO_BIN_EXE_cve_2021_44228_scanner");
+
+/// A `server --listen-uds` process, stopped when dropped
+struct Server {
+    child: Child,
+    socket: PathBuf,
+}
+
+impl Server {
+    fn start(socket: &Path) -> Self {
+        let child = Command::new(SCANNER)
+            .args(["server", "--listen-uds"])
+            .arg(socket)
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .unwrap();
+        let started = Instant::now();
+        while UnixStream::connect(socket).is_err() {
+            assert!(started.elapsed() < Duration::from_secs(30), "server did not start listening");
+            thread::sleep(Duration::from_millis(50));
+        }
+        Server { child, socket: socket.to_path_buf() }
+    }
+
+    fn connect(&self) -> Client {
+        let stream = UnixStream::connect(&self.socket).unwrap();
+        stream.set_read_timeout(Some(Duration::from_secs(30))).unwrap();
+        Client { reader: BufReader::new(stream.try_clone().unwrap()), stream }
+    }
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+struct Client {
+    stream: UnixStream,
+    reader: BufReader<UnixStream>,
+}
+
+impl Client {
+    fn send(&mut self, line: &str) {
+        writeln!(self.stream, "{}", line).unwrap();
+    }
+
+    fn reply(&mut self) -> Value {
+        let mut line = String::new();
+        assert!(self.reader.read_line(&mut line).unwrap() > 0, "connection closed");
+        serde_json::from_str(&line).unwrap()
+    }
+}
+
+fn fixture(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures").join(name)
+}
+
+fn is_vulnerable(reply: &Value) -> bool {
+    reply["results"].as_array().unwrap().iter().any(|r| r["vulnerable"] == true)
+}
+
+/// Requests by path and inline get their findings back, in request order,
+/// on a socket only the owner can use
+#[test]
+fn scans_round_trip_over_the_socket() {
+    let dir = tempfile::tempdir().unwrap();
+    let server = Server::start(&dir.path().join("scan.sock"));
+    assert_eq!(fs::metadata(&server.socket).unwrap().permissions().mode() & 0o777, 0o600);
+
+    let mut client = server.connect();
+    let class = base64::engine::general_purpose::STANDARD.encode(fs::read(fixture("JndiLookup.class")).unwrap());
+    client.send(&json!({ "id": 1, "path": fixture("log4j-fixture.jar") }).to_string());
+    client.send(&json!({ "id": 2, "inline_b64": class, "name": "upload/JndiLookup.class" }).to_string());
+    client.send(&json!({ "id": 3, "inline_b64": "", "name": "empty.class" }).to_string());
+
+    let reply = client.reply();
+    assert_eq!(reply["id"], 1);
+    assert!(is_vulnerable(&reply), "{}", reply);
+    assert!(reply["results"][0]["file_path"].as_str().unwrap().ends_with("log4j-fixture.jar"));
+    let reply = client.reply();
+    assert_eq!(reply["id"], 2);
+    assert!(is_vulnerable(&reply), "{}", reply);
+    assert_eq!(reply["results"][0]["file_path"], "upload/JndiLookup.class");
+    let reply = client.reply();
+    assert_eq!(reply["id"], 3);
+    assert!(!is_vulnerable(&reply), "{}", reply);
+}
+
+/// A bad request gets an error reply and the connection keeps serving
+#[test]
+fn malformed_requests_get_an_error_reply() {
+    let dir = tempfile::tempdir().unwrap();
+    let server = Server::start(&dir.path().join("scan.sock"));
+    let mut client = server.connect();
+
+    client.send("not json");
+    client.send(&json!({ "id": 2, "path": "/a", "inline_b64": "" }).to_string());
+    client.send(&json!({ "id": 3, "inline_b64": "not base64!" }).to_string());
+    client.send(&json!({ "id": 4, "path": dir.path().join("missing.jar") }).to_string());
+    client.send(&json!({ "id": 5, "path": fixture("log4j-fixture.jar") }).to_string());
+
+    for id in [Value::Null, json!(2), json!(3), json!(4)] {
+        let reply = client.reply();
+        assert_eq!(reply["id"], id);
+        assert!(reply["error"].is_string(), "{}", reply);
+        assert!(reply.get("results").is_none(), "{}", reply);
+    }
+    let reply = client.reply();
+    assert_eq!(reply["id"], 5);
+    assert!(is_vulnerable(&reply), "{}", reply);
+}
+
+/// A stale socket from an earlier run is replaced, and clients are served
+/// side by side
+#[test]
+fn clients_are_served_concurrently() {
+    let dir = tempfile::tempdir().unwrap();
+    let socket = dir.path().join("scan.sock");
+    drop(std::os::unix::net::UnixListener::bind(&socket).unwrap());
+    let server = Server::start(&socket);
+
+    let mut first = server.connect();
+    let mut second = server.connect();
+    first.send(&json!({ "id": "first", "path": fixture("log4j-fixture.war") }).to_string());
+    second.send(&json!({ "id": "second", "path": fixture("log4j-fixture.jar") }).to_string());
+    assert_eq!(second.reply()["id"], "second");
+    assert_eq!(first.reply()["id"], "first");
+}
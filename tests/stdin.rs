@@ -0,0 +1,62 @@
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+use std::process::{Command, Output, Stdio};
+
+const SCANNER: &str = env!("CARGO_BIN_EXE_cve_2021_44228_scanner");
+
+fn fixture(name: &str) -> Vec<u8> {
+    fs::read(Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures").join(name)).unwrap()
+}
+
+/// Run the scanner with `input` piped to it
+fn run(args: &[&str], input: &[u8]) -> Output {
+    let mut child = Command::new(SCANNER)
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    // The scanner may exit before reading anything
+    let _ = child.stdin.take().unwrap().write_all(input);
+    child.wait_with_output().unwrap()
+}
+
+fn reported_paths(output: &Output) -> Vec<String> {
+    let report: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    report["results"].as_array().unwrap().iter().map(|r| r["file_path"].as_str().unwrap().to_string()).collect()
+}
+
+#[test]
+fn filename_without_stdin_is_an_error() {
+    let fixtures = Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures");
+    let output = run(&["--path", fixtures.to_str().unwrap(), "--filename", "app.jar"], b"");
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("--filename names the --stdin results"));
+}
+
+#[test]
+fn filename_in_a_config_file_without_stdin_is_an_error() {
+    let dir = tempfile::tempdir().unwrap();
+    let config = dir.path().join("scan.toml");
+    fs::write(&config, "stdin_filename = \"app.jar\"\n").unwrap();
+    let output = run(&["--path", dir.path().to_str().unwrap(), "--config", config.to_str().unwrap()], b"");
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("--filename names the --stdin results"));
+}
+
+#[test]
+fn stdin_results_carry_the_filename() {
+    let output = run(&["--stdin", "--filename", "app.jar", "--format", "json"], &fixture("log4j-fixture.jar"));
+    assert!(reported_paths(&output).iter().any(|path| path == "app.jar"), "{:?}", reported_paths(&output));
+}
+
+#[test]
+fn filename_names_stdin_from_a_config_file() {
+    let dir = tempfile::tempdir().unwrap();
+    let config = dir.path().join("scan.toml");
+    fs::write(&config, "stdin = true\nstdin_filename = \"from-config.jar\"\n").unwrap();
+    let output = run(&["--config", config.to_str().unwrap(), "--filename", "app.jar", "--format", "json"], &fixture("log4j-fixture.jar"));
+    assert!(reported_paths(&output).iter().any(|path| path == "app.jar"), "{:?}", reported_paths(&output));
+}
@@ -0,0 +1,101 @@
+use serde_json::Value;
+use std::fs;
+use std::io::{Cursor, Write};
+use std::path::Path;
+use std::process::{Command, Output};
+use zip::write::FileOptions;
+use zip::{CompressionMethod, ZipWriter};
+
+const SCANNER: &str = env!("CARGO_BIN_EXE_cve_2021_44228_scanner");
+
+/// Bytes of the zeros in the highly compressible class entry
+const BOMB_CLASS_SIZE: usize = 1 << 20;
+
+/// A jar with one class entry of `BOMB_CLASS_SIZE` zeros
+fn class_jar(compression: CompressionMethod) -> Vec<u8> {
+    let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+    writer.start_file("Bomb.class", FileOptions::default().compression_method(compression)).unwrap();
+    writer.write_all(&vec![0; BOMB_CLASS_SIZE]).unwrap();
+    writer.finish().unwrap().into_inner()
+}
+
+/// A deflated and a stored jar of zeros next to the fixture jar
+fn write_tree(root: &Path) {
+    fs::write(root.join("bomb.jar"), class_jar(CompressionMethod::Deflated)).unwrap();
+    fs::write(root.join("stored.jar"), class_jar(CompressionMethod::Stored)).unwrap();
+    fs::copy(Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures/log4j-fixture.jar"), root.join("log4j-fixture.jar")).unwrap();
+}
+
+fn scan(root: &Path, args: &[&str]) -> Output {
+    Command::new(SCANNER)
+        .arg("--path").arg(root)
+        .args(["--format", "json"])
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+fn results(output: &Output) -> Vec<Value> {
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let report: Value = serde_json::from_slice(&output.stdout).unwrap();
+    report["results"].as_array().unwrap().clone()
+}
+
+fn suspicious(results: &[Value]) -> Vec<&Value> {
+    results.iter().filter(|r| r["rule_id"] == "suspicious-compression-ratio").collect()
+}
+
+/// The deflated zeros are a Low finding with both sizes; the stored copy
+/// and the fixture's entries are not
+#[test]
+fn highly_compressed_class_entries_are_low_findings() {
+    let dir = tempfile::tempdir().unwrap();
+    write_tree(dir.path());
+    let results = results(&scan(dir.path(), &[]));
+
+    let flagged = suspicious(&results);
+    assert_eq!(flagged.len(), 1, "{:?}", flagged);
+    let bomb = flagged[0];
+    assert!(bomb["file_path"].as_str().unwrap().ends_with("bomb.jar!Bomb.class"));
+    assert_eq!(bomb["severity"], "Low");
+    assert!(bomb["reason"].as_str().unwrap().starts_with("Suspicious compression ratio: "), "{}", bomb["reason"]);
+    assert_eq!(bomb["uncompressed_size_bytes"], BOMB_CLASS_SIZE);
+    let compressed = bomb["compressed_size_bytes"].as_u64().unwrap();
+    assert!(compressed > 0 && compressed * 100 < BOMB_CLASS_SIZE as u64, "{}", compressed);
+}
+
+/// A match in an archive entry carries the sizes of that entry
+#[test]
+fn findings_carry_the_sizes_of_their_entry() {
+    let dir = tempfile::tempdir().unwrap();
+    write_tree(dir.path());
+    let results = results(&scan(dir.path(), &[]));
+
+    let fixture = results.iter()
+        .find(|r| r["file_path"].as_str().unwrap().ends_with("log4j-fixture.jar") && r["vulnerable"] == true)
+        .unwrap();
+    assert!(fixture["compressed_size_bytes"].as_u64().unwrap() > 0);
+    assert!(fixture["uncompressed_size_bytes"].as_u64().unwrap() > 0);
+}
+
+#[test]
+fn suspicious_ratio_sets_the_threshold() {
+    let dir = tempfile::tempdir().unwrap();
+    write_tree(dir.path());
+    let results_at = |ratio: &str| results(&scan(dir.path(), &["--suspicious-ratio", ratio]));
+
+    assert!(suspicious(&results_at("100000")).is_empty());
+    // At 1 anything inflating at all is reported, still not the stored copy
+    let flagged = results_at("1");
+    let flagged: Vec<&str> = suspicious(&flagged).iter().map(|r| r["file_path"].as_str().unwrap()).collect();
+    assert!(flagged.iter().any(|path| path.ends_with("bomb.jar!Bomb.class")), "{:?}", flagged);
+    assert!(!flagged.iter().any(|path| path.contains("stored.jar")), "{:?}", flagged);
+}
+
+#[test]
+fn ratios_below_one_are_rejected() {
+    let dir = tempfile::tempdir().unwrap();
+    let output = scan(dir.path(), &["--suspicious-ratio", "0.5"]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("--suspicious-ratio must be a number of at least 1, not 0.5"));
+}
@@ -0,0 +1,64 @@
+use serde_json::Value;
+use std::fs;
+use std::path::Path;
+use std::process::{Command, Output};
+
+const SCANNER: &str = env!("CARGO_BIN_EXE_cve_2021_44228_scanner");
+
+fn fixture(name: &str) -> Vec<u8> {
+    fs::read(Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures").join(name)).unwrap()
+}
+
+/// A directory with the AES encrypted and the truncated jar fixtures
+fn unscannable_dir() -> tempfile::TempDir {
+    let dir = tempfile::tempdir().unwrap();
+    for name in ["encrypted-fixture.jar", "truncated-fixture.jar"] {
+        fs::write(dir.path().join(name), fixture(name)).unwrap();
+    }
+    dir
+}
+
+fn scan(dir: &Path, args: &[&str]) -> Output {
+    Command::new(SCANNER)
+        .arg("--path").arg(dir)
+        .args(["--format", "json"])
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+fn result<'a>(report: &'a Value, dir: &Path, name: &str) -> &'a Value {
+    let path = dir.join(name).to_string_lossy().to_string();
+    report["results"].as_array().unwrap().iter()
+        .find(|r| r["file_path"] == path.as_str())
+        .unwrap_or_else(|| panic!("no result for {}", path))
+}
+
+/// Neither jar vanishes from the report: the encrypted one is skipped with a
+/// Medium severity, the truncated one is an error naming the zip error
+#[test]
+fn encrypted_and_truncated_jars_are_reported() {
+    let dir = unscannable_dir();
+    let output = scan(dir.path(), &[]);
+    assert!(output.status.success());
+    let report: Value = serde_json::from_slice(&output.stdout).unwrap();
+
+    let encrypted = result(&report, dir.path(), "encrypted-fixture.jar");
+    assert_eq!(encrypted["status"], "Skipped");
+    assert_eq!(encrypted["reason"], "encrypted entries — cannot scan");
+    assert_eq!(encrypted["severity"], "Medium");
+    assert_eq!(encrypted["vulnerable"], false);
+
+    let truncated = result(&report, dir.path(), "truncated-fixture.jar");
+    assert_eq!(truncated["status"], "Error");
+    assert!(truncated["reason"].as_str().unwrap().starts_with("Corrupted archive: "), "{}", truncated["reason"]);
+
+    assert_eq!(report["summary"]["skipped"], 1);
+    assert_eq!(report["summary"]["errors"], 1);
+}
+
+#[test]
+fn fail_on_unscannable_fails_the_scan() {
+    let dir = unscannable_dir();
+    assert_eq!(scan(dir.path(), &["--fail-on-unscannable"]).status.code(), Some(1));
+}
@@ -0,0 +1,129 @@
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{Cursor, Read, Write};
+use std::path::Path;
+use std::process::{Command, Output};
+use zip::write::FileOptions;
+use zip::{ZipArchive, ZipWriter};
+
+const SCANNER: &str = env!("CARGO_BIN_EXE_cve_2021_44228_scanner");
+const JNDI_LOOKUP_ENTRY: &str = "org/apache/logging/log4j/core/lookup/JndiLookup.class";
+
+fn fixture(name: &str) -> Vec<u8> {
+    fs::read(Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures").join(name)).unwrap()
+}
+
+/// `jar` without its `name` entry, as the log4j mitigation `zip -d` leaves it
+fn strip_entry(jar: &[u8], name: &str) -> Vec<u8> {
+    let mut archive = ZipArchive::new(Cursor::new(jar)).unwrap();
+    let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+    for index in 0..archive.len() {
+        let entry = archive.by_index_raw(index).unwrap();
+        if entry.name() != name {
+            writer.raw_copy_file(entry).unwrap();
+        }
+    }
+    writer.finish().unwrap().into_inner()
+}
+
+/// `war` with the jar at `name` stripped of its JndiLookup class
+fn patch_war(war: &[u8], name: &str) -> Vec<u8> {
+    let mut archive = ZipArchive::new(Cursor::new(war)).unwrap();
+    let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+    for index in 0..archive.len() {
+        let mut entry = archive.by_index(index).unwrap();
+        let mut contents = Vec::new();
+        entry.read_to_end(&mut contents).unwrap();
+        if entry.name() == name {
+            contents = strip_entry(&contents, JNDI_LOOKUP_ENTRY);
+        }
+        writer.start_file(entry.name(), FileOptions::default()).unwrap();
+        writer.write_all(&contents).unwrap();
+    }
+    writer.finish().unwrap().into_inner()
+}
+
+fn run(dir: &Path, args: &[&str]) -> Output {
+    Command::new(SCANNER).args(args).current_dir(dir).output().unwrap()
+}
+
+/// `verify-fixes` of `report.json` in `dir`: its exit code and each path's status
+fn verify_fixes(dir: &Path) -> (Option<i32>, BTreeMap<String, String>) {
+    let output = run(dir, &["--format", "json", "verify-fixes", "report.json"]);
+    let verified: Vec<Value> = serde_json::from_slice(&output.stdout).unwrap();
+    let statuses = verified.iter()
+        .map(|v| (v["file_path"].as_str().unwrap().to_string(), v["status"].as_str().unwrap().to_string()))
+        .collect();
+    (output.status.code(), statuses)
+}
+
+/// The war's inner jar name, as the fixture holds it
+fn war_jar() -> String {
+    let war = fixture("log4j-fixture.war");
+    let archive = ZipArchive::new(Cursor::new(&war)).unwrap();
+    let name = archive.file_names().find(|name| name.ends_with(".jar")).unwrap().to_string();
+    name
+}
+
+/// Scan four files and a war, then patch the jar and the war's jar, replace
+/// a class with a different vulnerable one, leave one class alone and delete
+/// the other jar
+#[test]
+fn verify_fixes_tells_each_outcome_apart() {
+    let dir = tempfile::tempdir().unwrap();
+    let files = ["patched.jar", "Replaced.class", "Unchanged.class", "removed.jar", "app.war"];
+    for (name, fixture_name) in files.iter().zip(["log4j-fixture.jar", "JndiLookup.class", "JndiLookup.class", "log4j-fixture.jar", "log4j-fixture.war"]) {
+        fs::write(dir.path().join(name), fixture(fixture_name)).unwrap();
+    }
+    assert!(run(dir.path(), &["--path", ".", "--hashes", "sha256", "--format", "json", "--output", "report.json"]).status.success());
+
+    fs::write(dir.path().join("patched.jar"), strip_entry(&fixture("log4j-fixture.jar"), JNDI_LOOKUP_ENTRY)).unwrap();
+    fs::write(dir.path().join("Replaced.class"), [fixture("JndiLookup.class"), b"recompiled".to_vec()].concat()).unwrap();
+    fs::remove_file(dir.path().join("removed.jar")).unwrap();
+    fs::write(dir.path().join("app.war"), patch_war(&fixture("log4j-fixture.war"), &war_jar())).unwrap();
+
+    let (code, statuses) = verify_fixes(dir.path());
+    assert_eq!(code, Some(1));
+    let expected: BTreeMap<String, String> = [
+        ("./patched.jar".to_string(), "Fixed"),
+        ("./Replaced.class".to_string(), "ChangedStillVulnerable"),
+        ("./Unchanged.class".to_string(), "StillVulnerable"),
+        ("./removed.jar".to_string(), "FileMissing"),
+        (format!("./app.war!{}", war_jar()), "Fixed"),
+    ].into_iter().map(|(path, status)| (path, status.to_string())).collect();
+    assert_eq!(statuses, expected);
+}
+
+#[test]
+fn verify_fixes_succeeds_once_everything_is_fixed() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(dir.path().join("patched.jar"), fixture("log4j-fixture.jar")).unwrap();
+    fs::write(dir.path().join("removed.jar"), fixture("log4j-fixture.jar")).unwrap();
+    assert!(run(dir.path(), &["--path", ".", "--format", "json", "--output", "report.json"]).status.success());
+
+    fs::write(dir.path().join("patched.jar"), strip_entry(&fixture("log4j-fixture.jar"), JNDI_LOOKUP_ENTRY)).unwrap();
+    fs::remove_file(dir.path().join("removed.jar")).unwrap();
+
+    let output = run(dir.path(), &["verify-fixes", "report.json"]);
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "\
+./patched.jar: Fixed
+./removed.jar: FileMissing
+Fixed: 1, StillVulnerable: 0, ChangedStillVulnerable: 0, FileMissing: 1
+");
+}
+
+/// Without hashes in the report a replaced file cannot be told from the
+/// one scanned before
+#[test]
+fn report_without_hashes_cannot_tell_a_replaced_file() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(dir.path().join("Replaced.class"), fixture("JndiLookup.class")).unwrap();
+    assert!(run(dir.path(), &["--path", ".", "--hashes", "none", "--format", "json", "--output", "report.json"]).status.success());
+    fs::write(dir.path().join("Replaced.class"), [fixture("JndiLookup.class"), b"recompiled".to_vec()].concat()).unwrap();
+
+    let (code, statuses) = verify_fixes(dir.path());
+    assert_eq!(code, Some(1));
+    assert_eq!(statuses["./Replaced.class"], "StillVulnerable");
+}